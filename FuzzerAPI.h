@@ -6,14 +6,75 @@
 #include <filesystem>
 #include <iostream>
 #include <iomanip>
+#include <iterator>
 #include <sstream>
 #include <fstream>
 #include <string>
 
+#include <cstdint>
+#include <vector>
+
+#include <dlfcn.h>
 #include <unistd.h>
 
 #include "FuzzerCoverage.h"
 
+// Define FUZZING_SHMEM_INPUT in one translation unit before including this
+// header to let the fuzzer pass inputs through shared memory instead of the
+// input file (requires AFL++ instrumentation). The fuzzer falls back to the
+// file if the target doesn't support it.
+#ifdef FUZZING_SHMEM_INPUT
+extern "C" {
+int __afl_sharedmem_fuzzing = 1;
+}
+#endif
+
+/// Returns the input the fuzzer passed through shared memory, or nullptr if
+/// the input was passed as a file.
+/// @param size Set to the size of the input in bytes.
+__attribute__((no_sanitize("memory", "dataflow")))
+inline const std::uint8_t *getSharedMemoryInput(std::size_t &size) {
+    // Like the coverage map, the testcase buffer is provided by the AFL++
+    // runtime if the target is instrumented.
+    void *self = dlopen(nullptr, RTLD_NOW);
+    if (self == nullptr)
+        return nullptr;
+    auto **ptr = (std::uint8_t **) dlsym(self, "__afl_fuzz_ptr");
+    auto **len = (std::uint32_t **) dlsym(self, "__afl_fuzz_len");
+    if (ptr == nullptr || len == nullptr || *ptr == nullptr || *len == nullptr)
+        return nullptr;
+    size = **len;
+    return *ptr;
+}
+
+/// Reads the fuzzer input of this run. Uses the shared-memory input if the
+/// fuzzer passed one, otherwise the given file.
+/// @param path Path to the file containing the fuzzer input.
+__attribute__((no_sanitize("memory", "dataflow")))
+inline std::vector<std::uint8_t> readFuzzingInput(const std::string &path) {
+    std::size_t size = 0;
+    if (const std::uint8_t *input = getSharedMemoryInput(size))
+        return std::vector<std::uint8_t>(input, input + size);
+    std::ifstream file(path, std::ios::binary);
+    return std::vector<std::uint8_t>(std::istreambuf_iterator<char>(file),
+                                     std::istreambuf_iterator<char>());
+}
+
+/// Stores the fuzzer input of this run at the given path.
+/// @param pathToTestCase Path to the file containing the fuzzer input.
+/// @param outPath The path to write the input to.
+__attribute__((no_sanitize("memory", "dataflow")))
+inline void saveFuzzingInput(std::string pathToTestCase, std::string outPath) {
+    std::size_t size = 0;
+    if (const std::uint8_t *input = getSharedMemoryInput(size)) {
+        // The input file is stale when the input came through shared memory.
+        std::ofstream out(outPath, std::ios::binary);
+        out.write((const char *) input, size);
+        return;
+    }
+    std::filesystem::copy(pathToTestCase, outPath);
+}
+
 /// Returns the path that `reportFuzzingIssue` will save the input to.
 /// @param reason A string that will be displayed in the fuzzing interface.
 /// @param pathToTestCase Path to the test case on disk.
@@ -28,14 +89,19 @@ inline std::string getFuzzingSavePath(std::string reason, std::string pathToTest
 
     // Hash the test case file to always give the output an unique name.
     // The unique name is only necessary to record duplicates.
-    std::ifstream testCase(pathToTestCase);
-    if (testCase.bad()) {
-        std::cerr << "Failed to read test case: " << pathToTestCase << "\n";
-        abort();
-    }
-    // Read the test case.
+    std::size_t size = 0;
     std::stringstream buffer;
-    buffer << testCase.rdbuf();
+    if (const std::uint8_t *input = getSharedMemoryInput(size)) {
+        buffer.write((const char *) input, size);
+    } else {
+        std::ifstream testCase(pathToTestCase);
+        if (testCase.bad()) {
+            std::cerr << "Failed to read test case: " << pathToTestCase << "\n";
+            abort();
+        }
+        // Read the test case.
+        buffer << testCase.rdbuf();
+    }
 
     // Now hash the test case contents.
     std::uint64_t testCaseHashVal = std::hash<std::string>()(buffer.str());
@@ -59,6 +125,33 @@ inline std::string getFuzzingSavePath(std::string reason, std::string pathToTest
     return savedFileName;
 }
 
+/// Writes the JSON record of an input in the cause dir (see `CauseRecord` in
+/// src/causes.rs) to `<savedFileName>.json`.
+/// @param savedFileName The path returned by `getFuzzingSavePath`.
+/// @param reason The reason the input was saved for.
+__attribute__((no_sanitize("memory", "dataflow")))
+inline void writeFuzzingCauseRecord(std::string savedFileName, std::string reason) {
+    std::string hash = savedFileName.substr(savedFileName.rfind('%') + 1);
+    // Escape the reason as a JSON string.
+    std::stringstream cause;
+    for (char c : reason) {
+        if (c == '"' || c == '\\')
+            cause << '\\' << c;
+        else if ((unsigned char) c < 0x20)
+            cause << "\\u" << std::hex << std::setw(4) << std::setfill('0') << (int) c;
+        else
+            cause << c;
+    }
+    const auto now = std::chrono::system_clock::now();
+    const double time = std::chrono::duration<double>(now.time_since_epoch()).count();
+
+    std::ofstream out(savedFileName + ".json");
+    out << "{\"cause\": \"" << cause.str() << "\", ";
+    out << "\"time\": " << std::fixed << std::setprecision(3) << time << ", ";
+    if (const char *core = std::getenv("FUZZING_CORE_ID"))
+        out << "\"core\": " << core << ", ";
+    out << "\"hash\": \"" << hash << "\"}\n";
+}
 
 /// Saves the given test case and annotates it with the given reason string
 /// that will be displayed in the fuzzing interface.
@@ -80,14 +173,37 @@ inline void reportFuzzingIssue(std::string reason, std::string pathToTestCase) {
 
     std::string savedFileName = getFuzzingSavePath(reason, pathToTestCase);
 
+    // The record goes first, so the fuzzer never sees the input without it.
+    writeFuzzingCauseRecord(savedFileName, reason);
+
     // Copy the original test case to the cause dir.
     // This should probably move the file instead, but there is little
     // contention and it's not clear how AFL reacts to the input file being
     // moved.
-    std::filesystem::copy(pathToTestCase, savedFileName);
+    saveFuzzingInput(pathToTestCase, savedFileName);
     abort();
 }
 
+/// Like `reportFuzzingIssue`, for harnesses that get the input as bytes
+/// instead of a file (e.g. in-process targets, see src/in_process.rs).
+/// @param reason A string that will be displayed in the fuzzing interface.
+/// @param data The fuzzer input.
+/// @param size The size of the fuzzer input in bytes.
+[[noreturn]]
+__attribute__((no_sanitize("memory", "dataflow")))
+inline void reportFuzzingIssueData(std::string reason, const std::uint8_t *data,
+                                   std::size_t size) {
+    // The saved input is named after the hash of the file, so write the
+    // bytes to one first. Not in the cause dir, which only contains causes.
+    std::string path = (std::filesystem::temp_directory_path() /
+                        ("fuzzing-input-" + std::to_string(getpid()))).string();
+    {
+        std::ofstream out(path, std::ios::binary);
+        out.write((const char *) data, size);
+    }
+    reportFuzzingIssue(reason, path);
+}
+
 /// Should be called on every executed fuzz input.
 /// Takes care of storing all inputs if requested by the fuzzer.
 /// @param path Path to the file containing the fuzzer input.
@@ -109,7 +225,7 @@ inline void fuzzInputCallback(std::string path) {
         outPath << "-" << getpid();
         outPath << "-" << getppid();
 
-        std::filesystem::copy(path, outPath.str());
+        saveFuzzingInput(path, outPath.str());
     }
 
     if (const char *counterFolderC = std::getenv("COUNTER_FOLDER")) {
@@ -121,12 +237,17 @@ inline void fuzzInputCallback(std::string path) {
         counterFile += "/inputs_" + std::to_string(getppid());
 
         // Read and hash the file contents.
-        std::ifstream infile(path);
         std::string inputContents;
-        while (infile) {
-            char c;
-            infile.get(c);
-            inputContents.push_back(c);
+        std::size_t size = 0;
+        if (const std::uint8_t *input = getSharedMemoryInput(size)) {
+            inputContents.assign((const char *) input, size);
+        } else {
+            std::ifstream infile(path);
+            while (infile) {
+                char c;
+                infile.get(c);
+                inputContents.push_back(c);
+            }
         }
 
         const std::size_t hashSum = std::hash<std::string>()(inputContents);
@@ -147,4 +268,191 @@ inline void fuzzInputCallback(std::string path) {
     }
 }
 
+/// An interrupt the harness should inject while running the input.
+struct ScheduledInterrupt {
+    /// Cycle offset from the start of the program.
+    std::uint32_t cycle = 0;
+    /// 0 = external, 1 = timer, 2 = software.
+    std::uint32_t kind = 0;
+};
+
+/// A value the harness should write to an integer register before running
+/// the input.
+struct RegisterInit {
+    /// The register number (1-31).
+    std::uint32_t reg = 0;
+    std::uint64_t value = 0;
+};
+
+/// Configuration header that the fuzzer can place in front of the
+/// instructions (see `--config-header` and src/input_config.rs).
+struct InputConfig {
+    /// True if the input started with a configuration header.
+    bool present = false;
+    /// Harness specific feature toggles.
+    std::uint32_t features = 0;
+    /// Privilege level to start in (0 = user, 1 = supervisor, 3 = machine).
+    std::uint32_t privilege = 3;
+    /// Interrupts to inject, sorted by cycle.
+    std::vector<ScheduledInterrupt> interrupts;
+    /// Initial register values, sorted by register.
+    std::vector<RegisterInit> registers;
+    /// Size of the header in bytes. The instructions start after this.
+    std::size_t headerSize = 0;
+};
+
+/// Reads the configuration header at the start of the given input.
+/// @param input The raw bytes of the fuzzer input.
+inline InputConfig readInputConfig(const std::vector<std::uint8_t> &input) {
+    const std::uint32_t magic = 0x47464352;
+    auto readWord = [&input](std::size_t i) -> std::uint32_t {
+        std::uint32_t word = 0;
+        for (unsigned b = 0; b < 4; ++b)
+            word |= static_cast<std::uint32_t>(input[i * 4 + b]) << (8 * b);
+        return word;
+    };
+
+    InputConfig config;
+    if (input.size() < 16 || readWord(0) != magic)
+        return config;
+    // The second word is the number of header words after it, at least the
+    // features and privilege. Unknown trailing words are skipped. Checking
+    // the count before computing the size keeps it from wrapping.
+    const std::size_t length = readWord(1);
+    if (length < 2 || length > input.size() / 4 - 2)
+        return config;
+    const std::size_t headerSize = (2 + length) * 4;
+
+    config.present = true;
+    config.features = readWord(2);
+    config.privilege = readWord(3);
+    // Older headers don't contain an interrupt schedule.
+    if (headerSize >= 5 * 4) {
+        const std::size_t count = readWord(4);
+        for (std::size_t i = 0; i < count && (7 + 2 * i) * 4 <= headerSize; ++i) {
+            ScheduledInterrupt interrupt;
+            interrupt.cycle = readWord(5 + 2 * i);
+            interrupt.kind = readWord(6 + 2 * i);
+            config.interrupts.push_back(interrupt);
+        }
+        // Older headers don't contain register values.
+        const std::size_t start = 5 + 2 * config.interrupts.size();
+        if ((start + 1) * 4 <= headerSize) {
+            const std::size_t count = readWord(start);
+            for (std::size_t i = 0; i < count && (start + 4 + 3 * i) * 4 <= headerSize; ++i) {
+                RegisterInit init;
+                init.reg = readWord(start + 1 + 3 * i);
+                init.value = readWord(start + 2 + 3 * i) |
+                             static_cast<std::uint64_t>(readWord(start + 3 + 3 * i)) << 32;
+                config.registers.push_back(init);
+            }
+        }
+    }
+    config.headerSize = headerSize;
+    return config;
+}
+
+/// Data memory that the fuzzer can place between the configuration header
+/// and the instructions (see `--memory-image` and src/memory_image.rs).
+struct MemoryImage {
+    /// True if the input contained a memory image.
+    bool present = false;
+    /// The address of the first byte.
+    std::uint64_t address = 0;
+    /// The bytes to write to memory before running the input.
+    std::vector<std::uint8_t> bytes;
+    /// Size of the image in bytes (including padding). The instructions
+    /// start after this.
+    std::size_t imageSize = 0;
+};
+
+/// Reads the memory image at the given offset (usually the `headerSize` of
+/// the configuration header).
+/// @param input The raw bytes of the fuzzer input.
+/// @param offset The byte offset the image starts at.
+inline MemoryImage readMemoryImage(const std::vector<std::uint8_t> &input,
+                                   std::size_t offset) {
+    const std::uint32_t magic = 0x4d454d52;
+    auto readWord = [&input, offset](std::size_t i) -> std::uint32_t {
+        std::uint32_t word = 0;
+        for (unsigned b = 0; b < 4; ++b)
+            word |= static_cast<std::uint32_t>(input[offset + i * 4 + b]) << (8 * b);
+        return word;
+    };
+
+    MemoryImage image;
+    if (input.size() < offset + 16 || readWord(0) != magic)
+        return image;
+    const std::size_t count = readWord(3);
+    const std::size_t imageSize = 16 + (count + 3) / 4 * 4;
+    if (offset + imageSize > input.size())
+        return image;
+
+    image.present = true;
+    image.address = readWord(1) | static_cast<std::uint64_t>(readWord(2)) << 32;
+    image.bytes.assign(input.begin() + offset + 16, input.begin() + offset + 16 + count);
+    image.imageSize = imageSize;
+    return image;
+}
+
+/// A value the harness should write to a CSR before running the input.
+struct CsrInit {
+    std::uint32_t csr = 0;
+    std::uint64_t value = 0;
+};
+
+/// Reads the CSR values of the target profile (see `--profile` and
+/// src/target_profile.rs). Empty if the fuzzer didn't pass any.
+inline std::vector<CsrInit> readCsrInit() {
+    std::vector<CsrInit> result;
+    const char *var = std::getenv("FUZZING_CSR_INIT");
+    if (!var)
+        return result;
+    // Comma separated 'CSR=VALUE' pairs of hex numbers.
+    std::stringstream pairs(var);
+    std::string pair;
+    while (std::getline(pairs, pair, ',')) {
+        const std::size_t eq = pair.find('=');
+        if (eq == std::string::npos)
+            continue;
+        CsrInit init;
+        init.csr = std::stoul(pair.substr(0, eq), nullptr, 16);
+        init.value = std::stoull(pair.substr(eq + 1), nullptr, 16);
+        result.push_back(init);
+    }
+    return result;
+}
+
+/// The final architectural state of a run (see `--state-feedback` and
+/// src/state_feedback.rs).
+struct StateDump {
+    std::uint64_t pc = 0;
+    /// Integer registers, x0 included.
+    std::uint64_t xregs[32] = {};
+    /// Raw bits of the float registers. Left out if `hasFregs` is false.
+    std::uint64_t fregs[32] = {};
+    bool hasFregs = false;
+    /// Any CSRs the harness wants to distinguish states by.
+    std::vector<CsrInit> csrs;
+};
+
+/// Writes the final state of this run for the fuzzer. Does nothing if the
+/// fuzzer didn't ask for it.
+inline void writeStateDump(const StateDump &state) {
+    const char *path = std::getenv("FUZZING_STATE_DUMP");
+    if (!path)
+        return;
+    std::ofstream out(path);
+    out << std::hex;
+    out << "pc 0x" << state.pc << "\n";
+    for (unsigned i = 0; i < 32; ++i)
+        out << "x" << std::dec << i << std::hex << " 0x" << state.xregs[i] << "\n";
+    if (state.hasFregs) {
+        for (unsigned i = 0; i < 32; ++i)
+            out << "f" << std::dec << i << std::hex << " 0x" << state.fregs[i] << "\n";
+    }
+    for (const CsrInit &csr : state.csrs)
+        out << "0x" << csr.csr << " 0x" << csr.value << "\n";
+}
+
 #endif // FUZZER_API
@@ -6,6 +6,8 @@
 #include <cstdint>
 #include <iostream>
 #include <fstream>
+#include <string>
+#include <vector>
 
 #include <dlfcn.h>
 
@@ -72,6 +74,26 @@ inline void completedSimCallback() {
   }
 }
 
+/// A named range of the coverage map, e.g. the coverage of one RTL module.
+struct CoverageRegion {
+  std::string name;
+  uint32_t start = 0;
+  uint32_t length = 0;
+};
+
+/// Publishes the regions of the coverage map to the fuzzer, which uses them
+/// to break down the coverage (see src/map_regions.rs). Should be called
+/// once on startup.
+inline void publishCoverageRegions(const std::vector<CoverageRegion> &regions) {
+  const char *outpath = std::getenv("FUZZING_MAP_REGIONS");
+  if (!outpath)
+    return;
+  std::ofstream output(outpath);
+  for (const CoverageRegion &region : regions) {
+    output << region.name << " " << region.start << " " << region.length << "\n";
+  }
+}
+
 #undef COMMON_FUZZ_COVERAGE_ATTRS
 
 #endif // FUZZER_COVERAGE
@@ -0,0 +1,183 @@
+//! An AFL++ custom mutator with the RISC-V mutations of `riscv_mutator`, for
+//! fuzzing a simulator with AFL++ instead of sim-fuzzer. Inputs are in the
+//! format sim-fuzzer passes to the harness (see `ProgramInput::to_bytes`).
+//!
+//! Configured with environment variables:
+//!
+//! - `RISCV_MUTATIONS`: The mutations to use, like `sim-fuzzer --mutations`.
+//!   With 'inject-illegal', inputs may contain words that aren't
+//!   instructions, like with `sim-fuzzer --illegal-words`.
+//! - `RISCV_EXTENSIONS`: The extensions of new instructions, like
+//!   `sim-fuzzer --extensions`.
+//! - `RISCV_EPILOGUE`: A source file with instructions appended to every
+//!   input in the post processing, like `sim-fuzzer --epilogue`.
+//!
+//! Run AFL++ with `AFL_CUSTOM_MUTATOR_LIBRARY=libriscv_afl_mutator.so` and
+//! `AFL_CUSTOM_MUTATOR_ONLY=1`, as the byte-level mutations of AFL++ mostly
+//! produce inputs that don't decode.
+
+use std::{
+    ffi::{c_uint, c_void},
+    ptr, slice,
+};
+
+use libafl::mutators::{MutationResult, Mutator};
+
+use riscv_mutator::{
+    generator::InstGeneratorConfig,
+    instructions::sets,
+    mutator::{parse_mutation_list, Mutation, SelectedMutations, StandaloneState},
+    program_input::{epilogue, set_epilogue, ProgramInput},
+    text_format::parse_source,
+};
+
+// How often a mutation is tried before the input is returned unchanged.
+const MAX_ATTEMPTS: usize = 16;
+
+struct CustomMutator {
+    state: StandaloneState,
+    mutations: SelectedMutations,
+    // Whether inputs may contain words that aren't instructions.
+    raw_words: bool,
+    // The buffers handed to AFL++, valid until the next call.
+    fuzz_buf: Vec<u8>,
+    post_process_buf: Vec<u8>,
+}
+
+impl CustomMutator {
+    fn from_env(seed: u64) -> Result<Self, String> {
+        let mut config = InstGeneratorConfig::default();
+        if let Ok(names) = std::env::var("RISCV_EXTENSIONS") {
+            config = config.instruction_set(
+                sets::parse_extension_list(&names)
+                    .map_err(|e| format!("Invalid RISCV_EXTENSIONS: {}", e))?,
+            );
+        }
+        if let Ok(path) = std::env::var("RISCV_EPILOGUE") {
+            let insts = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|source| parse_source(&source))
+                .map_err(|e| format!("Failed to load RISCV_EPILOGUE: {}", e))?;
+            // Already set if AFL++ loaded the mutator before.
+            let _ = set_epilogue(&insts);
+        }
+        let list = std::env::var("RISCV_MUTATIONS").unwrap_or_else(|_| "default".to_string());
+        let selection =
+            parse_mutation_list(&list).map_err(|e| format!("Invalid RISCV_MUTATIONS: {}", e))?;
+        let raw_words = selection
+            .iter()
+            .any(|(mutation, _)| *mutation == Mutation::InjectIllegal);
+        config = config.allow_raw_words(raw_words);
+        config.validate()?;
+        Ok(Self {
+            state: StandaloneState::new(seed),
+            mutations: SelectedMutations::new(&selection, &config),
+            raw_words,
+            fuzz_buf: Vec::new(),
+            post_process_buf: Vec::new(),
+        })
+    }
+
+    // Mutates the input until a mutation applies and the result fits.
+    fn fuzz(&mut self, input: &[u8], max_size: usize) {
+        self.fuzz_buf.clear();
+        self.fuzz_buf.extend_from_slice(input);
+        let Ok(program) = ProgramInput::from_bytes_with(input, self.raw_words) else {
+            return;
+        };
+        for _ in 0..MAX_ATTEMPTS {
+            let mut mutated = program.clone();
+            let result = self.mutations.mutate(&mut self.state, &mut mutated, 0);
+            if !matches!(result, Ok(MutationResult::Mutated)) {
+                continue;
+            }
+            let bytes = mutated.to_bytes();
+            if bytes.len() <= max_size {
+                self.fuzz_buf = bytes;
+                return;
+            }
+        }
+    }
+}
+
+/// Creates the mutator. Returns null if the configuration is invalid.
+///
+/// # Safety
+///
+/// Called by AFL++, which passes its own state that is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn afl_custom_init(_afl: *mut c_void, seed: c_uint) -> *mut c_void {
+    match CustomMutator::from_env(seed as u64) {
+        Ok(mutator) => Box::into_raw(Box::new(mutator)) as *mut c_void,
+        Err(err) => {
+            eprintln!("riscv_afl_mutator: {}", err);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Mutates the input in `buf`. Inputs that don't decode are returned as they
+/// are. The splice input in `add_buf` is ignored.
+///
+/// # Safety
+///
+/// `data` must come from `afl_custom_init` and `buf` must point to
+/// `buf_size` bytes. The output stays valid until the next call.
+#[no_mangle]
+pub unsafe extern "C" fn afl_custom_fuzz(
+    data: *mut c_void,
+    buf: *mut u8,
+    buf_size: usize,
+    out_buf: *mut *mut u8,
+    _add_buf: *mut u8,
+    _add_buf_size: usize,
+    max_size: usize,
+) -> usize {
+    let mutator = &mut *(data as *mut CustomMutator);
+    let input: &[u8] = if buf.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(buf, buf_size)
+    };
+    mutator.fuzz(input, max_size);
+    *out_buf = mutator.fuzz_buf.as_mut_ptr();
+    mutator.fuzz_buf.len()
+}
+
+/// Appends the epilogue (if any) to the input before it is passed to the
+/// target, like sim-fuzzer does.
+///
+/// # Safety
+///
+/// `data` must come from `afl_custom_init` and `buf` must point to
+/// `buf_size` bytes. The output stays valid until the next call.
+#[no_mangle]
+pub unsafe extern "C" fn afl_custom_post_process(
+    data: *mut c_void,
+    buf: *mut u8,
+    buf_size: usize,
+    out_buf: *mut *mut u8,
+) -> usize {
+    let mutator = &mut *(data as *mut CustomMutator);
+    mutator.post_process_buf.clear();
+    if !buf.is_null() {
+        mutator
+            .post_process_buf
+            .extend_from_slice(slice::from_raw_parts(buf, buf_size));
+    }
+    mutator.post_process_buf.extend_from_slice(epilogue());
+    *out_buf = mutator.post_process_buf.as_mut_ptr();
+    mutator.post_process_buf.len()
+}
+
+/// Frees the mutator.
+///
+/// # Safety
+///
+/// `data` must come from `afl_custom_init` and isn't used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn afl_custom_deinit(data: *mut c_void) {
+    if !data.is_null() {
+        drop(Box::from_raw(data as *mut CustomMutator));
+    }
+}
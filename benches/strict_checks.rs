@@ -0,0 +1,25 @@
+//! Compares the cost of the checks enabled by the `strict-checks` feature
+//! with the work every execution does anyway (assembling the program).
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use libafl::prelude::{Rand, Xoshiro256StarRand};
+use riscv_mutator::{
+    assembler::assemble_instructions, generator::InstGenerator, instructions,
+    parser::check_instructions,
+};
+
+fn strict_checks(c: &mut Criterion) {
+    let mut rng = Xoshiro256StarRand::default();
+    rng.set_seed(0);
+    let set = instructions::sets::riscv_g();
+    let program = InstGenerator::new().generate_instructions(&mut rng, set, 256);
+
+    c.bench_function("assemble 256 instructions", |b| {
+        b.iter(|| assemble_instructions(black_box(&program)))
+    });
+    c.bench_function("check 256 instructions", |b| {
+        b.iter(|| check_instructions(black_box(&program), set))
+    });
+}
+
+criterion_group!(benches, strict_checks);
+criterion_main!(benches);
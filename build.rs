@@ -1,19 +1,350 @@
-use std::process::Command;
+//! Generates the instruction tables (`raw_instructions.rs`) from the
+//! riscv-opcodes checkout in `opcodes`, without depending on python3.
 
-fn main() {
-    println!("cargo:rerun-if-changed=opcodes");
+use std::{
+    collections::HashSet,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
 
-    // The RISC-V extensions that we should support.
-    let extensions = [
-        "rv_i", "rv_m", "rv_a", "rv_f", "rv_d", "rv64_i", "rv64_m", "rv64_a", "rv64_f", "rv64_d",
-    ];
+// The RISC-V extensions that we should support. Keep `sets::REGISTRY` in
+// sync.
+const EXTENSIONS: [&str; 14] = [
+    "rv_i",
+    "rv_m",
+    "rv_a",
+    "rv_f",
+    "rv_d",
+    "rv64_i",
+    "rv64_m",
+    "rv64_a",
+    "rv64_f",
+    "rv64_d",
+    "rv_zicsr",
+    "rv_zifencei",
+    "rv_system",
+    "rv_s",
+];
 
-    let src_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+// The instruction templates only have room for this many operands.
+const MAX_OPERANDS: usize = 5;
+
+/// An operand field from arg_lut.csv.
+struct Field {
+    name: String,
+    length: u32,
+    offset: u32,
+}
+
+/// An instruction of an extension file.
+struct Template {
+    name: String,
+    operands: Vec<String>,
+    match_pattern: u32,
+    mask_pattern: u32,
+}
+
+/// A line of an extension file.
+enum Line {
+    Inst(Template),
+    /// An alias of an instruction of another extension, only used if that
+    /// extension isn't generated (e.g. the RV32 'slli' of 'rv_i').
+    Pseudo {
+        origin: String,
+        inst: Template,
+    },
+    /// An instruction of another extension that also belongs to this one.
+    Import {
+        ext: String,
+        name: String,
+    },
+}
+
+// Parses the 'name, msb, lsb' lines of arg_lut.csv.
+fn parse_arg_lut(text: &str) -> Result<Vec<Field>, String> {
+    let mut result = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let parts: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [name, msb, lsb] = parts[..] else {
+            return Err(format!("Invalid arg_lut.csv line: '{}'", line));
+        };
+        let bit = |text: &str| {
+            text.parse::<u32>()
+                .map_err(|_| format!("Invalid bit in arg_lut.csv: '{}'", line))
+        };
+        let (msb, lsb) = (bit(msb)?, bit(lsb)?);
+        if lsb > msb || msb > 31 {
+            return Err(format!("Invalid bit range in arg_lut.csv: '{}'", line));
+        }
+        result.push(Field {
+            name: name.trim_matches('"').to_string(),
+            length: msb - lsb + 1,
+            offset: lsb,
+        });
+    }
+    Ok(result)
+}
+
+// Parses a decimal, hex ('0x..') or binary ('0b..') value.
+fn parse_value(text: &str) -> Option<u32> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = text.strip_prefix("0b") {
+        u32::from_str_radix(bin, 2).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+// Parses the operands and fixed bits ('14..12=0' or '30=1') of an
+// instruction.
+fn parse_template(name: &str, tokens: &[&str], fields: &HashSet<&str>) -> Result<Template, String> {
+    let mut result = Template {
+        name: name.to_string(),
+        operands: Vec::new(),
+        match_pattern: 0,
+        mask_pattern: 0,
+    };
+    for token in tokens {
+        let Some((bits, value)) = token.split_once('=') else {
+            if !fields.contains(token) {
+                return Err(format!("Unknown operand {} of {}", token, name));
+            }
+            result.operands.push(token.to_string());
+            continue;
+        };
+        let (msb, lsb) = match bits.split_once("..") {
+            Some((msb, lsb)) => (parse_value(msb), parse_value(lsb)),
+            None => (parse_value(bits), parse_value(bits)),
+        };
+        let (Some(msb), Some(lsb), Some(value)) = (msb, lsb, parse_value(value)) else {
+            return Err(format!("Invalid encoding {} of {}", token, name));
+        };
+        let length = msb.checked_sub(lsb).filter(|_| msb < 32).map(|len| len + 1);
+        let Some(length) = length else {
+            return Err(format!("Invalid bit range {} of {}", token, name));
+        };
+        let mask = (u32::MAX >> (32 - length)) << lsb;
+        if u64::from(value) >> length != 0 || result.mask_pattern & mask != 0 {
+            return Err(format!(
+                "Invalid or overlapping encoding {} of {}",
+                token, name
+            ));
+        }
+        result.mask_pattern |= mask;
+        result.match_pattern |= value << lsb;
+    }
+    if result.operands.len() > MAX_OPERANDS {
+        return Err(format!("{} has more than {} operands", name, MAX_OPERANDS));
+    }
+    Ok(result)
+}
+
+// Parses a line of an extension file. Returns None for empty lines and
+// comments.
+fn parse_line(line: &str, fields: &HashSet<&str>) -> Result<Option<Line>, String> {
+    let line = line.split('#').next().unwrap_or_default();
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    match tokens[..] {
+        [] => Ok(None),
+        ["$import", origin] => {
+            let (ext, name) = origin
+                .split_once("::")
+                .ok_or_else(|| format!("Invalid import: '{}'", line.trim()))?;
+            Ok(Some(Line::Import {
+                ext: ext.to_string(),
+                name: name.to_string(),
+            }))
+        }
+        ["$pseudo_op", origin, name, ref rest @ ..] => {
+            let (ext, _) = origin
+                .split_once("::")
+                .ok_or_else(|| format!("Invalid pseudo op: '{}'", line.trim()))?;
+            Ok(Some(Line::Pseudo {
+                origin: ext.to_string(),
+                inst: parse_template(name, rest, fields)?,
+            }))
+        }
+        [name, ref rest @ ..] if !name.starts_with('$') => {
+            Ok(Some(Line::Inst(parse_template(name, rest, fields)?)))
+        }
+        _ => Err(format!("Unsupported line: '{}'", line.trim())),
+    }
+}
+
+// The file of an extension. Newer riscv-opcodes versions keep them in a
+// subdirectory.
+fn extension_path(opcodes: &Path, ext: &str) -> PathBuf {
+    let path = opcodes.join("extensions").join(ext);
+    if path.exists() {
+        path
+    } else {
+        opcodes.join(ext)
+    }
+}
 
-    Command::new("python3")
-        .current_dir(src_dir + "/opcodes")
-        .args(["parse.py", "-rust_mutator"])
-        .args(extensions)
-        .status()
+fn read_lines(opcodes: &Path, ext: &str, fields: &HashSet<&str>) -> Result<Vec<Line>, String> {
+    let path = extension_path(opcodes, ext);
+    let text =
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    let mut result = Vec::new();
+    for line in text.lines() {
+        if let Some(line) = parse_line(line, fields).map_err(|e| format!("{:?}: {}", path, e))? {
+            result.push(line);
+        }
+    }
+    Ok(result)
+}
+
+// The templates of an extension, including its imports and the pseudo ops
+// whose original isn't generated.
+fn extension_templates(
+    opcodes: &Path,
+    ext: &str,
+    fields: &HashSet<&str>,
+) -> Result<Vec<Template>, String> {
+    let mut result = Vec::new();
+    for line in read_lines(opcodes, ext, fields)? {
+        match line {
+            Line::Inst(inst) => result.push(inst),
+            Line::Pseudo { origin, inst } if !EXTENSIONS.contains(&origin.as_str()) => {
+                result.push(inst)
+            }
+            Line::Pseudo { .. } => {}
+            Line::Import { ext: origin, .. } if EXTENSIONS.contains(&origin.as_str()) => {}
+            Line::Import { ext: origin, name } => {
+                let inst = read_lines(opcodes, &origin, fields)?
+                    .into_iter()
+                    .find_map(|line| match line {
+                        Line::Inst(inst) if inst.name == name => Some(inst),
+                        _ => None,
+                    })
+                    .ok_or_else(|| format!("{}: Failed to import {}::{}", ext, origin, name))?;
+                result.push(inst);
+            }
+        }
+    }
+    Ok(result)
+}
+
+// The `ArgKind` of a field. Returns None for fields that none of the
+// supported extensions use. Register fields are integer registers here,
+// `InstructionTemplate::arg_kind` tells FP registers apart.
+fn field_kind(name: &str) -> Option<&'static str> {
+    let kind = match name {
+        "rd" | "rs1" | "rs2" | "rs3" => "Gpr",
+        "csr" => "Csr",
+        "rm" => "Rm",
+        "shamt" | "shamtw" | "shamtd" => "Shamt",
+        "imm12" | "imm20" | "jimm20" | "imm12hi" | "imm12lo" | "bimm12hi" | "bimm12lo" | "zimm" => {
+            "Imm"
+        }
+        "fm" | "pred" | "succ" | "aq" | "rl" | "aqrl" => "Ordering",
+        _ => return None,
+    };
+    Some(kind)
+}
+
+// The name of the static of an instruction or field.
+fn static_name(name: &str) -> String {
+    name.to_uppercase().replace('.', "_")
+}
+
+fn generate(opcodes: &Path) -> Result<String, String> {
+    let lut_path = opcodes.join("arg_lut.csv");
+    let lut = fs::read_to_string(&lut_path)
+        .map_err(|e| format!("Failed to read {:?}: {}", lut_path, e))?;
+    let lut = parse_arg_lut(&lut)?;
+    let names: HashSet<&str> = lut.iter().map(|field| field.name.as_str()).collect();
+
+    let mut out = String::new();
+    out += "pub mod riscv {\n    pub mod args {\n        use super::super::*;\n";
+    // Fields without a kind are left out, using one is an error below.
+    for field in &lut {
+        let Some(kind) = field_kind(&field.name) else {
+            continue;
+        };
+        writeln!(
+            out,
+            "        pub static {}: ArgumentSpec = ArgumentSpec {{ name: \"{}\", length: {}, offset: {}, kind: ArgKind::{} }};",
+            static_name(&field.name),
+            field.name,
+            field.length,
+            field.offset,
+            kind
+        )
         .unwrap();
+    }
+    out += "    }\n";
+
+    let mut all = Vec::new();
+    for ext in EXTENSIONS {
+        let templates = extension_templates(opcodes, ext, &names)?;
+        writeln!(out, "    pub mod {} {{", ext).unwrap();
+        out += "        use super::super::*;\n        #[allow(unused_imports)] use super::args;\n";
+        let mut seen = HashSet::<String>::new();
+        let mut statics = Vec::new();
+        for inst in &templates {
+            // Extensions may list an instruction twice (e.g. by importing it).
+            let name = static_name(&inst.name);
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            if let Some(op) = inst.operands.iter().find(|op| field_kind(op).is_none()) {
+                return Err(format!("Unknown kind of operand {} of {}", op, inst.name));
+            }
+            let mut operands: Vec<String> = inst
+                .operands
+                .iter()
+                .map(|op| format!("Some(&args::{})", static_name(op)))
+                .collect();
+            operands.resize(MAX_OPERANDS, "None".to_string());
+            writeln!(
+                out,
+                "        pub static {}: InstructionTemplate = InstructionTemplate {{ name: \"{}\", match_pattern: {:#x}, mask_pattern: {:#x}, operand1: {}, operand2: {}, operand3: {}, operand4: {}, operand5: {} }};",
+                name,
+                inst.name,
+                inst.match_pattern,
+                inst.mask_pattern,
+                operands[0],
+                operands[1],
+                operands[2],
+                operands[3],
+                operands[4]
+            )
+            .unwrap();
+            statics.push(format!("&{}", name));
+            all.push(format!("&{}::{}", ext, name));
+        }
+        writeln!(
+            out,
+            "        pub static INSTS: [&InstructionTemplate; {}] = [{}];",
+            statics.len(),
+            statics.join(", ")
+        )
+        .unwrap();
+        out += "    }\n";
+    }
+
+    writeln!(
+        out,
+        "    pub static ALL: [&super::InstructionTemplate; {}] = [{}];",
+        all.len(),
+        all.join(", ")
+    )
+    .unwrap();
+    out += "    pub fn all() -> &'static [&'static super::InstructionTemplate] {\n        &ALL\n    }\n}\n";
+    Ok(out)
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=opcodes");
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let src_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let code = generate(&Path::new(&src_dir).join("opcodes"))
+        .unwrap_or_else(|err| panic!("Failed to generate the instructions: {}", err));
+    fs::write(Path::new(&out_dir).join("raw_instructions.rs"), code).unwrap();
 }
@@ -0,0 +1,63 @@
+#ifndef RISCV_MUTATOR_H
+#define RISCV_MUTATOR_H
+
+// C API for decoding the inputs of sim-fuzzer (e.g. corpus entries and
+// objectives) in testbenches. Link against libriscv_mutator_capi, see
+// capi/Cargo.toml.
+//
+// The functions only change in backwards compatible ways as long as
+// RISCV_MUTATOR_API_VERSION stays the same.
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RISCV_MUTATOR_API_VERSION 1
+
+/// A decoded input. Created by `riscv_mutator_parse`.
+typedef struct RiscvMutatorProgram RiscvMutatorProgram;
+
+/// Returns the RISCV_MUTATOR_API_VERSION the library was built with.
+uint32_t riscv_mutator_api_version(void);
+
+/// Decodes the contents of an input file as sim-fuzzer stores it.
+/// @param data The file contents.
+/// @param size The size of the file contents in bytes.
+/// @param error If not NULL, set to an error message on failure that must
+///              be freed with `riscv_mutator_free_string`.
+/// @return The program that must be freed with `riscv_mutator_free`, or
+///         NULL on failure.
+RiscvMutatorProgram *riscv_mutator_parse(const uint8_t *data, size_t size,
+                                         char **error);
+
+/// Returns the number of instructions in the program.
+size_t riscv_mutator_instruction_count(const RiscvMutatorProgram *program);
+
+/// Encodes the program as it is passed to the harness: The config header
+/// (if any), the memory image (if any) and the instructions in little
+/// endian. Call it with out_size 0 to query the size.
+/// @param out The buffer for the bytes. Only written if large enough.
+/// @param out_size The size of the buffer in bytes.
+/// @return The size of the encoded program in bytes.
+size_t riscv_mutator_assemble(const RiscvMutatorProgram *program,
+                              uint8_t *out, size_t out_size);
+
+/// Formats the program in the text format of inst-assembler (one
+/// instruction per line, ABI register names).
+/// @return The text that must be freed with `riscv_mutator_free_string`.
+char *riscv_mutator_disassemble_text(const RiscvMutatorProgram *program);
+
+/// Frees a string returned by the library.
+void riscv_mutator_free_string(char *string);
+
+/// Frees a program returned by `riscv_mutator_parse`.
+void riscv_mutator_free(RiscvMutatorProgram *program);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
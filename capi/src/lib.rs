@@ -0,0 +1,166 @@
+//! The C API declared in riscv_mutator.h.
+
+use std::{
+    ffi::{c_char, CString},
+    ptr, slice,
+};
+
+use riscv_mutator::{program_input::ProgramInput, registers::RegisterStyle};
+
+/// Must match RISCV_MUTATOR_API_VERSION in riscv_mutator.h.
+pub const API_VERSION: u32 = 1;
+
+/// A decoded input, opaque to C.
+pub struct RiscvMutatorProgram {
+    input: ProgramInput,
+}
+
+// Converts the string for C. Interior NUL bytes can't occur in the text the
+// library produces, but are replaced to be safe.
+fn to_c_string(text: String) -> *mut c_char {
+    CString::new(text.replace('\0', " "))
+        .map(CString::into_raw)
+        .unwrap_or(ptr::null_mut())
+}
+
+// Decodes a stored input: The postcard-serialized bytes of the storage
+// format (see `ProgramInput::to_storage_bytes`).
+fn parse(data: &[u8]) -> Result<ProgramInput, String> {
+    let stored = postcard::from_bytes::<&[u8]>(data)
+        .map_err(|e| format!("Not a sim-fuzzer input file: {}", e))?;
+    ProgramInput::from_storage_bytes(stored)
+}
+
+#[no_mangle]
+pub extern "C" fn riscv_mutator_api_version() -> u32 {
+    API_VERSION
+}
+
+/// # Safety
+///
+/// `data` must point to `size` bytes and `error` must be NULL or valid.
+#[no_mangle]
+pub unsafe extern "C" fn riscv_mutator_parse(
+    data: *const u8,
+    size: usize,
+    error: *mut *mut c_char,
+) -> *mut RiscvMutatorProgram {
+    let data: &[u8] = if data.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(data, size)
+    };
+    match parse(data) {
+        Ok(input) => Box::into_raw(Box::new(RiscvMutatorProgram { input })),
+        Err(err) => {
+            if !error.is_null() {
+                *error = to_c_string(err);
+            }
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+///
+/// `program` must come from `riscv_mutator_parse`.
+#[no_mangle]
+pub unsafe extern "C" fn riscv_mutator_instruction_count(
+    program: *const RiscvMutatorProgram,
+) -> usize {
+    (*program).input.insts().len()
+}
+
+/// # Safety
+///
+/// `program` must come from `riscv_mutator_parse` and `out` must be NULL or
+/// point to `out_size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn riscv_mutator_assemble(
+    program: *const RiscvMutatorProgram,
+    out: *mut u8,
+    out_size: usize,
+) -> usize {
+    let bytes = (*program).input.to_bytes();
+    if !out.is_null() && bytes.len() <= out_size {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), out, bytes.len());
+    }
+    bytes.len()
+}
+
+/// # Safety
+///
+/// `program` must come from `riscv_mutator_parse`.
+#[no_mangle]
+pub unsafe extern "C" fn riscv_mutator_disassemble_text(
+    program: *const RiscvMutatorProgram,
+) -> *mut c_char {
+    to_c_string((*program).input.to_text(RegisterStyle::Abi))
+}
+
+/// # Safety
+///
+/// `string` must be NULL or come from this library and isn't used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn riscv_mutator_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// # Safety
+///
+/// `program` must be NULL or come from `riscv_mutator_parse` and isn't used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn riscv_mutator_free(program: *mut RiscvMutatorProgram) {
+    if !program.is_null() {
+        drop(Box::from_raw(program));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CStr, ptr};
+
+    use riscv_mutator::{
+        program_input::ProgramInput, registers::RegisterStyle, text_format::parse_source,
+    };
+
+    use super::{
+        riscv_mutator_assemble, riscv_mutator_disassemble_text, riscv_mutator_free,
+        riscv_mutator_free_string, riscv_mutator_instruction_count, riscv_mutator_parse,
+    };
+
+    #[test]
+    fn parse_and_disassemble() {
+        let input = ProgramInput::new(parse_source("addi x10, x10, 1\nadd x1, x2, x3\n").unwrap());
+        let stored = postcard::to_allocvec(&input).unwrap();
+
+        unsafe {
+            let program = riscv_mutator_parse(stored.as_ptr(), stored.len(), ptr::null_mut());
+            assert!(!program.is_null());
+            assert_eq!(riscv_mutator_instruction_count(program), 2);
+
+            let size = riscv_mutator_assemble(program, ptr::null_mut(), 0);
+            let mut bytes = vec![0u8; size];
+            riscv_mutator_assemble(program, bytes.as_mut_ptr(), bytes.len());
+            assert_eq!(bytes, input.to_bytes());
+
+            let text = riscv_mutator_disassemble_text(program);
+            assert_eq!(
+                CStr::from_ptr(text).to_str().unwrap(),
+                input.to_text(RegisterStyle::Abi)
+            );
+            riscv_mutator_free_string(text);
+            riscv_mutator_free(program);
+
+            let mut error = ptr::null_mut();
+            let garbage = [0xffu8; 3];
+            assert!(riscv_mutator_parse(garbage.as_ptr(), garbage.len(), &mut error).is_null());
+            assert!(!error.is_null());
+            riscv_mutator_free_string(error);
+        }
+    }
+}
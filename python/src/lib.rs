@@ -0,0 +1,156 @@
+//! Python bindings for the instruction model, e.g. for coverage analysis
+//! notebooks and test generation scripts:
+//!
+//! ```python
+//! import riscv_mutator_py as rv
+//! insts = rv.generate_program(10, seed=1, extensions="rv_i,rv_m")
+//! print("\n".join(str(inst) for inst in insts))
+//! mutated = rv.mutate(rv.assemble(insts), seed=2)
+//! ```
+//!
+//! Programs are passed as bytes in the format sim-fuzzer passes to the
+//! harness (see `ProgramInput::to_bytes`).
+
+use libafl::{
+    bolts::{current_nanos, rands::StdRand},
+    mutators::{MutationResult, Mutator},
+};
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyBytes};
+
+use riscv_mutator::{
+    assembler::assemble_instructions,
+    generator::{InstGenerator, InstGeneratorConfig},
+    instructions::{self, sets},
+    mutator::{parse_mutation_list, Mutation, SelectedMutations, StandaloneState},
+    parser::parse_instructions,
+    program_input::ProgramInput,
+};
+
+// How often a mutation is tried before the input is returned unchanged.
+const MAX_ATTEMPTS: usize = 16;
+
+/// A decoded instruction.
+#[pyclass(name = "Instruction")]
+#[derive(Clone)]
+struct PyInstruction {
+    inst: instructions::Instruction,
+}
+
+#[pymethods]
+impl PyInstruction {
+    /// The mnemonic, e.g. 'addi'.
+    #[getter]
+    fn name(&self) -> &str {
+        self.inst.template().name()
+    }
+
+    /// The operands as (name, raw value) pairs in encoding order.
+    #[getter]
+    fn operands(&self) -> Vec<(String, u32)> {
+        self.inst
+            .arguments()
+            .iter()
+            .map(|arg| (arg.spec().name().to_string(), arg.value()))
+            .collect()
+    }
+
+    /// The 32-bit encoding.
+    fn encode(&self) -> u32 {
+        self.inst.encode()
+    }
+
+    fn __str__(&self) -> String {
+        self.inst.to_string().replace('\t', " ")
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Instruction('{}')", self.__str__())
+    }
+}
+
+fn value_error(err: String) -> PyErr {
+    PyValueError::new_err(err)
+}
+
+/// Decodes little-endian machine code into instructions.
+#[pyfunction]
+fn parse(data: &[u8]) -> PyResult<Vec<PyInstruction>> {
+    let insts = parse_instructions(data, instructions::riscv::all()).map_err(value_error)?;
+    Ok(insts
+        .into_iter()
+        .map(|inst| PyInstruction { inst })
+        .collect())
+}
+
+/// Encodes instructions into little-endian machine code.
+#[pyfunction]
+fn assemble(py: Python<'_>, insts: Vec<PyInstruction>) -> Py<PyBytes> {
+    let insts: Vec<_> = insts.into_iter().map(|inst| inst.inst).collect();
+    PyBytes::new(py, &assemble_instructions(&insts)).into()
+}
+
+/// Generates random instructions from the given extensions (e.g.
+/// 'rv_i,rv_m', RV64I by default).
+#[pyfunction]
+#[pyo3(signature = (length, seed=None, extensions=None))]
+fn generate_program(
+    length: u32,
+    seed: Option<u64>,
+    extensions: Option<&str>,
+) -> PyResult<Vec<PyInstruction>> {
+    let mut config = InstGeneratorConfig::default();
+    if let Some(names) = extensions {
+        config = config.instruction_set(sets::parse_extension_list(names).map_err(value_error)?);
+    }
+    let generator = InstGenerator::try_with_config(config).map_err(value_error)?;
+    let mut rand = StdRand::with_seed(seed.unwrap_or_else(current_nanos));
+    let insts = generator.generate_instructions(&mut rand, generator.instruction_set(), length);
+    Ok(insts
+        .into_iter()
+        .map(|inst| PyInstruction { inst })
+        .collect())
+}
+
+/// Applies one of the given mutations (like `sim-fuzzer --mutations`) to a
+/// program. Returns the program unchanged if none applies.
+#[pyfunction]
+#[pyo3(signature = (data, mutations="default", seed=None))]
+fn mutate(
+    py: Python<'_>,
+    data: &[u8],
+    mutations: &str,
+    seed: Option<u64>,
+) -> PyResult<Py<PyBytes>> {
+    let selection = parse_mutation_list(mutations).map_err(value_error)?;
+    // Words that aren't instructions are only expected with 'inject-illegal'.
+    let raw_words = selection
+        .iter()
+        .any(|(mutation, _)| *mutation == Mutation::InjectIllegal);
+    let program = ProgramInput::from_bytes_with(data, raw_words).map_err(value_error)?;
+    let config = InstGeneratorConfig::default().allow_raw_words(raw_words);
+    let mut mutators = SelectedMutations::new(&selection, &config);
+    let mut state = StandaloneState::new(seed.unwrap_or_else(current_nanos));
+
+    let mut result = program.to_bytes();
+    for _ in 0..MAX_ATTEMPTS {
+        let mut mutated = program.clone();
+        let applied = mutators
+            .mutate(&mut state, &mut mutated, 0)
+            .map_err(|e| value_error(e.to_string()))?;
+        if applied == MutationResult::Mutated {
+            result = mutated.to_bytes();
+            break;
+        }
+    }
+    Ok(PyBytes::new(py, &result).into())
+}
+
+#[pymodule]
+fn riscv_mutator_py(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyInstruction>()?;
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    m.add_function(wrap_pyfunction!(assemble, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_program, m)?)?;
+    m.add_function(wrap_pyfunction!(mutate, m)?)?;
+    Ok(())
+}
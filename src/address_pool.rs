@@ -0,0 +1,187 @@
+//! A small per-program pool of registers holding base addresses inside the
+//! safe memory region. Memory snippets address relative to a pool register
+//! when possible instead of materializing a fresh address each time, which
+//! makes programs denser in accesses and reuses the same cache sets.
+
+use alloc::vec::Vec;
+
+use libafl::prelude::Rand;
+
+use crate::{
+    instructions::{
+        riscv::{
+            args,
+            rv64_i::{ADDIW, SLLI, SRLI},
+            rv_i::{ADDI, LUI},
+        },
+        Instruction,
+    },
+    pmp::PmpConfig,
+    rewrites::sign_extend,
+    system_snippets::{load_address, SCRATCH_REG},
+};
+
+/// Registers that hold the pool addresses (s2-s5).
+pub const POOL_REGS: [u32; 4] = [18, 19, 20, 21];
+
+// Offsets reachable from a base register with a 12-bit immediate.
+const MIN_OFFSET: i64 = -2048;
+const MAX_OFFSET: i64 = 2047;
+
+/// The base addresses that are held in pool registers at some point of a
+/// program.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AddressPool {
+    // (register, address) pairs.
+    entries: Vec<(u32, u32)>,
+}
+
+impl AddressPool {
+    /// Finds the pool addresses that are known after executing the given
+    /// instructions in order. Only the instructions created by `load_address`
+    /// are understood, any other write to a pool register discards it.
+    pub fn of(program: &[Instruction]) -> Self {
+        let mut values: [Option<u64>; POOL_REGS.len()] = [None; POOL_REGS.len()];
+        for inst in program {
+            let Some(rd) = inst.arg_value(&args::RD) else {
+                continue;
+            };
+            let Some(idx) = POOL_REGS.iter().position(|reg| *reg == rd) else {
+                continue;
+            };
+            let same_reg = inst.arg_value(&args::RS1) == Some(rd);
+            let template = inst.template();
+            values[idx] = if template == &LUI {
+                let imm = inst.arg_value(&args::IMM20).unwrap_or(0);
+                // LUI sign-extends the 32-bit result on RV64.
+                Some(((imm << 12) as i32) as i64 as u64)
+            } else if template == &ADDI && same_reg {
+                let imm = sign_extend(inst.arg_value(&args::IMM12).unwrap_or(0), 12);
+                values[idx].map(|value| value.wrapping_add(imm as i64 as u64))
+            } else if template == &ADDIW && same_reg {
+                // ADDIW sign-extends the lower 32 bits of the sum.
+                let imm = sign_extend(inst.arg_value(&args::IMM12).unwrap_or(0), 12);
+                values[idx].map(|value| (value as i32).wrapping_add(imm) as i64 as u64)
+            } else if template == &SLLI && same_reg {
+                let shamt = inst.arg_value(&args::SHAMTD).unwrap_or(0);
+                values[idx].map(|value| value << shamt)
+            } else if template == &SRLI && same_reg {
+                let shamt = inst.arg_value(&args::SHAMTD).unwrap_or(0);
+                values[idx].map(|value| value >> shamt)
+            } else {
+                None
+            };
+        }
+        let entries = POOL_REGS
+            .iter()
+            .zip(values)
+            .filter_map(|(reg, value)| Some((*reg, u32::try_from(value?).ok()?)))
+            .collect();
+        Self { entries }
+    }
+
+    /// Picks two to four doubleword aligned addresses inside the region and
+    /// returns the pool together with the instructions that materialize it.
+    pub fn create<R: Rand>(rng: &mut R, region: &PmpConfig) -> (Self, Vec<Instruction>) {
+        let mut entries = Vec::<(u32, u32)>::new();
+        let mut setup = Vec::<Instruction>::new();
+        for reg in POOL_REGS.iter().take(rng.between(2, 4) as usize) {
+            let address = region.base() + (rng.below(region.size() as u64 / 8) * 8) as u32;
+            entries.push((*reg, address));
+            setup.append(&mut load_address(*reg, address));
+        }
+        (Self { entries }, setup)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> &[(u32, u32)] {
+        &self.entries
+    }
+
+    /// Returns a pool register and the offset from it to the address if the
+    /// address is reachable with a 12-bit immediate.
+    pub fn base_for(&self, address: u32) -> Option<(u32, i32)> {
+        self.entries.iter().find_map(|(reg, base)| {
+            let offset = address as i64 - *base as i64;
+            (MIN_OFFSET..=MAX_OFFSET)
+                .contains(&offset)
+                .then_some((*reg, offset as i32))
+        })
+    }
+
+    /// Returns the instructions that make the address reachable, the base
+    /// register and the offset to use. Falls back to materializing the
+    /// address in t0 if no pool register is close enough.
+    pub fn address_operand(&self, address: u32) -> (Vec<Instruction>, u32, i32) {
+        match self.base_for(address) {
+            Some((reg, offset)) => (Vec::new(), reg, offset),
+            None => (load_address(SCRATCH_REG, address), SCRATCH_REG, 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::prelude::Xoshiro256StarRand;
+
+    use crate::{
+        instructions::{
+            riscv::{args, rv_i::ADD},
+            Argument, Instruction,
+        },
+        pmp::PmpConfig,
+        system_snippets::{load_address, SCRATCH_REG},
+    };
+
+    use super::{AddressPool, POOL_REGS};
+
+    #[test]
+    fn find_pool_in_program() {
+        let mut rng = Xoshiro256StarRand::default();
+        let region = PmpConfig::new(0x8000_1010, 0x4000).unwrap();
+        for _ in 0..100 {
+            let (pool, setup) = AddressPool::create(&mut rng, &region);
+            assert!((2..=4).contains(&pool.entries().len()));
+            for (_, address) in pool.entries() {
+                assert!(*address >= region.base());
+                assert!(*address < region.base() + region.size());
+            }
+            assert_eq!(AddressPool::of(&setup), pool);
+        }
+
+        // Overwriting a pool register removes it from the pool.
+        let mut program = load_address(POOL_REGS[0], 0x8000_0000);
+        program.append(&mut load_address(POOL_REGS[1], 0x8000_1000));
+        program.push(Instruction::new(
+            &ADD,
+            vec![
+                Argument::new(&args::RD, POOL_REGS[0]),
+                Argument::new(&args::RS1, 1),
+                Argument::new(&args::RS2, 2),
+            ],
+        ));
+        assert_eq!(
+            AddressPool::of(&program).entries(),
+            &[(POOL_REGS[1], 0x8000_1000)]
+        );
+    }
+
+    #[test]
+    fn address_relative_to_pool() {
+        let mut program = load_address(POOL_REGS[0], 0x8000_0800);
+        program.append(&mut load_address(POOL_REGS[1], 0x8001_0000));
+        program.append(&mut load_address(POOL_REGS[2], 0x7fff_f800));
+        let pool = AddressPool::of(&program);
+        assert_eq!(pool.base_for(0x7fff_f800), Some((POOL_REGS[2], 0)));
+        assert_eq!(pool.base_for(0x8000_0000), Some((POOL_REGS[0], -2048)));
+        assert_eq!(pool.base_for(0x8001_07ff), Some((POOL_REGS[1], 2047)));
+        assert_eq!(pool.base_for(0x8001_0800), None);
+
+        let (setup, reg, offset) = pool.address_operand(0x9000_0000);
+        assert!(!setup.is_empty());
+        assert_eq!((reg, offset), (SCRATCH_REG, 0));
+    }
+}
@@ -0,0 +1,298 @@
+//! The architectural state at the end of a run, reconstructed from a commit
+//! log in the format of `spike --log-commits`:
+//!
+//! ```text
+//! core   0: 3 0x0000000080000000 (0x00500293) x5  0x0000000000000005
+//! core   0: 3 0x0000000080000004 (0x0051a023) mem 0x0000000080001000 0x00000005
+//! core   0: 3 0x0000000080000008 (0x30529073) c773_mtvec 0x0000000080000000
+//! ```
+//!
+//! Only the written state is known, so two states are compared on the
+//! registers, CSRs and memory that either run wrote.
+//!
+//! Harnesses can also dump the final state directly, one 'NAME VALUE' pair
+//! per line (see `writeStateDump` in FuzzerAPI.h):
+//!
+//! ```text
+//! pc 0x80000040
+//! x5 0x5
+//! a0 0x0
+//! mstatus 0x1800
+//! ```
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    instructions::ArgKind,
+    objdump::{csr_name, csr_number},
+    registers::parse_register,
+};
+
+/// The final values of everything a run wrote.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ArchState {
+    /// The address of the last committed instruction.
+    pub pc: Option<u64>,
+    pub xregs: BTreeMap<u32, u64>,
+    pub fregs: BTreeMap<u32, u64>,
+    pub csrs: BTreeMap<u32, u64>,
+    /// Stored values by address. Values keep the width of the store.
+    pub memory: BTreeMap<u64, u64>,
+}
+
+/// One way in which two states differ.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Difference {
+    /// What differs (e.g. 'x5', 'mstatus', 'pc' or 'mem').
+    pub location: String,
+    pub expected: Option<u64>,
+    pub actual: Option<u64>,
+}
+
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = |value: Option<u64>| match value {
+            Some(value) => format!("{:#x}", value),
+            None => "unwritten".to_string(),
+        };
+        write!(
+            f,
+            "{}: expected {}, got {}",
+            self.location,
+            value(self.expected),
+            value(self.actual)
+        )
+    }
+}
+
+fn parse_hex(token: &str) -> Option<u64> {
+    u64::from_str_radix(token.strip_prefix("0x")?, 16).ok()
+}
+
+fn parse_index(name: &str, prefix: char) -> Option<u32> {
+    name.strip_prefix(prefix)?.parse::<u32>().ok()
+}
+
+/// A coarse class of a value, so that states that only differ in the exact
+/// values of registers have the same signature.
+fn value_class(value: u64) -> u8 {
+    match value {
+        0 => 0,
+        1 => 1,
+        u64::MAX => 2,
+        _ if value.is_power_of_two() => 3,
+        _ if value >> 63 == 1 => 4,
+        _ if value <= u32::MAX as u64 => 5,
+        _ => 6,
+    }
+}
+
+impl ArchState {
+    /// Replays the writes of a commit log. Lines that aren't commits (e.g.
+    /// traps or disassembly) are skipped.
+    pub fn from_commit_log(log: &str) -> ArchState {
+        let mut state = ArchState::default();
+        for line in log.lines() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            // 'core', '0:', privilege, pc, '(encoding)' and the writes.
+            if tokens.len() < 5 || tokens[0] != "core" || !tokens[4].starts_with("(0x") {
+                continue;
+            }
+            let Some(pc) = parse_hex(tokens[3]) else {
+                continue;
+            };
+            state.pc = Some(pc);
+
+            let mut writes = tokens[5..].iter();
+            while let Some(name) = writes.next() {
+                let Some(value) = writes.next().and_then(|v| parse_hex(v)) else {
+                    break;
+                };
+                if *name == "mem" {
+                    // Loads only log the address, stores also the value.
+                    let next = writes.clone().next();
+                    if let Some(stored) = next.and_then(|v| parse_hex(v)) {
+                        writes.next();
+                        state.memory.insert(value, stored);
+                    }
+                } else if let Some(reg) = parse_index(name, 'x') {
+                    state.xregs.insert(reg, value);
+                } else if let Some(reg) = parse_index(name, 'f') {
+                    state.fregs.insert(reg, value);
+                } else if let Some(csr) = name
+                    .strip_prefix('c')
+                    .and_then(|rest| rest.split('_').next())
+                    .and_then(|num| num.parse::<u32>().ok())
+                {
+                    state.csrs.insert(csr, value);
+                }
+            }
+        }
+        state
+    }
+
+    /// Parses a state dump of the harness. Registers can be given by their
+    /// architectural or ABI name, CSRs by name or number.
+    pub fn from_dump(text: &str) -> Result<ArchState, String> {
+        let mut state = ArchState::default();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let (Some(name), Some(value), None) = (parts.next(), parts.next(), parts.next()) else {
+                return Err(format!("Expected 'NAME VALUE' in state dump: '{}'", line));
+            };
+            let value = match value.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => value.parse::<u64>(),
+            }
+            .map_err(|_| format!("Invalid value in state dump: '{}'", line))?;
+
+            if name == "pc" {
+                state.pc = Some(value);
+            } else if let Some(reg) = parse_register(ArgKind::Gpr, name) {
+                state.xregs.insert(reg, value);
+            } else if let Some(reg) = parse_register(ArgKind::Fpr, name) {
+                state.fregs.insert(reg, value);
+            } else if let Some(csr) = csr_number(name) {
+                state.csrs.insert(csr, value);
+            } else {
+                return Err(format!("Unknown register in state dump: '{}'", name));
+            }
+        }
+        Ok(state)
+    }
+
+    /// A hash of the state in which register values are reduced to coarse
+    /// classes (zero, one, power of two, ...). CSRs are kept exactly as they
+    /// encode the machine state. The pc and memory are ignored.
+    pub fn signature(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for (reg, value) in &self.xregs {
+            (reg, value_class(*value)).hash(&mut hasher);
+        }
+        // Separates the register files.
+        u32::MAX.hash(&mut hasher);
+        for (reg, value) in &self.fregs {
+            (reg, value_class(*value)).hash(&mut hasher);
+        }
+        self.csrs.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The differences to the expected state (e.g. of a reference model).
+    pub fn differences(&self, expected: &ArchState) -> Vec<Difference> {
+        fn compare<K: Ord + Copy>(
+            result: &mut Vec<Difference>,
+            expected: &BTreeMap<K, u64>,
+            actual: &BTreeMap<K, u64>,
+            location: impl Fn(K) -> String,
+        ) {
+            let mut keys: Vec<K> = expected.keys().chain(actual.keys()).copied().collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let (expected, actual) = (expected.get(&key), actual.get(&key));
+                if expected != actual {
+                    result.push(Difference {
+                        location: location(key),
+                        expected: expected.copied(),
+                        actual: actual.copied(),
+                    });
+                }
+            }
+        }
+
+        let mut result = Vec::<Difference>::new();
+        if self.pc != expected.pc {
+            result.push(Difference {
+                location: "pc".to_string(),
+                expected: expected.pc,
+                actual: self.pc,
+            });
+        }
+        compare(&mut result, &expected.xregs, &self.xregs, |reg| {
+            format!("x{}", reg)
+        });
+        compare(&mut result, &expected.fregs, &self.fregs, |reg| {
+            format!("f{}", reg)
+        });
+        compare(&mut result, &expected.csrs, &self.csrs, csr_name);
+        compare(&mut result, &expected.memory, &self.memory, |addr| {
+            format!("mem {:#x}", addr)
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArchState;
+
+    const LOG: &str = "\
+core   0: 0x0000000000001000 (0x00000297) auipc   t0, 0x0
+core   0: 3 0x0000000080000000 (0x00500293) x5  0x0000000000000005
+core   0: 3 0x0000000080000004 (0x0051a023) mem 0x0000000080001000 0x00000005
+core   0: 3 0x0000000080000008 (0x0001a303) x6  0x0000000000000005 mem 0x0000000080001000
+core   0: exception trap_illegal_instruction, epc 0x000000008000000c
+core   0: 3 0x0000000080000010 (0x30529073) c773_mtvec 0x0000000080000000
+";
+
+    #[test]
+    fn parse_commit_log() {
+        let state = ArchState::from_commit_log(LOG);
+        assert_eq!(state.pc, Some(0x8000_0010));
+        assert_eq!(state.xregs.get(&5), Some(&5));
+        assert_eq!(state.xregs.get(&6), Some(&5));
+        assert_eq!(state.memory.get(&0x8000_1000), Some(&5));
+        assert_eq!(state.memory.len(), 1);
+        assert_eq!(state.csrs.get(&0x305), Some(&0x8000_0000));
+    }
+
+    #[test]
+    fn parse_state_dump() {
+        let state = ArchState::from_dump(
+            "# final state\npc 0x80000040\nx5 0x5\na0 7\nft0 0x1\nmstatus 0x1800\n",
+        )
+        .unwrap();
+        assert_eq!(state.pc, Some(0x8000_0040));
+        assert_eq!(state.xregs.get(&5), Some(&5));
+        assert_eq!(state.xregs.get(&10), Some(&7));
+        assert_eq!(state.fregs.get(&0), Some(&1));
+        assert_eq!(state.csrs.get(&0x300), Some(&0x1800));
+        assert!(ArchState::from_dump("x5").is_err());
+        assert!(ArchState::from_dump("x32 1").is_err());
+        assert!(ArchState::from_dump("x5 zero").is_err());
+    }
+
+    #[test]
+    fn state_signatures() {
+        let state = ArchState::from_dump("x5 0x1234\nx6 0").unwrap();
+        let similar = ArchState::from_dump("x5 0x4321\nx6 0").unwrap();
+        let different = ArchState::from_dump("x5 0x1234\nx6 1").unwrap();
+        assert_eq!(state.signature(), similar.signature());
+        assert_ne!(state.signature(), different.signature());
+    }
+
+    #[test]
+    fn compare_states() {
+        let expected = ArchState::from_commit_log(LOG);
+        assert!(expected.differences(&expected).is_empty());
+
+        let mut actual = expected.clone();
+        actual.xregs.insert(5, 6);
+        actual.csrs.remove(&0x305);
+        let differences = actual.differences(&expected);
+        assert_eq!(differences.len(), 2);
+        assert_eq!(differences[0].to_string(), "x5: expected 0x5, got 0x6");
+        assert_eq!(
+            differences[1].to_string(),
+            "mtvec: expected 0x80000000, got unwritten"
+        );
+    }
+}
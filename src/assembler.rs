@@ -1,11 +1,52 @@
+use alloc::vec::Vec;
+
 use crate::instructions::Instruction;
 
+/// The byte order of instruction words in machine code. RISC-V is little
+/// endian, but some testbench memories expect big-endian word dumps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ByteOrder {
+    #[default]
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    /// Parses 'little'/'le' or 'big'/'be'.
+    pub fn from_name(name: &str) -> Option<ByteOrder> {
+        match name.to_lowercase().as_str() {
+            "little" | "le" => Some(ByteOrder::Little),
+            "big" | "be" => Some(ByteOrder::Big),
+            _ => None,
+        }
+    }
+
+    pub fn word_to_bytes(self, word: u32) -> [u8; 4] {
+        match self {
+            ByteOrder::Little => word.to_le_bytes(),
+            ByteOrder::Big => word.to_be_bytes(),
+        }
+    }
+
+    pub fn word_from_bytes(self, bytes: [u8; 4]) -> u32 {
+        match self {
+            ByteOrder::Little => u32::from_le_bytes(bytes),
+            ByteOrder::Big => u32::from_be_bytes(bytes),
+        }
+    }
+}
+
 /// Returns a list of instructions to their encoded machine code (in bytes).
-pub fn assemble_instructions(input: &Vec<Instruction>) -> Vec<u8> {
+pub fn assemble_instructions(input: &[Instruction]) -> Vec<u8> {
+    assemble_instructions_with_order(input, ByteOrder::Little)
+}
+
+/// Like `assemble_instructions`, but writes the words in the given order.
+pub fn assemble_instructions_with_order(input: &[Instruction], order: ByteOrder) -> Vec<u8> {
     let mut result = Vec::<u8>::new();
 
     for inst in input {
-        for byte in inst.encode().to_le_bytes() {
+        for byte in order.word_to_bytes(inst.encode()) {
             result.push(byte);
         }
     }
@@ -13,6 +54,102 @@ pub fn assemble_instructions(input: &Vec<Instruction>) -> Vec<u8> {
     result
 }
 
+/// The default entry address of ELF files (the reset vector of Spike).
+pub const DEFAULT_ELF_ENTRY: u64 = 0x8000_0000;
+
+const ELF_HEADER_SIZE: u16 = 64;
+const PROGRAM_HEADER_SIZE: u16 = 56;
+const SECTION_HEADER_SIZE: u16 = 64;
+pub(crate) const EM_RISCV: u16 = 243;
+const PAGE_SIZE: u64 = 0x1000;
+const SECTION_NAMES: &[u8] = b"\0.text\0.shstrtab\0";
+
+#[allow(clippy::too_many_arguments)]
+fn push_section_header(
+    out: &mut Vec<u8>,
+    name: u32,
+    kind: u32,
+    flags: u64,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    align: u64,
+) {
+    out.extend(name.to_le_bytes());
+    out.extend(kind.to_le_bytes());
+    out.extend(flags.to_le_bytes());
+    out.extend(addr.to_le_bytes());
+    out.extend(offset.to_le_bytes());
+    out.extend(size.to_le_bytes());
+    // sh_link, sh_info
+    out.extend(0u32.to_le_bytes());
+    out.extend(0u32.to_le_bytes());
+    out.extend(align.to_le_bytes());
+    // sh_entsize
+    out.extend(0u64.to_le_bytes());
+}
+
+/// Wraps the encoded instructions in a minimal RV64 ELF executable with a
+/// single loadable segment that starts at the entry address. It also has a
+/// '.text' section so that objdump can disassemble it.
+pub fn assemble_elf(input: &[Instruction], entry: u64) -> Vec<u8> {
+    code_to_elf(&assemble_instructions(input), entry)
+}
+
+/// Like `assemble_elf`, for already encoded instructions.
+pub fn code_to_elf(code: &[u8], entry: u64) -> Vec<u8> {
+    // The file offset of a segment has to match its address modulo the
+    // page size.
+    let code_offset = PAGE_SIZE + entry % PAGE_SIZE;
+    let names_offset = code_offset + code.len() as u64;
+    let section_offset = (names_offset + SECTION_NAMES.len() as u64 + 7) & !7;
+
+    let mut out = Vec::<u8>::new();
+    // e_ident: 64-bit, little endian, version 1, System V ABI.
+    out.extend(b"\x7fELF");
+    out.extend([2, 1, 1, 0]);
+    out.extend([0; 8]);
+    // e_type (executable), e_machine, e_version
+    out.extend(2u16.to_le_bytes());
+    out.extend(EM_RISCV.to_le_bytes());
+    out.extend(1u32.to_le_bytes());
+    // e_entry, e_phoff, e_shoff, e_flags
+    out.extend(entry.to_le_bytes());
+    out.extend((ELF_HEADER_SIZE as u64).to_le_bytes());
+    out.extend(section_offset.to_le_bytes());
+    out.extend(0u32.to_le_bytes());
+    // e_ehsize, e_phentsize, e_phnum, e_shentsize, e_shnum, e_shstrndx
+    out.extend(ELF_HEADER_SIZE.to_le_bytes());
+    out.extend(PROGRAM_HEADER_SIZE.to_le_bytes());
+    out.extend(1u16.to_le_bytes());
+    out.extend(SECTION_HEADER_SIZE.to_le_bytes());
+    out.extend(3u16.to_le_bytes());
+    out.extend(2u16.to_le_bytes());
+
+    // The loadable, readable and executable segment with the code.
+    out.extend(1u32.to_le_bytes());
+    out.extend(5u32.to_le_bytes());
+    out.extend(code_offset.to_le_bytes());
+    out.extend(entry.to_le_bytes());
+    out.extend(entry.to_le_bytes());
+    out.extend((code.len() as u64).to_le_bytes());
+    out.extend((code.len() as u64).to_le_bytes());
+    out.extend(PAGE_SIZE.to_le_bytes());
+
+    out.resize(code_offset as usize, 0);
+    out.extend(code);
+    out.extend(SECTION_NAMES);
+    out.resize(section_offset as usize, 0);
+
+    // The null section, .text (alloc + exec) and .shstrtab.
+    out.extend([0; SECTION_HEADER_SIZE as usize]);
+    let code_len = code.len() as u64;
+    push_section_header(&mut out, 1, 1, 0x6, entry, code_offset, code_len, 4);
+    let names_len = SECTION_NAMES.len() as u64;
+    push_section_header(&mut out, 7, 3, 0, 0, names_offset, names_len, 1);
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use libafl::prelude::Rand;
@@ -23,9 +160,9 @@ mod tests {
     use crate::instructions::riscv::args;
     use crate::instructions::riscv::rv_i::*;
     use crate::instructions::*;
-    use crate::parser::parse_instructions;
+    use crate::parser::{parse_instructions, parse_instructions_with_order};
 
-    use super::assemble_instructions;
+    use super::{assemble_elf, assemble_instructions, assemble_instructions_with_order, ByteOrder};
 
     #[test]
     fn assemble_two_instructions() {
@@ -52,10 +189,36 @@ mod tests {
         assert_eq!(assembled.len(), 8);
 
         // Parse the output and check that we get the same result.
-        let parsed = parse_instructions(&assembled, &instructions::sets::riscv_g()).unwrap();
+        let parsed = parse_instructions(&assembled, instructions::sets::riscv_g()).unwrap();
         assert_eq!(insts, parsed);
     }
 
+    #[test]
+    fn assemble_big_endian() {
+        let inst = Instruction::new(
+            &ADDI,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 0),
+                Argument::new(&args::IMM12, 1),
+            ],
+        );
+        let insts = vec![inst];
+        let assembled = assemble_instructions_with_order(&insts, ByteOrder::Big);
+        assert_eq!(assembled, vec![0x00, 0x10, 0x00, 0x93]);
+        let parsed = parse_instructions_with_order(
+            &assembled,
+            instructions::sets::riscv_g(),
+            ByteOrder::Big,
+        )
+        .unwrap();
+        assert_eq!(parsed, insts);
+
+        assert_eq!(ByteOrder::from_name("BE"), Some(ByteOrder::Big));
+        assert_eq!(ByteOrder::from_name("little"), Some(ByteOrder::Little));
+        assert_eq!(ByteOrder::from_name("middle"), None);
+    }
+
     #[test]
     fn assemble_and_parse_random_instructions() {
         for i in 0..1000 {
@@ -69,7 +232,7 @@ mod tests {
             for _ in 0..rng.below(5) {
                 let inst = generator.generate_instruction::<Xoshiro256StarRand>(
                     &mut rng,
-                    &instructions::sets::riscv_g(),
+                    instructions::sets::riscv_g(),
                 );
                 insts.push(inst);
             }
@@ -77,9 +240,40 @@ mod tests {
             let assembled = assemble_instructions(&insts);
 
             // Parse the output and check that we get the same result.
-            let parsed = parse_instructions(&assembled, &instructions::sets::riscv_g())
+            let parsed = parse_instructions(&assembled, instructions::sets::riscv_g())
                 .expect(format!("{}: Failed to parse instructions: {:?}", i, insts).as_str());
             assert_eq!(insts, parsed, "Instructions: {:?}", insts);
         }
     }
+
+    #[test]
+    fn assemble_elf_file() {
+        let inst = Instruction::new(
+            &ADDI,
+            vec![
+                Argument::new(&args::RD, 3),
+                Argument::new(&args::RS1, 5),
+                Argument::new(&args::IMM12, 11),
+            ],
+        );
+        let entry = 0x8000_0010u64;
+        let elf = assemble_elf(std::slice::from_ref(&inst), entry);
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(elf[offset..offset + 8].try_into().unwrap());
+
+        assert_eq!(&elf[0..4], b"\x7fELF");
+        assert_eq!(u16::from_le_bytes([elf[18], elf[19]]), 243);
+        assert_eq!(u64_at(24), entry);
+        // The segment maps its file offset to the entry address.
+        let phdr = u64_at(32) as usize;
+        let code_offset = u64_at(phdr + 8) as usize;
+        assert_eq!(u64_at(phdr + 16), entry);
+        assert_eq!(code_offset % 0x1000, (entry % 0x1000) as usize);
+        assert_eq!(
+            &elf[code_offset..code_offset + 4],
+            inst.encode().to_le_bytes().as_slice()
+        );
+        // The section headers are the last part of the file.
+        assert_eq!(u64_at(40) as usize + 3 * 64, elf.len());
+    }
 }
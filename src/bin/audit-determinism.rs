@@ -0,0 +1,179 @@
+use clap::Parser;
+use colored::Colorize;
+use riscv_mutator::isa_hash::check_isa_hash_file;
+use riscv_mutator::program_input::ProgramInput;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+/// Runs every corpus entry several times against the target and reports
+/// entries whose coverage map or output differs between runs.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Corpus entries or directories containing them.
+    inputs: Vec<String>,
+    /// How often each entry is executed.
+    #[arg(long, default_value_t = 2)]
+    runs: usize,
+    /// Print the differing map indices of every nondeterministic entry.
+    #[arg(short, long, default_value_t = false)]
+    verbose: bool,
+    /// The target command. '@@' is replaced by the path to the input.
+    #[arg(last = true, required = true)]
+    target: Vec<String>,
+}
+
+/// The observable result of one execution.
+#[derive(PartialEq, Eq)]
+struct Execution {
+    coverage: Vec<u8>,
+    status: Option<i32>,
+    stdout: Vec<u8>,
+}
+
+fn collect_inputs(path: &Path, result: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        let mut entries: Vec<PathBuf> = fs::read_dir(path)
+            .expect("Failed to read directory")
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        entries.sort();
+        for entry in entries {
+            collect_inputs(&entry, result);
+        }
+        return;
+    }
+    // Skip the metadata and lock files next to the corpus entries.
+    let hidden = path
+        .file_name()
+        .map_or(true, |name| name.to_string_lossy().starts_with('.'));
+    if !hidden {
+        result.push(path.to_path_buf());
+    }
+}
+
+fn execute(target: &[String], input_path: &Path, map_path: &Path) -> Execution {
+    let _ = fs::remove_file(map_path);
+    let args: Vec<String> = target[1..]
+        .iter()
+        .map(|arg| arg.replace("@@", input_path.to_str().unwrap()))
+        .collect();
+    let output = Command::new(&target[0])
+        .args(args)
+        .env("PRINT_COVERAGE_MAP", map_path)
+        .output()
+        .expect("Failed to run target");
+    Execution {
+        // The harness writes the map as a string of bits.
+        coverage: fs::read(map_path).unwrap_or_default(),
+        status: output.status.code(),
+        stdout: output.stdout,
+    }
+}
+
+/// Returns the indices of the map entries (in bytes) that differ.
+fn diff_maps(a: &[u8], b: &[u8]) -> Vec<usize> {
+    let len = a.len().max(b.len());
+    (0..len)
+        .filter(|i| a.get(*i) != b.get(*i))
+        .map(|i| i / 8)
+        .fold(Vec::<usize>::new(), |mut acc, idx| {
+            if acc.last() != Some(&idx) {
+                acc.push(idx);
+            }
+            acc
+        })
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.runs < 2 {
+        eprintln!("Need at least two runs per entry.");
+        process::exit(1);
+    }
+
+    let mut inputs = Vec::<PathBuf>::new();
+    for input in &args.inputs {
+        if let Err(err) = check_isa_hash_file(Path::new(input)) {
+            eprintln!("Warning: {}", err);
+        }
+        collect_inputs(Path::new(input), &mut inputs);
+    }
+
+    let tmp_dir = std::env::temp_dir();
+    let input_path = tmp_dir.join(format!("audit-input-{}", process::id()));
+    let map_path = tmp_dir.join(format!("audit-map-{}", process::id()));
+
+    let mut nondeterministic = 0;
+    let mut checked = 0;
+    // How often each map entry differed across all entries.
+    let mut unstable_entries = HashMap::<usize, usize>::new();
+
+    for path in &inputs {
+        let buffer = fs::read(path).expect("Failed to read file");
+        let Ok(input) = postcard::from_bytes::<ProgramInput>(buffer.as_slice()) else {
+            continue;
+        };
+        fs::write(&input_path, input.to_bytes()).expect("Failed to write input");
+        checked += 1;
+
+        let first = execute(&args.target, &input_path, &map_path);
+        let mut differing = Vec::<usize>::new();
+        let mut output_differs = false;
+        for _ in 1..args.runs {
+            let other = execute(&args.target, &input_path, &map_path);
+            if other == first {
+                continue;
+            }
+            output_differs |= other.status != first.status || other.stdout != first.stdout;
+            for idx in diff_maps(&first.coverage, &other.coverage) {
+                if !differing.contains(&idx) {
+                    differing.push(idx);
+                }
+            }
+        }
+
+        if differing.is_empty() && !output_differs {
+            continue;
+        }
+        nondeterministic += 1;
+        for idx in &differing {
+            *unstable_entries.entry(*idx).or_default() += 1;
+        }
+        println!(
+            "{} {} map entries differ{}",
+            path.display().to_string().bold(),
+            differing.len(),
+            if output_differs {
+                ", output/exit status differs"
+            } else {
+                ""
+            }
+        );
+        if args.verbose {
+            differing.sort_unstable();
+            println!("  {:?}", differing);
+        }
+    }
+
+    let _ = fs::remove_file(&input_path);
+    let _ = fs::remove_file(&map_path);
+
+    println!();
+    println!(
+        "{} of {} entries are nondeterministic ({:.1}%)",
+        nondeterministic,
+        checked,
+        100.0 * nondeterministic as f64 / checked.max(1) as f64
+    );
+    let mut unstable: Vec<(usize, usize)> = unstable_entries.into_iter().collect();
+    unstable.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    if !unstable.is_empty() {
+        println!("Most unstable map entries (index: entries):");
+        for (idx, count) in unstable.iter().take(10) {
+            println!("  {:#x}: {}", idx, count);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use clap::Parser;
+use libafl::inputs::Input;
+use riscv_mutator::assembler::{
+    assemble_elf, assemble_instructions_with_order, ByteOrder, DEFAULT_ELF_ENTRY,
+};
+use riscv_mutator::isa_hash::{check_isa_hash_file, ISA_HASH_FILE};
+use riscv_mutator::program_input::ProgramInput;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Converts a corpus directory (e.g. 'queue' or 'found') to raw machine code
+/// ('.bin') and optionally ELF executables ('.elf') with the same file names,
+/// e.g. to run reproducers on Spike or the RTL testbench. Config headers and
+/// memory images are dropped, see 'inst-unpack --text' to keep them.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The corpus directory to export.
+    input: String,
+    /// The directory to write the converted files to.
+    #[arg(short, long)]
+    out: String,
+    /// Also write an RV64 ELF executable per input.
+    #[arg(long, default_value_t = false)]
+    elf: bool,
+    /// The address at which the ELF executables are loaded and started.
+    #[arg(long, value_parser = parse_address, default_value_t = DEFAULT_ELF_ENTRY)]
+    entry: u64,
+    /// The byte order of the instruction words in '.bin' files: 'little' or
+    /// 'big'. ELF executables are always little endian.
+    #[arg(long, default_value = "little")]
+    byte_order: String,
+}
+
+fn parse_address(value: &str) -> Result<u64, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse::<u64>(),
+    }
+    .map_err(|_| format!("Invalid decimal or hex address: {}", value))
+}
+
+/// The inputs in the corpus directory, skipping the hidden metadata and lock
+/// files LibAFL stores next to them.
+fn corpus_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read {:?}: {}", dir, e))?;
+    let mut result: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            !name.starts_with('.') && name != ISA_HASH_FILE
+        })
+        .collect();
+    result.sort();
+    Ok(result)
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let Some(byte_order) = ByteOrder::from_name(&args.byte_order) else {
+        eprintln!("Unknown byte order '{}'", args.byte_order);
+        return ExitCode::FAILURE;
+    };
+
+    let in_dir = PathBuf::from(&args.input);
+    if let Err(err) = check_isa_hash_file(&in_dir) {
+        eprintln!("Warning: {}", err);
+    }
+    let files = match corpus_files(&in_dir) {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let out_dir = PathBuf::from(&args.out);
+    fs::create_dir_all(&out_dir).expect("Failed to create output directory");
+
+    let mut exported = 0;
+    let mut skipped = 0;
+    for path in &files {
+        let input = match ProgramInput::from_file(path) {
+            Ok(input) => input,
+            Err(err) => {
+                eprintln!("Skipping {:?}: {}", path, err);
+                skipped += 1;
+                continue;
+            }
+        };
+        let name = path.file_name().unwrap().to_string_lossy();
+        let code = assemble_instructions_with_order(input.insts(), byte_order);
+        let mut written = fs::write(out_dir.join(format!("{}.bin", name)), code);
+        if args.elf && written.is_ok() {
+            let elf = assemble_elf(input.insts(), args.entry);
+            written = fs::write(out_dir.join(format!("{}.elf", name)), elf);
+        }
+        if let Err(err) = written {
+            eprintln!("Failed to write {}: {}", name, err);
+            return ExitCode::FAILURE;
+        }
+        exported += 1;
+    }
+    println!(
+        "Exported {} inputs to {:?} ({} skipped)",
+        exported, out_dir, skipped
+    );
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,131 @@
+use clap::Parser;
+use libafl::inputs::Input;
+use riscv_mutator::assembler::ByteOrder;
+use riscv_mutator::elf::{decode_code, executable_code};
+use riscv_mutator::instructions::{sets::riscv_g, Instruction};
+use riscv_mutator::isa_hash::write_isa_hash_file;
+use riscv_mutator::parser::parse_instructions_with_order;
+use riscv_mutator::program_input::ProgramInput;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Turns a directory of raw machine code ('.bin') and RISC-V ELF ('.elf')
+/// files into a seed corpus for sim-fuzzer. Files that can't be decoded are
+/// skipped.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The directory to import (searched recursively).
+    input: String,
+    /// The corpus directory to write the seeds to.
+    #[arg(short, long)]
+    out: String,
+    /// The byte order of the instruction words in '.bin' files: 'little' or
+    /// 'big'.
+    #[arg(long, default_value = "little")]
+    byte_order: String,
+    /// Drop programs with fewer instructions.
+    #[arg(long, default_value_t = 1)]
+    min_insts: usize,
+    /// Split programs into seeds of at most this many instructions.
+    #[arg(long)]
+    max_insts: Option<usize>,
+}
+
+/// Collects the '.bin' and '.elf' files in the directory and its
+/// subdirectories.
+fn walk(dir: &Path, result: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read {:?}: {}", dir, e))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk(&path, result)?;
+        } else if matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("bin" | "elf")
+        ) {
+            result.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Decodes the programs of one file. Raw files must only contain known
+/// instructions, while the code of ELF files is split at unknown words.
+fn decode(path: &Path, args: &Args, order: ByteOrder) -> Result<Vec<Vec<Instruction>>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read: {}", e))?;
+    if path.extension().and_then(|ext| ext.to_str()) == Some("bin") {
+        return Ok(vec![parse_instructions_with_order(
+            &bytes,
+            riscv_g(),
+            order,
+        )?]);
+    }
+    let mut programs = Vec::<Vec<Instruction>>::new();
+    for code in executable_code(&bytes)? {
+        programs.extend(decode_code(&code.data, args.min_insts));
+    }
+    Ok(programs)
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let Some(order) = ByteOrder::from_name(&args.byte_order) else {
+        eprintln!("Unknown byte order '{}'", args.byte_order);
+        return ExitCode::FAILURE;
+    };
+
+    let mut files = Vec::<PathBuf>::new();
+    if let Err(err) = walk(Path::new(&args.input), &mut files) {
+        eprintln!("{}", err);
+        return ExitCode::FAILURE;
+    }
+    files.sort();
+
+    let out_dir = PathBuf::from(&args.out);
+    fs::create_dir_all(&out_dir).expect("Failed to create output directory");
+    write_isa_hash_file(&out_dir).expect("Failed to write ISA hash file.");
+
+    // Seeds are named by their content, so duplicates are only written once.
+    let mut names = HashSet::<String>::new();
+    let mut skipped = 0;
+    let mut duplicates = 0;
+    for path in &files {
+        let programs = match decode(path, &args, order) {
+            Ok(programs) => programs,
+            Err(err) => {
+                eprintln!("Skipping {:?}: {}", path, err);
+                skipped += 1;
+                continue;
+            }
+        };
+        for program in programs {
+            let chunk_size = args.max_insts.unwrap_or(program.len()).max(1);
+            for insts in program.chunks(chunk_size) {
+                if insts.is_empty() || insts.len() < args.min_insts {
+                    continue;
+                }
+                let input = ProgramInput::new(insts.to_vec());
+                let name = input.generate_name(0);
+                if !names.insert(name.clone()) {
+                    duplicates += 1;
+                    continue;
+                }
+                if let Err(err) = input.to_file(out_dir.join(name)) {
+                    eprintln!("Failed to write seed: {}", err);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+    }
+    println!(
+        "Imported {} seeds from {} files ({} unparseable, {} duplicates)",
+        names.len(),
+        files.len() - skipped,
+        skipped,
+        duplicates
+    );
+    ExitCode::SUCCESS
+}
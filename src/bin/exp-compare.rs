@@ -0,0 +1,194 @@
+use clap::Parser;
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::Pid;
+use riscv_mutator::experiment::{coverage_at, parse_iterations_log, times_to_exposure, Comparison};
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::time::{Duration, Instant};
+
+/// Runs two sim-fuzzer configurations in interleaved trials against the same
+/// target and compares their coverage over time and their time to exposure
+/// of every cause with the Mann-Whitney U test and the A12 effect size.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The sim-fuzzer arguments of configuration A (e.g. '-i in --cores 0').
+    #[arg(long, default_value = "")]
+    a: String,
+    /// The sim-fuzzer arguments of configuration B.
+    #[arg(long, default_value = "")]
+    b: String,
+    /// The number of trials per configuration.
+    #[arg(long, default_value_t = 10)]
+    trials: usize,
+    /// The duration of every trial in seconds.
+    #[arg(long, default_value_t = 3600)]
+    duration: u64,
+    /// The times in seconds at which coverage is compared, separated by
+    /// commas. Defaults to the end of the trials.
+    #[arg(long)]
+    sample_at: Option<String>,
+    /// The directory for the output directories of the trials and the
+    /// summary.
+    #[arg(short, long, default_value = "exp")]
+    out: String,
+    /// The sim-fuzzer binary. Defaults to the one next to this binary.
+    #[arg(long)]
+    fuzzer: Option<String>,
+    /// The target command passed to sim-fuzzer.
+    #[arg(last = true, required = true)]
+    target: Vec<String>,
+}
+
+/// The samples of one trial.
+struct Trial {
+    coverage: Vec<u64>,
+    tte: Vec<(String, Duration)>,
+}
+
+fn run_trial(fuzzer: &Path, config: &str, out_dir: &Path, args: &Args) -> Trial {
+    let _ = fs::remove_dir_all(out_dir);
+    let log = fs::File::create(out_dir.with_extension("log")).expect("Failed to create log file");
+    let mut child = Command::new(fuzzer)
+        .args(config.split_whitespace())
+        .arg("--simple-ui")
+        .arg("--out")
+        .arg(out_dir)
+        .arg("--")
+        .args(&args.target)
+        .stdout(log.try_clone().unwrap())
+        .stderr(log)
+        // Run in a new process group so that all clients can be stopped.
+        .process_group(0)
+        .spawn()
+        .expect("Failed to start fuzzer");
+
+    // The fuzzer stops on its own once all expected causes are found.
+    let start = Instant::now();
+    let deadline = Duration::from_secs(args.duration);
+    while start.elapsed() < deadline {
+        if child
+            .try_wait()
+            .expect("Failed to wait for fuzzer")
+            .is_some()
+        {
+            break;
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+    let _ = killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL);
+    let _ = child.wait();
+
+    let log = fs::read_to_string(out_dir.join("iterations_time")).unwrap_or_default();
+    let samples = parse_iterations_log(&log);
+    let coverage = sample_times(args)
+        .iter()
+        .map(|secs| coverage_at(&samples, *secs))
+        .collect();
+    let tte = times_to_exposure(out_dir).unwrap_or_else(|err| {
+        eprintln!("Warning: {}", err);
+        Vec::new()
+    });
+    Trial { coverage, tte }
+}
+
+fn sample_times(args: &Args) -> Vec<u64> {
+    match &args.sample_at {
+        Some(list) => list
+            .split(',')
+            .map(|s| s.trim().parse::<u64>().expect("Invalid sample time"))
+            .collect(),
+        None => vec![args.duration],
+    }
+}
+
+/// The time to exposure of the cause in every trial. Trials that didn't
+/// find the cause count with the full trial duration.
+fn tte_samples(trials: &[Trial], cause: &str, duration: u64) -> Vec<f64> {
+    trials
+        .iter()
+        .map(|trial| {
+            trial
+                .tte
+                .iter()
+                .find(|(c, _)| c == cause)
+                .map_or(duration as f64, |(_, tte)| tte.as_secs_f64())
+        })
+        .collect()
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let fuzzer = match &args.fuzzer {
+        Some(path) => PathBuf::from(path),
+        None => std::env::current_exe()
+            .expect("Failed to find own binary")
+            .with_file_name("sim-fuzzer"),
+    };
+    let out = PathBuf::from(&args.out);
+    fs::create_dir_all(&out).expect("Failed to create output directory");
+
+    let configs = [("a", args.a.clone()), ("b", args.b.clone())];
+    let mut trials: [Vec<Trial>; 2] = [Vec::new(), Vec::new()];
+    let mut raw = String::from("config\ttrial\tmetric\tvalue\n");
+    for trial in 0..args.trials {
+        // Alternate which configuration runs first so that drift of the
+        // machine (e.g. thermal throttling) affects both equally.
+        let order = if trial % 2 == 1 { [1, 0] } else { [0, 1] };
+        for idx in order {
+            let (name, config) = &configs[idx];
+            println!("Trial {}/{} of config {}", trial + 1, args.trials, name);
+            let result = run_trial(
+                &fuzzer,
+                config,
+                &out.join(format!("{}-{}", name, trial)),
+                &args,
+            );
+            for (secs, coverage) in sample_times(&args).iter().zip(&result.coverage) {
+                raw += &format!("{}\t{}\tcoverage@{}s\t{}\n", name, trial, secs, coverage);
+            }
+            for (cause, tte) in &result.tte {
+                raw += &format!("{}\t{}\ttte:{}\t{}\n", name, trial, cause, tte.as_secs());
+            }
+            trials[idx].push(result);
+        }
+    }
+
+    let mut comparisons = Vec::<Comparison>::new();
+    for (idx, secs) in sample_times(&args).iter().enumerate() {
+        let coverage = |trials: &[Trial]| -> Vec<f64> {
+            trials.iter().map(|t| t.coverage[idx] as f64).collect()
+        };
+        comparisons.push(Comparison::new(
+            &format!("coverage@{}s", secs),
+            &coverage(&trials[0]),
+            &coverage(&trials[1]),
+        ));
+    }
+    let mut causes: Vec<&String> = trials
+        .iter()
+        .flatten()
+        .flat_map(|t| t.tte.iter().map(|(cause, _)| cause))
+        .collect();
+    causes.sort();
+    causes.dedup();
+    for cause in causes {
+        comparisons.push(Comparison::new(
+            &format!("tte:{}", cause),
+            &tte_samples(&trials[0], cause, args.duration),
+            &tte_samples(&trials[1], cause, args.duration),
+        ));
+    }
+
+    let mut summary = format!("# A: {}\n# B: {}\n{}\n", args.a, args.b, Comparison::HEADER);
+    for comparison in &comparisons {
+        summary += &comparison.to_line();
+        summary.push('\n');
+    }
+    print!("{}", summary);
+    fs::write(out.join("samples.tsv"), raw).expect("Failed to write samples");
+    fs::write(out.join("summary.tsv"), summary).expect("Failed to write summary");
+    ExitCode::SUCCESS
+}
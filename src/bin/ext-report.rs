@@ -0,0 +1,78 @@
+use clap::Parser;
+use riscv_mutator::extension_stats::{
+    attribute, parse_growth_log, GrowthEvent, EXTENSION_STATS_DIR,
+};
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::SystemTime;
+
+/// Reports how much of the coverage growth of a sim-fuzzer campaign the
+/// instructions of every ISA extension contributed over time.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The output directory of the campaign.
+    out: String,
+    /// Also report the attribution at every multiple of this many seconds.
+    #[arg(long)]
+    interval: Option<u64>,
+}
+
+fn print_table(events: &[GrowthEvent], start: u64, until: u64) {
+    let attribution = attribute(events, until);
+    let total: f64 = attribution.iter().map(|a| a.growth).sum();
+    println!("after {}s:", until - start);
+    println!(
+        " {:<10} {:>8} {:>10} {:>7}",
+        "extension", "entries", "growth", "share"
+    );
+    for entry in &attribution {
+        println!(
+            " {:<10} {:>8} {:>10.1} {:>6.1}%",
+            entry.extension,
+            entry.entries,
+            entry.growth,
+            100.0 * entry.growth / total.max(1.0)
+        );
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let out = PathBuf::from(&args.out);
+    let dir = out.join(EXTENSION_STATS_DIR);
+    let Ok(entries) = fs::read_dir(&dir) else {
+        eprintln!("No extension stats at {:?}", dir);
+        return ExitCode::FAILURE;
+    };
+
+    // Every client writes its own log.
+    let mut events = Vec::<GrowthEvent>::new();
+    for entry in entries.flatten() {
+        let text = fs::read_to_string(entry.path()).unwrap_or_default();
+        events.extend(parse_growth_log(&text));
+    }
+    if events.is_empty() {
+        println!("No coverage growth recorded yet.");
+        return ExitCode::SUCCESS;
+    }
+    let first = events.iter().map(|e| e.time).min().unwrap();
+    let last = events.iter().map(|e| e.time).max().unwrap();
+    // Times are relative to the start of the campaign if it is known.
+    let start = fs::metadata(out.join("start_time_marker"))
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(first, |time| time.as_secs().min(first));
+
+    if let Some(interval) = args.interval.filter(|i| *i > 0) {
+        let mut until = start + interval;
+        while until < last {
+            print_table(&events, start, until);
+            until += interval;
+        }
+    }
+    print_table(&events, start, last);
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,148 @@
+use clap::Parser;
+use colored::Colorize;
+use riscv_mutator::bisect::{find_culprits, neutralize};
+use riscv_mutator::causes::{read_causes, FUZZING_CAUSE_DIR_VAR};
+use riscv_mutator::instructions::Instruction;
+use riscv_mutator::isa_hash::check_isa_hash_file;
+use riscv_mutator::program_input::ProgramInput;
+use riscv_mutator::registers::RegisterStyle;
+use riscv_mutator::text_format::format_inst;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, ExitCode, Stdio};
+
+/// Finds the instructions of a reproducer that trigger a cause by replacing
+/// the others with nops and re-running the target.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// A corpus entry or a reproducer from the 'causes' directory.
+    input: String,
+    /// The cause to look for. Defaults to the first cause the unchanged
+    /// input triggers.
+    #[arg(long)]
+    cause: Option<String>,
+    /// Write the reproducer with all other instructions replaced by nops to
+    /// this file.
+    #[arg(short, long)]
+    output: Option<String>,
+    /// How registers are printed: 'hex', 'arch' (x10) or 'abi' (a0).
+    #[arg(long, default_value = "hex")]
+    reg_style: String,
+    /// The target command. '@@' is replaced by the path to the input.
+    #[arg(last = true, required = true)]
+    target: Vec<String>,
+}
+
+fn load_input(path: &Path) -> Result<ProgramInput, String> {
+    let buffer = fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    // Corpus entries are serialized, reproducers are in the harness format
+    // and may contain words that aren't instructions (see 'inject-illegal').
+    match postcard::from_bytes::<ProgramInput>(buffer.as_slice()) {
+        Ok(input) => Ok(input),
+        Err(_) => ProgramInput::from_bytes_with(&buffer, true),
+    }
+}
+
+/// Runs the target and returns the causes it reported.
+fn run_target(target: &[String], input: &ProgramInput, work_dir: &Path) -> Vec<String> {
+    let input_path = work_dir.join("input");
+    let cause_dir = work_dir.join("causes");
+    let _ = fs::remove_dir_all(&cause_dir);
+    fs::create_dir_all(&cause_dir).expect("Failed to create cause directory");
+    fs::write(&input_path, input.to_bytes()).expect("Failed to write input");
+
+    let args: Vec<String> = target[1..]
+        .iter()
+        .map(|arg| arg.replace("@@", input_path.to_str().unwrap()))
+        .collect();
+    let _ = Command::new(&target[0])
+        .args(args)
+        .env(FUZZING_CAUSE_DIR_VAR, &cause_dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .expect("Failed to run target");
+
+    let mut causes: Vec<String> = read_causes(&cause_dir)
+        .into_iter()
+        .map(|(_, record)| record.cause)
+        .collect();
+    causes.sort();
+    causes
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let Some(reg_style) = RegisterStyle::from_name(&args.reg_style) else {
+        eprintln!("Unknown register style '{}'", args.reg_style);
+        return ExitCode::FAILURE;
+    };
+    if let Err(err) = check_isa_hash_file(Path::new(&args.input)) {
+        eprintln!("Warning: {}", err);
+    }
+    let input = match load_input(Path::new(&args.input)) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let work_dir = std::env::temp_dir().join(format!("inst-bisect-{}", process::id()));
+    fs::create_dir_all(&work_dir).expect("Failed to create work directory");
+
+    let cause = match &args.cause {
+        Some(cause) => cause.replace('_', " "),
+        None => match run_target(&args.target, &input, &work_dir)
+            .into_iter()
+            .next()
+        {
+            Some(cause) => cause,
+            None => {
+                eprintln!("The input doesn't trigger any cause.");
+                let _ = fs::remove_dir_all(&work_dir);
+                return ExitCode::FAILURE;
+            }
+        },
+    };
+    println!("Bisecting cause: {}", cause.as_str().bold());
+
+    let mut runs = 0;
+    let culprits = find_culprits(input.insts(), |insts: &[Instruction]| {
+        runs += 1;
+        let mut candidate = input.clone();
+        *candidate.insts_mut() = insts.to_vec();
+        run_target(&args.target, &candidate, &work_dir).contains(&cause)
+    });
+    let _ = fs::remove_dir_all(&work_dir);
+
+    let Some(culprits) = culprits else {
+        eprintln!("The input doesn't trigger '{}'.", cause);
+        return ExitCode::FAILURE;
+    };
+    println!(
+        "{} of {} instructions are needed ({} runs):",
+        culprits.len(),
+        input.insts().len(),
+        runs
+    );
+    for pos in &culprits {
+        println!(
+            " {:>5}: {}",
+            pos,
+            format_inst(&input.insts()[*pos], reg_style)
+        );
+    }
+
+    if let Some(output) = &args.output {
+        let others: Vec<usize> = (0..input.insts().len())
+            .filter(|pos| !culprits.contains(pos))
+            .collect();
+        let mut reduced = input.clone();
+        *reduced.insts_mut() = neutralize(input.insts(), &others);
+        fs::write(PathBuf::from(output), reduced.to_bytes()).expect("Failed to write output");
+        println!("Written reduced reproducer to {}", output.as_str().blue());
+    }
+    ExitCode::SUCCESS
+}
@@ -1,10 +1,17 @@
 use clap::Parser;
 use colored::Colorize;
 use crossterm::style::Stylize;
+use libafl::inputs::Input;
+use riscv_mutator::assembler::ByteOrder;
 use riscv_mutator::instructions::Instruction;
+use riscv_mutator::isa_hash::check_isa_hash_file;
+use riscv_mutator::objdump::format_objdump_line;
 use riscv_mutator::program_input::ProgramInput;
+use riscv_mutator::provenance::{Provenance, ProvenanceMetadata};
+use riscv_mutator::registers::{format_argument, RegisterStyle};
 use riscv_mutator::{instructions, parser};
 use std::fs;
+use std::path::Path;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -12,10 +19,75 @@ struct Args {
     input: Vec<String>,
     #[arg(long, default_value_t = false)]
     raw: bool,
+    /// The byte order of the instruction words of '--raw' input: 'little'
+    /// or 'big'.
+    #[arg(long, default_value = "little")]
+    byte_order: String,
+    /// How registers are printed: 'hex', 'arch' (x10) or 'abi' (a0).
+    #[arg(long, default_value = "hex")]
+    reg_style: String,
+    /// Colors operand values by how they were generated, using the
+    /// 'provenance' directory of a fuzzer output directory.
+    #[arg(long)]
+    provenance: Option<String>,
+    /// The output format: 'text' (the assembler syntax) or 'objdump' (like
+    /// 'objdump -d -M no-aliases', with ABI register names by default).
+    #[arg(long, default_value = "text")]
+    format: String,
+    /// The address of the first instruction in 'objdump' output.
+    #[arg(long, value_parser = parse_address, default_value_t = 0)]
+    address: u64,
+}
+
+fn parse_address(value: &str) -> Result<u64, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse::<u64>(),
+    }
+    .map_err(|_| format!("Invalid decimal or hex address: {}", value))
+}
+
+fn color_value(value: String, provenance: Option<Provenance>) -> colored::ColoredString {
+    match provenance {
+        Some(Provenance::Reused) => Colorize::green(value),
+        Some(Provenance::Dictionary) => Colorize::magenta(value),
+        Some(Provenance::PowerOfTwo) => Colorize::yellow(value),
+        Some(Provenance::Random) => Colorize::red(value),
+        Some(Provenance::Unknown) | None => Colorize::normal(value),
+    }
 }
 
 fn main() {
     let args = Args::parse();
+    let Some(reg_style) = RegisterStyle::from_name(&args.reg_style) else {
+        eprintln!("Unknown register style '{}'", args.reg_style);
+        return;
+    };
+    let Some(byte_order) = ByteOrder::from_name(&args.byte_order) else {
+        eprintln!("Unknown byte order '{}'", args.byte_order);
+        return;
+    };
+    let objdump = match args.format.as_str() {
+        "text" => false,
+        "objdump" => true,
+        _ => {
+            eprintln!("Unknown format '{}'", args.format);
+            return;
+        }
+    };
+    // objdump prints ABI names unless '-M numeric' is given.
+    let objdump_style = match reg_style {
+        RegisterStyle::Hex => RegisterStyle::Abi,
+        style => style,
+    };
+
+    if args.provenance.is_some() {
+        let legend: Vec<String> = Provenance::ALL
+            .into_iter()
+            .map(|p| color_value(p.name().to_string(), Some(p)).to_string())
+            .collect();
+        println!("provenance: {}", legend.join(" "));
+    }
 
     let multiple_files = args.input.len() != 1;
     for filename in args.input {
@@ -24,31 +96,94 @@ fn main() {
             println!("{}:", filename.clone().bold().blue());
         }
 
+        if let Err(err) = check_isa_hash_file(Path::new(&filename)) {
+            eprintln!("Warning: {}", err);
+        }
+
         let buffer = fs::read(filename).expect("Failed to read file");
 
         let program: Vec<Instruction>;
+        let mut provenance = ProvenanceMetadata::new();
 
         if args.raw {
-            let program_or_err =
-                parser::parse_instructions(&buffer, &instructions::sets::riscv_g());
-            if program_or_err.is_err() {
-                eprintln!("Failed to decode raw instructions.");
-                continue;
+            let program_or_err = parser::InstDecoder::new(&buffer, instructions::sets::riscv_g())
+                .with_order(byte_order)
+                .collect::<Result<Vec<Instruction>, _>>();
+            match program_or_err {
+                Ok(insts) => program = insts,
+                Err(err) => {
+                    eprintln!("Failed to decode raw instructions: {}", err);
+                    continue;
+                }
             }
-            program = program_or_err.unwrap();
         } else {
-            let input = postcard::from_bytes::<ProgramInput>(buffer.as_slice());
-            program = input.unwrap().insts().to_vec();
+            let input = postcard::from_bytes::<ProgramInput>(buffer.as_slice()).unwrap();
+            if let Some(config) = input.config() {
+                println!(
+                    " {} features={:#x} privilege={:?}",
+                    Colorize::bold("config"),
+                    config.features,
+                    config.privilege
+                );
+                for interrupt in &config.interrupts {
+                    println!(
+                        " {} cycle={} kind={:?}",
+                        Colorize::bold("interrupt"),
+                        interrupt.cycle,
+                        interrupt.kind
+                    );
+                }
+                for init in &config.registers {
+                    println!(
+                        " {} x{}={:#x}",
+                        Colorize::bold("register"),
+                        init.reg,
+                        init.value
+                    );
+                }
+            }
+            if let Some(memory) = input.memory() {
+                let hex: Vec<String> = memory.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                println!(
+                    " {} {:#x}: {}",
+                    Colorize::bold("memory"),
+                    memory.address,
+                    hex.join(" ")
+                );
+            }
+            if let Some(dir) = &args.provenance {
+                let path = Path::new(dir).join(input.generate_name(0));
+                match fs::read_to_string(&path) {
+                    Ok(text) => match ProvenanceMetadata::from_text(&text) {
+                        Ok(parsed) => provenance = parsed,
+                        Err(err) => eprintln!("Warning: {:?}: {}", path, err),
+                    },
+                    Err(_) => eprintln!("Warning: No provenance at {:?}", path),
+                }
+            }
+            program = input.insts().to_vec();
+        }
+
+        if objdump {
+            let mut addr = args.address;
+            for inst in &program {
+                println!("{}", format_objdump_line(inst, addr, objdump_style));
+                addr += 4;
+            }
+            continue;
         }
 
         for inst in program {
             print!(" {}", Colorize::bold(inst.template().name()));
-            for op in inst.arguments() {
-                print!(
-                    " {}={}",
-                    Colorize::cyan(op.spec().name()),
-                    format!("{:#x}", op.value()).red()
-                );
+            let origin = provenance.get(&inst);
+            for (idx, op) in inst.arguments().iter().enumerate() {
+                let value = format_argument(inst.template(), op, reg_style);
+                let value = if args.provenance.is_some() {
+                    color_value(value, origin.and_then(|o| o.get(idx).copied()))
+                } else {
+                    Colorize::red(value)
+                };
+                print!(" {}={}", Colorize::cyan(op.spec().name()), value);
             }
             println!("");
         }
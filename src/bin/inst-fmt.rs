@@ -0,0 +1,58 @@
+use clap::Parser;
+use riscv_mutator::registers::RegisterStyle;
+use riscv_mutator::text_format::format_source;
+use std::fs;
+use std::process::ExitCode;
+
+/// Reformats files in the text assembly format of inst-assembler.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The files to format. The result is printed unless --write or --check
+    /// are given.
+    input: Vec<String>,
+    /// Overwrite the files with the formatted result.
+    #[arg(short, long, default_value_t = false)]
+    write: bool,
+    /// Only report files that are not formatted.
+    #[arg(long, default_value_t = false)]
+    check: bool,
+    /// How registers are printed: 'hex', 'arch' (x10) or 'abi' (a0).
+    #[arg(long, default_value = "hex")]
+    reg_style: String,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let Some(reg_style) = RegisterStyle::from_name(&args.reg_style) else {
+        eprintln!("Unknown register style '{}'", args.reg_style);
+        return ExitCode::FAILURE;
+    };
+
+    let mut result = ExitCode::SUCCESS;
+    for filename in &args.input {
+        let source = fs::read_to_string(filename).expect("Failed to read file");
+        let formatted = match format_source(&source, reg_style) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                eprintln!("error: {}: {}", filename, err);
+                result = ExitCode::FAILURE;
+                continue;
+            }
+        };
+
+        if args.check {
+            if formatted != source {
+                println!("{} is not formatted", filename);
+                result = ExitCode::FAILURE;
+            }
+        } else if args.write {
+            if formatted != source {
+                fs::write(filename, formatted).expect("Failed to write file");
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+    result
+}
@@ -0,0 +1,75 @@
+use clap::Parser;
+use libafl::inputs::Input;
+use riscv_mutator::elf::{decode_code, executable_code};
+use riscv_mutator::isa_hash::write_isa_hash_file;
+use riscv_mutator::program_input::ProgramInput;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Imports the executable code of RISC-V ELF files (e.g. riscv-tests or
+/// compiler output) as corpus seeds.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The ELF files to import.
+    #[arg(required = true)]
+    inputs: Vec<String>,
+    /// The corpus directory to write the seeds to.
+    #[arg(short, long)]
+    out: String,
+    /// Drop decoded programs with fewer instructions.
+    #[arg(long, default_value_t = 1)]
+    min_insts: usize,
+    /// Split decoded programs into seeds of at most this many instructions.
+    #[arg(long)]
+    max_insts: Option<usize>,
+}
+
+/// Writes the seeds of one ELF file and returns how many were written.
+fn import(path: &Path, out_dir: &Path, args: &Args) -> Result<usize, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut written = 0;
+    for code in executable_code(&bytes)? {
+        let section = code.name.trim_start_matches('.');
+        let programs = decode_code(&code.data, args.min_insts);
+        for (idx, program) in programs.iter().enumerate() {
+            let chunk_size = args.max_insts.unwrap_or(program.len()).max(1);
+            for (part, insts) in program.chunks(chunk_size).enumerate() {
+                if insts.len() < args.min_insts {
+                    continue;
+                }
+                let name = format!("{}-{}-{}-{}", stem, section, idx, part);
+                ProgramInput::new(insts.to_vec())
+                    .to_file(out_dir.join(name))
+                    .map_err(|e| format!("Failed to write seed: {}", e))?;
+                written += 1;
+            }
+        }
+    }
+    Ok(written)
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let out_dir = PathBuf::from(&args.out);
+    fs::create_dir_all(&out_dir).expect("Failed to create output directory");
+    write_isa_hash_file(&out_dir).expect("Failed to write ISA hash file.");
+
+    let mut failed = false;
+    for input in &args.inputs {
+        match import(Path::new(input), &out_dir, &args) {
+            Ok(written) => println!("{}: {} seeds", input, written),
+            Err(err) => {
+                eprintln!("{}: {}", input, err);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,211 @@
+use clap::Parser;
+use core::time::Duration;
+use libafl::{
+    bolts::{
+        current_nanos,
+        rands::StdRand,
+        shmem::{ShMem, ShMemProvider, UnixShMemProvider},
+        tuples::tuple_list,
+        AsMutSlice,
+    },
+    corpus::InMemoryCorpus,
+    events::NopEventManager,
+    executors::{
+        forkserver::{ForkserverExecutor, TimeoutForkserverExecutor},
+        Executor,
+    },
+    fuzzer::StdFuzzer,
+    inputs::Input,
+    mutators::{MutationResult, Mutator, StdScheduledMutator},
+    observers::{HitcountsMapObserver, StdMapObserver},
+    schedulers::QueueScheduler,
+    state::StdState,
+};
+use nix::sys::signal::Signal;
+use riscv_mutator::bisect::nop;
+use riscv_mutator::causes::{read_causes, record_path, FUZZING_CAUSE_DIR_VAR};
+use riscv_mutator::isa_hash::check_isa_hash_file;
+use riscv_mutator::mutator::reducing_mutations;
+use riscv_mutator::program_input::ProgramInput;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, ExitCode};
+
+/// Shrinks a reproducer by removing instructions and replacing them with
+/// nops as long as the target still reports the same cause. The target is
+/// run through the forkserver like in sim-fuzzer.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// A corpus entry or a reproducer from the 'causes' directory.
+    input: String,
+    /// The file to write the minimized reproducer to (in the harness format).
+    #[arg(short, long)]
+    output: String,
+    /// The cause to keep. Defaults to the first cause the unchanged input
+    /// triggers.
+    #[arg(long)]
+    cause: Option<String>,
+    /// Stop after this many reduction attempts in a row that lost the cause.
+    #[arg(long, default_value_t = 500)]
+    max_fails: usize,
+    /// Timeout of a single run in milliseconds.
+    #[arg(short, long, default_value_t = 60000)]
+    timeout: u64,
+    /// The target command. '@@' is replaced by the path to the input.
+    #[arg(last = true, required = true)]
+    target: Vec<String>,
+}
+
+// Same as in sim-fuzzer, the harness expects a map of this size.
+const MAP_SIZE: usize = 2_621_440;
+
+fn load_input(path: &Path) -> Result<ProgramInput, String> {
+    // Corpus entries are serialized, reproducers are in the harness format
+    // and may contain words that aren't instructions (see 'inject-illegal').
+    match ProgramInput::from_file(path) {
+        Ok(input) => Ok(input),
+        Err(_) => {
+            let buffer = fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+            ProgramInput::from_bytes_with(&buffer, true)
+        }
+    }
+}
+
+/// The causes the last run reported, and clears them for the next run.
+fn take_causes(cause_dir: &Path) -> Vec<String> {
+    let mut causes: Vec<String> = read_causes(cause_dir)
+        .into_iter()
+        .map(|(path, record)| {
+            let _ = fs::remove_file(record_path(&path));
+            let _ = fs::remove_file(path);
+            record.cause
+        })
+        .collect();
+    causes.sort();
+    causes
+}
+
+/// Smaller is better: first fewer instructions, then fewer non-nops.
+fn size_of(input: &ProgramInput) -> (usize, usize) {
+    let nop = nop();
+    let insts = input.insts();
+    (
+        insts.len(),
+        insts.iter().filter(|inst| **inst != nop).count(),
+    )
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if let Err(err) = check_isa_hash_file(Path::new(&args.input)) {
+        eprintln!("Warning: {}", err);
+    }
+    let mut best = match load_input(Path::new(&args.input)) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // The forkserver passes the environment on to every run of the target.
+    let work_dir = std::env::temp_dir().join(format!("inst-minimize-{}", process::id()));
+    let cause_dir = work_dir.join("causes");
+    fs::create_dir_all(&cause_dir).expect("Failed to create cause directory");
+    std::env::set_var(FUZZING_CAUSE_DIR_VAR, &cause_dir);
+    std::env::set_var("AFL_MAP_SIZE", format!("{}", MAP_SIZE));
+
+    let mut shmem_provider = UnixShMemProvider::new().expect("Failed to init shared memory");
+    let mut shmem = shmem_provider.new_shmem(MAP_SIZE).unwrap();
+    shmem.write_to_env("__AFL_SHM_ID").unwrap();
+    let edges_observer = unsafe {
+        HitcountsMapObserver::new(StdMapObserver::new("shared_mem", shmem.as_mut_slice()))
+    };
+
+    let mut state = StdState::new(
+        StdRand::with_seed(current_nanos()),
+        InMemoryCorpus::<ProgramInput>::new(),
+        InMemoryCorpus::new(),
+        &mut (),
+        &mut (),
+    )
+    .unwrap();
+    let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), (), ());
+    let mut mgr = NopEventManager::new();
+
+    let forkserver = ForkserverExecutor::builder()
+        .program(args.target[0].clone())
+        .parse_afl_cmdline(&args.target[1..])
+        .coverage_map_size(MAP_SIZE)
+        .is_persistent(false)
+        .is_deferred_frksrv(true)
+        .build_dynamic_map(edges_observer, tuple_list!())
+        .expect("Failed to start the forkserver");
+    let signal = str::parse::<Signal>("SIGKILL").unwrap();
+    let mut executor = TimeoutForkserverExecutor::with_signal(
+        forkserver,
+        Duration::from_millis(args.timeout),
+        signal,
+    )
+    .expect("Failed to create the executor.");
+
+    // Takes the state as argument because the mutator needs it as well.
+    let mut run = |state: &mut _, input: &ProgramInput| -> Vec<String> {
+        executor
+            .run_target(&mut fuzzer, state, &mut mgr, input)
+            .expect("Failed to run the target");
+        take_causes(&cause_dir)
+    };
+
+    let first_causes = run(&mut state, &best);
+    let cause = match &args.cause {
+        Some(cause) => cause.replace('_', " "),
+        None => match first_causes.first() {
+            Some(cause) => cause.clone(),
+            None => {
+                eprintln!("The input doesn't trigger any cause.");
+                let _ = fs::remove_dir_all(&work_dir);
+                return ExitCode::FAILURE;
+            }
+        },
+    };
+    if !first_causes.contains(&cause) {
+        eprintln!("The input doesn't trigger '{}'.", cause);
+        let _ = fs::remove_dir_all(&work_dir);
+        return ExitCode::FAILURE;
+    }
+    println!("Minimizing cause: {}", cause);
+
+    let original = size_of(&best);
+    let mut mutator = StdScheduledMutator::new(reducing_mutations());
+    let mut runs = 1;
+    let mut fails = 0;
+    while fails < args.max_fails {
+        let mut candidate = best.clone();
+        let mutated = mutator
+            .mutate(&mut state, &mut candidate, 0)
+            .expect("Failed to mutate");
+        if mutated == MutationResult::Skipped || size_of(&candidate) >= size_of(&best) {
+            fails += 1;
+            continue;
+        }
+        runs += 1;
+        if run(&mut state, &candidate).contains(&cause) {
+            best = candidate;
+            fails = 0;
+        } else {
+            fails += 1;
+        }
+    }
+    let _ = fs::remove_dir_all(&work_dir);
+
+    let (len, non_nops) = size_of(&best);
+    println!(
+        "Reduced {} instructions ({} non-nops) to {} ({} non-nops) in {} runs",
+        original.0, original.1, len, non_nops, runs
+    );
+    fs::write(PathBuf::from(&args.output), best.to_bytes()).expect("Failed to write output");
+    println!("Written minimized reproducer to {}", args.output);
+    ExitCode::SUCCESS
+}
@@ -1,29 +1,75 @@
 use clap::Parser;
 use crossterm::style::Stylize;
-use riscv_mutator::assembler::assemble_instructions;
+use riscv_mutator::assembler::{assemble_instructions_with_order, ByteOrder};
+use riscv_mutator::isa_hash::check_isa_hash_file;
 use riscv_mutator::program_input::ProgramInput;
+use riscv_mutator::registers::RegisterStyle;
+use riscv_mutator::storage::StorageFormat;
 use std::fs;
+use std::path::Path;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     input: Vec<String>,
+    /// The byte order of the written instruction words: 'little' or 'big'.
+    #[arg(long, default_value = "little")]
+    byte_order: String,
+    /// Write the whole input (including the config header and memory image)
+    /// in the text format of inst-assembler instead.
+    #[arg(long, default_value_t = false)]
+    text: bool,
+    /// Write the whole input as JSON (instructions by mnemonic and operand
+    /// names) instead.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["text", "toml"])]
+    json: bool,
+    /// Write the whole input as TOML instead, like `--json`.
+    #[arg(long, default_value_t = false, conflicts_with = "text")]
+    toml: bool,
 }
 
 fn main() {
     let args = Args::parse();
+    let Some(byte_order) = ByteOrder::from_name(&args.byte_order) else {
+        eprintln!("Unknown byte order '{}'", args.byte_order);
+        return;
+    };
 
     for filename in args.input {
-        let buffer = fs::read(filename.clone()).expect("Failed to read file");
-        let input = postcard::from_bytes::<ProgramInput>(buffer.as_slice());
+        if let Err(err) = check_isa_hash_file(Path::new(&filename)) {
+            eprintln!("Warning: {}", err);
+        }
 
-        if input.is_err() {
+        let buffer = fs::read(filename.clone()).expect("Failed to read file");
+        let Ok(stored) = postcard::from_bytes::<&[u8]>(buffer.as_slice()) else {
             eprintln!("Note: File not in internal serialized format.");
             continue;
+        };
+        if let StorageFormat::Envelope { version } = StorageFormat::of(stored) {
+            println!("{}: storage format version {}", filename, version);
+        } else {
+            println!("{}: legacy storage format", filename);
         }
-        let program = input.unwrap().insts().to_vec();
-        let bytes = assemble_instructions(&program);
-        let output = filename + ".insts";
+        let input = match ProgramInput::from_storage_bytes(stored) {
+            Ok(input) => input,
+            Err(err) => {
+                eprintln!("Failed to parse {}: {}", filename, err);
+                continue;
+            }
+        };
+        let (output, bytes) = if args.json {
+            let json = serde_json::to_string_pretty(&input).expect("Failed to serialize input");
+            (filename + ".json", json.into_bytes())
+        } else if args.toml {
+            let toml = toml::to_string(&input).expect("Failed to serialize input");
+            (filename + ".toml", toml.into_bytes())
+        } else if args.text {
+            let text = input.to_text(RegisterStyle::Abi);
+            (filename + ".s", text.into_bytes())
+        } else {
+            let bytes = assemble_instructions_with_order(input.insts(), byte_order);
+            (filename + ".insts", bytes)
+        };
         fs::write(output.clone(), bytes).expect("Unable to write output file");
         println!("Written output to {}:", output.bold().blue());
     }
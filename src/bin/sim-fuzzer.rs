@@ -3,9 +3,10 @@ use std::{
     collections::HashMap,
     fs::{self, OpenOptions},
     io::Write,
-    path::PathBuf,
+    path::{Path, PathBuf},
     process,
     sync::{Arc, Mutex},
+    time::Instant,
 };
 
 use clap::Parser;
@@ -14,26 +15,32 @@ use libafl::{
         current_nanos,
         rands::StdRand,
         shmem::{ShMem, ShMemProvider, UnixShMemProvider},
-        tuples::tuple_list,
-        AsMutSlice,
+        tuples::{tuple_list, MatchName, Named},
+        AsMutSlice, AsSlice,
+    },
+    corpus::{Corpus, InMemoryCorpus, OnDiskCorpus},
+    events::NopEventManager,
+    executors::{
+        forkserver::{ForkserverExecutor, TimeoutForkserverExecutor},
+        Executor, ExitKind, HasObservers, InProcessExecutor, TimeoutExecutor,
     },
-    corpus::{OnDiskCorpus},
-    executors::forkserver::{ForkserverExecutor, TimeoutForkserverExecutor},
     feedback_or,
     feedbacks::{CrashFeedback, MaxMapFeedback, TimeFeedback},
     fuzzer::{Fuzzer, StdFuzzer},
+    inputs::HasTargetBytes,
     mutators::StdScheduledMutator,
-    observers::{HitcountsMapObserver, StdMapObserver, TimeObserver},
+    observers::{HitcountsMapObserver, MapObserver, StdMapObserver, TimeObserver},
     prelude::current_time,
     schedulers::{
-        powersched::PowerSchedule, IndexesLenTimeMinimizerScheduler, StdWeightedScheduler,
+        powersched::PowerSchedule, IndexesLenTimeMinimizerScheduler, QueueScheduler,
+        StdWeightedScheduler,
     },
     stages::power::StdPowerMutationalStage,
-    state::StdState,
+    state::{HasCorpus, HasRand, StdState},
     Error, Evaluator,
 };
 use libafl::{
-    events::ProgressReporter,
+    events::{EventRestarter, ProgressReporter},
     prelude::{Cores, EventConfig, Launcher, LlmpRestartingEventManager},
 };
 use libafl::{
@@ -41,19 +48,51 @@ use libafl::{
 };
 use nix::sys::signal::Signal;
 use riscv_mutator::{
-    calibration::DummyCalibration,
-    causes::{list_causes, FUZZING_CAUSE_DIR_VAR},
+    address_pool::AddressPool,
+    budget::{clear_stop, parse_duration, stop_requested, Budget},
+    cache::CacheGeometry,
+    calibration::{DummyCalibration, ExecTimeModel},
+    causes::{list_causes, FUZZING_CAUSE_DIR_VAR, FUZZING_CORE_ID_VAR},
+    differential::{dut_log_path, DifferentialExecutor, SpikeModel, FUZZING_COMMIT_LOG_VAR},
+    extension_stats::{ExtensionStatsFeedback, EXTENSION_STATS_DIR},
     fuzz_ui::FuzzUI,
+    generator::{parse_register_list, InstGenerator, InstGeneratorConfig},
+    http_stats::{serve as serve_stats, StatsSnapshot},
+    in_process::{InProcessTarget, TargetExecutor},
+    inst_class::{parse_class_weights, InstClassFilter},
+    input_config::{InputConfig, PrivilegeLevel},
+    input_filter::{FilteringExecutor, InputFilter, MaxLengthFilter},
+    isa_hash::{check_isa_hash_file, write_isa_hash_file},
     instructions::{
         riscv::{
             args,
             rv_i::{ADDI},
         },
-        Argument, Instruction,
+        sets, Argument, Instruction,
     },
+    map_regions::{RegionNoveltyFeedback, FUZZING_MAP_REGIONS_VAR},
+    memory_image::MemoryImage,
     monitor::HWFuzzMonitor,
-    mutator::{all_riscv_mutations},
-    program_input::ProgramInput,
+    mutation_stats::MutationStatsFeedback,
+    mutator::{parse_mutation_list, Mutation, SelectedMutations},
+    paging::{sv39_setup, PageTableConfig},
+    pmp::PmpConfig,
+    program_input::{set_epilogue, ProgramInput},
+    provenance::ProvenanceFeedback,
+    retirement::CorpusRetirement,
+    seeds::{load_seed, load_seed_dir},
+    snippets::SnippetExtractionFeedback,
+    state_feedback::{ArchStateObserver, NovelStateFeedback, FUZZING_STATE_DUMP_VAR},
+    target_profile::{TargetProfile, FUZZING_CSR_INIT_VAR},
+    template_coverage::{TemplateCoverageFeedback, TemplateObserver},
+    text_format::parse_source,
+    timeline::{TimelineEvent, TimelineLog},
+    triage::CoverageHashFeedback,
+    trimming::ProgramTrimStage,
+    virgin_bits::{
+        count_bits, count_new_bits, load_virgin_bits, restore_virgin_bits, VirginBitsFeedback,
+        VIRGIN_BITS_FILE,
+    },
 };
 
 use log::{LevelFilter, Metadata, Record};
@@ -87,6 +126,8 @@ static LOGGER: FuzzLogger = FuzzLogger;
 #[command(author, version, about, long_about = None)]
 struct Args {
     arguments: Vec<String>,
+    /// Directory with the initial seeds: corpus entries, reproducers, raw
+    /// machine code ('.bin') and text assembly ('.s').
     #[arg(short, long, default_value = "in")]
     input: String,
     #[arg(short, long, default_value = "out")]
@@ -103,10 +144,145 @@ struct Args {
     simple_ui: bool,
     #[arg(long, default_value = "explore")]
     scheduler: String,
+    /// How the power schedules estimate the execution time of an input:
+    /// 'measured' (wall-clock), 'insts' (instruction count) or 'classes:'
+    /// with costs per instruction class (e.g. 'classes:load=4,fp=8').
+    #[arg(long, default_value = "insts")]
+    exec_time_model: String,
+    /// The mutations to use, separated by commas and with optional weights
+    /// (e.g. 'add,replace,snippet:3').
     #[arg(long, default_value = "default")]
     mutations: String,
     #[arg(long, default_value_t = 0)]
     port: u16,
+    /// Serves the campaign stats as JSON and a small web page on this port.
+    #[arg(long)]
+    http_stats: Option<u16>,
+    /// Restricts register operands to the given registers (e.g. 'x1-x7').
+    #[arg(long)]
+    registers: Option<String>,
+    /// Only mutate/insert instructions of these classes (e.g. 'loads,stores').
+    #[arg(long)]
+    inst_classes: Option<String>,
+    /// Relative weights of instruction classes for new instructions
+    /// (e.g. 'alu=40,load=15,store=15,branch=20,system=10').
+    #[arg(long)]
+    class_weights: Option<String>,
+    /// Never generate these instructions (e.g. 'fence.i,ebreak'). Existing
+    /// corpus entries that contain them are still accepted.
+    #[arg(long)]
+    exclude_insts: Option<String>,
+    /// Generate new instructions only from these extensions
+    /// (e.g. 'rv_i,rv_m,rv_a' or 'g'). Defaults to RV64I.
+    #[arg(long, alias = "isa")]
+    extensions: Option<String>,
+    /// Don't execute programs with more than this many instructions.
+    #[arg(long)]
+    max_insts: Option<usize>,
+    /// Privilege levels the target supports (e.g. 'm,s,u'). Enables snippets
+    /// switching between them.
+    #[arg(long)]
+    privilege_levels: Option<String>,
+    /// Emit Sv39 page-table setups. Format is 'TABLE,PHYS' (hex), the
+    /// physical addresses of the root page table and the mapped gigapage.
+    #[arg(long)]
+    page_table: Option<String>,
+    /// Emit PMP configuration snippets. Format is 'BASE,SIZE' (hex), the
+    /// memory region the snippets may protect and access.
+    #[arg(long)]
+    pmp_region: Option<String>,
+    /// Chance (0-100) of accesses in memory snippets being misaligned.
+    #[arg(long, default_value_t = 0)]
+    misaligned_chance: u64,
+    /// Cache geometry as 'LINE,SETS,WAYS' (e.g. '64,64,4'). Enables snippets
+    /// that evict lines from a cache set inside the PMP region.
+    #[arg(long)]
+    cache_geometry: Option<String>,
+    /// File in the text format with instructions (e.g. a result dump and
+    /// exit sequence) appended to every executed program. They are never
+    /// mutated or stored in the corpus.
+    #[arg(long)]
+    epilogue: Option<String>,
+    /// Address (hex) of a routine in the target that flags a failure.
+    /// Enables snippets that store a value, load it back and jump to the
+    /// routine if it differs. Requires --pmp-region.
+    #[arg(long)]
+    failure_routine: Option<String>,
+    /// Address (hex) of a data-memory image that inputs carry in front of
+    /// the instructions. The harness initializes memory with it (see
+    /// readMemoryImage in FuzzerAPI.h).
+    #[arg(long)]
+    memory_image: Option<String>,
+    /// Also generate architecturally reserved encodings.
+    #[arg(long, default_value_t = false)]
+    allow_reserved: bool,
+    /// Accept seeds with words that aren't instructions of the known
+    /// extensions. Required by the 'inject-illegal' mutation, which inserts
+    /// such words.
+    #[arg(long, default_value_t = false)]
+    illegal_words: bool,
+    /// Prefix inputs with a configuration header for the harness.
+    #[arg(long, default_value_t = false)]
+    config_header: bool,
+    /// Coverage map region manifest ('NAME START LEN' per line). By default
+    /// the harness publishes it to $FUZZING_MAP_REGIONS.
+    #[arg(long)]
+    region_manifest: Option<String>,
+    /// Fuzz inputs with new coverage in this map region (e.g. 'decode')
+    /// more often.
+    #[arg(long)]
+    focus_region: Option<String>,
+    /// Start with the coverage that previous campaigns in the out dir found
+    /// (the 'virgin_bits' file), so only new coverage is interesting.
+    #[arg(long, default_value_t = false)]
+    continue_coverage: bool,
+    /// Seed for the random decisions of the fuzzer (combined with the core
+    /// id). Every mutator then uses its own random stream, so enabling or
+    /// disabling a mutation doesn't change what the others do. The choice of
+    /// the mutator itself still depends on all of them.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Move corpus entries that haven't produced new corpus entries for this
+    /// many queue cycles to 'archive' in the out dir, keeping the queue small
+    /// on long campaigns.
+    #[arg(long)]
+    retire_after: Option<u64>,
+    /// Before fuzzing a new corpus entry, remove instructions that don't
+    /// change its coverage, using at most this many executions (0 disables
+    /// trimming).
+    #[arg(long, default_value_t = 0)]
+    trim_execs: usize,
+    /// Stop the campaign after this time (e.g. '90s', '30m', '12h', '2d').
+    #[arg(long, value_parser = parse_duration)]
+    max_time: Option<Duration>,
+    /// Stop the campaign after this many executions of all clients.
+    #[arg(long)]
+    max_execs: Option<u64>,
+    /// Run only this input (e.g. a reproducer) once, print the result and
+    /// its coverage compared to the 'virgin_bits' in the out dir, and exit.
+    #[arg(long)]
+    replay: Option<String>,
+    /// Run the target in the fuzzer process from this shared library (see
+    /// src/in_process.rs) instead of forking the target command.
+    #[arg(long)]
+    in_process: Option<PathBuf>,
+    /// Compare every run against this reference model command (e.g.
+    /// 'spike --isa=rv64gc --log-commits @@', see src/differential.rs).
+    /// Divergences are reported as causes.
+    #[arg(long)]
+    spike: Option<String>,
+    /// Keep inputs that end in a new architectural state, as dumped by the
+    /// harness (see src/state_feedback.rs).
+    #[arg(long, default_value_t = false)]
+    state_feedback: bool,
+    /// Keep inputs with instructions (or operand values of them) that no
+    /// corpus entry has yet.
+    #[arg(long, default_value_t = false)]
+    template_feedback: bool,
+    /// TOML file describing the target (see 'profiles'). Options given on
+    /// the command line override the profile.
+    #[arg(long)]
+    profile: Option<String>,
 }
 
 pub fn main() {
@@ -133,6 +309,15 @@ pub fn main() {
             return;
         }
     }
+    clear_stop(&out_dir).expect("Failed to remove the stop file of a previous campaign.");
+    // Refuse to continue a campaign that was started with different opcode
+    // tables, as the existing inputs would be misparsed.
+    if let Err(err) = check_isa_hash_file(&out_dir) {
+        println!("{}", err);
+        return;
+    }
+    write_isa_hash_file(&out_dir).expect("Failed to write ISA hash file.");
+
     let mut crashes = out_dir.clone();
     crashes.push("found");
 
@@ -140,12 +325,53 @@ pub fn main() {
     cause_dir.push("causes");
     std::fs::create_dir_all(cause_dir.clone()).expect("Failed to create 'causes' directory.");
 
+    // The operand provenance of every corpus entry, for 'inst-disassembler --provenance'.
+    let mut provenance_dir = out_dir.clone();
+    provenance_dir.push("provenance");
+    std::fs::create_dir_all(provenance_dir.clone())
+        .expect("Failed to create 'provenance' directory.");
+
+    // The union of the coverage of all campaigns in this out dir.
+    let mut virgin_bits = out_dir.clone();
+    virgin_bits.push(VIRGIN_BITS_FILE);
+    let initial_coverage = if args.continue_coverage {
+        match load_virgin_bits(&virgin_bits) {
+            Ok(bits) => {
+                println!("Continuing with {} covered map entries.", count_bits(&bits));
+                bits
+            }
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
+    // Logs of the coverage growth per ISA extension, for 'ext-report'.
+    let mut extension_stats_dir = out_dir.clone();
+    extension_stats_dir.push(EXTENSION_STATS_DIR);
+    std::fs::create_dir_all(extension_stats_dir.clone())
+        .expect("Failed to create 'extension_stats' directory.");
+
     let mut start_time_marker = out_dir.clone();
     start_time_marker.push("start_time_marker");
     std::fs::File::create(start_time_marker).expect("Failed to create start time marker");
 
     std::env::set_var(FUZZING_CAUSE_DIR_VAR, cause_dir.as_os_str());
 
+    // Let the harness publish its map regions unless the user provided them.
+    let region_manifest = match &args.region_manifest {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let mut path = out_dir.clone();
+            path.push("map_regions");
+            std::env::set_var(FUZZING_MAP_REGIONS_VAR, path.as_os_str());
+            path
+        }
+    };
+
     // If asked to save inputs, set the environment variable so the driver can
     // save the inputs for us. Also see the FuzzerAPI.h header.
     if args.save_inputs {
@@ -159,19 +385,53 @@ pub fn main() {
     let mut queue_dir = out_dir.clone();
     queue_dir.push("queue");
 
+    let mut archive_dir = out_dir.clone();
+    archive_dir.push("archive");
+
+    // Replaying a single input doesn't use the seeds.
     let in_dir = PathBuf::from(args.input);
-    if !in_dir.is_dir() {
-        println!("In dir at {:?} is not a valid directory!", &in_dir);
-        return;
-    }
+    let seeds = if args.replay.is_some() {
+        Vec::new()
+    } else {
+        if !in_dir.is_dir() {
+            println!("In dir at {:?} is not a valid directory!", &in_dir);
+            return;
+        }
+        if let Err(err) = check_isa_hash_file(&in_dir) {
+            println!("Warning: {}", err);
+        }
+        match load_seed_dir(&in_dir, args.illegal_words) {
+            Ok((seeds, failures)) => {
+                for (path, err) in &failures {
+                    println!("Skipping seed {:?}: {}", path, err);
+                }
+                println!(
+                    "Loaded {} seeds from {:?} ({} skipped).",
+                    seeds.len(),
+                    &in_dir,
+                    failures.len()
+                );
+                seeds
+            }
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        }
+    };
 
     let timeout = Duration::from_millis(args.timeout);
-    let executable = args.arguments.first().unwrap();
+    // The in-process mode doesn't need a target command.
+    if args.arguments.is_empty() && args.in_process.is_none() {
+        println!("No target command given.");
+        return;
+    }
+    let executable = &args.arguments.first().cloned().unwrap_or_default();
     let debug_child = false;
     let simple_ui = args.simple_ui;
     let cores = Cores::from_cmdline(&args.cores.to_string()).expect("Failed to parse --cores arg");
     let signal = str::parse::<Signal>("SIGKILL").unwrap();
-    let arguments = &args.arguments[1..];
+    let arguments = args.arguments.get(1..).unwrap_or_default();
 
     let scheduler_map: HashMap<String, PowerSchedule> = HashMap::from([
         ("explore".to_owned(), PowerSchedule::EXPLORE),
@@ -188,17 +448,250 @@ pub fn main() {
         return;
     }
 
+    let exec_time_model = match ExecTimeModel::parse(&args.exec_time_model) {
+        Ok(model) => model,
+        Err(err) => {
+            println!("Failed to parse --exec-time-model: {}", err);
+            return;
+        }
+    };
+
+    let mutations = match parse_mutation_list(&args.mutations) {
+        Ok(mutations) => mutations,
+        Err(err) => {
+            let names: Vec<&str> = Mutation::ALL.iter().map(|m| m.name()).collect();
+            println!(
+                "Invalid --mutations: {}. Supported mutations: {}",
+                err,
+                names.join(", ")
+            );
+            return;
+        }
+    };
+
+    if mutations
+        .iter()
+        .any(|(mutation, _)| *mutation == Mutation::InjectIllegal)
+        && !args.illegal_words
+    {
+        println!("The inject-illegal mutation requires --illegal-words");
+        return;
+    }
+
+    let spike = match &args.spike {
+        Some(command) => match SpikeModel::parse(command, timeout) {
+            Ok(model) => Some(model),
+            Err(err) => {
+                println!("Invalid --spike: {}", err);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    let mut config = InstGeneratorConfig::new();
+    let profile = match &args.profile {
+        Some(path) => match TargetProfile::from_file(&PathBuf::from(path)) {
+            Ok(profile) => Some(profile),
+            Err(err) => {
+                println!("Failed to load --profile: {}", err);
+                return;
+            }
+        },
+        None => None,
+    };
+    if let Some(profile) = &profile {
+        config = profile.apply(config).expect("Profile was validated");
+        std::env::set_var(
+            FUZZING_CSR_INIT_VAR,
+            profile.csr_init_var().expect("Profile was validated"),
+        );
+    }
+    let has_pmp_region =
+        args.pmp_region.is_some() || profile.as_ref().and_then(|p| p.pmp_region).is_some();
+
+    if let Some(registers) = &args.registers {
+        match parse_register_list(registers) {
+            Ok(list) => config = config.register_subset(list),
+            Err(err) => {
+                println!("Failed to parse --registers: {}", err);
+                return;
+            }
+        }
+    }
+
+    if let Some(classes) = &args.inst_classes {
+        match InstClassFilter::parse(classes) {
+            Ok(filter) => config = config.class_filter(filter),
+            Err(err) => {
+                println!("Failed to parse --inst-classes: {}", err);
+                return;
+            }
+        }
+    }
+
+    if let Some(names) = &args.exclude_insts {
+        match sets::parse_template_list(names) {
+            Ok(templates) => config = config.exclude_templates(templates),
+            Err(err) => {
+                println!("Failed to parse --exclude-insts: {}", err);
+                return;
+            }
+        }
+    }
+
+    if let Some(names) = &args.extensions {
+        match sets::parse_extension_list(names) {
+            Ok(templates) => config = config.instruction_set(templates),
+            Err(err) => {
+                println!("Failed to parse --extensions: {}", err);
+                return;
+            }
+        }
+    }
+
+    if let Some(weights) = &args.class_weights {
+        match parse_class_weights(weights) {
+            Ok(weights) => {
+                for (class, weight) in weights {
+                    config = config.class_weight(class, weight);
+                }
+            }
+            Err(err) => {
+                println!("Failed to parse --class-weights: {}", err);
+                return;
+            }
+        }
+    }
+
+    if let Some(levels) = &args.privilege_levels {
+        match PrivilegeLevel::parse_list(levels) {
+            Ok(levels) => config = config.privilege_levels(levels),
+            Err(err) => {
+                println!("Failed to parse --privilege-levels: {}", err);
+                return;
+            }
+        }
+    }
+
+    if let Some(page_table) = &args.page_table {
+        match PageTableConfig::parse(page_table) {
+            Ok(page_table) => config = config.page_table(page_table),
+            Err(err) => {
+                println!("Failed to parse --page-table: {}", err);
+                return;
+            }
+        }
+    }
+
+    if let Some(pmp_region) = &args.pmp_region {
+        match PmpConfig::parse(pmp_region) {
+            Ok(region) => config = config.pmp_region(region),
+            Err(err) => {
+                println!("Failed to parse --pmp-region: {}", err);
+                return;
+            }
+        }
+    }
+
+    if let Some(geometry) = &args.cache_geometry {
+        if !has_pmp_region {
+            println!("--cache-geometry requires --pmp-region");
+            return;
+        }
+        match CacheGeometry::parse(geometry) {
+            Ok(geometry) => config = config.cache_geometry(geometry),
+            Err(err) => {
+                println!("Failed to parse --cache-geometry: {}", err);
+                return;
+            }
+        }
+    }
+
+    if let Some(address) = &args.failure_routine {
+        if !has_pmp_region {
+            println!("--failure-routine requires --pmp-region");
+            return;
+        }
+        match u32::from_str_radix(address.trim_start_matches("0x"), 16) {
+            Ok(address) => config = config.failure_routine(address),
+            Err(err) => {
+                println!("Failed to parse --failure-routine: {}", err);
+                return;
+            }
+        }
+    }
+
+    if let Some(address) = &args.memory_image {
+        match u64::from_str_radix(address.trim_start_matches("0x"), 16) {
+            Ok(address) => config = config.memory_image(address),
+            Err(err) => {
+                println!("Failed to parse --memory-image: {}", err);
+                return;
+            }
+        }
+    }
+
+    let epilogue = args
+        .epilogue
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(|| profile.as_ref().and_then(|p| p.epilogue.clone()));
+    if let Some(path) = &epilogue {
+        let parsed = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| parse_source(&source))
+            .and_then(|insts| set_epilogue(&insts));
+        if let Err(err) = parsed {
+            println!("Failed to load --epilogue: {}", err);
+            return;
+        }
+    }
+
+    if args.misaligned_chance > 100 {
+        println!("--misaligned-chance must be between 0 and 100");
+        return;
+    }
+    config = config
+        .misaligned_chance(args.misaligned_chance)
+        .allow_reserved(args.allow_reserved)
+        .allow_raw_words(args.illegal_words);
+    // E.g. --exclude-insts covering all of --extensions.
+    if let Err(err) = config.validate() {
+        println!("Invalid generator configuration: {}", err);
+        return;
+    }
+
+    if let Some(path) = &args.replay {
+        if let Err(err) = replay(
+            Path::new(path),
+            &virgin_bits,
+            timeout,
+            executable,
+            signal,
+            arguments,
+        ) {
+            println!("Failed to replay {}: {}", path, err);
+        }
+        return;
+    }
+
     let port = if args.port == 0 {
         None
     } else {
         Some(args.port)
     };
 
+    TimelineLog::new(&out_dir).record(TimelineEvent::CampaignStart {
+        cores: cores.ids.len(),
+        scheduler: args.scheduler.clone(),
+    });
+
     fuzz(
         out_dir,
         queue_dir,
         crashes,
-        &in_dir,
+        seeds,
         timeout,
         executable,
         debug_child,
@@ -207,17 +700,108 @@ pub fn main() {
         cores,
         simple_ui,
         scheduler.copied(),
+        exec_time_model,
         port,
+        InstGenerator::with_config(config),
+        mutations,
+        args.max_insts,
+        args.config_header,
+        region_manifest,
+        args.focus_region,
+        provenance_dir,
+        virgin_bits,
+        initial_coverage,
+        extension_stats_dir,
+        args.seed,
+        archive_dir,
+        args.retire_after,
+        args.trim_execs,
+        Budget::new(args.max_time, args.max_execs),
+        args.in_process,
+        spike,
+        args.state_feedback,
+        args.template_feedback,
+        args.http_stats,
     )
     .expect("An error occurred while fuzzing");
 }
 
+/// Runs a single input through the forkserver and prints the result and its
+/// coverage compared to the union of all previous campaigns.
+fn replay(
+    path: &Path,
+    virgin_bits: &Path,
+    timeout: Duration,
+    executable: &String,
+    signal: Signal,
+    arguments: &[String],
+) -> Result<(), String> {
+    const MAP_SIZE: usize = 2_621_440;
+
+    // Replays whatever the input contains, including words that aren't
+    // instructions.
+    let input = load_seed(path, true)?;
+    let union = load_virgin_bits(virgin_bits)?;
+
+    let mut shmem_provider = UnixShMemProvider::new().expect("Failed to init shared memory");
+    let mut shmem = shmem_provider.new_shmem(MAP_SIZE).unwrap();
+    shmem.write_to_env("__AFL_SHM_ID").unwrap();
+    std::env::set_var("AFL_MAP_SIZE", format!("{}", MAP_SIZE));
+    let edges_observer = unsafe {
+        HitcountsMapObserver::new(StdMapObserver::new("shared_mem", shmem.as_mut_slice()))
+    };
+
+    let mut state = StdState::new(
+        StdRand::with_seed(current_nanos()),
+        InMemoryCorpus::<ProgramInput>::new(),
+        InMemoryCorpus::new(),
+        &mut (),
+        &mut (),
+    )
+    .map_err(|e| e.to_string())?;
+    let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), (), ());
+    let mut mgr = NopEventManager::new();
+
+    let forkserver = ForkserverExecutor::builder()
+        .program(executable.clone())
+        .parse_afl_cmdline(arguments)
+        .coverage_map_size(MAP_SIZE)
+        .is_persistent(false)
+        .is_deferred_frksrv(true)
+        .build_dynamic_map(edges_observer, tuple_list!())
+        .map_err(|e| format!("Failed to start the forkserver: {}", e))?;
+    let mut executor = TimeoutForkserverExecutor::with_signal(forkserver, timeout, signal)
+        .map_err(|e| format!("Failed to create the executor: {}", e))?;
+
+    let start = Instant::now();
+    let exit_kind = executor
+        .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+        .map_err(|e| format!("Failed to run the target: {}", e))?;
+    let elapsed = start.elapsed();
+
+    let map = executor
+        .observers()
+        .match_name::<HitcountsMapObserver<StdMapObserver<u8, false>>>("shared_mem")
+        .expect("The forkserver has no coverage map")
+        .to_vec();
+
+    println!("Exit kind: {:?}", exit_kind);
+    println!("Executed in: {:.3}ms", elapsed.as_secs_f64() * 1000.0);
+    println!("Covered map entries: {}", count_bits(&map));
+    println!(
+        "New map entries: {} (previously covered: {})",
+        count_new_bits(&union, &map),
+        count_bits(&union)
+    );
+    Ok(())
+}
+
 /// The actual fuzzer
 fn fuzz(
     out_dir: PathBuf,
     base_corpus_dir: PathBuf,
     base_objective_dir: PathBuf,
-    _seed_dir: &PathBuf, // Currently unused because seed parsing not implemented.
+    seeds: Vec<ProgramInput>,
     timeout: Duration,
     executable: &String,
     debug_child: bool,
@@ -226,19 +810,46 @@ fn fuzz(
     cores: Cores,
     simple_ui: bool,
     schedule: Option<PowerSchedule>,
+    exec_time_model: ExecTimeModel,
     port: Option<u16>,
+    generator: InstGenerator,
+    mutations: Vec<(Mutation, usize)>,
+    max_insts: Option<usize>,
+    config_header: bool,
+    region_manifest: PathBuf,
+    focus_region: Option<String>,
+    provenance_dir: PathBuf,
+    virgin_bits: PathBuf,
+    initial_coverage: Vec<u8>,
+    extension_stats_dir: PathBuf,
+    seed: Option<u64>,
+    base_archive_dir: PathBuf,
+    retire_after: Option<u64>,
+    trim_execs: usize,
+    budget: Budget,
+    in_process: Option<PathBuf>,
+    spike: Option<SpikeModel>,
+    state_feedback: bool,
+    template_feedback: bool,
+    http_stats: Option<u16>,
 ) -> Result<(), Error> {
     let ui: Arc<Mutex<FuzzUI>> = Arc::new(Mutex::new(FuzzUI::new(simple_ui)));
     const MAP_SIZE: usize = 2_621_440;
     let start_time = current_time();
 
-    let monitor = HWFuzzMonitor::new(
+    let mut monitor = HWFuzzMonitor::new(
         ui,
         out_dir
             .to_str()
             .expect("Out dir is not valid utf-8?")
             .to_owned(),
-    );
+    )
+    .with_budget(budget);
+    if let Some(port) = http_stats {
+        let snapshot = Arc::new(Mutex::new(StatsSnapshot::default()));
+        serve_stats(port, snapshot.clone()).map_err(Error::illegal_argument)?;
+        monitor = monitor.with_http_stats(snapshot);
+    }
 
     let shmem_provider = UnixShMemProvider::new().expect("Failed to init shared memory");
     let mut shmem_provider_client = shmem_provider.clone();
@@ -255,6 +866,27 @@ fn fuzz(
             // To let know the AFL++ binary that we have a big map
             std::env::set_var("AFL_MAP_SIZE", format!("{}", MAP_SIZE));
 
+            // Put into the cause records of this client.
+            std::env::set_var(FUZZING_CORE_ID_VAR, format!("{}", core_id.0));
+
+            // The commit logs of the differential runs of this client.
+            let differential_dir = out_dir.join("differential").join(format!("{}", core_id.0));
+            if spike.is_some() {
+                fs::create_dir_all(&differential_dir)
+                    .expect("Failed to create the differential directory");
+                std::env::set_var(FUZZING_COMMIT_LOG_VAR, dut_log_path(&differential_dir));
+            }
+
+            // Loaded after the map is set up so its AFL++ runtime attaches to
+            // the map of this client.
+            let target = in_process.as_ref().map(|path| {
+                InProcessTarget::load(path).expect("Failed to load the in-process target")
+            });
+            let mut harness = |input: &ProgramInput| match &target {
+                Some(target) => target.run(input.target_bytes().as_slice()),
+                None => ExitKind::Ok,
+            };
+
             // Create an observation channel using the hitcounts map of AFL++
             let edges_observer =
                 unsafe { HitcountsMapObserver::new(StdMapObserver::new("shared_mem", shmem_buf)) };
@@ -262,9 +894,35 @@ fn fuzz(
             // Create an observation channel to keep track of the execution time
             let time_observer = TimeObserver::new("time");
 
+            // Reads the final state the harness dumps after every run.
+            let state_dump = if state_feedback {
+                let path = out_dir.join("state_dumps").join(format!("{}", core_id.0));
+                fs::create_dir_all(path.parent().unwrap())
+                    .expect("Failed to create the state dump directory");
+                std::env::set_var(FUZZING_STATE_DUMP_VAR, &path);
+                Some(path)
+            } else {
+                None
+            };
+            let state_observer = ArchStateObserver::new("arch_state", state_dump);
+            let template_observer = TemplateObserver::new("templates", template_feedback);
+
             let map_feedback = MaxMapFeedback::tracking(&edges_observer, true, false);
 
-            let calibration = DummyCalibration::new(&map_feedback);
+            let region_feedback = RegionNoveltyFeedback::new(
+                &edges_observer,
+                region_manifest.clone(),
+                focus_region.clone(),
+            );
+
+            let calibration =
+                DummyCalibration::new(&map_feedback).with_exec_time_model(exec_time_model.clone());
+            let trimming = ProgramTrimStage::new(&map_feedback, trim_execs);
+
+            let virgin_bits_feedback = VirginBitsFeedback::new(&map_feedback, virgin_bits.clone());
+            let map_feedback_name = map_feedback.name().to_string();
+            let extension_feedback =
+                ExtensionStatsFeedback::new(&map_feedback, extension_stats_dir.clone());
 
             // Feedback to rate the interestingness of an input
             // This one is composed by two Feedbacks in OR
@@ -272,7 +930,23 @@ fn fuzz(
                 // New maximization map feedback linked to the edges observer and the feedback state
                 map_feedback,
                 // Time feedback, this one does not need a feedback state
-                TimeFeedback::with_observer(&time_observer)
+                TimeFeedback::with_observer(&time_observer),
+                // Never interesting by itself, but learns snippets from new entries
+                SnippetExtractionFeedback::new(),
+                // Records the map regions the entry found new coverage in
+                region_feedback,
+                // Records how the operands of generated instructions were chosen
+                ProvenanceFeedback::new(Some(provenance_dir.clone())),
+                // Merges the coverage into the union of all campaigns
+                virgin_bits_feedback,
+                // Logs the coverage growth per ISA extension
+                extension_feedback,
+                // Keeps inputs that end in a new architectural state
+                NovelStateFeedback::new(&state_observer),
+                // Keeps inputs with instructions the corpus doesn't have yet
+                TemplateCoverageFeedback::new(&template_observer),
+                // Credits the mutations of new entries
+                MutationStatsFeedback::corpus()
             );
 
             // Create client specific directories to avoid race conditions when
@@ -281,13 +955,24 @@ fn fuzz(
             corpus_dir.push(format!("{}", core_id.0));
             let mut objective_dir = base_objective_dir.clone();
             objective_dir.push(format!("{}", core_id.0));
+            let mut archive_dir = base_archive_dir.clone();
+            archive_dir.push(format!("{}", core_id.0));
 
             // A feedback to choose if an input is a solution or not
-            let mut objective = CrashFeedback::new();
+            let mut objective = feedback_or!(
+                CrashFeedback::new(),
+                // Credits the mutations of objectives and reports the counts
+                MutationStatsFeedback::objective(),
+                // Records the coverage hashes of crashes for the triage tool
+                CoverageHashFeedback::new(&edges_observer, &objective_dir)
+            );
+
+            // Every client gets its own seed so they don't fuzz the same inputs.
+            let client_seed = seed.map(|seed| seed ^ core_id.0 as u64);
 
             // Create the fuzz state.
             let mut state = StdState::new(
-                StdRand::with_seed(current_nanos()),
+                StdRand::with_seed(client_seed.unwrap_or_else(current_nanos)),
                 OnDiskCorpus::<ProgramInput>::with_meta_format(
                     corpus_dir,
                     OnDiskMetadataFormat::Postcard,
@@ -299,9 +984,22 @@ fn fuzz(
             )
             .unwrap();
 
-            let mutator = StdScheduledMutator::new(all_riscv_mutations());
+            // Coverage from previous campaigns isn't interesting anymore.
+            restore_virgin_bits(&mut state, &map_feedback_name, &initial_coverage)
+                .expect("Failed to restore the coverage of previous campaigns");
+
+            let mut mutator_config = generator.config().clone();
+            if let Some(seed) = client_seed {
+                mutator_config = mutator_config.mutation_seed(seed);
+            }
+            let mutator = StdScheduledMutator::new(tuple_list!(SelectedMutations::new(
+                &mutations,
+                &mutator_config,
+            )));
 
             let power = StdPowerMutationalStage::new(mutator);
+            // Retires entries that stopped finding new coverage.
+            let power = CorpusRetirement::new(power, retire_after, archive_dir);
 
             // A minimization+queue policy to get testcasess from the corpus
             let scheduler = IndexesLenTimeMinimizerScheduler::new(
@@ -311,26 +1009,53 @@ fn fuzz(
             // A fuzzer with feedbacks and a corpus scheduler
             let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
 
-            let forkserver = ForkserverExecutor::builder()
-                .program(executable.clone())
-                .debug_child(debug_child)
-                .parse_afl_cmdline(arguments)
-                .coverage_map_size(MAP_SIZE)
-                .is_persistent(false)
-                .is_deferred_frksrv(true)
-                .build_dynamic_map(edges_observer, tuple_list!(time_observer))
-                .unwrap();
-
-            let mut executor = TimeoutForkserverExecutor::with_signal(forkserver, timeout, signal)
-                .expect("Failed to create the executor.");
-
-            // Load the initial seeds from the user directory.
-            // state
-            //     .load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, &[seed_dir.clone()])
-            //     .unwrap_or_else(|_| {
-            //         println!("Failed to load initial corpus at {:?}", &seed_dir);
-            //         process::exit(0);
-            //     });
+            let timeout_executor = if target.is_some() {
+                let executor = InProcessExecutor::new(
+                    &mut harness,
+                    tuple_list!(
+                        edges_observer,
+                        time_observer,
+                        state_observer,
+                        template_observer
+                    ),
+                    &mut fuzzer,
+                    &mut state,
+                    &mut mgr,
+                )
+                .expect("Failed to create the in-process executor.");
+                TargetExecutor::InProcess(TimeoutExecutor::new(executor, timeout))
+            } else {
+                // Inputs are passed through shared memory if the target
+                // supports it (see FUZZING_SHMEM_INPUT in FuzzerAPI.h),
+                // otherwise through the '@@' file.
+                let forkserver = ForkserverExecutor::builder()
+                    .program(executable.clone())
+                    .debug_child(debug_child)
+                    .parse_afl_cmdline(arguments)
+                    .coverage_map_size(MAP_SIZE)
+                    .is_persistent(false)
+                    .is_deferred_frksrv(true)
+                    .shmem_provider(&mut shmem_provider_client)
+                    .build_dynamic_map(
+                        edges_observer,
+                        tuple_list!(time_observer, state_observer, template_observer),
+                    )
+                    .unwrap();
+
+                TargetExecutor::Forkserver(
+                    TimeoutForkserverExecutor::with_signal(forkserver, timeout, signal)
+                        .expect("Failed to create the executor."),
+                )
+            };
+
+            // Filters that can reject inputs before they are executed.
+            let mut filters = Vec::<Box<dyn InputFilter>>::new();
+            if let Some(max_len) = max_insts {
+                filters.push(Box::new(MaxLengthFilter::new(max_len)));
+            }
+            let timeout_executor =
+                DifferentialExecutor::new(timeout_executor, spike.clone(), differential_dir);
+            let mut executor = FilteringExecutor::new(timeout_executor, filters);
 
             let nop = Instruction::new(
                 &ADDI,
@@ -341,13 +1066,55 @@ fn fuzz(
                 ],
             );
 
-            let init = ProgramInput::new([nop].to_vec());
-            fuzzer
-                .add_input(&mut state, &mut executor, &mut mgr, init)
-                .expect("Failed to load initial inputs");
+            // In virtual-memory mode, start with a page-table setup.
+            let mut seed = match generator.page_table() {
+                Some(config) => sv39_setup(
+                    state.rand_mut(),
+                    config,
+                    generator.misaligned_chance(),
+                    &AddressPool::default(),
+                ),
+                None => Vec::<Instruction>::new(),
+            };
+            seed.push(nop);
+
+            let mut init = if config_header {
+                ProgramInput::with_config(seed, InputConfig::default())
+            } else {
+                ProgramInput::new(seed)
+            };
+            if let Some(address) = generator.memory_image() {
+                init = init.with_memory(MemoryImage::new(address));
+            }
+
+            // Load the initial seeds from the user directory. Like other new
+            // inputs, they are only kept if they are interesting.
+            for seed in &seeds {
+                let mut input = seed.clone();
+                if config_header && input.config().is_none() {
+                    let mut with_config =
+                        ProgramInput::with_config(input.insts().to_vec(), InputConfig::default());
+                    if let Some(memory) = input.memory() {
+                        with_config = with_config.with_memory(memory.clone());
+                    }
+                    input = with_config;
+                }
+                if let (Some(address), None) = (generator.memory_image(), input.memory()) {
+                    input = input.with_memory(MemoryImage::new(address));
+                }
+                fuzzer
+                    .evaluate_input(&mut state, &mut executor, &mut mgr, input)
+                    .expect("Failed to load initial inputs");
+            }
+            // The fuzzer needs at least one entry to start from.
+            if state.corpus().count() == 0 {
+                fuzzer
+                    .add_input(&mut state, &mut executor, &mut mgr, init)
+                    .expect("Failed to load initial inputs");
+            }
 
-            // First calibrate the initial seed and then mutate.
-            let mut stages = tuple_list!(calibration, power);
+            // First trim and calibrate new entries and then mutate.
+            let mut stages = tuple_list!(trimming, calibration, power);
 
             // Main fuzzing loop.
             let mut last = current_time();
@@ -362,7 +1129,14 @@ fn fuzz(
                 if last_err.is_err() {
                     log::error!("last_err error: {}", last_err.err().unwrap());
                 } else {
-                    last = last_err.ok().unwrap()
+                    let reported = last_err.ok().unwrap();
+                    // The monitor asks all clients to stop, e.g. once the
+                    // budget is used up.
+                    if reported != last && stop_requested(&out_dir).is_some() {
+                        mgr.send_exiting()?;
+                        return Ok(());
+                    }
+                    last = reported
                 }
 
                 // If we have a simple UI, we need to manually list all causes
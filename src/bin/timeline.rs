@@ -0,0 +1,48 @@
+use clap::Parser;
+use riscv_mutator::timeline::{format_offset, parse_timeline, TimelineEvent, TIMELINE_FILE};
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Prints the timeline of a sim-fuzzer campaign as a readable chronology.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The output directory of the campaign.
+    out: String,
+    /// Hide the (frequent) coverage growth events.
+    #[arg(long, default_value_t = false)]
+    no_coverage: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let path = PathBuf::from(&args.out).join(TIMELINE_FILE);
+    let Ok(text) = fs::read_to_string(&path) else {
+        eprintln!("No timeline at {:?}", path);
+        return ExitCode::FAILURE;
+    };
+
+    let mut entries = parse_timeline(&text);
+    // Several processes append to the timeline, so lines can be slightly out
+    // of order.
+    entries.sort_by_key(|entry| entry.time);
+    let Some(first) = entries.first().map(|entry| entry.time) else {
+        println!("No events recorded yet.");
+        return ExitCode::SUCCESS;
+    };
+
+    for entry in &entries {
+        if args.no_coverage {
+            if let TimelineEvent::NewCoverage { .. } = entry.event {
+                continue;
+            }
+        }
+        println!(
+            "+{:>12}  {}",
+            format_offset(entry.time - first),
+            entry.event.describe()
+        );
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,61 @@
+use clap::Parser;
+use riscv_mutator::isa_hash::check_isa_hash_file;
+use riscv_mutator::triage::{bucket_findings, prune, read_findings, report, TRIAGE_REPORT_FILE};
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Groups the objectives of a sim-fuzzer campaign by cause and coverage hash,
+/// keeps only the smallest objectives of every group and writes a report of
+/// the groups to the output directory.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The output directory of the campaign.
+    out: String,
+    /// How many objectives to keep per group.
+    #[arg(long, default_value_t = 3)]
+    keep: usize,
+    /// Only print the report, don't delete any objectives.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let out = PathBuf::from(&args.out);
+    // Misparsed objectives would end up in the wrong groups and be deleted.
+    if let Err(err) = check_isa_hash_file(&out) {
+        eprintln!("{}", err);
+        return ExitCode::FAILURE;
+    }
+    let buckets = match read_findings(&out) {
+        Ok(findings) => bucket_findings(findings),
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let text = report(&buckets, args.keep);
+    print!("{}", text);
+    if args.dry_run {
+        return ExitCode::SUCCESS;
+    }
+
+    let report_path = out.join(TRIAGE_REPORT_FILE);
+    if let Err(err) = fs::write(&report_path, &text) {
+        eprintln!("Failed to write {:?}: {}", report_path, err);
+        return ExitCode::FAILURE;
+    }
+    match prune(&buckets, args.keep) {
+        Ok(deleted) => {
+            println!("\nDeleted {} duplicate objectives", deleted);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
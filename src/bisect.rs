@@ -0,0 +1,111 @@
+//! Finds the instructions of a reproducer that are needed to trigger a cause
+//! by replacing the others with nops.
+
+use crate::instructions::{
+    riscv::{args, rv_i::ADDI},
+    Argument, Instruction,
+};
+
+/// The canonical nop ('addi x0, x0, 0').
+pub fn nop() -> Instruction {
+    Instruction::new(
+        &ADDI,
+        vec![
+            Argument::new(&args::RD, 0),
+            Argument::new(&args::RS1, 0),
+            Argument::new(&args::IMM12, 0),
+        ],
+    )
+}
+
+/// The program with the instructions at the given positions replaced by
+/// nops.
+pub fn neutralize(program: &[Instruction], positions: &[usize]) -> Vec<Instruction> {
+    let mut result = program.to_vec();
+    for pos in positions {
+        result[*pos] = nop();
+    }
+    result
+}
+
+/// Returns the positions of the instructions that can't be replaced by nops
+/// without `triggers` returning false. Neutralizes chunks of instructions
+/// that start at half of the program and are halved until single
+/// instructions are tried, so large irrelevant parts only cost one check.
+/// Returns None if the unchanged program doesn't trigger.
+pub fn find_culprits<F>(program: &[Instruction], mut triggers: F) -> Option<Vec<usize>>
+where
+    F: FnMut(&[Instruction]) -> bool,
+{
+    if !triggers(program) {
+        return None;
+    }
+    let nop = nop();
+    // Nops can't be culprits.
+    let mut remaining: Vec<usize> = (0..program.len())
+        .filter(|pos| program[*pos] != nop)
+        .collect();
+    let mut neutralized: Vec<usize> = (0..program.len())
+        .filter(|pos| program[*pos] == nop)
+        .collect();
+
+    let mut chunk_size = (remaining.len() / 2).max(1);
+    loop {
+        let mut idx = 0;
+        while idx < remaining.len() {
+            let end = (idx + chunk_size).min(remaining.len());
+            let mut candidate = neutralized.clone();
+            candidate.extend(&remaining[idx..end]);
+            if triggers(&neutralize(program, &candidate)) {
+                neutralized = candidate;
+                remaining.drain(idx..end);
+            } else {
+                idx = end;
+            }
+        }
+        if chunk_size == 1 {
+            break;
+        }
+        chunk_size /= 2;
+    }
+    Some(remaining)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instructions::{
+        riscv::{args, rv_i::ADDI},
+        Argument, Instruction,
+    };
+
+    use super::{find_culprits, neutralize, nop};
+
+    fn addi(imm: u32) -> Instruction {
+        Instruction::new(
+            &ADDI,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 1),
+                Argument::new(&args::IMM12, imm),
+            ],
+        )
+    }
+
+    #[test]
+    fn bisect_culprits() {
+        let program: Vec<Instruction> = (1..=64).map(addi).collect();
+        // The cause needs the instructions at 5 and 40.
+        let mut runs = 0;
+        let culprits = find_culprits(&program, |insts| {
+            runs += 1;
+            insts[5] == program[5] && insts[40] == program[40]
+        });
+        assert_eq!(culprits, Some(vec![5, 40]));
+        assert!(runs < 40, "{} runs", runs);
+
+        assert_eq!(neutralize(&program, &[1])[1], nop());
+        assert_eq!(find_culprits(&program, |_| false), None);
+        assert_eq!(find_culprits(&program, |_| true), Some(vec![]));
+        assert_eq!(find_culprits(&[], |_| true), Some(vec![]));
+    }
+}
@@ -0,0 +1,130 @@
+//! Limits on how long a campaign runs, and the stop file through which the
+//! monitor tells all clients to stop.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The name of the file in the out dir that asks all clients to stop. It
+/// contains the reason.
+pub const STOP_FILE: &str = "stop";
+
+/// The name of the file in the out dir with the final summary.
+pub const SUMMARY_FILE: &str = "summary";
+
+/// How long the campaign may run. Unset limits never stop it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Budget {
+    max_time: Option<Duration>,
+    max_execs: Option<u64>,
+}
+
+impl Budget {
+    pub fn new(max_time: Option<Duration>, max_execs: Option<u64>) -> Self {
+        Self {
+            max_time,
+            max_execs,
+        }
+    }
+
+    /// The reason to stop after running for the given time with the given
+    /// total number of executions, or None if the budget isn't used up.
+    pub fn exceeded(&self, elapsed: Duration, execs: u64) -> Option<String> {
+        if let Some(max_time) = self.max_time {
+            if elapsed >= max_time {
+                return Some(format!("Time budget of {}s used up", max_time.as_secs()));
+            }
+        }
+        if let Some(max_execs) = self.max_execs {
+            if execs >= max_execs {
+                return Some(format!("Budget of {} executions used up", max_execs));
+            }
+        }
+        None
+    }
+}
+
+/// Parses a duration such as '90', '90s', '30m', '12h' or '2d'. Plain
+/// numbers are seconds.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let (number, unit) = match input.find(|c: char| !c.is_ascii_digit()) {
+        Some(pos) => input.split_at(pos),
+        None => (input, "s"),
+    };
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("Invalid duration: '{}'", input))?;
+    let factor = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("Unknown duration unit in '{}'", input)),
+    };
+    Ok(Duration::from_secs(number * factor))
+}
+
+fn stop_file(out_dir: &Path) -> PathBuf {
+    out_dir.join(STOP_FILE)
+}
+
+/// Asks all clients of the campaign in the out dir to stop.
+pub fn request_stop(out_dir: &Path, reason: &str) -> std::io::Result<()> {
+    fs::write(stop_file(out_dir), format!("{}\n", reason))
+}
+
+/// The reason the campaign in the out dir should stop, if any.
+pub fn stop_requested(out_dir: &Path) -> Option<String> {
+    let reason = fs::read_to_string(stop_file(out_dir)).ok()?;
+    Some(reason.trim().to_string())
+}
+
+/// Removes the stop file a previous campaign in the out dir left behind.
+pub fn clear_stop(out_dir: &Path) -> std::io::Result<()> {
+    match fs::remove_file(stop_file(out_dir)) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{clear_stop, parse_duration, request_stop, stop_requested, Budget};
+
+    #[test]
+    fn budget_limits() {
+        let budget = Budget::new(Some(Duration::from_secs(60)), Some(1000));
+        assert_eq!(budget.exceeded(Duration::from_secs(59), 999), None);
+        assert!(budget.exceeded(Duration::from_secs(60), 0).is_some());
+        assert!(budget.exceeded(Duration::ZERO, 1000).is_some());
+        assert_eq!(Budget::default().exceeded(Duration::MAX, u64::MAX), None);
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(172800)));
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("5w").is_err());
+    }
+
+    #[test]
+    fn stop_file() {
+        let dir = std::env::temp_dir().join(format!("budget-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        clear_stop(&dir).unwrap();
+        assert_eq!(stop_requested(&dir), None);
+        request_stop(&dir, "Done").unwrap();
+        assert_eq!(stop_requested(&dir), Some("Done".to_string()));
+        clear_stop(&dir).unwrap();
+        assert_eq!(stop_requested(&dir), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+//! Snippets that target a single cache set with more lines than it has ways
+//! to exercise the replacement and writeback logic.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use libafl::prelude::Rand;
+
+use crate::{
+    address_pool::AddressPool,
+    instructions::{
+        riscv::rv64_i::{LD, SD},
+        Instruction,
+    },
+    memory_ops::{make_load, make_store},
+    pmp::PmpConfig,
+    system_snippets::SCRATCH_REG,
+};
+
+// Upper bound for the number of candidate addresses in a set.
+const MAX_SLOTS: u64 = 64;
+
+/// The geometry of the data cache of the target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheGeometry {
+    line_size: u32,
+    sets: u32,
+    ways: u32,
+}
+
+impl CacheGeometry {
+    pub fn new(line_size: u32, sets: u32, ways: u32) -> Result<Self, String> {
+        if !line_size.is_power_of_two() || line_size < 8 {
+            return Err(format!("Invalid cache line size {}", line_size));
+        }
+        if !sets.is_power_of_two() {
+            return Err(format!("Number of sets {} is not a power of two", sets));
+        }
+        if ways == 0 {
+            return Err("A cache needs at least one way".to_string());
+        }
+        if (line_size as u64) * (sets as u64) > u32::MAX as u64 {
+            return Err("Cache way exceeds 32 bit addresses".to_string());
+        }
+        Ok(Self {
+            line_size,
+            sets,
+            ways,
+        })
+    }
+
+    /// Parses 'LINE,SETS,WAYS' where all are decimal numbers.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = input.split(',').collect();
+        if parts.len() != 3 {
+            return Err(format!("Expected 'LINE,SETS,WAYS' but got '{}'", input));
+        }
+        let parse_num = |s: &str| -> Result<u32, String> {
+            s.trim()
+                .parse::<u32>()
+                .map_err(|e| format!("Invalid number '{}': {}", s, e))
+        };
+        Self::new(
+            parse_num(parts[0])?,
+            parse_num(parts[1])?,
+            parse_num(parts[2])?,
+        )
+    }
+
+    pub fn line_size(&self) -> u32 {
+        self.line_size
+    }
+
+    pub fn sets(&self) -> u32 {
+        self.sets
+    }
+
+    pub fn ways(&self) -> u32 {
+        self.ways
+    }
+
+    /// The distance between two addresses in the same set.
+    pub fn way_size(&self) -> u32 {
+        self.line_size * self.sets
+    }
+
+    /// The set the address maps to.
+    pub fn set_of(&self, address: u32) -> u32 {
+        (address / self.line_size) % self.sets
+    }
+
+    /// The tag of the address.
+    pub fn tag_of(&self, address: u32) -> u32 {
+        address / self.way_size()
+    }
+}
+
+/// Returns up to `count` line-aligned addresses inside the region that map to
+/// the same random cache set but have different tags.
+pub fn same_set_addresses<R: Rand>(
+    rng: &mut R,
+    geometry: &CacheGeometry,
+    region: &PmpConfig,
+    count: usize,
+) -> Vec<u32> {
+    let way_size = geometry.way_size() as u64;
+    let line_size = geometry.line_size() as u64;
+    let end = region.base() as u64 + region.size() as u64;
+
+    let set = rng.below(geometry.sets() as u64);
+    let mut first = (region.base() as u64 / way_size) * way_size + set * line_size;
+    if first < region.base() as u64 {
+        first += way_size;
+    }
+    let mut slots: Vec<u32> = (0..MAX_SLOTS)
+        .map(|i| first + i * way_size)
+        .take_while(|address| address + line_size <= end)
+        .map(|address| address as u32)
+        .collect();
+
+    let mut result = Vec::<u32>::new();
+    while result.len() < count && !slots.is_empty() {
+        let idx = rng.below(slots.len() as u64) as usize;
+        result.push(slots.swap_remove(idx));
+    }
+    result
+}
+
+/// Creates a load or store of a doubleword at the given address.
+fn access(pool: &AddressPool, address: u32, store: bool) -> Vec<Instruction> {
+    let (mut result, base, offset) = pool.address_operand(address);
+    result.push(if store {
+        make_store(&SD, base, SCRATCH_REG, offset)
+    } else {
+        make_load(&LD, SCRATCH_REG, base, offset)
+    });
+    result
+}
+
+/// Accesses one more line than the cache has ways in a single set. Either
+/// sweeps over the lines and returns to the (now evicted) first one, dirties
+/// all lines before sweeping to force writebacks, or alternates between the
+/// first line and the others. Clobbers t0.
+pub fn eviction_snippet<R: Rand>(
+    rng: &mut R,
+    geometry: &CacheGeometry,
+    region: &PmpConfig,
+    pool: &AddressPool,
+) -> Vec<Instruction> {
+    let addresses = same_set_addresses(rng, geometry, region, geometry.ways() as usize + 1);
+    let mut result = Vec::<Instruction>::new();
+    match rng.below(3) {
+        0 => {
+            for address in addresses.iter().chain(addresses.first()) {
+                result.append(&mut access(pool, *address, false));
+            }
+        }
+        1 => {
+            for address in &addresses {
+                result.append(&mut access(pool, *address, true));
+            }
+            for address in &addresses {
+                result.append(&mut access(pool, *address, false));
+            }
+        }
+        _ => {
+            for address in addresses.iter().skip(1) {
+                result.append(&mut access(pool, addresses[0], false));
+                result.append(&mut access(pool, *address, rng.below(2) == 0));
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::prelude::Xoshiro256StarRand;
+
+    use crate::{
+        address_pool::AddressPool, assembler::assemble_instructions, instructions,
+        parser::parse_instructions, pmp::PmpConfig,
+    };
+
+    use super::{eviction_snippet, same_set_addresses, CacheGeometry};
+
+    #[test]
+    fn parse_geometry() {
+        let geometry = CacheGeometry::parse("64, 64, 4").unwrap();
+        assert_eq!(geometry.way_size(), 4096);
+        assert!(CacheGeometry::parse("48,64,4").is_err());
+        assert!(CacheGeometry::parse("64,64,0").is_err());
+        assert!(CacheGeometry::parse("64,64").is_err());
+    }
+
+    #[test]
+    fn addresses_share_set() {
+        let mut rng = Xoshiro256StarRand::default();
+        let geometry = CacheGeometry::new(64, 64, 4).unwrap();
+        let region = PmpConfig::new(0x8000_0100, 0x10000).unwrap();
+        for _ in 0..100 {
+            let addresses = same_set_addresses(&mut rng, &geometry, &region, 5);
+            assert_eq!(addresses.len(), 5);
+            let set = geometry.set_of(addresses[0]);
+            let mut tags: Vec<u32> = addresses.iter().map(|a| geometry.tag_of(*a)).collect();
+            tags.sort_unstable();
+            tags.dedup();
+            assert_eq!(tags.len(), 5);
+            for address in addresses {
+                assert_eq!(geometry.set_of(address), set);
+                assert_eq!(address % 64, 0);
+                assert!(address >= region.base());
+                assert!(address + 64 <= region.base() + region.size());
+            }
+        }
+    }
+
+    #[test]
+    fn snippet_roundtrip() {
+        let mut rng = Xoshiro256StarRand::default();
+        let geometry = CacheGeometry::new(64, 64, 4).unwrap();
+        let region = PmpConfig::new(0x8000_0000, 0x10000).unwrap();
+        let (pool, _) = AddressPool::create(&mut rng, &region);
+        for _ in 0..100 {
+            let snippet = eviction_snippet(&mut rng, &geometry, &region, &pool);
+            assert!(!snippet.is_empty());
+            let bytes = assemble_instructions(&snippet);
+            assert_eq!(
+                parse_instructions(&bytes, instructions::riscv::all()).unwrap(),
+                snippet
+            );
+        }
+    }
+}
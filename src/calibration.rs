@@ -7,7 +7,7 @@ use hashbrown::HashSet;
 use serde::{Deserialize, Serialize};
 
 use libafl::{
-    bolts::{tuples::Named, AsIter},
+    bolts::{current_time, tuples::Named, AsIter},
     corpus::{Corpus, CorpusId, SchedulerTestcaseMetadata},
     events::{EventFirer, LogSeverity},
     executors::{Executor, ExitKind, HasObservers},
@@ -21,7 +21,66 @@ use libafl::{
     Error,
 };
 
-use crate::program_input::ProgramInput;
+use crate::{
+    inst_class::{parse_class_weights, InstClass},
+    instructions::Instruction,
+    map_regions::RegionNoveltyMetadata,
+    program_input::ProgramInput,
+};
+
+// Added to the handicap of entries with new coverage in the focus region.
+const FOCUS_HANDICAP: u64 = 4;
+
+/// How the calibration estimates the execution time of an input, which the
+/// power schedules use to assign it energy.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ExecTimeModel {
+    /// The wall-clock time of the calibration run. Includes the noise of the
+    /// simulator startup, which often dominates short programs.
+    Measured,
+    /// One second per instruction plus one for the startup.
+    #[default]
+    InstructionCount,
+    /// Like `InstructionCount`, but an instruction takes as many seconds as
+    /// its most expensive class (one for classes without a cost).
+    ClassCosts(Vec<(InstClass, u32)>),
+}
+
+impl ExecTimeModel {
+    /// Parses 'measured', 'insts' or 'classes:' followed by class costs
+    /// (e.g. 'classes:load=4,store=4,fp=8').
+    pub fn parse(input: &str) -> Result<ExecTimeModel, String> {
+        match input.trim() {
+            "measured" => Ok(ExecTimeModel::Measured),
+            "insts" => Ok(ExecTimeModel::InstructionCount),
+            other => match other.strip_prefix("classes:") {
+                Some(costs) => Ok(ExecTimeModel::ClassCosts(parse_class_weights(costs)?)),
+                None => Err(format!(
+                    "Unknown exec time model '{}', expected 'measured', 'insts' or 'classes:...'",
+                    other
+                )),
+            },
+        }
+    }
+
+    /// The estimated execution time of the program, given the measured time
+    /// of a run.
+    pub fn estimate(&self, insts: &[Instruction], measured: Duration) -> Duration {
+        let cost = |inst: &Instruction| match self {
+            ExecTimeModel::ClassCosts(costs) => InstClass::of(inst.template())
+                .iter()
+                .filter_map(|class| costs.iter().find(|(c, _)| c == class))
+                .map(|(_, cost)| *cost as u64)
+                .max()
+                .unwrap_or(1),
+            _ => 1,
+        };
+        match self {
+            ExecTimeModel::Measured => measured,
+            _ => Duration::from_secs(1 + insts.iter().map(cost).sum::<u64>()),
+        }
+    }
+}
 
 libafl::impl_serdeany!(UnstableEntriesMetadata);
 /// The metadata to keep unstable entries
@@ -60,6 +119,7 @@ impl UnstableEntriesMetadata {
 #[derive(Clone, Debug)]
 pub struct DummyCalibration<O, OT, S> {
     map_observer_name: String,
+    exec_time_model: ExecTimeModel,
     phantom: PhantomData<(O, OT, S)>,
 }
 
@@ -110,7 +170,9 @@ where
 
         executor.observers_mut().pre_exec_all(state, &input)?;
 
+        let start = current_time();
         let exit_kind = executor.run_target(fuzzer, state, mgr, &input)?;
+        let measured = current_time() - start;
         if exit_kind != ExitKind::Ok {
             mgr.log(
                 state,
@@ -123,9 +185,8 @@ where
             .observers_mut()
             .post_exec_all(state, &input, &exit_kind)?;
 
-        // Estimate duration based on number of instructions.
         let program: ProgramInput = input.into();
-        let total_time = Duration::from_secs((program.insts().len() + 1) as u64);
+        let total_time = self.exec_time_model.estimate(program.insts(), measured);
 
         // If weighted scheduler or powerscheduler is used, update it
         if state.has_metadata::<SchedulerMetadata>() {
@@ -154,6 +215,12 @@ where
             testcase.set_exec_time(total_time / (iter as u32));
             testcase.set_scheduled_count(scheduled_count + 1);
 
+            // Fuzz entries that reached new parts of the focus region longer.
+            let focused = testcase
+                .metadata_map()
+                .get::<RegionNoveltyMetadata>()
+                .map_or(false, |meta| meta.focused());
+
             // If the testcase doesn't have its own `SchedulerTestcaseMetadata`, create it.
             let data = if let Ok(metadata) = testcase.metadata_mut::<SchedulerTestcaseMetadata>() {
                 metadata
@@ -179,7 +246,11 @@ where
 
             data.set_cycle_and_time((total_time, iter));
             data.set_bitmap_size(bitmap_size);
-            data.set_handicap(handicap);
+            data.set_handicap(if focused {
+                handicap + FOCUS_HANDICAP
+            } else {
+                handicap
+            });
         }
 
         Ok(())
@@ -200,7 +271,71 @@ where
     {
         Self {
             map_observer_name: map_feedback.observer_name().to_string(),
+            exec_time_model: ExecTimeModel::default(),
             phantom: PhantomData,
         }
     }
+
+    /// Estimates the execution times with the given model.
+    #[must_use]
+    pub fn with_exec_time_model(mut self, exec_time_model: ExecTimeModel) -> Self {
+        self.exec_time_model = exec_time_model;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use crate::{
+        inst_class::InstClass,
+        instructions::{
+            riscv::{
+                args,
+                rv_i::{ADDI, LW},
+            },
+            Argument, Instruction,
+        },
+    };
+
+    use super::ExecTimeModel;
+
+    #[test]
+    fn estimate_exec_times() {
+        let addi = Instruction::new(
+            &ADDI,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 1),
+                Argument::new(&args::IMM12, 1),
+            ],
+        );
+        let lw = Instruction::new(
+            &LW,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 1),
+                Argument::new(&args::IMM12, 0),
+            ],
+        );
+        let program = [addi, lw.clone(), lw];
+        let measured = Duration::from_millis(20);
+
+        let insts = ExecTimeModel::parse("insts").unwrap();
+        assert_eq!(insts.estimate(&program, measured), Duration::from_secs(4));
+        let classes = ExecTimeModel::parse("classes:load=5").unwrap();
+        assert_eq!(
+            classes,
+            ExecTimeModel::ClassCosts(vec![(InstClass::Load, 5)])
+        );
+        assert_eq!(
+            classes.estimate(&program, measured),
+            Duration::from_secs(12)
+        );
+        let wall_clock = ExecTimeModel::parse("measured").unwrap();
+        assert_eq!(wall_clock.estimate(&program, measured), measured);
+        assert!(ExecTimeModel::parse("fast").is_err());
+        assert!(ExecTimeModel::parse("classes:slow=5").is_err());
+    }
 }
@@ -1,14 +1,132 @@
 use std::{
-    collections::HashSet,
+    collections::{hash_map::DefaultHasher, BTreeMap, HashSet},
     fs::File,
+    hash::{Hash, Hasher},
     io::{BufRead, BufReader, Write},
-    path::Path,
-    process::Command,
-    time::{Duration, UNIX_EPOCH},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use serde::{Deserialize, Serialize};
+
+use crate::budget::request_stop;
+
 pub const FUZZING_CAUSE_DIR_VAR: &'static str = "FUZZING_CAUSE_DIR";
 pub const FUZZING_EXPECTED_LIST_VAR: &'static str = "FUZZING_EXPECTED_LIST";
+/// The id of the core a client runs on, set by the fuzzer for the target to
+/// put into its cause records.
+pub const FUZZING_CORE_ID_VAR: &str = "FUZZING_CORE_ID";
+
+/// Extension of the records next to the inputs in the cause dir, e.g.
+/// 'Illegal_instruction%0123456789abcdef.json'.
+pub const CAUSE_RECORD_EXTENSION: &str = "json";
+
+/// Describes an input in the cause dir. Written by the target (see
+/// `reportFuzzingIssue` in FuzzerAPI.h) next to the input.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CauseRecord {
+    /// The cause as reported, e.g. 'Illegal instruction'.
+    pub cause: String,
+    /// When the cause was found in seconds since the UNIX epoch.
+    pub time: f64,
+    /// The core of the client that found it, if known.
+    #[serde(default)]
+    pub core: Option<usize>,
+    /// The hash of the input, also used in its file name.
+    pub hash: String,
+}
+
+impl CauseRecord {
+    /// The record of an input that was just found.
+    pub fn new(cause: &str, input: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        input.hash(&mut hasher);
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self {
+            cause: cause.to_string(),
+            time: time.as_secs_f64(),
+            core: std::env::var(FUZZING_CORE_ID_VAR)
+                .ok()
+                .and_then(|core| core.parse().ok()),
+            hash: format!("{:016x}", hasher.finish()),
+        }
+    }
+
+    /// The file name of the input in the cause dir.
+    pub fn input_name(&self) -> String {
+        format!("{}%{}", self.cause.replace(' ', "_"), self.hash)
+    }
+
+    /// The time since the given start time (since the UNIX epoch).
+    pub fn time_since(&self, start_time: Duration) -> Duration {
+        Duration::try_from_secs_f64(self.time)
+            .unwrap_or_default()
+            .saturating_sub(start_time)
+    }
+
+    // Derives a record from the file name and modification time of an input
+    // without one, e.g. from an older campaign.
+    fn from_input(filename: &str, path: &Path) -> Self {
+        let time = std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        Self {
+            cause: cause_name(filename),
+            time: time.as_secs_f64(),
+            core: None,
+            hash: filename.split('%').nth(1).unwrap_or_default().to_string(),
+        }
+    }
+}
+
+/// The path of the record of the given input.
+pub fn record_path(input: &Path) -> PathBuf {
+    let mut path = input.as_os_str().to_owned();
+    path.push(".");
+    path.push(CAUSE_RECORD_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// Saves the input in the cause dir together with its record, like
+/// `reportFuzzingIssue` in FuzzerAPI.h does. Returns the path of the input.
+pub fn save_cause(cause_dir: &Path, cause: &str, input: &[u8]) -> std::io::Result<PathBuf> {
+    let record = CauseRecord::new(cause, input);
+    let path = cause_dir.join(record.input_name());
+    // The record goes first, so readers never see the input without it.
+    std::fs::write(record_path(&path), serde_json::to_string(&record)?)?;
+    std::fs::write(&path, input)?;
+    Ok(path)
+}
+
+/// The inputs in the cause dir with their records, sorted by time. Inputs
+/// without a (readable) record get one from their file name and modification
+/// time.
+pub fn read_causes(cause_dir: &Path) -> Vec<(PathBuf, CauseRecord)> {
+    let Ok(entries) = std::fs::read_dir(cause_dir) else {
+        return Vec::new();
+    };
+    let mut result = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(filename) = entry.file_name().into_string() else {
+            continue;
+        };
+        if path.extension().and_then(|ext| ext.to_str()) == Some(CAUSE_RECORD_EXTENSION) {
+            continue;
+        }
+        let record = std::fs::read_to_string(record_path(&path))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_else(|| CauseRecord::from_input(&filename, &path));
+        result.push((path, record));
+    }
+    result.sort_by(|a, b| a.1.time.total_cmp(&b.1.time).then_with(|| a.0.cmp(&b.0)));
+    result
+}
 
 pub struct TestCaseData {
     pub cause: String,
@@ -33,6 +151,37 @@ fn get_expected() -> HashSet<String> {
         .collect()
 }
 
+/// The cause of a file in the cause dir as displayed (e.g. 'Illegal
+/// instruction' for 'Illegal_instruction%0123456789abcdef').
+pub fn cause_name(filename: &str) -> String {
+    filename
+        .split('%')
+        .next()
+        .unwrap_or("Bad cause name")
+        .replace('_', " ")
+}
+
+/// The smallest input in the cause dir for every cause, by cause name.
+pub fn smallest_inputs(cause_dir: &Path) -> BTreeMap<String, PathBuf> {
+    let mut result = BTreeMap::<String, (u64, PathBuf)>::new();
+    for (path, record) in read_causes(cause_dir) {
+        let Ok(metadata) = std::fs::metadata(&path) else {
+            continue;
+        };
+        let smaller = match result.get(&record.cause) {
+            Some((size, _)) => metadata.len() < *size,
+            None => true,
+        };
+        if smaller {
+            result.insert(record.cause, (metadata.len(), path));
+        }
+    }
+    result
+        .into_iter()
+        .map(|(cause, (_, path))| (cause, path))
+        .collect()
+}
+
 pub struct CausesList {
     pub found: Vec<TestCaseData>,
     pub still_missing: Vec<String>,
@@ -42,30 +191,15 @@ pub fn list_causes(start_time: std::time::Duration) -> CausesList {
     let cause_dir =
         std::env::var(FUZZING_CAUSE_DIR_VAR).expect("Driver failed to set cause env var?");
 
-    let causes = std::fs::read_dir(Path::new(&cause_dir)).expect("Failed to read causes dir");
-
     let mut expected = get_expected();
 
     let mut case_list = Vec::<TestCaseData>::new();
-    for cause_or_err in causes {
-        let cause = cause_or_err.unwrap();
-        let creation_time = cause.metadata().unwrap().created().unwrap();
-        let creation_unix_time = creation_time.duration_since(UNIX_EPOCH).unwrap();
-        let diff_time = creation_unix_time - start_time;
-
-        let filename = cause.file_name().into_string().unwrap();
-        let cause_str = filename
-            .split("%")
-            .nth(0)
-            .or(Some("Bad cause name"))
-            .unwrap();
-        let display_str = cause_str.replace("_", " ");
-
-        expected.remove(&display_str);
+    for (_, record) in read_causes(Path::new(&cause_dir)) {
+        expected.remove(&record.cause);
 
         case_list.push(TestCaseData {
-            cause: display_str.to_string(),
-            time_to_exposure: diff_time,
+            time_to_exposure: record.time_since(start_time),
+            cause: record.cause,
         })
     }
 
@@ -90,13 +224,9 @@ pub fn list_causes(start_time: std::time::Duration) -> CausesList {
         }
         results.flush().expect("Failed to flush results file");
 
-        // Run killall to stop the fuzzer.
-        // FIXME: That cann't be the only way to stop the fuzzer, but it seems
-        // it is...
-        Command::new("killall")
-            .arg("sim-fuzzer")
-            .spawn()
-            .expect("Failed to stop sim-fuzzer:");
+        // Ask all clients to stop, the monitor then writes the summary.
+        let out_dir = Path::new(&cause_dir).join("..");
+        request_stop(&out_dir, "All expected causes found").expect("Failed to stop sim-fuzzer");
     }
 
     CausesList {
@@ -104,3 +234,66 @@ pub fn list_causes(start_time: std::time::Duration) -> CausesList {
         still_missing: missing,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{cause_name, read_causes, record_path, save_cause, smallest_inputs, CauseRecord};
+
+    #[test]
+    fn cause_names() {
+        assert_eq!(
+            cause_name("Illegal_instruction%0123456789abcdef"),
+            "Illegal instruction"
+        );
+        assert_eq!(cause_name("Divergence_x5"), "Divergence x5");
+    }
+
+    #[test]
+    fn cause_records() {
+        let dir = std::env::temp_dir().join(format!("cause-records-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = save_cause(&dir, "Illegal instruction", &[1, 2, 3, 4]).unwrap();
+        let record: CauseRecord =
+            serde_json::from_str(&std::fs::read_to_string(record_path(&path)).unwrap()).unwrap();
+        assert_eq!(record.cause, "Illegal instruction");
+        assert_eq!(dir.join(record.input_name()), path);
+        // Inputs of older campaigns have no record.
+        std::fs::write(dir.join("Hang%0123"), [0u8; 4]).unwrap();
+
+        let causes = read_causes(&dir);
+        assert_eq!(causes.len(), 2);
+        let hang = causes.iter().find(|(_, r)| r.cause == "Hang").unwrap();
+        assert_eq!(hang.1.hash, "0123");
+        assert_eq!(hang.1.core, None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn time_since_start() {
+        let record: CauseRecord =
+            serde_json::from_str(r#"{"cause": "Hang", "time": 100.5, "hash": "1"}"#).unwrap();
+        assert_eq!(
+            record.time_since(Duration::from_secs(90)),
+            Duration::from_millis(10500)
+        );
+        // Clocks of different machines may disagree.
+        assert_eq!(record.time_since(Duration::from_secs(200)), Duration::ZERO);
+    }
+
+    #[test]
+    fn smallest_cause_inputs() {
+        let dir = std::env::temp_dir().join(format!("causes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Hang%1"), [0u8; 8]).unwrap();
+        std::fs::write(dir.join("Hang%2"), [0u8; 4]).unwrap();
+        std::fs::write(dir.join("Bad_store%3"), [0u8; 12]).unwrap();
+
+        let inputs = smallest_inputs(&dir);
+        assert_eq!(inputs.len(), 2);
+        assert_eq!(inputs["Hang"], dir.join("Hang%2"));
+        assert_eq!(inputs["Bad store"], dir.join("Bad_store%3"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,281 @@
+use std::ops::Range;
+
+use crate::{
+    inst_class::InstClass,
+    instructions::{
+        riscv::rv_i::AUIPC,
+        split_immediates::{BRANCH_OFFSET, JUMP_OFFSET},
+        ArgKind, Instruction,
+    },
+    rewrites::sign_extend,
+};
+
+/// The registers and resources an instruction reads and writes.
+/// Integer and FP registers with the same index are not distinguished, which
+/// only adds false dependencies.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstEffects {
+    reads: Vec<u32>,
+    writes: Vec<u32>,
+    reads_memory: bool,
+    writes_memory: bool,
+    // Control flow, fences, CSR accesses and PC-relative instructions.
+    // Nothing can be moved across these.
+    barrier: bool,
+}
+
+impl InstEffects {
+    pub fn of(inst: &Instruction) -> Self {
+        let mut effects = InstEffects::default();
+        for arg in inst.arguments() {
+            if !arg.spec().is_register() {
+                continue;
+            }
+            // x0 is hardwired to zero and never creates a dependency, f0 is
+            // an ordinary register.
+            let kind = inst.template().arg_kind(arg.spec());
+            if kind == ArgKind::Gpr && arg.value() == 0 {
+                continue;
+            }
+            if arg.spec().name() == "rd" {
+                effects.writes.push(arg.value());
+            } else {
+                effects.reads.push(arg.value());
+            }
+        }
+
+        for class in InstClass::of(inst.template()) {
+            match class {
+                InstClass::Load => effects.reads_memory = true,
+                InstClass::Store => effects.writes_memory = true,
+                InstClass::Atomic => {
+                    effects.reads_memory = true;
+                    effects.writes_memory = true;
+                }
+                InstClass::Branch | InstClass::System => effects.barrier = true,
+                InstClass::Fp | InstClass::Alu => {}
+            }
+        }
+        if inst.template() == &AUIPC {
+            effects.barrier = true;
+        }
+        effects
+    }
+
+    /// Whether the two instructions can be executed in either order without
+    /// changing the result of the program.
+    pub fn independent_of(&self, other: &InstEffects) -> bool {
+        if self.barrier || other.barrier {
+            return false;
+        }
+        let overlaps = |a: &[u32], b: &[u32]| a.iter().any(|reg| b.contains(reg));
+        // Read-after-write, write-after-read and write-after-write.
+        if overlaps(&self.writes, &other.reads)
+            || overlaps(&self.reads, &other.writes)
+            || overlaps(&self.writes, &other.writes)
+        {
+            return false;
+        }
+        let memory_conflict = (self.writes_memory && (other.reads_memory || other.writes_memory))
+            || (other.writes_memory && self.reads_memory);
+        !memory_conflict
+    }
+}
+
+/// Marks the positions that a branch or jal in the program jumps to.
+/// Targets outside of the program are ignored.
+fn branch_targets(program: &[Instruction]) -> Vec<bool> {
+    let mut targets = vec![false; program.len()];
+    for (pos, inst) in program.iter().enumerate() {
+        let offset = [&BRANCH_OFFSET, &JUMP_OFFSET]
+            .into_iter()
+            .find_map(|split| Some(sign_extend(split.extract(inst)?, split.length())));
+        let Some(offset) = offset else {
+            continue;
+        };
+        let target = pos as i64 + offset as i64 / 4;
+        if offset % 4 == 0 && (0..program.len() as i64).contains(&target) {
+            targets[target as usize] = true;
+        }
+    }
+    targets
+}
+
+/// Returns the range of positions the instruction at `pos` can be moved to
+/// without moving it across an instruction it depends on.
+/// Moving an instruction shifts the ones in between by one position, so it
+/// is also never moved across the target of a branch or jump, which would
+/// make the branch land on a different instruction.
+pub fn movable_range(program: &[Instruction], pos: usize) -> Range<usize> {
+    let effects: Vec<InstEffects> = program.iter().map(InstEffects::of).collect();
+    let targets = branch_targets(program);
+    let inst = &effects[pos];
+
+    let mut start = pos;
+    while start > 0 && !targets[start] && inst.independent_of(&effects[start - 1]) {
+        start -= 1;
+    }
+    let mut end = pos + 1;
+    while end < program.len() && !targets[end] && inst.independent_of(&effects[end]) {
+        end += 1;
+    }
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        instructions::{
+            riscv::{
+                args,
+                rv_d::{FADD_D, FSD},
+                rv_i::{ADD, BEQ, JAL, LW, SW},
+            },
+            split_immediates::{BRANCH_OFFSET, JUMP_OFFSET},
+            Argument, Instruction,
+        },
+        rewrites::truncate,
+    };
+
+    use super::{movable_range, InstEffects};
+
+    fn add(rd: u32, rs1: u32, rs2: u32) -> Instruction {
+        Instruction::new(
+            &ADD,
+            vec![
+                Argument::new(&args::RD, rd),
+                Argument::new(&args::RS1, rs1),
+                Argument::new(&args::RS2, rs2),
+            ],
+        )
+    }
+
+    fn lw(rd: u32, rs1: u32) -> Instruction {
+        Instruction::new(
+            &LW,
+            vec![
+                Argument::new(&args::RD, rd),
+                Argument::new(&args::RS1, rs1),
+                Argument::new(&args::IMM12, 0),
+            ],
+        )
+    }
+
+    fn sw(rs1: u32, rs2: u32) -> Instruction {
+        Instruction::new(
+            &SW,
+            vec![
+                Argument::new(&args::IMM12HI, 0),
+                Argument::new(&args::RS1, rs1),
+                Argument::new(&args::RS2, rs2),
+                Argument::new(&args::IMM12LO, 0),
+            ],
+        )
+    }
+
+    #[test]
+    fn register_dependencies() {
+        let effects = |inst: &Instruction| InstEffects::of(inst);
+        // Independent.
+        assert!(effects(&add(1, 2, 3)).independent_of(&effects(&add(4, 5, 6))));
+        // Read-after-write.
+        assert!(!effects(&add(1, 2, 3)).independent_of(&effects(&add(4, 1, 6))));
+        // Write-after-write.
+        assert!(!effects(&add(1, 2, 3)).independent_of(&effects(&add(1, 5, 6))));
+        // x0 never creates a dependency.
+        assert!(effects(&add(0, 2, 3)).independent_of(&effects(&add(4, 0, 0))));
+    }
+
+    #[test]
+    fn memory_dependencies() {
+        let effects = |inst: &Instruction| InstEffects::of(inst);
+        assert!(effects(&lw(1, 2)).independent_of(&effects(&lw(3, 4))));
+        assert!(!effects(&lw(1, 2)).independent_of(&effects(&sw(3, 4))));
+        assert!(!effects(&sw(1, 2)).independent_of(&effects(&sw(3, 4))));
+    }
+
+    #[test]
+    fn movable_ranges() {
+        let beq = Instruction::new(
+            &BEQ,
+            vec![
+                Argument::new(&args::BIMM12HI, 0),
+                Argument::new(&args::RS1, 1),
+                Argument::new(&args::RS2, 2),
+                Argument::new(&args::BIMM12LO, 0),
+            ],
+        );
+        let program = vec![beq, add(1, 2, 3), add(4, 5, 6), add(7, 8, 9), add(10, 1, 1)];
+        assert_eq!(movable_range(&program, 1), 1..4);
+        assert_eq!(movable_range(&program, 2), 1..5);
+        assert_eq!(movable_range(&program, 4), 2..5);
+
+        // f0 is no hardwired zero like x0.
+        let fadd = Instruction::new(
+            &FADD_D,
+            vec![
+                Argument::new(&args::RD, 0),
+                Argument::new(&args::RS1, 1),
+                Argument::new(&args::RS2, 2),
+                Argument::new(&args::RM, 0),
+            ],
+        );
+        let fsd = Instruction::new(
+            &FSD,
+            vec![
+                Argument::new(&args::IMM12HI, 0),
+                Argument::new(&args::RS1, 3),
+                Argument::new(&args::RS2, 0),
+                Argument::new(&args::IMM12LO, 0),
+            ],
+        );
+        let program = vec![fadd, add(4, 5, 6), fsd];
+        assert_eq!(movable_range(&program, 0), 0..2);
+        assert_eq!(movable_range(&program, 2), 1..3);
+    }
+
+    #[test]
+    fn movable_ranges_branch_targets() {
+        let mut beq = Instruction::new(
+            &BEQ,
+            vec![
+                Argument::new(&args::BIMM12HI, 0),
+                Argument::new(&args::RS1, 1),
+                Argument::new(&args::RS2, 2),
+                Argument::new(&args::BIMM12LO, 0),
+            ],
+        );
+        // Skips over the instruction at position 2.
+        BRANCH_OFFSET.apply(&mut beq, 8);
+        let program = vec![
+            add(3, 4, 5),
+            beq,
+            add(6, 7, 8),
+            add(9, 10, 11),
+            add(12, 13, 14),
+        ];
+        // Nothing moves across the branch itself.
+        assert_eq!(movable_range(&program, 0), 0..1);
+        assert_eq!(movable_range(&program, 2), 2..3);
+        // The branch target at position 3 can't be moved away from or over.
+        assert_eq!(movable_range(&program, 3), 3..5);
+        assert_eq!(movable_range(&program, 4), 3..5);
+
+        let mut jal = Instruction::new(
+            &JAL,
+            vec![Argument::new(&args::RD, 0), Argument::new(&args::JIMM20, 0)],
+        );
+        // Jumps back to position 1.
+        JUMP_OFFSET.apply(&mut jal, truncate(-12, JUMP_OFFSET.length()));
+        let program = vec![
+            add(3, 4, 5),
+            add(6, 7, 8),
+            add(9, 10, 11),
+            add(12, 13, 14),
+            jal,
+        ];
+        assert_eq!(movable_range(&program, 0), 0..1);
+        assert_eq!(movable_range(&program, 1), 1..4);
+        assert_eq!(movable_range(&program, 3), 1..4);
+    }
+}
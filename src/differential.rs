@@ -0,0 +1,293 @@
+//! Differential fuzzing against Spike (see `sim-fuzzer --spike`). Every
+//! input that runs through on the DUT is also run on Spike, and the final
+//! architectural states from both commit logs are compared (see
+//! `arch_state`). A divergence is reported like a harness cause
+//! ('Divergence_x5', 'Divergence_mem', ...) and makes the input an
+//! objective.
+//!
+//! The harness writes the commit log of the DUT in the format of `spike
+//! --log-commits` to the path in `FUZZING_COMMIT_LOG_VAR`. Inputs with a
+//! configuration header or memory image aren't compared because Spike can't
+//! apply them.
+
+use core::{fmt::Debug, marker::PhantomData};
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use libafl::{
+    events::{Event, EventFirer},
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::UsesInput,
+    monitors::UserStats,
+    observers::UsesObservers,
+    state::UsesState,
+    Error,
+};
+
+use crate::{
+    arch_state::{ArchState, Difference},
+    assembler::{assemble_instructions, code_to_elf, DEFAULT_ELF_ENTRY},
+    causes::{save_cause, FUZZING_CAUSE_DIR_VAR},
+    program_input::{epilogue, ProgramInput},
+};
+
+/// Environment variable with the path the harness writes the commit log of
+/// the DUT to.
+pub const FUZZING_COMMIT_LOG_VAR: &str = "FUZZING_COMMIT_LOG";
+
+// How often a running reference model is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Runs programs on Spike or another model that writes a commit log in the
+/// same format to stderr.
+#[derive(Clone, Debug)]
+pub struct SpikeModel {
+    command: Vec<String>,
+    timeout: Duration,
+}
+
+impl SpikeModel {
+    /// Parses a command line like 'spike --isa=rv64gc --log-commits @@'.
+    /// '@@' is replaced by the path to the ELF file with the program.
+    pub fn parse(command: &str, timeout: Duration) -> Result<SpikeModel, String> {
+        let command: Vec<String> = command.split_whitespace().map(str::to_string).collect();
+        if command.is_empty() {
+            return Err("Empty reference model command".to_string());
+        }
+        if !command.iter().any(|arg| arg == "@@") {
+            return Err("The reference model command needs a '@@' argument".to_string());
+        }
+        Ok(SpikeModel { command, timeout })
+    }
+
+    /// Runs the encoded instructions and returns the final state, or None
+    /// if the model timed out. The ELF file and log are kept in `work_dir`.
+    pub fn run(&self, code: &[u8], work_dir: &Path) -> Result<Option<ArchState>, String> {
+        let elf_path = work_dir.join("reference.elf");
+        let log_path = work_dir.join("reference.log");
+        fs::write(&elf_path, code_to_elf(code, DEFAULT_ELF_ENTRY))
+            .map_err(|e| format!("Failed to write {:?}: {}", elf_path, e))?;
+        let log = File::create(&log_path)
+            .map_err(|e| format!("Failed to create {:?}: {}", log_path, e))?;
+
+        let args = self.command[1..].iter().map(|arg| match arg.as_str() {
+            "@@" => elf_path.to_string_lossy().to_string(),
+            _ => arg.clone(),
+        });
+        let mut child = Command::new(&self.command[0])
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(log)
+            .spawn()
+            .map_err(|e| format!("Failed to start {}: {}", self.command[0], e))?;
+
+        let start = Instant::now();
+        loop {
+            match child.try_wait().map_err(|e| e.to_string())? {
+                Some(_) => break,
+                None if start.elapsed() >= self.timeout => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Ok(None);
+                }
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        }
+        let log = fs::read_to_string(&log_path)
+            .map_err(|e| format!("Failed to read {:?}: {}", log_path, e))?;
+        Ok(Some(ArchState::from_commit_log(&log)))
+    }
+}
+
+/// The cause name of a divergence (e.g. 'Divergence x5'). Memory
+/// divergences share one cause regardless of the address.
+pub fn divergence_cause(difference: &Difference) -> String {
+    let location = if difference.location.starts_with("mem ") {
+        "mem"
+    } else {
+        &difference.location
+    };
+    format!("Divergence {}", location)
+}
+
+/// The path in the work dir of a `DifferentialExecutor` that the harness
+/// should write the commit log of the DUT to.
+pub fn dut_log_path(work_dir: &Path) -> PathBuf {
+    work_dir.join("dut.log")
+}
+
+/// Executor that compares every run of the wrapped executor against a
+/// reference model. Without a model, it only runs the wrapped executor.
+pub struct DifferentialExecutor<E> {
+    executor: E,
+    model: Option<SpikeModel>,
+    work_dir: PathBuf,
+    divergences: u64,
+}
+
+impl<E> DifferentialExecutor<E> {
+    /// `work_dir` is private to this executor. It contains the commit logs
+    /// of both runs.
+    pub fn new(executor: E, model: Option<SpikeModel>, work_dir: PathBuf) -> Self {
+        Self {
+            executor,
+            model,
+            work_dir,
+            divergences: 0,
+        }
+    }
+
+    /// The number of divergences found so far.
+    pub fn divergences(&self) -> u64 {
+        self.divergences
+    }
+
+    /// Compares the finished run of the input against the model and returns
+    /// the first difference.
+    fn compare(&self, input: &ProgramInput) -> Option<Difference> {
+        let model = self.model.as_ref()?;
+        if input.config().is_some() || input.memory().is_some() {
+            return None;
+        }
+        // The harness didn't write a log (e.g. it stopped early).
+        let dut_log = fs::read_to_string(dut_log_path(&self.work_dir)).ok()?;
+        let mut code = assemble_instructions(input.insts());
+        code.extend_from_slice(epilogue());
+        let expected = match model.run(&code, &self.work_dir) {
+            Ok(state) => state?,
+            Err(err) => {
+                log::error!("Reference model failed: {}", err);
+                return None;
+            }
+        };
+        let differences = ArchState::from_commit_log(&dut_log).differences(&expected);
+        for difference in &differences {
+            log::info!("Divergence: {}", difference);
+        }
+        differences.into_iter().next()
+    }
+}
+
+/// Saves the input in the cause dir like `reportFuzzingIssue` in
+/// FuzzerAPI.h does.
+fn report_cause(cause: &str, input: &ProgramInput) -> Result<(), Error> {
+    let cause_dir = std::env::var(FUZZING_CAUSE_DIR_VAR)
+        .map_err(|_| Error::illegal_state("The cause dir isn't set"))?;
+    save_cause(Path::new(&cause_dir), cause, &input.to_bytes())?;
+    Ok(())
+}
+
+impl<E: Debug> Debug for DifferentialExecutor<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DifferentialExecutor")
+            .field("executor", &self.executor)
+            .field("model", &self.model)
+            .field("divergences", &self.divergences)
+            .finish()
+    }
+}
+
+impl<E> UsesState for DifferentialExecutor<E>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E> UsesObservers for DifferentialExecutor<E>
+where
+    E: UsesObservers,
+{
+    type Observers = E::Observers;
+}
+
+impl<E> HasObservers for DifferentialExecutor<E>
+where
+    E: HasObservers,
+{
+    fn observers(&self) -> &Self::Observers {
+        self.executor.observers()
+    }
+
+    fn observers_mut(&mut self) -> &mut Self::Observers {
+        self.executor.observers_mut()
+    }
+}
+
+impl<E, EM, Z> Executor<EM, Z> for DifferentialExecutor<E>
+where
+    E: Executor<EM, Z>,
+    E::State: UsesInput<Input = ProgramInput>,
+    EM: EventFirer<State = E::State>,
+    Z: UsesState<State = E::State>,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut Self::State,
+        mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        if self.model.is_none() {
+            return self.executor.run_target(fuzzer, state, mgr, input);
+        }
+
+        // A stale log would be compared against the wrong input.
+        let _ = fs::remove_file(dut_log_path(&self.work_dir));
+        let exit_kind = self.executor.run_target(fuzzer, state, mgr, input)?;
+        if exit_kind != ExitKind::Ok {
+            return Ok(exit_kind);
+        }
+        let Some(difference) = self.compare(input) else {
+            return Ok(exit_kind);
+        };
+
+        report_cause(&divergence_cause(&difference), input)?;
+        self.divergences += 1;
+        mgr.fire(
+            state,
+            Event::UpdateUserStats {
+                name: "divergences".to_string(),
+                value: UserStats::Number(self.divergences),
+                phantom: PhantomData,
+            },
+        )?;
+        // Makes the input an objective like a harness cause does.
+        Ok(ExitKind::Crash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{divergence_cause, SpikeModel};
+    use crate::arch_state::Difference;
+    use std::time::Duration;
+
+    #[test]
+    fn parse_model_command() {
+        let timeout = Duration::from_secs(1);
+        assert!(SpikeModel::parse("spike --isa=rv64gc --log-commits @@", timeout).is_ok());
+        assert!(SpikeModel::parse("spike --log-commits", timeout).is_err());
+        assert!(SpikeModel::parse(" ", timeout).is_err());
+    }
+
+    #[test]
+    fn divergence_causes() {
+        let difference = |location: &str| Difference {
+            location: location.to_string(),
+            expected: Some(1),
+            actual: None,
+        };
+        assert_eq!(divergence_cause(&difference("x5")), "Divergence x5");
+        assert_eq!(
+            divergence_cause(&difference("mem 0x80001000")),
+            "Divergence mem"
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Reads the executable code of RISC-V ELF files (e.g. riscv-tests or
+//! compiler output) to seed the corpus.
+
+use crate::{
+    assembler::EM_RISCV,
+    instructions::{self, Instruction},
+    parser::parse_instructions_lossy,
+};
+
+/// Code read from an ELF section (or segment if there are no sections).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ElfCode {
+    pub name: String,
+    pub addr: u64,
+    pub data: Vec<u8>,
+}
+
+const SHT_PROGBITS: u32 = 1;
+const SHF_EXECINSTR: u64 = 0x4;
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 0x1;
+
+/// Little endian reader for 32 and 64-bit ELF files.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    is_64: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn slice(&self, offset: u64, len: u64) -> Result<&'a [u8], String> {
+        let end = offset.checked_add(len).ok_or("ELF offset overflows")?;
+        if end > self.bytes.len() as u64 {
+            return Err(format!("ELF is truncated at offset {:#x}", offset));
+        }
+        Ok(&self.bytes[offset as usize..end as usize])
+    }
+
+    fn u16(&self, offset: u64) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(
+            self.slice(offset, 2)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u32(&self, offset: u64) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(
+            self.slice(offset, 4)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u64(&self, offset: u64) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(
+            self.slice(offset, 8)?.try_into().unwrap(),
+        ))
+    }
+
+    /// Reads an address-sized field.
+    fn addr(&self, offset: u64) -> Result<u64, String> {
+        if self.is_64 {
+            self.u64(offset)
+        } else {
+            self.u32(offset).map(u64::from)
+        }
+    }
+
+    fn string(&self, offset: u64) -> Result<String, String> {
+        let rest = self.slice(
+            offset,
+            self.bytes.len() as u64 - offset.min(self.bytes.len() as u64),
+        )?;
+        let end = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+        Ok(String::from_utf8_lossy(&rest[..end]).to_string())
+    }
+}
+
+/// Returns the executable sections of a little endian RISC-V ELF file. Files
+/// without section headers return their executable segments instead.
+pub fn executable_code(bytes: &[u8]) -> Result<Vec<ElfCode>, String> {
+    if bytes.len() < 20 || &bytes[0..4] != b"\x7fELF" {
+        return Err("Not an ELF file".to_string());
+    }
+    let is_64 = match bytes[4] {
+        1 => false,
+        2 => true,
+        class => return Err(format!("Unknown ELF class {}", class)),
+    };
+    if bytes[5] != 1 {
+        return Err("Only little endian ELF files are supported".to_string());
+    }
+    let reader = Reader { bytes, is_64 };
+    let machine = reader.u16(18)?;
+    if machine != EM_RISCV {
+        return Err(format!("Not a RISC-V ELF file (machine {})", machine));
+    }
+
+    // The offsets of the header fields after e_entry depend on the class.
+    let (phoff, shoff, sizes) = if is_64 {
+        (reader.u64(32)?, reader.u64(40)?, 52)
+    } else {
+        (reader.u32(28)? as u64, reader.u32(32)? as u64, 40)
+    };
+    let phentsize = reader.u16(sizes + 2)? as u64;
+    let phnum = reader.u16(sizes + 4)? as u64;
+    let shentsize = reader.u16(sizes + 6)? as u64;
+    let shnum = reader.u16(sizes + 8)? as u64;
+    let shstrndx = reader.u16(sizes + 10)? as u64;
+
+    let mut result = Vec::<ElfCode>::new();
+    if shnum > 0 {
+        let section = |idx: u64| shoff + idx * shentsize;
+        // sh_offset of the section name table.
+        let names_offset = reader.addr(section(shstrndx) + if is_64 { 24 } else { 16 })?;
+        for idx in 0..shnum {
+            let header = section(idx);
+            let kind = reader.u32(header + 4)?;
+            let (flags, addr, offset, size) = if is_64 {
+                (
+                    reader.u64(header + 8)?,
+                    reader.u64(header + 16)?,
+                    reader.u64(header + 24)?,
+                    reader.u64(header + 32)?,
+                )
+            } else {
+                (
+                    reader.u32(header + 8)? as u64,
+                    reader.u32(header + 12)? as u64,
+                    reader.u32(header + 16)? as u64,
+                    reader.u32(header + 20)? as u64,
+                )
+            };
+            if kind != SHT_PROGBITS || flags & SHF_EXECINSTR == 0 {
+                continue;
+            }
+            let name = reader.string(names_offset + reader.u32(header)? as u64)?;
+            let data = reader.slice(offset, size)?.to_vec();
+            result.push(ElfCode { name, addr, data });
+        }
+        return Ok(result);
+    }
+
+    for idx in 0..phnum {
+        let header = phoff + idx * phentsize;
+        let kind = reader.u32(header)?;
+        let (flags, offset, addr, size) = if is_64 {
+            (
+                reader.u32(header + 4)?,
+                reader.u64(header + 8)?,
+                reader.u64(header + 16)?,
+                reader.u64(header + 32)?,
+            )
+        } else {
+            (
+                reader.u32(header + 24)?,
+                reader.u32(header + 4)? as u64,
+                reader.u32(header + 8)? as u64,
+                reader.u32(header + 16)? as u64,
+            )
+        };
+        if kind != PT_LOAD || flags & PF_X == 0 {
+            continue;
+        }
+        let data = reader.slice(offset, size)?.to_vec();
+        result.push(ElfCode {
+            name: format!("segment{}", idx),
+            addr,
+            data,
+        });
+    }
+    Ok(result)
+}
+
+/// Decodes the code into programs. Words that aren't known instructions
+/// (e.g. data or compressed instructions) split the code, and programs
+/// shorter than `min_len` instructions are dropped.
+pub fn decode_code(data: &[u8], min_len: usize) -> Vec<Vec<Instruction>> {
+    let (insts, skipped) = parse_instructions_lossy(data, instructions::riscv::all());
+    let mut result = Vec::<Vec<Instruction>>::new();
+    let mut start = 0;
+    let ends = skipped
+        .iter()
+        .map(|word| word.position)
+        .chain([insts.len()]);
+    for end in ends {
+        if end - start >= min_len.max(1) {
+            result.push(insts[start..end].to_vec());
+        }
+        start = end;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assembler::assemble_elf,
+        instructions::{
+            riscv::{args, rv_i::ADDI},
+            Argument, Instruction,
+        },
+    };
+
+    use super::{decode_code, executable_code};
+
+    fn addi(imm: u32) -> Instruction {
+        Instruction::new(
+            &ADDI,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 1),
+                Argument::new(&args::IMM12, imm),
+            ],
+        )
+    }
+
+    #[test]
+    fn read_elf() {
+        let program = vec![addi(1), addi(2), addi(3)];
+        let elf = assemble_elf(&program, 0x8000_0000);
+        let code = executable_code(&elf).unwrap();
+        assert_eq!(code.len(), 1);
+        assert_eq!(code[0].name, ".text");
+        assert_eq!(code[0].addr, 0x8000_0000);
+        assert_eq!(decode_code(&code[0].data, 1), vec![program]);
+
+        assert!(executable_code(b"not an elf file at all").is_err());
+        assert!(executable_code(&elf[..100]).is_err());
+    }
+
+    #[test]
+    fn split_at_unknown_words() {
+        let mut data = Vec::<u8>::new();
+        for inst in [addi(1), addi(2)] {
+            data.extend(inst.encode().to_le_bytes());
+        }
+        // An all-zero word is not a valid instruction.
+        data.extend([0; 4]);
+        data.extend(addi(3).encode().to_le_bytes());
+        // A trailing half word.
+        data.extend([0x13, 0x00]);
+
+        assert_eq!(
+            decode_code(&data, 1),
+            vec![vec![addi(1), addi(2)], vec![addi(3)]]
+        );
+        assert_eq!(decode_code(&data, 2), vec![vec![addi(1), addi(2)]]);
+    }
+}
@@ -0,0 +1,201 @@
+//! Reading the results of fuzzing campaigns and comparing two sets of trials
+//! with the Mann-Whitney U test and the Vargha-Delaney A12 effect size.
+
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::causes::read_causes;
+
+/// Parses the 'iterations_time' log of the monitor into (seconds, coverage)
+/// samples.
+pub fn parse_iterations_log(text: &str) -> Vec<(u64, u64)> {
+    text.lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let secs = parts.next()?.parse::<u64>().ok()?;
+            // Skip the execs and corpus size.
+            let coverage = parts.nth(2)?.parse::<u64>().ok()?;
+            Some((secs, coverage))
+        })
+        .collect()
+}
+
+/// The coverage at the given time, i.e. of the last sample taken before.
+pub fn coverage_at(samples: &[(u64, u64)], secs: u64) -> u64 {
+    samples
+        .iter()
+        .filter(|(time, _)| *time <= secs)
+        .map(|(_, coverage)| *coverage)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The time to exposure of every cause found in a fuzzer output directory,
+/// relative to the start time marker of the campaign.
+pub fn times_to_exposure(out_dir: &Path) -> Result<Vec<(String, Duration)>, String> {
+    let modified = |path: &Path| -> Result<SystemTime, String> {
+        fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .map_err(|e| format!("Failed to read {:?}: {}", path, e))
+    };
+    let start = modified(&out_dir.join("start_time_marker"))?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let cause_dir = out_dir.join("causes");
+    if !cause_dir.is_dir() {
+        return Err(format!("Failed to read {:?}: Not a directory", cause_dir));
+    }
+
+    let mut result = Vec::<(String, Duration)>::new();
+    for (_, record) in read_causes(&cause_dir) {
+        let tte = record.time_since(start);
+        // Only the first exposure of a cause counts.
+        match result.iter_mut().find(|(c, _)| *c == record.cause) {
+            Some(existing) => existing.1 = existing.1.min(tte),
+            None => result.push((record.cause, tte)),
+        }
+    }
+    result.sort();
+    Ok(result)
+}
+
+pub fn median(samples: &[f64]) -> f64 {
+    if samples.is_empty() {
+        return f64::NAN;
+    }
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 1 {
+        sorted[mid]
+    } else {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    }
+}
+
+/// The Vargha-Delaney A12 statistic: the probability that a sample of `a`
+/// is larger than one of `b` (ties count half).
+pub fn a12(a: &[f64], b: &[f64]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return f64::NAN;
+    }
+    let mut wins = 0.0;
+    for x in a {
+        for y in b {
+            if x > y {
+                wins += 1.0;
+            } else if x == y {
+                wins += 0.5;
+            }
+        }
+    }
+    wins / (a.len() * b.len()) as f64
+}
+
+/// Result of a two-sided Mann-Whitney U test.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MannWhitney {
+    /// The U statistic of the first sample.
+    pub u: f64,
+    /// The p-value from the normal approximation with tie and continuity
+    /// correction.
+    pub p: f64,
+}
+
+pub fn mann_whitney_u(a: &[f64], b: &[f64]) -> MannWhitney {
+    let (n1, n2) = (a.len() as f64, b.len() as f64);
+    let u = a12(a, b) * n1 * n2;
+    if a.is_empty() || b.is_empty() {
+        return MannWhitney { u, p: 1.0 };
+    }
+
+    // Ties reduce the variance of U.
+    let mut all: Vec<f64> = a.iter().chain(b).copied().collect();
+    all.sort_by(|x, y| x.total_cmp(y));
+    let n = n1 + n2;
+    let mut tie_sum = 0.0;
+    let mut idx = 0;
+    while idx < all.len() {
+        let run = all[idx..].iter().take_while(|v| **v == all[idx]).count() as f64;
+        tie_sum += run * run * run - run;
+        idx += run as usize;
+    }
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - tie_sum / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        return MannWhitney { u, p: 1.0 };
+    }
+
+    let mean = n1 * n2 / 2.0;
+    let z = ((u - mean).abs() - 0.5).max(0.0) / variance.sqrt();
+    let p = libm::erfc(z / std::f64::consts::SQRT_2).min(1.0);
+    MannWhitney { u, p }
+}
+
+/// The comparison of one metric between two configurations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    pub metric: String,
+    pub median_a: f64,
+    pub median_b: f64,
+    pub test: MannWhitney,
+    pub a12: f64,
+}
+
+impl Comparison {
+    pub fn new(metric: &str, a: &[f64], b: &[f64]) -> Self {
+        Self {
+            metric: metric.to_string(),
+            median_a: median(a),
+            median_b: median(b),
+            test: mann_whitney_u(a, b),
+            a12: a12(a, b),
+        }
+    }
+
+    pub const HEADER: &'static str = "metric\tmedian_a\tmedian_b\tu\tp\ta12";
+
+    /// One tab-separated line in the order of `HEADER`.
+    pub fn to_line(&self) -> String {
+        format!(
+            "{}\t{:.1}\t{:.1}\t{:.1}\t{:.4}\t{:.3}",
+            self.metric, self.median_a, self.median_b, self.test.u, self.test.p, self.a12
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{a12, coverage_at, mann_whitney_u, median, parse_iterations_log};
+
+    #[test]
+    fn read_iterations_log() {
+        let samples =
+            parse_iterations_log("10 500 3 120 4096 0:500:120\n30 1000 5 180 4096\nbad\n");
+        assert_eq!(samples, vec![(10, 120), (30, 180)]);
+        assert_eq!(coverage_at(&samples, 5), 0);
+        assert_eq!(coverage_at(&samples, 29), 120);
+        assert_eq!(coverage_at(&samples, 60), 180);
+    }
+
+    #[test]
+    fn statistics() {
+        assert_eq!(median(&[3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&[4.0, 1.0, 2.0, 3.0]), 2.5);
+
+        let a = [10.0, 11.0, 12.0, 13.0, 14.0];
+        let b = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(a12(&a, &b), 1.0);
+        assert_eq!(a12(&b, &a), 0.0);
+        assert_eq!(a12(&a, &a), 0.5);
+
+        let test = mann_whitney_u(&a, &b);
+        assert_eq!(test.u, 25.0);
+        // scipy.stats.mannwhitneyu(a, b, method="asymptotic") gives 0.0122.
+        assert!((test.p - 0.0122).abs() < 0.0005, "{}", test.p);
+        assert_eq!(mann_whitney_u(&a, &a).p, 1.0);
+        assert_eq!(mann_whitney_u(&[1.0, 1.0], &[1.0]).p, 1.0);
+    }
+}
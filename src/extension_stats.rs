@@ -0,0 +1,298 @@
+//! Attributes the coverage growth of a campaign to the ISA extensions of the
+//! inputs that found it, to show which extensions are worth more fuzzing.
+
+use core::{fmt::Debug, marker::PhantomData};
+use std::{collections::HashMap, fs::OpenOptions, io::Write, path::PathBuf, time::SystemTime};
+
+use libafl::{
+    bolts::tuples::Named,
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::{Feedback, MapFeedbackMetadata},
+    inputs::UsesInput,
+    observers::ObserversTuple,
+    state::{HasClientPerfMonitor, HasNamedMetadata},
+    Error,
+};
+
+use crate::{
+    instructions::{sets, Instruction},
+    program_input::ProgramInput,
+};
+
+/// The name of the directory in the out dir with the growth logs.
+pub const EXTENSION_STATS_DIR: &str = "extension_stats";
+
+/// A corpus entry with new coverage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrowthEvent {
+    /// When the entry was found (seconds since the UNIX epoch).
+    pub time: u64,
+    /// The number of map entries the entry covered first.
+    pub growth: u64,
+    /// The extensions of the instructions of the entry.
+    pub extensions: Vec<String>,
+}
+
+impl GrowthEvent {
+    /// Formats the event as a 'TIME GROWTH EXT,EXT,...' line.
+    pub fn to_line(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.time,
+            self.growth,
+            self.extensions.join(",")
+        )
+    }
+
+    /// Parses the output of `to_line`.
+    pub fn parse_line(line: &str) -> Option<GrowthEvent> {
+        let mut parts = line.split_whitespace();
+        let time = parts.next()?.parse::<u64>().ok()?;
+        let growth = parts.next()?.parse::<u64>().ok()?;
+        let extensions = parts
+            .next()
+            .unwrap_or_default()
+            .split(',')
+            .filter(|ext| !ext.is_empty())
+            .map(|ext| ext.to_string())
+            .collect();
+        Some(GrowthEvent {
+            time,
+            growth,
+            extensions,
+        })
+    }
+}
+
+/// Parses a growth log, skipping malformed (e.g. partially written) lines.
+pub fn parse_growth_log(text: &str) -> Vec<GrowthEvent> {
+    text.lines().filter_map(GrowthEvent::parse_line).collect()
+}
+
+/// The sorted extensions of the instructions in the program.
+pub fn program_extensions(insts: &[Instruction]) -> Vec<&'static str> {
+    let mut result: Vec<&'static str> = insts
+        .iter()
+        .filter_map(|inst| sets::extension_of(inst.template()))
+        .collect();
+    result.sort();
+    result.dedup();
+    result
+}
+
+/// The coverage growth attributed to one extension.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attribution {
+    pub extension: String,
+    /// The number of entries with new coverage that used the extension.
+    pub entries: u64,
+    /// The growth of these entries, shared equally between the extensions of
+    /// every entry.
+    pub growth: f64,
+}
+
+/// Attributes the growth of the events until the given time to their
+/// extensions, sorted by decreasing growth.
+pub fn attribute(events: &[GrowthEvent], until: u64) -> Vec<Attribution> {
+    let mut result = Vec::<Attribution>::new();
+    for event in events.iter().filter(|event| event.time <= until) {
+        let share = event.growth as f64 / event.extensions.len().max(1) as f64;
+        for extension in &event.extensions {
+            let idx = match result.iter().position(|a| a.extension == *extension) {
+                Some(idx) => idx,
+                None => {
+                    result.push(Attribution {
+                        extension: extension.clone(),
+                        entries: 0,
+                        growth: 0.0,
+                    });
+                    result.len() - 1
+                }
+            };
+            result[idx].entries += 1;
+            result[idx].growth += share;
+        }
+    }
+    result.sort_by(|a, b| {
+        b.growth
+            .total_cmp(&a.growth)
+            .then(a.extension.cmp(&b.extension))
+    });
+    result
+}
+
+/// Feedback that is never interesting by itself, but tags every execution
+/// with the extensions of its input and logs how much coverage new corpus
+/// entries added (according to the history of a `MapFeedback`) to a file in
+/// the given directory. The coverage before the first entry (the seed, or
+/// that restored from previous campaigns) is not attributed.
+#[derive(Clone, Debug)]
+pub struct ExtensionStatsFeedback<S> {
+    map_feedback: String,
+    path: PathBuf,
+    // The extension of every instruction, by mnemonic.
+    extensions: HashMap<&'static str, &'static str>,
+    // The extensions of the last executed input.
+    last: Vec<&'static str>,
+    // The number of covered map entries after the last corpus entry.
+    covered: Option<u64>,
+    phantom: PhantomData<S>,
+}
+
+impl<S> ExtensionStatsFeedback<S> {
+    #[must_use]
+    pub fn new<F: Named>(map_feedback: &F, dir: PathBuf) -> Self {
+        let mut extensions = HashMap::new();
+        for (extension, insts) in sets::extensions() {
+            for inst in insts {
+                extensions.insert(inst.name(), *extension);
+            }
+        }
+        Self {
+            map_feedback: map_feedback.name().to_string(),
+            path: dir.join(format!("{}", std::process::id())),
+            extensions,
+            last: Vec::new(),
+            covered: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for ExtensionStatsFeedback<S> {
+    fn name(&self) -> &str {
+        "ExtensionStatsFeedback"
+    }
+}
+
+impl<S> Feedback<S> for ExtensionStatsFeedback<S>
+where
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor + HasNamedMetadata + Debug,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        input: &ProgramInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.last.clear();
+        for inst in input.insts() {
+            if let Some(extension) = self.extensions.get(inst.template().name()) {
+                if !self.last.contains(extension) {
+                    self.last.push(extension);
+                }
+            }
+        }
+        self.last.sort();
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        let Some(history) = state
+            .named_metadata()
+            .get::<MapFeedbackMetadata<u8>>(&self.map_feedback)
+        else {
+            return Ok(());
+        };
+        let covered = history.history_map.iter().filter(|v| **v != 0).count() as u64;
+        let Some(last_covered) = self.covered.replace(covered) else {
+            return Ok(());
+        };
+        let growth = covered.saturating_sub(last_covered);
+        if growth == 0 {
+            return Ok(());
+        }
+
+        let event = GrowthEvent {
+            time: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            growth,
+            extensions: self.last.iter().map(|ext| ext.to_string()).collect(),
+        };
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", event.to_line()));
+        if let Err(err) = written {
+            log::warn!("Failed to write {:?}: {}", self.path, err);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instructions::{
+        riscv::{
+            args,
+            rv64_m::MULW,
+            rv_i::{ADDI, FENCE},
+        },
+        Argument, Instruction,
+    };
+
+    use super::{attribute, parse_growth_log, program_extensions, GrowthEvent};
+
+    #[test]
+    fn growth_log() {
+        let event = GrowthEvent {
+            time: 100,
+            growth: 12,
+            extensions: vec!["I".to_string(), "M".to_string()],
+        };
+        assert_eq!(GrowthEvent::parse_line(&event.to_line()), Some(event));
+        let events = parse_growth_log("1 2 I\nbroken\n3 4\n5 6 I,A");
+        assert_eq!(events.len(), 3);
+        assert!(events[1].extensions.is_empty());
+    }
+
+    #[test]
+    fn attribute_growth() {
+        let events = parse_growth_log("10 8 I\n20 6 I,M\n30 3 A\n");
+        let at_20 = attribute(&events, 20);
+        assert_eq!(at_20.len(), 2);
+        assert_eq!((at_20[0].extension.as_str(), at_20[0].entries), ("I", 2));
+        assert_eq!(at_20[0].growth, 11.0);
+        assert_eq!(at_20[1].growth, 3.0);
+        assert_eq!(attribute(&events, 30).len(), 3);
+        assert!(attribute(&events, 5).is_empty());
+    }
+
+    #[test]
+    fn extensions_of_program() {
+        let addi = Instruction::new(
+            &ADDI,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 1),
+                Argument::new(&args::IMM12, 1),
+            ],
+        );
+        let mulw = Instruction::new(
+            &MULW,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 1),
+                Argument::new(&args::RS2, 1),
+            ],
+        );
+        assert_eq!(program_extensions(&[mulw, addi.clone(), addi]), ["I", "M"]);
+        assert_eq!(program_extensions(&[]), Vec::<&str>::new());
+        assert_eq!(crate::instructions::sets::extension_of(&FENCE), Some("I"));
+    }
+}
@@ -1,12 +1,14 @@
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use libafl::prelude::{current_time, format_duration_hms};
 use std::{
     collections::{HashMap, HashSet, VecDeque},
+    fs,
     io::{self, Stdout},
+    path::Path,
     time::{Duration, Instant},
 };
 use tui::{
@@ -15,14 +17,159 @@ use tui::{
     style::{Color, Modifier, Style},
     symbols,
     text::Span,
-    widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, List, ListItem},
+    widgets::{
+        Axis, Block, Borders, Cell, Chart, Dataset, GraphType, List, ListItem, ListState,
+        Paragraph, Row, Table,
+    },
     Frame, Terminal,
 };
 
-use crate::causes::list_causes;
+use crate::{
+    causes::{list_causes, smallest_inputs, FUZZING_CAUSE_DIR_VAR},
+    objdump::format_objdump_line,
+    program_input::ProgramInput,
+    registers::RegisterStyle,
+};
 
 // Every nth corpus increase that should be logged.
 const EVERY_N_CORPUS: u64 = 1000;
+// The maximum number of points kept per chart.
+const MAX_CHART_POINTS: usize = 1000;
+// The minimum time between two points of the execs/sec chart.
+const EXECS_PER_SEC_INTERVAL: f64 = 1.0;
+
+/// The time series the main chart can show. Cycled with 'c'.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChartKind {
+    Coverage,
+    ExecsPerSec,
+    CorpusSize,
+}
+
+impl ChartKind {
+    /// The chart shown after this one.
+    pub fn next(self) -> ChartKind {
+        match self {
+            ChartKind::Coverage => ChartKind::ExecsPerSec,
+            ChartKind::ExecsPerSec => ChartKind::CorpusSize,
+            ChartKind::CorpusSize => ChartKind::Coverage,
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            ChartKind::Coverage => "Coverage",
+            ChartKind::ExecsPerSec => "Execs/sec",
+            ChartKind::CorpusSize => "Corpus size",
+        }
+    }
+}
+
+/// Something the user asked for that the monitor has to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UiAction {
+    /// Stop the campaign like an exhausted budget does.
+    Quit,
+    /// Write a stats record and the summary now.
+    Snapshot,
+}
+
+/// The panels that can be hidden to make room for the others. The main
+/// chart is always shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Panels {
+    messages: bool,
+    clients: bool,
+    findings: bool,
+    regions: bool,
+    find_times: bool,
+}
+
+impl Default for Panels {
+    fn default() -> Self {
+        Self {
+            messages: true,
+            clients: true,
+            findings: true,
+            regions: true,
+            find_times: true,
+        }
+    }
+}
+
+/// The smallest input of a cause, disassembled.
+struct CauseDetail {
+    title: String,
+    lines: Vec<String>,
+}
+
+impl CauseDetail {
+    fn load(cause: &str) -> CauseDetail {
+        let cause_dir = std::env::var(FUZZING_CAUSE_DIR_VAR).unwrap_or_default();
+        let Some(path) = smallest_inputs(Path::new(&cause_dir)).remove(cause) else {
+            return CauseDetail {
+                title: cause.to_string(),
+                lines: vec!["No input found".to_string()],
+            };
+        };
+        let title = format!("{} ({})", cause, path.display());
+        let input = match fs::read(&path) {
+            Ok(bytes) => ProgramInput::from_bytes_with(&bytes, true),
+            Err(err) => Err(err.to_string()),
+        };
+        let input = match input {
+            Ok(input) => input,
+            Err(err) => {
+                return CauseDetail {
+                    title,
+                    lines: vec![format!("Failed to decode the input: {}", err)],
+                }
+            }
+        };
+
+        let mut lines = Vec::new();
+        if input.config().is_some() {
+            lines.push("(with configuration header)".to_string());
+        }
+        if let Some(memory) = input.memory() {
+            lines.push(format!(
+                "(with {} byte memory image at {:#x})",
+                memory.bytes.len(),
+                memory.address
+            ));
+        }
+        for (idx, inst) in input.insts().iter().enumerate() {
+            let line = format_objdump_line(inst, idx as u64 * 4, RegisterStyle::Abi);
+            // Tabs aren't expanded by the terminal backend.
+            lines.push(line.replace('\t', "  "));
+        }
+        CauseDetail { title, lines }
+    }
+}
+
+/// What the user chose to see.
+#[derive(Default)]
+struct View {
+    // While paused, the screen is only redrawn after key presses.
+    paused: bool,
+    panels: Panels,
+    // The index of the selected cause among the found causes.
+    selected_finding: usize,
+    // Shown instead of the top panels if set.
+    detail: Option<CauseDetail>,
+}
+
+// The key bindings shown at the bottom of the screen.
+const HELP: &str = "q: quit  p: pause  s: snapshot  c: next chart  \
+m/l/f/r/t: toggle messages/clients/findings/regions/find times  up/down/enter: inspect finding";
+
+// Appends the point, dropping the oldest one if the series is full.
+fn push_point(series: &mut Vec<(f64, f64)>, point: (f64, f64)) {
+    if series.len() >= MAX_CHART_POINTS {
+        series.remove(0);
+    }
+    series.push(point);
+}
 
 pub struct TimeData {
     // The time when this data point was created.
@@ -33,12 +180,41 @@ pub struct TimeData {
     rel_time: f64,
 }
 
+/// The latest stats of a single fuzzing client.
+pub struct ClientRow {
+    pub id: u32,
+    pub execs: u64,
+    pub execs_per_sec: String,
+    pub corpus_size: u64,
+    pub objective_size: u64,
+    pub coverage: u64,
+    /// How long ago the client last found new coverage.
+    pub since_new_coverage: Option<Duration>,
+    /// Whether the client stopped executing inputs (e.g. it hangs or died).
+    pub stalled: bool,
+}
+
+/// The coverage of a named region of the coverage map.
+pub struct RegionRow {
+    pub name: String,
+    pub covered: u64,
+    pub len: u64,
+}
+
 pub struct FuzzUIData {
     pub max_coverage: Vec<(f64, f64)>,
+    /// Total execs/sec over time as (seconds since start, execs/sec).
+    pub execs_per_sec: Vec<(f64, f64)>,
+    /// Total corpus size over time as (seconds since start, entries).
+    pub corpus_sizes: Vec<(f64, f64)>,
     pub time_since_last_find: Vec<TimeData>,
     time_since_last_find_group: f64,
     start_time: std::time::Duration,
     messages: VecDeque<String>,
+    clients: Vec<ClientRow>,
+    regions: Vec<RegionRow>,
+    // The series shown in the main chart.
+    chart: ChartKind,
 }
 
 impl FuzzUIData {
@@ -48,14 +224,18 @@ impl FuzzUIData {
 
     pub fn add_max_coverage(&mut self, value: f64) {
         if self.max_coverage.is_empty() || self.max_coverage.last().unwrap().1 < value {
-            self.max_coverage.push((self.rel_time_secs(), value))
+            let time = self.rel_time_secs();
+            // The full history is in the coverage file (see `stats`).
+            push_point(&mut self.max_coverage, (time, value));
         }
-        // Only keep the last 200 messages as we won't be able to display
-        // more than that with any reasonable terminal size.
-        self.max_coverage.shrink_to(200);
     }
 
     pub fn add_corpus_size(&mut self, corpus_size: u64) {
+        let time = self.rel_time_secs();
+        if self.corpus_sizes.last().map(|p| p.1) != Some(corpus_size as f64) {
+            push_point(&mut self.corpus_sizes, (time, corpus_size as f64));
+        }
+
         // Keep track how long it took us to find the newest corpus item.
         let last = self.time_since_last_find.last().unwrap();
         let time = self.rel_time_secs();
@@ -75,10 +255,29 @@ impl FuzzUIData {
         });
     }
 
+    pub fn add_execs_per_sec(&mut self, value: f64) {
+        // Sampled as it changes with every event.
+        let time = self.rel_time_secs();
+        match self.execs_per_sec.last() {
+            Some(last) if time - last.0 < EXECS_PER_SEC_INTERVAL => {}
+            _ => push_point(&mut self.execs_per_sec, (time, value)),
+        }
+    }
+
     pub fn add_message(&mut self, value: String) {
         self.messages.push_front(value);
     }
 
+    /// Replaces the per-client stats with the given rows.
+    pub fn set_clients(&mut self, rows: Vec<ClientRow>) {
+        self.clients = rows;
+    }
+
+    /// Replaces the per-region coverage with the given rows.
+    pub fn set_regions(&mut self, rows: Vec<RegionRow>) {
+        self.regions = rows;
+    }
+
     fn rel_time_secs(&self) -> f64 {
         (current_time() - self.start_time).as_secs_f64()
     }
@@ -88,16 +287,24 @@ pub struct FuzzUI {
     terminal: Option<Terminal<CrosstermBackend<Stdout>>>,
     last_tick: Instant,
     data: FuzzUIData,
+    view: View,
+    // The actions requested since the monitor last asked.
+    actions: Vec<UiAction>,
 }
 
 impl FuzzUI {
     pub fn new(simple_ui: bool) -> FuzzUI {
         let mut data = FuzzUIData {
             max_coverage: Vec::<(f64, f64)>::new(),
+            execs_per_sec: Vec::<(f64, f64)>::new(),
+            corpus_sizes: Vec::<(f64, f64)>::new(),
             time_since_last_find: Vec::<TimeData>::new(),
             time_since_last_find_group: 0.0,
             start_time: current_time(),
             messages: VecDeque::<String>::new(),
+            clients: Vec::<ClientRow>::new(),
+            regions: Vec::<RegionRow>::new(),
+            chart: ChartKind::Coverage,
         };
         data.time_since_last_find.push(TimeData {
             time: 0.0,
@@ -117,12 +324,16 @@ impl FuzzUI {
                 terminal: Some(terminal),
                 last_tick: Instant::now(),
                 data,
+                view: View::default(),
+                actions: Vec::new(),
             }
         } else {
             FuzzUI {
                 terminal: None,
                 last_tick: Instant::now(),
                 data,
+                view: View::default(),
+                actions: Vec::new(),
             }
         }
     }
@@ -131,9 +342,22 @@ impl FuzzUI {
         &mut self.data
     }
 
-    fn on_tick(&mut self) {
+    /// Returns the actions the user requested since the last call.
+    pub fn take_actions(&mut self) -> Vec<UiAction> {
+        std::mem::take(&mut self.actions)
+    }
+
+    fn draw(&mut self) {
         if let Some(term) = self.terminal.as_mut() {
-            term.draw(|f| ui(f, &self.data)).unwrap();
+            term.draw(|f| ui(f, &self.data, &self.view)).unwrap();
+        }
+    }
+
+    fn on_tick(&mut self) {
+        if self.terminal.is_some() {
+            if !self.view.paused {
+                self.draw();
+            }
         } else {
             if !self.data.messages.is_empty() {
                 println!("{}", self.data.messages.front().unwrap());
@@ -141,20 +365,68 @@ impl FuzzUI {
         }
     }
 
+    // Handles the keys pressed since the last tick.
+    fn handle_events(&mut self) {
+        if self.terminal.is_none() {
+            return;
+        }
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            let Ok(Event::Key(key)) = event::read() else {
+                continue;
+            };
+            // The raw mode swallows the SIGINT of Ctrl-C.
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+            let view = &mut self.view;
+            let panels = &mut view.panels;
+            match key.code {
+                KeyCode::Char('c') if ctrl => self.actions.push(UiAction::Quit),
+                KeyCode::Char('q') => self.actions.push(UiAction::Quit),
+                KeyCode::Char('s') => {
+                    self.actions.push(UiAction::Snapshot);
+                    self.data.add_message("Writing a snapshot".to_string());
+                }
+                KeyCode::Char('p') => view.paused = !view.paused,
+                KeyCode::Char('c') => self.data.chart = self.data.chart.next(),
+                KeyCode::Char('m') => panels.messages = !panels.messages,
+                KeyCode::Char('l') => panels.clients = !panels.clients,
+                KeyCode::Char('f') => panels.findings = !panels.findings,
+                KeyCode::Char('r') => panels.regions = !panels.regions,
+                KeyCode::Char('t') => panels.find_times = !panels.find_times,
+                KeyCode::Up => view.selected_finding = view.selected_finding.saturating_sub(1),
+                KeyCode::Down => {
+                    let found = found_causes(&self.data).len();
+                    view.selected_finding = (view.selected_finding + 1).min(found.max(1) - 1);
+                }
+                KeyCode::Enter => {
+                    view.detail = match view.detail {
+                        Some(_) => None,
+                        None => found_causes(&self.data)
+                            .get(view.selected_finding)
+                            .map(|cause| CauseDetail::load(cause)),
+                    }
+                }
+                KeyCode::Esc => view.detail = None,
+                _ => continue,
+            }
+            // Also shows the effect of the key while paused.
+            self.draw();
+        }
+    }
+
     pub fn try_tick(&mut self) {
         let tick_rate = Duration::from_millis(250);
 
         if self.last_tick.elapsed() >= tick_rate {
+            self.handle_events();
             self.on_tick();
             self.last_tick = Instant::now();
         }
     }
-}
 
-impl Drop for FuzzUI {
-    fn drop(&mut self) {
-        if let Some(term) = self.terminal.as_mut() {
-            // restore terminal
+    /// Leaves the full-screen UI and restores the terminal, e.g. before the
+    /// process exits. Afterwards, messages are printed like in the simple UI.
+    pub fn restore_terminal(&mut self) {
+        if let Some(mut term) = self.terminal.take() {
             disable_raw_mode().unwrap();
             execute!(
                 term.backend_mut(),
@@ -167,6 +439,23 @@ impl Drop for FuzzUI {
     }
 }
 
+impl Drop for FuzzUI {
+    fn drop(&mut self) {
+        self.restore_terminal();
+    }
+}
+
+// The found causes in the order of `summarize_findings`.
+fn found_causes(data: &FuzzUIData) -> Vec<String> {
+    let mut result = Vec::<String>::new();
+    for case in list_causes(data.start_time).found {
+        if !result.contains(&case.cause) {
+            result.push(case.cause);
+        }
+    }
+    result
+}
+
 fn summarize_findings(data: &FuzzUIData) -> Vec<String> {
     let case_list = list_causes(data.start_time);
 
@@ -200,15 +489,15 @@ fn summarize_findings(data: &FuzzUIData) -> Vec<String> {
     result
 }
 
-fn render_coverage<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData, chunk: Rect) {
-    let last_slot = data
-        .max_coverage
-        .last()
-        .or(Some(&(1.0 as f64, 10.0 as f64)))
-        .unwrap()
-        .clone();
-
-    let coverage = data.max_coverage.clone();
+fn render_chart<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData, chunk: Rect) {
+    let series = match data.chart {
+        ChartKind::Coverage => &data.max_coverage,
+        ChartKind::ExecsPerSec => &data.execs_per_sec,
+        ChartKind::CorpusSize => &data.corpus_sizes,
+    };
+    // Unlike the coverage, the other series can go down again.
+    let max_value = series.iter().map(|p| p.1).fold(0.0, f64::max);
+    let max_value = if series.is_empty() { 10.0 } else { max_value };
 
     let max_time = format_duration_hms(&(current_time() - data.start_time));
 
@@ -218,20 +507,20 @@ fn render_coverage<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData, chunk: Rect)
             .marker(symbols::Marker::Braille)
             .style(Style::default().fg(Color::White))
             .graph_type(GraphType::Line)
-            .data(coverage.as_slice()),
+            .data(series.as_slice()),
         Dataset::default()
             .name("")
             .marker(symbols::Marker::Braille)
             .style(Style::default().fg(Color::Red))
             .graph_type(GraphType::Scatter)
-            .data(coverage.as_slice()),
+            .data(series.as_slice()),
     ];
 
     let chart = Chart::new(datasets)
         .block(
             Block::default()
                 .title(Span::styled(
-                    "Coverage",
+                    format!("{} (c: next chart)", data.chart.title()),
                     Style::default()
                         .fg(Color::Cyan)
                         .add_modifier(Modifier::BOLD),
@@ -253,13 +542,13 @@ fn render_coverage<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData, chunk: Rect)
         )
         .y_axis(
             Axis::default()
-                .title("Coverage")
+                .title(data.chart.title())
                 .style(Style::default().fg(Color::Gray))
-                .bounds([0.0, last_slot.1 * 1.2])
+                .bounds([0.0, max_value * 1.2])
                 .labels(vec![
                     Span::styled("0", Style::default().add_modifier(Modifier::BOLD)),
                     Span::styled(
-                        format!("{:0}", last_slot.1),
+                        format!("{:.0}", max_value),
                         Style::default().add_modifier(Modifier::BOLD),
                     ),
                 ]),
@@ -331,25 +620,78 @@ fn render_time_between_findings<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData,
     f.render_widget(chart, chunk);
 }
 
-fn ui<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData) {
-    let size = f.size();
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-        .split(size);
+fn render_clients<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData, chunk: Rect) {
+    let header = Row::new([
+        "Core", "Execs", "Exec/s", "Corpus", "Found", "Coverage", "New cov",
+    ])
+    .style(Style::default().add_modifier(Modifier::BOLD));
 
-    let top_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Min(10), Constraint::Length(70)])
-        .split(chunks[0]);
+    let rows: Vec<Row> = data
+        .clients
+        .iter()
+        .map(|client| {
+            let since_new_coverage = match client.since_new_coverage {
+                Some(since) => format_duration_hms(&since),
+                None => "-".to_string(),
+            };
+            let row = Row::new([
+                Cell::from(format!("{}", client.id)),
+                Cell::from(format!("{}", client.execs)),
+                Cell::from(client.execs_per_sec.clone()),
+                Cell::from(format!("{}", client.corpus_size)),
+                Cell::from(format!("{}", client.objective_size)),
+                Cell::from(format!("{}", client.coverage)),
+                Cell::from(since_new_coverage),
+            ]);
+            // Stalled clients are easy to miss among many working ones.
+            if client.stalled {
+                row.style(Style::default().fg(Color::Red))
+            } else {
+                row
+            }
+        })
+        .collect();
 
-    let bottom_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-        .split(chunks[1]);
+    let total_execs: u64 = data.clients.iter().map(|c| c.execs).sum();
+    let stalled = data.clients.iter().filter(|c| c.stalled).count();
+    let title = if stalled == 0 {
+        format!("Clients ({}, {} execs total)", data.clients.len(), total_execs)
+    } else {
+        format!(
+            "Clients ({}, {} stalled, {} execs total)",
+            data.clients.len(),
+            stalled,
+            total_execs
+        )
+    };
+
+    let table = Table::new(rows)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .widths(&[
+            Constraint::Length(6),
+            Constraint::Length(12),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Length(10),
+            Constraint::Length(10),
+        ]);
+    f.render_widget(table, chunk);
+}
 
-    let cause_list = summarize_findings(data);
+fn render_messages<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData, chunk: Rect) {
+    let items: Vec<ListItem> = data
+        .messages
+        .iter()
+        .map(|i| ListItem::new(i.as_str()).style(Style::default()))
+        .collect();
+    let items = List::new(items).block(Block::default().borders(Borders::ALL).title("Messages"));
+    f.render_widget(items, chunk);
+}
 
+fn render_findings<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData, view: &View, chunk: Rect) {
+    let cause_list = summarize_findings(data);
     let findings: Vec<ListItem> = cause_list
         .iter()
         .map(|i| {
@@ -360,23 +702,142 @@ fn ui<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData) {
             }
         })
         .collect();
-    let findings_list =
-        List::new(findings).block(Block::default().borders(Borders::ALL).title("Findings"));
+    let findings_list = List::new(findings)
+        .block(Block::default().borders(Borders::ALL).title("Findings"))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default();
+    if !cause_list.is_empty() {
+        state.select(Some(view.selected_finding.min(cause_list.len() - 1)));
+    }
+    f.render_stateful_widget(findings_list, chunk, &mut state);
+}
 
-    f.render_widget(findings_list, top_chunks[1]);
+fn render_detail<B: Backend>(f: &mut Frame<B>, detail: &CauseDetail, chunk: Rect) {
+    let items: Vec<ListItem> = detail
+        .lines
+        .iter()
+        .map(|line| ListItem::new(line.as_str()))
+        .collect();
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("{} (enter/esc: close)", detail.title)),
+    );
+    f.render_widget(list, chunk);
+}
 
-    // Iterate through all elements in the `items` app and append some debug text to it.
-    let items: Vec<ListItem> = data
-        .messages
+fn render_regions<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData, chunk: Rect) {
+    let header = Row::new(["Region", "Covered", "Size", "%"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+
+    let rows: Vec<Row> = data
+        .regions
         .iter()
-        .map(|i| ListItem::new(i.as_str()).style(Style::default()))
+        .map(|region| {
+            let percent = 100.0 * region.covered as f64 / region.len.max(1) as f64;
+            Row::new([
+                Cell::from(region.name.clone()),
+                Cell::from(format!("{}", region.covered)),
+                Cell::from(format!("{}", region.len)),
+                Cell::from(format!("{:.1}", percent)),
+            ])
+        })
         .collect();
 
-    let items = List::new(items).block(Block::default().borders(Borders::ALL).title("Messages"));
+    let table = Table::new(rows)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title("Map regions"))
+        .widths(&[
+            Constraint::Min(12),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(6),
+        ]);
+    f.render_widget(table, chunk);
+}
 
-    // We can now render the item list
-    f.render_widget(items, top_chunks[0]);
+// Splits the area into equal parts.
+fn split_evenly(area: Rect, direction: Direction, parts: usize) -> Vec<Rect> {
+    let constraints: Vec<Constraint> = (0..parts)
+        .map(|_| Constraint::Ratio(1, parts as u32))
+        .collect();
+    Layout::default()
+        .direction(direction)
+        .constraints(constraints)
+        .split(area)
+}
 
-    render_coverage(f, data, bottom_chunks[0]);
-    render_time_between_findings(f, data, bottom_chunks[1]);
+fn ui<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData, view: &View) {
+    let panels = &view.panels;
+    let screen = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(f.size());
+
+    let help = if view.paused {
+        format!("PAUSED  {}", HELP)
+    } else {
+        HELP.to_string()
+    };
+    f.render_widget(
+        Paragraph::new(help).style(Style::default().fg(Color::Gray)),
+        screen[1],
+    );
+
+    // Only show the map regions if the harness published any.
+    let show_regions = panels.regions && !data.regions.is_empty();
+    let left_panels = [panels.messages, panels.clients];
+    let right_panels = [panels.findings, show_regions];
+    let show_left = left_panels.contains(&true);
+    let show_right = right_panels.contains(&true);
+
+    let bottom = if let Some(detail) = &view.detail {
+        let halves = split_evenly(screen[0], Direction::Vertical, 2);
+        render_detail(f, detail, halves[0]);
+        halves[1]
+    } else if show_left || show_right {
+        let halves = split_evenly(screen[0], Direction::Vertical, 2);
+        let (left, right) = match (show_left, show_right) {
+            (true, true) => {
+                let columns = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Min(10), Constraint::Length(70)])
+                    .split(halves[0]);
+                (Some(columns[0]), Some(columns[1]))
+            }
+            (true, false) => (Some(halves[0]), None),
+            (false, _) => (None, Some(halves[0])),
+        };
+        if let Some(left) = left {
+            let count = left_panels.iter().filter(|shown| **shown).count();
+            let mut areas = split_evenly(left, Direction::Vertical, count).into_iter();
+            if panels.messages {
+                render_messages(f, data, areas.next().unwrap());
+            }
+            if panels.clients {
+                render_clients(f, data, areas.next().unwrap());
+            }
+        }
+        if let Some(right) = right {
+            let count = right_panels.iter().filter(|shown| **shown).count();
+            let mut areas = split_evenly(right, Direction::Vertical, count).into_iter();
+            if panels.findings {
+                render_findings(f, data, view, areas.next().unwrap());
+            }
+            if show_regions {
+                render_regions(f, data, areas.next().unwrap());
+            }
+        }
+        halves[1]
+    } else {
+        screen[0]
+    };
+
+    if panels.find_times {
+        let areas = split_evenly(bottom, Direction::Horizontal, 2);
+        render_chart(f, data, areas[0]);
+        render_time_between_findings(f, data, areas[1]);
+    } else {
+        render_chart(f, data, bottom);
+    }
 }
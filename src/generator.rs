@@ -1,72 +1,720 @@
-use crate::instructions::{Argument, ArgumentSpec, Instruction, InstructionTemplate};
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 
-/// Generates random RISC-V instructions.
-#[derive(Default)]
-pub struct InstGenerator {
-    /// List of known arguments the generator should try to reuse.
-    known_args: Vec<Argument>,
+use crate::cache::CacheGeometry;
+use crate::input_config::PrivilegeLevel;
+use crate::inst_class::{InstClass, InstClassFilter};
+use crate::instructions::riscv::{args, rv_i::FENCE, rv_zifencei::FENCE_I};
+use crate::instructions::{
+    sets, ArgKind, Argument, ArgumentSpec, Instruction, InstructionTemplate,
+};
+use crate::paging::PageTableConfig;
+use crate::pmp::PmpConfig;
+use crate::provenance::Provenance;
+
+/// Parses a register list such as `x1-x7` or `1,2,5-6` into the list of
+/// register numbers.
+pub fn parse_register_list(input: &str) -> Result<Vec<u32>, String> {
+    let parse_reg = |s: &str| -> Result<u32, String> {
+        let num = s.trim().trim_start_matches('x');
+        match num.parse::<u32>() {
+            Ok(reg) if reg < 32 => Ok(reg),
+            _ => Err(format!("Invalid register: '{}'", s)),
+        }
+    };
+
+    let mut result = Vec::<u32>::new();
+    for part in input.split(',') {
+        if part.trim().is_empty() {
+            continue;
+        }
+        let range: Vec<&str> = part.split('-').collect();
+        match range.as_slice() {
+            [reg] => result.push(parse_reg(reg)?),
+            [start, end] => {
+                let (start, end) = (parse_reg(start)?, parse_reg(end)?);
+                if start > end {
+                    return Err(format!("Invalid register range: '{}'", part));
+                }
+                result.extend(start..=end);
+            }
+            _ => return Err(format!("Invalid register range: '{}'", part)),
+        }
+    }
+
+    if result.is_empty() {
+        return Err("Empty register list".to_string());
+    }
+    result.sort();
+    result.dedup();
+    Ok(result)
+}
+
+// FENCE.TSO is encoded as a FENCE with this fm value and pred = succ = RW.
+const FENCE_FM_TSO: u32 = 0b1000;
+const FENCE_RW: u32 = 0b0011;
+// How often an argument is regenerated before falling back to 0.
+const RESERVED_RETRIES: usize = 16;
+
+/// Whether the value of an operand is architecturally reserved regardless of
+/// the other operands of the instruction.
+pub fn is_reserved_value(spec: &ArgumentSpec, value: u32) -> bool {
+    match spec.name() {
+        // Rounding modes 5 and 6 are reserved.
+        "rm" => value == 5 || value == 6,
+        // Only normal fences and FENCE.TSO are defined.
+        "fm" => value != 0 && value != FENCE_FM_TSO,
+        _ => false,
+    }
+}
+
+/// Zeroes the operands of fences that are reserved for future use and
+/// turns invalid FENCE.TSO encodings into a valid one.
+fn clear_reserved_fields(inst: &mut Instruction) {
+    if inst.template() == &FENCE {
+        inst.set_arg(Argument::new(&args::RD, 0));
+        inst.set_arg(Argument::new(&args::RS1, 0));
+        if inst.arg_value(&args::FM) == Some(FENCE_FM_TSO) {
+            inst.set_arg(Argument::new(&args::PRED, FENCE_RW));
+            inst.set_arg(Argument::new(&args::SUCC, FENCE_RW));
+        }
+    } else if inst.template() == &FENCE_I {
+        inst.set_arg(Argument::new(&args::RD, 0));
+        inst.set_arg(Argument::new(&args::RS1, 0));
+        inst.set_arg(Argument::new(&args::IMM12, 0));
+    }
+}
+
+/// Picks an index with a probability proportional to its weight.
+/// Returns None if all weights are 0.
+fn weighted_index<R: libafl::prelude::Rand>(rand: &mut R, weights: &[u64]) -> Option<usize> {
+    let total: u64 = weights.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let mut pick = rand.below(total);
+    for (idx, weight) in weights.iter().enumerate() {
+        if pick < *weight {
+            return Some(idx);
+        }
+        pick -= weight;
+    }
+    unreachable!("pick is always below the total weight")
+}
+
+/// Settings for [`InstGenerator`]. Built with the methods below, e.g.
+/// `InstGeneratorConfig::new().reuse_chance(20).register_subset(vec![1, 2])`.
+#[derive(Clone, Debug)]
+pub struct InstGeneratorConfig {
     // Chance (0-100) of reusing a known arg value in the program.
     reuse_chance: u64,
     // Chance (0-100) of choosing a power of two as arg value.
     power_of_two_chance: u64,
+    // Chance (0-100) of choosing an immediate from the immediate pool.
+    immediate_pool_chance: u64,
+    // Chance (0-100) of a memory snippet using a misaligned address.
+    misaligned_chance: u64,
+    /// Values immediates are preferably chosen from.
+    immediate_pool: Vec<u32>,
+    /// If set, register operands only use these registers.
+    register_subset: Option<Vec<u32>>,
+    /// Relative weights of templates. Templates without entry have weight 1.
+    template_weights: Vec<(&'static InstructionTemplate, u32)>,
+    /// Relative weights of instruction classes. If empty, templates are
+    /// chosen independently of their class.
+    class_weights: Vec<(InstClass, u32)>,
+    /// If set, only instructions accepted by this filter are generated.
+    class_filter: Option<InstClassFilter>,
+    /// Privilege levels the target supports. Transitions between them are
+    /// only generated if this contains at least two levels.
+    privilege_levels: Vec<PrivilegeLevel>,
+    /// If set, Sv39 page-table setups using this layout are generated.
+    page_table: Option<PageTableConfig>,
+    /// If set, PMP snippets are generated that stay inside this region.
+    pmp_region: Option<PmpConfig>,
+    /// If set, snippets evicting lines from a cache with this geometry are
+    /// generated. Requires `pmp_region` for the addresses.
+    cache_geometry: Option<CacheGeometry>,
+    /// If set, snippets are generated that store a value, load it back and
+    /// jump to this address if it differs. Requires `pmp_region` for the
+    /// addresses.
+    failure_routine: Option<u32>,
+    /// If set, inputs carry a data-memory image at this address that is
+    /// mutated alongside the instructions.
+    memory_image: Option<u64>,
+    /// Whether reserved operand values and encodings may be generated.
+    allow_reserved: bool,
+    /// Whether programs may contain words that aren't instructions (see
+    /// `RAW_WORD`), both when parsing inputs and when mutating them.
+    allow_raw_words: bool,
+    /// Templates that are never generated (e.g. ones that wedge the harness).
+    /// Existing instructions are still parsed and kept.
+    excluded_templates: Vec<&'static InstructionTemplate>,
+    /// If set, the mutators generate new instructions from these templates
+    /// instead of `sets::riscv_base`.
+    instruction_set: Option<Vec<&'static InstructionTemplate>>,
+    /// If set, every mutator draws from its own RNG stream derived from this
+    /// seed instead of the fuzzer's RNG.
+    mutation_seed: Option<u64>,
 }
 
-impl InstGenerator {
-    pub fn new() -> Self {
+impl Default for InstGeneratorConfig {
+    fn default() -> Self {
         Self {
-            known_args: Vec::<Argument>::new(),
             reuse_chance: 50,
             power_of_two_chance: 50,
+            immediate_pool_chance: 50,
+            misaligned_chance: 0,
+            immediate_pool: Vec::<u32>::new(),
+            register_subset: None,
+            template_weights: Vec::new(),
+            class_weights: Vec::new(),
+            class_filter: None,
+            privilege_levels: Vec::<PrivilegeLevel>::new(),
+            page_table: None,
+            pmp_region: None,
+            cache_geometry: None,
+            failure_routine: None,
+            memory_image: None,
+            allow_reserved: false,
+            allow_raw_words: false,
+            excluded_templates: Vec::new(),
+            instruction_set: None,
+            mutation_seed: None,
+        }
+    }
+}
+
+impl InstGeneratorConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Chance (0-100) of reusing an argument value seen in the program.
+    pub fn reuse_chance(mut self, chance: u64) -> Self {
+        assert!(chance <= 100);
+        self.reuse_chance = chance;
+        self
+    }
+
+    /// Chance (0-100) of choosing a power of two as argument value.
+    pub fn power_of_two_chance(mut self, chance: u64) -> Self {
+        assert!(chance <= 100);
+        self.power_of_two_chance = chance;
+        self
+    }
+
+    /// Immediates are chosen from the given values with the given chance
+    /// (0-100). Values are truncated to the width of the immediate.
+    pub fn immediate_pool(mut self, values: Vec<u32>, chance: u64) -> Self {
+        assert!(chance <= 100);
+        self.immediate_pool = values;
+        self.immediate_pool_chance = chance;
+        self
+    }
+
+    /// Enables snippets that access more lines of a cache set than the
+    /// cache has ways. The lines are placed in the PMP region.
+    pub fn cache_geometry(mut self, geometry: CacheGeometry) -> Self {
+        self.cache_geometry = Some(geometry);
+        self
+    }
+
+    /// Chance (0-100) of the accesses in memory snippets using a misaligned
+    /// address (off by one or two, crossing a cache line or a page).
+    pub fn misaligned_chance(mut self, chance: u64) -> Self {
+        assert!(chance <= 100);
+        self.misaligned_chance = chance;
+        self
+    }
+
+    /// Restricts all generated register operands to the given registers.
+    /// This creates programs with dense dependencies between instructions.
+    pub fn register_subset(mut self, registers: Vec<u32>) -> Self {
+        assert!(!registers.is_empty());
+        self.register_subset = Some(registers);
+        self
+    }
+
+    /// Sets the relative weight of a template. The default weight is 1 and a
+    /// weight of 0 disables the template.
+    pub fn template_weight(mut self, template: &'static InstructionTemplate, weight: u32) -> Self {
+        self.template_weights.retain(|(t, _)| *t != template);
+        self.template_weights.push((template, weight));
+        self
+    }
+
+    /// Sets the relative weight of an instruction class. A class is picked
+    /// first according to these weights and then a template within it.
+    /// Once any class weight is set, classes without weight are disabled.
+    /// Templates count towards their first class (FP loads are loads).
+    pub fn class_weight(mut self, class: InstClass, weight: u32) -> Self {
+        self.class_weights.retain(|(c, _)| *c != class);
+        self.class_weights.push((class, weight));
+        self
+    }
+
+    /// Only generate instructions of the classes accepted by the filter.
+    pub fn class_filter(mut self, filter: InstClassFilter) -> Self {
+        self.class_filter = Some(filter);
+        self
+    }
+
+    /// Sets the privilege levels the target supports.
+    pub fn privilege_levels(mut self, levels: Vec<PrivilegeLevel>) -> Self {
+        self.privilege_levels = levels;
+        self
+    }
+
+    /// Enables the virtual-memory mode which emits page-table setups.
+    pub fn page_table(mut self, config: PageTableConfig) -> Self {
+        self.page_table = Some(config);
+        self
+    }
+
+    /// Enables PMP snippets that only access memory in the given region.
+    pub fn pmp_region(mut self, config: PmpConfig) -> Self {
+        self.pmp_region = Some(config);
+        self
+    }
+
+    /// Enables store-load checks that jump to the routine at the given
+    /// address if a load doesn't return the stored value.
+    pub fn failure_routine(mut self, address: u32) -> Self {
+        self.failure_routine = Some(address);
+        self
+    }
+
+    /// Enables mutations of a data-memory image starting at the given
+    /// address.
+    pub fn memory_image(mut self, address: u64) -> Self {
+        self.memory_image = Some(address);
+        self
+    }
+
+    /// The weight of the given class.
+    pub fn class_weight_of(&self, class: InstClass) -> u32 {
+        self.class_weights
+            .iter()
+            .find(|(c, _)| *c == class)
+            .map_or(0, |(_, weight)| *weight)
+    }
+
+    /// Also generate architecturally reserved operand values (e.g. reserved
+    /// rounding modes) and nonzero reserved fields in fences.
+    pub fn allow_reserved(mut self, allow: bool) -> Self {
+        self.allow_reserved = allow;
+        self
+    }
+
+    /// Lets the 'inject-illegal' mutation insert words that aren't
+    /// instructions. Inputs then have to be parsed with raw words allowed
+    /// (e.g. `ProgramInput::from_bytes_with`).
+    pub fn allow_raw_words(mut self, allow: bool) -> Self {
+        self.allow_raw_words = allow;
+        self
+    }
+
+    /// Never generate the given templates.
+    pub fn exclude_templates(mut self, templates: Vec<&'static InstructionTemplate>) -> Self {
+        self.excluded_templates = templates;
+        self
+    }
+
+    /// Generate new instructions from the given templates (e.g. from
+    /// `sets::parse_extension_list`).
+    pub fn instruction_set(mut self, templates: Vec<&'static InstructionTemplate>) -> Self {
+        self.instruction_set = Some(templates);
+        self
+    }
+
+    /// Gives every mutator a deterministic RNG stream derived from the seed,
+    /// so enabling or disabling one mutation doesn't change the random
+    /// decisions of the others.
+    pub fn mutation_seed(mut self, seed: u64) -> Self {
+        self.mutation_seed = Some(seed);
+        self
+    }
+
+    /// Creates a generator using this configuration. Panics if the
+    /// configuration is invalid, see `validate`.
+    pub fn build(self) -> InstGenerator {
+        InstGenerator::with_config(self)
+    }
+
+    /// Checks that new instructions can be generated, i.e. the excluded
+    /// templates don't cover the whole instruction set.
+    pub fn validate(&self) -> Result<(), String> {
+        let set = self
+            .instruction_set
+            .as_deref()
+            .unwrap_or_else(|| sets::riscv_base());
+        if set.iter().all(|t| self.excluded_templates.contains(t)) {
+            return Err("All instructions of the instruction set are excluded".to_string());
+        }
+        Ok(())
+    }
+
+    /// The weight of the given template.
+    pub fn weight_of(&self, template: &InstructionTemplate) -> u32 {
+        self.template_weights
+            .iter()
+            .find(|(t, _)| *t == template)
+            .map_or(1, |(_, weight)| *weight)
+    }
+}
+
+/// Templates of equal weight or with the given weights.
+#[derive(Debug, Default)]
+struct TemplateGroup {
+    templates: Vec<&'static InstructionTemplate>,
+    /// Empty if no template weights are configured.
+    weights: Vec<u64>,
+}
+
+impl TemplateGroup {
+    fn new(templates: Vec<&'static InstructionTemplate>, config: &InstGeneratorConfig) -> Self {
+        let weights = if config.template_weights.is_empty() {
+            Vec::new()
+        } else {
+            templates
+                .iter()
+                .map(|t| config.weight_of(t) as u64)
+                .collect()
+        };
+        Self { templates, weights }
+    }
+
+    /// Picks a template according to the weights. Falls back to a uniform
+    /// choice if all templates have weight 0.
+    fn choose<R: libafl::prelude::Rand>(&self, rand: &mut R) -> &'static InstructionTemplate {
+        match weighted_index(rand, &self.weights) {
+            Some(idx) => self.templates[idx],
+            None => rand.choose(&self.templates),
         }
     }
+}
+
+/// The templates an instruction set leaves after applying the exclusions
+/// and the class filter, grouped by class for the class weights.
+#[derive(Debug, Default)]
+struct TemplateChoice {
+    all: TemplateGroup,
+    /// The group of every class in `InstClass::ALL`, empty if no class
+    /// weights are configured.
+    classes: Vec<TemplateGroup>,
+    class_weights: Vec<u64>,
+}
+
+impl TemplateChoice {
+    fn new(insts: &[&'static InstructionTemplate], config: &InstGeneratorConfig) -> Self {
+        let insts = sets::without(insts, &config.excluded_templates);
+        assert!(!insts.is_empty(), "All instructions are excluded");
+        // Fall back to all instructions if the filter rejects the whole set.
+        let insts = config
+            .class_filter
+            .as_ref()
+            .map(|filter| filter.filter(&insts))
+            .filter(|filtered| !filtered.is_empty())
+            .unwrap_or(insts);
+
+        let mut classes = Vec::new();
+        let mut class_weights = Vec::new();
+        if !config.class_weights.is_empty() {
+            for class in InstClass::ALL.iter() {
+                let group: Vec<_> = insts
+                    .iter()
+                    .filter(|t| InstClass::of(t)[0] == *class)
+                    .copied()
+                    .collect();
+                class_weights.push(if group.is_empty() {
+                    0
+                } else {
+                    config.class_weight_of(*class) as u64
+                });
+                classes.push(TemplateGroup::new(group, config));
+            }
+        }
+        Self {
+            all: TemplateGroup::new(insts, config),
+            classes,
+            class_weights,
+        }
+    }
+
+    /// Picks a class according to the class weights and a template of it
+    /// according to the template weights. Picks from all templates if no
+    /// class weights are set or none of the classes has a weight.
+    fn choose<R: libafl::prelude::Rand>(&self, rand: &mut R) -> &'static InstructionTemplate {
+        let group = match weighted_index(rand, &self.class_weights) {
+            Some(idx) => &self.classes[idx],
+            None => &self.all,
+        };
+        group.choose(rand)
+    }
+}
+
+/// Generates random RISC-V instructions.
+#[derive(Clone)]
+pub struct InstGenerator {
+    /// List of known arguments the generator should try to reuse, with
+    /// the kind they have in their instruction.
+    known_args: Vec<(ArgKind, Argument)>,
+    // Shared by clones, which are made for every generated instruction.
+    config: Arc<InstGeneratorConfig>,
+    // The choice of templates for `instruction_set`.
+    choice: Arc<TemplateChoice>,
+}
+
+impl Default for InstGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InstGenerator {
+    pub fn new() -> Self {
+        Self::with_config(InstGeneratorConfig::default())
+    }
+
+    /// Creates a generator using the configuration. Panics if it is invalid,
+    /// use `try_with_config` for configurations from the user.
+    pub fn with_config(config: InstGeneratorConfig) -> Self {
+        Self::try_with_config(config).unwrap_or_else(|err| panic!("{}", err))
+    }
 
+    /// Like `with_config`, but returns an error if the configuration is
+    /// invalid (see `InstGeneratorConfig::validate`).
+    pub fn try_with_config(config: InstGeneratorConfig) -> Result<Self, String> {
+        config.validate()?;
+        let mut result = Self {
+            known_args: Vec::new(),
+            config: Arc::new(config),
+            choice: Arc::default(),
+        };
+        result.choice = Arc::new(TemplateChoice::new(
+            result.instruction_set(),
+            &result.config,
+        ));
+        Ok(result)
+    }
+
+    pub fn config(&self) -> &InstGeneratorConfig {
+        &self.config
+    }
+
+    /// Adds arguments to reuse. Register arguments are treated as integer
+    /// registers, use `forward_inst` for operands of FP instructions.
     pub fn forward_args(&mut self, args: &[Argument]) {
-        self.known_args.append(&mut args.to_vec())
+        self.known_args
+            .extend(args.iter().map(|arg| (arg.spec().kind(), arg.clone())))
+    }
+
+    /// Adds the arguments of the given instruction to reuse.
+    pub fn forward_inst(&mut self, inst: &Instruction) {
+        let template = inst.template();
+        self.known_args.extend(
+            inst.arguments()
+                .iter()
+                .map(|arg| (template.arg_kind(arg.spec()), arg.clone())),
+        )
+    }
+
+    pub fn register_subset(&self) -> Option<&[u32]> {
+        self.config.register_subset.as_deref()
+    }
+
+    pub fn class_filter(&self) -> Option<&InstClassFilter> {
+        self.config.class_filter.as_ref()
+    }
+
+    pub fn is_excluded(&self, template: &InstructionTemplate) -> bool {
+        self.config.excluded_templates.contains(&template)
+    }
+
+    /// The templates new instructions are generated from.
+    pub fn instruction_set(&self) -> &[&'static InstructionTemplate] {
+        match &self.config.instruction_set {
+            Some(templates) => templates,
+            None => sets::riscv_base(),
+        }
+    }
+
+    /// Whether new instructions with this template may be emitted, i.e. the
+    /// class filter accepts it and it isn't excluded.
+    pub fn accepts(&self, template: &InstructionTemplate) -> bool {
+        let class_ok = match &self.config.class_filter {
+            Some(filter) => filter.accepts(template),
+            None => true,
+        };
+        class_ok && !self.is_excluded(template)
+    }
+
+    /// Whether programs may contain words that aren't instructions.
+    pub fn raw_words_allowed(&self) -> bool {
+        self.config.allow_raw_words
+    }
+
+    pub fn privilege_levels(&self) -> &[PrivilegeLevel] {
+        &self.config.privilege_levels
+    }
+
+    pub fn page_table(&self) -> Option<&PageTableConfig> {
+        self.config.page_table.as_ref()
+    }
+
+    pub fn pmp_region(&self) -> Option<&PmpConfig> {
+        self.config.pmp_region.as_ref()
+    }
+
+    pub fn cache_geometry(&self) -> Option<&CacheGeometry> {
+        self.config.cache_geometry.as_ref()
+    }
+
+    pub fn failure_routine(&self) -> Option<u32> {
+        self.config.failure_routine
+    }
+
+    pub fn memory_image(&self) -> Option<u64> {
+        self.config.memory_image
+    }
+
+    pub fn misaligned_chance(&self) -> u64 {
+        self.config.misaligned_chance
+    }
+
+    pub fn mutation_seed(&self) -> Option<u64> {
+        self.config.mutation_seed
     }
 
+    /// Generates a random value for the given operand of `template`.
+    /// Reserved values are only generated if the config allows them.
     pub fn generate_argument<R: libafl::prelude::Rand>(
         &self,
         rand: &mut R,
+        template: &InstructionTemplate,
         arg: &'static ArgumentSpec,
     ) -> Argument {
-        if rand.below(100) < self.reuse_chance {
+        self.generate_argument_traced(rand, template, arg).0
+    }
+
+    /// Like `generate_argument`, but also returns how the value was chosen.
+    pub fn generate_argument_traced<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        template: &InstructionTemplate,
+        arg: &'static ArgumentSpec,
+    ) -> (Argument, Provenance) {
+        for _ in 0..RESERVED_RETRIES {
+            let result = self.generate_any_argument(rand, template, arg);
+            if self.config.allow_reserved || !is_reserved_value(arg, result.0.value()) {
+                return result;
+            }
+        }
+        // 0 is a valid value for all operands with reserved values.
+        (Argument::new(arg, 0), Provenance::Unknown)
+    }
+
+    fn generate_any_argument<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        template: &InstructionTemplate,
+        arg: &'static ArgumentSpec,
+    ) -> (Argument, Provenance) {
+        if arg.is_register() {
+            if let Some(registers) = &self.config.register_subset {
+                return (
+                    Argument::new(arg, *rand.choose(registers)),
+                    Provenance::Random,
+                );
+            }
+        }
+
+        if arg.is_immediate()
+            && !self.config.immediate_pool.is_empty()
+            && rand.below(100) < self.config.immediate_pool_chance
+        {
+            let value = *rand.choose(&self.config.immediate_pool);
+            return (
+                Argument::new(arg, value & (arg.max_value() - 1)),
+                Provenance::Dictionary,
+            );
+        }
+
+        if rand.below(100) < self.config.reuse_chance {
+            // Only reuse values of the same kind, e.g. a 5-bit immediate is
+            // not a good register number and neither is an FP register.
+            let kind = template.arg_kind(arg);
             let filtered = self
                 .known_args
                 .iter()
-                .filter(|x| x.spec().length() == arg.length());
+                .filter(|(x_kind, x)| *x_kind == kind && x.spec().length() == arg.length())
+                .map(|(_, x)| x);
             let options = filtered.collect::<Vec<&Argument>>();
             if !options.is_empty() {
                 let chosen = rand.choose(options).clone();
-                return Argument::new(arg, chosen.value());
+                return (Argument::new(arg, chosen.value()), Provenance::Reused);
             }
         }
 
-        if rand.below(100) < self.power_of_two_chance {
-            Argument::new(arg, 1 << rand.below(arg.length() as u64) as u32)
+        if rand.below(100) < self.config.power_of_two_chance {
+            (
+                Argument::new(arg, 1 << rand.below(arg.length() as u64) as u32),
+                Provenance::PowerOfTwo,
+            )
         } else {
-            Argument::new(arg, rand.below(arg.max_value() as u64) as u32)
+            (
+                Argument::new(arg, rand.below(arg.max_value() as u64) as u32),
+                Provenance::Random,
+            )
         }
     }
 
     pub fn generate_instruction<R: libafl::prelude::Rand>(
         &self,
         rand: &mut R,
-        insts: &Vec<&'static InstructionTemplate>,
+        insts: &[&'static InstructionTemplate],
     ) -> Instruction {
-        assert!(!insts.is_empty());
-        let template = rand.choose(insts.iter());
+        self.generate_instruction_traced(rand, insts).0
+    }
+
+    /// Like `generate_instruction`, but also returns how the value of every
+    /// operand was chosen.
+    pub fn generate_instruction_traced<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        insts: &[&'static InstructionTemplate],
+    ) -> (Instruction, Vec<Provenance>) {
+        // The choice for the configured set is made once, other sets are
+        // only used by tests and tools.
+        let template = if core::ptr::eq(insts, self.instruction_set()) {
+            self.choice.choose(rand)
+        } else {
+            TemplateChoice::new(insts, &self.config).choose(rand)
+        };
 
         let mut arguments = Vec::<Argument>::new();
+        let mut provenance = Vec::<Provenance>::new();
         for arg in template.operands() {
-            arguments.push(self.generate_argument(rand, arg));
+            let (argument, origin) = self.generate_argument_traced(rand, template, arg);
+            arguments.push(argument);
+            provenance.push(origin);
         }
-        Instruction::new(template, arguments)
+        let mut inst = Instruction::new(template, arguments);
+        if !self.config.allow_reserved {
+            clear_reserved_fields(&mut inst);
+        }
+        (inst, provenance)
     }
 
     pub fn generate_instructions<R: libafl::prelude::Rand>(
         &self,
         rand: &mut R,
-        insts: &Vec<&'static InstructionTemplate>,
+        insts: &[&'static InstructionTemplate],
         number: u32,
     ) -> Vec<Instruction> {
         let mut result = Vec::<Instruction>::new();
@@ -81,9 +729,21 @@ impl InstGenerator {
 mod tests {
     use libafl::prelude::{Rand, Xoshiro256StarRand};
 
-    use crate::instructions::{self, Argument};
+    use crate::instructions::{
+        self,
+        riscv::{
+            args,
+            rv_f::FADD_S,
+            rv_i::{ADD, ADDI, FENCE, SUB},
+            rv_zifencei::FENCE_I,
+        },
+        Argument, Instruction,
+    };
+
+    use crate::inst_class::InstClass;
+    use crate::provenance::Provenance;
 
-    use super::InstGenerator;
+    use super::{is_reserved_value, parse_register_list, InstGenerator, InstGeneratorConfig};
 
     #[test]
     fn generate_random_instructions() {
@@ -94,7 +754,7 @@ mod tests {
             let generator = InstGenerator::new();
             let _inst = generator.generate_instruction::<Xoshiro256StarRand>(
                 &mut rng,
-                &instructions::sets::riscv_g(),
+                instructions::sets::riscv_g(),
             );
         }
     }
@@ -121,7 +781,7 @@ mod tests {
             for _ in 0..100 {
                 let inst = generator.generate_instruction::<Xoshiro256StarRand>(
                     &mut rng,
-                    &instructions::sets::riscv_g(),
+                    instructions::sets::riscv_g(),
                 );
                 for arg in inst.arguments() {
                     if arg.spec() == &instructions::riscv::args::RD && arg.value() == magic_value {
@@ -133,4 +793,202 @@ mod tests {
             assert!(found);
         }
     }
+
+    #[test]
+    fn reuse_arguments_by_kind() {
+        let mut rng = Xoshiro256StarRand::default();
+        let mut generator =
+            InstGenerator::with_config(InstGeneratorConfig::new().reuse_chance(100));
+        // SHAMTW has the same length as RD but is no register.
+        generator.forward_args(&[Argument::new(&args::SHAMTW, 17)]);
+
+        let reused = (0..1000)
+            .map(|_| generator.generate_instruction(&mut rng, &[&ADD]))
+            .filter(|inst| inst.arg_value(&args::RD) == Some(17))
+            .count();
+        assert!(reused < 100, "{}", reused);
+    }
+
+    #[test]
+    fn reuse_fp_registers_by_kind() {
+        let mut rng = Xoshiro256StarRand::default();
+        let mut generator =
+            InstGenerator::with_config(InstGeneratorConfig::new().reuse_chance(100));
+        // All registers of fadd.s are FP registers.
+        generator.forward_inst(&Instruction::new(
+            &FADD_S,
+            vec![
+                Argument::new(&args::RD, 17),
+                Argument::new(&args::RS1, 17),
+                Argument::new(&args::RS2, 17),
+                Argument::new(&args::RM, 0),
+            ],
+        ));
+
+        let mut count = |template| {
+            (0..1000)
+                .map(|_| generator.generate_instruction(&mut rng, &[template]))
+                .filter(|inst| inst.arg_value(&args::RS2) == Some(17))
+                .count()
+        };
+        assert!(count(&ADD) < 100);
+        assert!(count(&FADD_S) > 900);
+    }
+
+    #[test]
+    fn reject_excluding_all_instructions() {
+        let config = InstGeneratorConfig::new()
+            .instruction_set(vec![&ADD, &SUB])
+            .exclude_templates(vec![&ADD, &SUB]);
+        assert!(config.validate().is_err());
+        assert!(InstGenerator::try_with_config(config).is_err());
+
+        let mut rng = Xoshiro256StarRand::default();
+        let generator = InstGeneratorConfig::new()
+            .instruction_set(vec![&ADD, &SUB])
+            .exclude_templates(vec![&ADD])
+            .build();
+        for _ in 0..100 {
+            let inst = generator.generate_instruction(&mut rng, generator.instruction_set());
+            assert_eq!(inst.template(), &SUB);
+        }
+    }
+
+    #[test]
+    fn generate_with_register_subset() {
+        let mut rng = Xoshiro256StarRand::default();
+        let generator = InstGeneratorConfig::new()
+            .register_subset(vec![1, 2, 3])
+            .build();
+
+        for _ in 0..1000 {
+            let inst = generator.generate_instruction::<Xoshiro256StarRand>(
+                &mut rng,
+                instructions::sets::riscv_g(),
+            );
+            // The register fields of fences are reserved and always zero.
+            if inst.template() == &FENCE || inst.template() == &FENCE_I {
+                continue;
+            }
+            for arg in inst.arguments() {
+                if arg.spec().is_register() {
+                    assert!((1..=3).contains(&arg.value()), "{:?}", inst);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generate_with_template_weights() {
+        let mut rng = Xoshiro256StarRand::default();
+        let generator = InstGeneratorConfig::new()
+            .template_weight(&ADD, 3)
+            .template_weight(&SUB, 0)
+            .build();
+
+        let mut adds = 0;
+        for _ in 0..1000 {
+            let inst = generator.generate_instruction(&mut rng, &[&ADD, &ADDI, &SUB]);
+            assert_ne!(inst.template(), &SUB);
+            if inst.template() == &ADD {
+                adds += 1;
+            }
+        }
+        // ADD should be picked about 75% of the time.
+        assert!((650..850).contains(&adds), "{}", adds);
+    }
+
+    #[test]
+    fn generate_with_class_weights() {
+        let mut rng = Xoshiro256StarRand::default();
+        let generator = InstGeneratorConfig::new()
+            .class_weight(InstClass::Alu, 1)
+            .class_weight(InstClass::Load, 1)
+            .build();
+
+        let mut loads = 0;
+        for _ in 0..1000 {
+            let inst = generator.generate_instruction(&mut rng, instructions::sets::riscv_g());
+            let class = InstClass::of(inst.template())[0];
+            assert!(class == InstClass::Alu || class == InstClass::Load);
+            if class == InstClass::Load {
+                loads += 1;
+            }
+        }
+        // Both classes are picked equally often regardless of their size.
+        assert!((400..600).contains(&loads), "{}", loads);
+    }
+
+    #[test]
+    fn generate_without_reserved_values() {
+        let mut rng = Xoshiro256StarRand::default();
+        let templates = vec![&FADD_S, &FENCE];
+        let generator = InstGenerator::new();
+        for _ in 0..1000 {
+            let inst = generator.generate_instruction(&mut rng, &templates);
+            for arg in inst.arguments() {
+                assert!(!is_reserved_value(arg.spec(), arg.value()), "{:?}", inst);
+            }
+            if inst.template() == &FENCE {
+                assert_eq!(inst.arg_value(&args::RD), Some(0));
+                assert_eq!(inst.arg_value(&args::RS1), Some(0));
+            }
+        }
+
+        let generator = InstGeneratorConfig::new().allow_reserved(true).build();
+        let reserved = (0..1000)
+            .map(|_| generator.generate_instruction(&mut rng, &templates))
+            .filter(|inst| {
+                inst.arguments()
+                    .iter()
+                    .any(|arg| is_reserved_value(arg.spec(), arg.value()))
+            })
+            .count();
+        assert!(reserved > 0);
+    }
+
+    #[test]
+    fn generate_with_immediate_pool() {
+        let mut rng = Xoshiro256StarRand::default();
+        let generator = InstGeneratorConfig::new()
+            .immediate_pool(vec![0x7ff, 0x1234], 100)
+            .build();
+
+        for _ in 0..100 {
+            let inst = generator.generate_instruction(&mut rng, &[&ADDI]);
+            let imm = inst.arg_value(&args::IMM12).unwrap();
+            assert!(imm == 0x7ff || imm == 0x234, "{:#x}", imm);
+        }
+    }
+
+    #[test]
+    fn trace_argument_provenance() {
+        let mut rng = Xoshiro256StarRand::default();
+        let mut generator = InstGeneratorConfig::new()
+            .reuse_chance(100)
+            .immediate_pool(vec![0x10], 100)
+            .build();
+        generator.forward_args(&[Argument::new(&args::RD, 7)]);
+
+        let (inst, provenance) = generator.generate_instruction_traced(&mut rng, &[&ADDI]);
+        assert_eq!(
+            provenance,
+            vec![
+                Provenance::Reused,
+                Provenance::Reused,
+                Provenance::Dictionary
+            ]
+        );
+        assert_eq!(inst.arg_value(&args::RS1), Some(7));
+    }
+
+    #[test]
+    fn parse_register_lists() {
+        assert_eq!(parse_register_list("x1-x3").unwrap(), vec![1, 2, 3]);
+        assert_eq!(parse_register_list("5,1-2").unwrap(), vec![1, 2, 5]);
+        assert_eq!(parse_register_list("x7").unwrap(), vec![7]);
+        assert!(parse_register_list("x3-x1").is_err());
+        assert!(parse_register_list("x32").is_err());
+        assert!(parse_register_list("").is_err());
+    }
 }
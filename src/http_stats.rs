@@ -0,0 +1,136 @@
+//! A minimal HTTP server for watching headless campaigns (see `sim-fuzzer
+//! --http-stats`). It serves:
+//!
+//! - `/stats.json`: The latest `StatsRecord` (the same as in the stats file).
+//! - `/coverage.json`: The total coverage over time as `[seconds, entries]`.
+//! - `/`: A page that shows both and refreshes itself.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::stats::StatsRecord;
+
+// How long a client can take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+const INDEX_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>sim-fuzzer</title></head>
+<body style="font-family: monospace">
+<h1>sim-fuzzer</h1>
+<pre id="stats">Waiting for stats...</pre>
+<svg id="coverage" width="800" height="300" style="border: 1px solid gray">
+<polyline id="line" fill="none" stroke="black"/>
+</svg>
+<script>
+async function update() {
+  const stats = await (await fetch("stats.json")).json();
+  if (stats) {
+    document.getElementById("stats").textContent = JSON.stringify(stats, null, 2);
+  }
+  const coverage = await (await fetch("coverage.json")).json();
+  if (coverage.length > 0) {
+    const maxTime = Math.max(1, coverage[coverage.length - 1][0]);
+    const maxCoverage = Math.max(1, ...coverage.map(p => p[1]));
+    const points = coverage.map(
+      p => `${p[0] / maxTime * 800},${300 - p[1] / maxCoverage * 300}`);
+    document.getElementById("line").setAttribute("points", points.join(" "));
+  }
+}
+update();
+setInterval(update, 5000);
+</script>
+</body>
+</html>
+"#;
+
+/// What the server shows, updated by the monitor.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct StatsSnapshot {
+    /// The latest statistics, None before the first client reported.
+    pub stats: Option<StatsRecord>,
+    /// The total coverage over time as (seconds since start, entries).
+    pub coverage: Vec<(f64, u64)>,
+}
+
+/// Returns the status line, content type and body for a GET of the path.
+pub fn respond(path: &str, snapshot: &StatsSnapshot) -> (&'static str, &'static str, String) {
+    // Query strings (e.g. from cache busting) are ignored.
+    let path = path.split('?').next().unwrap_or_default();
+    let json = |body: serde_json::Result<String>| match body {
+        Ok(body) => ("200 OK", "application/json", body),
+        Err(err) => ("500 Internal Server Error", "text/plain", err.to_string()),
+    };
+    match path {
+        "/" | "/index.html" => ("200 OK", "text/html", INDEX_PAGE.to_string()),
+        "/stats.json" => json(serde_json::to_string(&snapshot.stats)),
+        "/coverage.json" => json(serde_json::to_string(&snapshot.coverage)),
+        _ => ("404 Not Found", "text/plain", "Not found".to_string()),
+    }
+}
+
+fn handle(stream: TcpStream, snapshot: &Mutex<StatsSnapshot>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    // E.g. 'GET /stats.json HTTP/1.1'. The headers are ignored.
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) => respond(path, &snapshot.lock().unwrap()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Only GET is supported".to_string(),
+        ),
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Serves the snapshot on the given port of all interfaces in a background
+/// thread. Requests are handled one after another.
+pub fn serve(port: u16, snapshot: Arc<Mutex<StatsSnapshot>>) -> Result<(), String> {
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .map_err(|e| format!("Failed to listen on port {}: {}", port, e))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(err) = handle(stream, &snapshot) {
+                log::warn!("Failed to answer a stats request: {}", err);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{respond, StatsSnapshot};
+
+    #[test]
+    fn respond_to_paths() {
+        let mut snapshot = StatsSnapshot::default();
+        assert_eq!(respond("/stats.json", &snapshot).2, "null");
+        snapshot.coverage = vec![(0.0, 10), (1.5, 20)];
+        let (status, content_type, body) = respond("/coverage.json?t=1", &snapshot);
+        assert_eq!(status, "200 OK");
+        assert_eq!(content_type, "application/json");
+        assert_eq!(body, "[[0.0,10],[1.5,20]]");
+        assert_eq!(respond("/", &snapshot).1, "text/html");
+        assert_eq!(respond("/missing", &snapshot).0, "404 Not Found");
+    }
+}
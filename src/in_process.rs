@@ -0,0 +1,138 @@
+//! Runs a target that is linked as a shared library in the fuzzer process
+//! instead of forking a simulator for every input (see `sim-fuzzer
+//! --in-process`). Much faster for ISS-level models, but a crashing input
+//! restarts the whole client.
+//!
+//! The library exports the libFuzzer entry points:
+//!
+//! ```c
+//! int LLVMFuzzerTestOneInput(const uint8_t *data, size_t size);
+//! // Optional, called once after loading.
+//! int LLVMFuzzerInitialize(int *argc, char ***argv);
+//! ```
+//!
+//! Causes are reported with `reportFuzzingIssueData` from FuzzerAPI.h. If
+//! the library is instrumented with AFL++, its runtime attaches to the
+//! coverage map in `__AFL_SHM_ID` when it is loaded, so the map has to be
+//! set up before calling `InProcessTarget::load`.
+
+use core::fmt::Debug;
+use std::{os::raw::c_char, os::raw::c_int, path::Path, ptr};
+
+use libafl::{
+    executors::{Executor, ExitKind, HasObservers},
+    observers::UsesObservers,
+    state::UsesState,
+    Error,
+};
+use libloading::{Library, Symbol};
+
+type TestOneInputFn = unsafe extern "C" fn(*const u8, usize) -> c_int;
+type InitializeFn = unsafe extern "C" fn(*mut c_int, *mut *mut *mut c_char) -> c_int;
+
+/// A target library loaded into the fuzzer process.
+pub struct InProcessTarget {
+    test_one_input: TestOneInputFn,
+    // Keeps the library (and with it `test_one_input`) loaded.
+    _library: Library,
+}
+
+impl InProcessTarget {
+    /// Loads the library and calls its `LLVMFuzzerInitialize` if it has one.
+    pub fn load(path: &Path) -> Result<InProcessTarget, String> {
+        let library = unsafe { Library::new(path) }
+            .map_err(|e| format!("Failed to load {:?}: {}", path, e))?;
+        let test_one_input = unsafe {
+            let symbol: Symbol<TestOneInputFn> = library
+                .get(b"LLVMFuzzerTestOneInput\0")
+                .map_err(|e| format!("{:?} has no LLVMFuzzerTestOneInput: {}", path, e))?;
+            *symbol
+        };
+        unsafe {
+            if let Ok(initialize) = library.get::<InitializeFn>(b"LLVMFuzzerInitialize\0") {
+                let mut argc: c_int = 0;
+                let mut argv: [*mut c_char; 1] = [ptr::null_mut()];
+                let mut argv_ptr = argv.as_mut_ptr();
+                initialize(&mut argc, &mut argv_ptr);
+            }
+        }
+        Ok(InProcessTarget {
+            test_one_input,
+            _library: library,
+        })
+    }
+
+    /// Runs the target on the given bytes (usually the target bytes of a
+    /// `ProgramInput`).
+    pub fn run(&self, bytes: &[u8]) -> ExitKind {
+        // Like libFuzzer, the return value is ignored. Causes abort.
+        unsafe { (self.test_one_input)(bytes.as_ptr(), bytes.len()) };
+        ExitKind::Ok
+    }
+}
+
+/// Either the forkserver or the in-process executor, so the rest of the
+/// client doesn't depend on which one is used. Both need the same
+/// observers.
+#[derive(Debug)]
+pub enum TargetExecutor<F, P> {
+    Forkserver(F),
+    InProcess(P),
+}
+
+impl<F, P> UsesState for TargetExecutor<F, P>
+where
+    F: UsesState,
+    P: UsesState<State = F::State>,
+{
+    type State = F::State;
+}
+
+impl<F, P> UsesObservers for TargetExecutor<F, P>
+where
+    F: UsesObservers,
+    P: UsesObservers<Observers = F::Observers> + UsesState<State = F::State>,
+{
+    type Observers = F::Observers;
+}
+
+impl<F, P> HasObservers for TargetExecutor<F, P>
+where
+    F: HasObservers,
+    P: HasObservers<Observers = F::Observers> + UsesState<State = F::State>,
+{
+    fn observers(&self) -> &Self::Observers {
+        match self {
+            TargetExecutor::Forkserver(executor) => executor.observers(),
+            TargetExecutor::InProcess(executor) => executor.observers(),
+        }
+    }
+
+    fn observers_mut(&mut self) -> &mut Self::Observers {
+        match self {
+            TargetExecutor::Forkserver(executor) => executor.observers_mut(),
+            TargetExecutor::InProcess(executor) => executor.observers_mut(),
+        }
+    }
+}
+
+impl<F, P, EM, Z> Executor<EM, Z> for TargetExecutor<F, P>
+where
+    F: Executor<EM, Z> + Debug,
+    P: Executor<EM, Z> + UsesState<State = F::State> + Debug,
+    EM: UsesState<State = F::State>,
+    Z: UsesState<State = F::State>,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut Self::State,
+        mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        match self {
+            TargetExecutor::Forkserver(executor) => executor.run_target(fuzzer, state, mgr, input),
+            TargetExecutor::InProcess(executor) => executor.run_target(fuzzer, state, mgr, input),
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! Optional per-input configuration header that is placed in front of the
+//! instructions and consumed by the harness (see `readInputConfig` in
+//! FuzzerAPI.h).
+//!
+//! Layout (all words are little-endian u32):
+//!   CONFIG_HEADER_MAGIC, number of following header words, features,
+//!   privilege level, number of interrupts, (cycle, kind) per interrupt,
+//!   number of register values, (register, low word, high word) per value
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+/// Marks the start of a configuration header. The lowest two bits are not
+/// 0b11, so this word can never be confused with a 32-bit instruction.
+pub const CONFIG_HEADER_MAGIC: u32 = 0x4746_4352;
+
+/// The privilege level the harness should start the program in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PrivilegeLevel {
+    User = 0,
+    Supervisor = 1,
+    #[default]
+    Machine = 3,
+}
+
+impl PrivilegeLevel {
+    pub const ALL: [PrivilegeLevel; 3] = [
+        PrivilegeLevel::User,
+        PrivilegeLevel::Supervisor,
+        PrivilegeLevel::Machine,
+    ];
+
+    pub fn from_u32(value: u32) -> Option<PrivilegeLevel> {
+        PrivilegeLevel::ALL
+            .iter()
+            .find(|level| **level as u32 == value)
+            .copied()
+    }
+
+    /// The full name of the level (e.g. 'machine').
+    pub fn name(&self) -> &'static str {
+        match self {
+            PrivilegeLevel::User => "user",
+            PrivilegeLevel::Supervisor => "supervisor",
+            PrivilegeLevel::Machine => "machine",
+        }
+    }
+
+    /// Parses a privilege level name (e.g. 'm' or 'machine').
+    pub fn from_name(name: &str) -> Option<PrivilegeLevel> {
+        match name.trim().to_lowercase().as_str() {
+            "u" | "user" => Some(PrivilegeLevel::User),
+            "s" | "supervisor" => Some(PrivilegeLevel::Supervisor),
+            "m" | "machine" => Some(PrivilegeLevel::Machine),
+            _ => None,
+        }
+    }
+
+    /// Parses a comma separated list of privilege levels (e.g. 'm,s,u').
+    pub fn parse_list(input: &str) -> Result<Vec<PrivilegeLevel>, String> {
+        let mut result = Vec::<PrivilegeLevel>::new();
+        for name in input.split(',') {
+            let level = PrivilegeLevel::from_name(name)
+                .ok_or(format!("Unknown privilege level: '{}'", name))?;
+            if !result.contains(&level) {
+                result.push(level);
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// The maximum number of interrupts in a schedule.
+pub const MAX_INTERRUPTS: usize = 8;
+
+/// The kind of interrupt the harness should raise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InterruptKind {
+    External = 0,
+    Timer = 1,
+    Software = 2,
+}
+
+impl InterruptKind {
+    pub const ALL: [InterruptKind; 3] = [
+        InterruptKind::External,
+        InterruptKind::Timer,
+        InterruptKind::Software,
+    ];
+
+    pub fn from_u32(value: u32) -> Option<InterruptKind> {
+        InterruptKind::ALL
+            .iter()
+            .find(|kind| **kind as u32 == value)
+            .copied()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InterruptKind::External => "external",
+            InterruptKind::Timer => "timer",
+            InterruptKind::Software => "software",
+        }
+    }
+
+    /// Parses the output of `name`.
+    pub fn from_name(name: &str) -> Option<InterruptKind> {
+        let name = name.trim().to_lowercase();
+        InterruptKind::ALL
+            .iter()
+            .find(|kind| kind.name() == name)
+            .copied()
+    }
+}
+
+/// An interrupt the harness injects after the given number of cycles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Interrupt {
+    /// Cycle offset from the start of the program.
+    pub cycle: u32,
+    pub kind: InterruptKind,
+}
+
+/// The maximum number of initial register values (one per register x1-x31).
+pub const MAX_REGISTER_INITS: usize = 31;
+
+/// A value the harness writes to an integer register before running the
+/// program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RegisterInit {
+    /// The register number (1-31).
+    pub reg: u32,
+    pub value: u64,
+}
+
+/// Configuration the harness applies before running the program.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct InputConfig {
+    /// Harness specific feature toggles (e.g. enabling DUT features).
+    pub features: u32,
+    /// The privilege level the program starts in.
+    pub privilege: PrivilegeLevel,
+    /// Interrupts the harness should inject, sorted by cycle.
+    pub interrupts: Vec<Interrupt>,
+    /// Initial values of integer registers, sorted by register. Registers
+    /// without a value keep the harness's default.
+    pub registers: Vec<RegisterInit>,
+}
+
+impl InputConfig {
+    /// Encodes the header in the format described in the module docs.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = vec![
+            self.features,
+            self.privilege as u32,
+            self.interrupts.len() as u32,
+        ];
+        for interrupt in &self.interrupts {
+            body.push(interrupt.cycle);
+            body.push(interrupt.kind as u32);
+        }
+        body.push(self.registers.len() as u32);
+        for init in &self.registers {
+            body.push(init.reg);
+            body.push(init.value as u32);
+            body.push((init.value >> 32) as u32);
+        }
+        let mut words = vec![CONFIG_HEADER_MAGIC, body.len() as u32];
+        words.extend_from_slice(&body);
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    /// Decodes a header at the start of the given bytes. Returns the config
+    /// and the size of the header in bytes, or None if there is no header.
+    pub fn decode(bytes: &[u8]) -> Result<Option<(InputConfig, usize)>, String> {
+        let word = |i: usize| -> Option<u32> {
+            let chunk = bytes.get(i * 4..i * 4 + 4)?;
+            Some(u32::from_le_bytes(chunk.try_into().unwrap()))
+        };
+
+        if word(0) != Some(CONFIG_HEADER_MAGIC) {
+            return Ok(None);
+        }
+        let len = word(1).ok_or("Truncated config header")? as usize;
+        if len < 2 {
+            return Err(format!("Config header too short: {} words", len));
+        }
+        let features = word(2).ok_or("Truncated config header")?;
+        let privilege_raw = word(3).ok_or("Truncated config header")?;
+        let privilege = PrivilegeLevel::from_u32(privilege_raw)
+            .ok_or(format!("Invalid privilege level: {}", privilege_raw))?;
+        // Skip header words we don't know about. The length is bounded
+        // before computing the size so that it can't overflow.
+        if len > bytes.len() / 4 - 2 {
+            return Err("Truncated config header".to_string());
+        }
+        let size = (2 + len) * 4;
+
+        // Headers from before interrupt schedules existed end here.
+        let mut interrupts = Vec::<Interrupt>::new();
+        if len > 2 {
+            let count = word(4).ok_or("Truncated config header")? as usize;
+            if count > MAX_INTERRUPTS || 3 + 2 * count > len {
+                return Err(format!("Invalid number of interrupts: {}", count));
+            }
+            for i in 0..count {
+                let cycle = word(5 + 2 * i).ok_or("Truncated config header")?;
+                let kind_raw = word(6 + 2 * i).ok_or("Truncated config header")?;
+                let kind = InterruptKind::from_u32(kind_raw)
+                    .ok_or(format!("Invalid interrupt kind: {}", kind_raw))?;
+                interrupts.push(Interrupt { cycle, kind });
+            }
+        }
+
+        // Headers from before register values existed end here.
+        let mut registers = Vec::<RegisterInit>::new();
+        let registers_start = 5 + 2 * interrupts.len();
+        if len > registers_start - 2 {
+            let count = word(registers_start).ok_or("Truncated config header")? as usize;
+            if count > MAX_REGISTER_INITS || registers_start - 1 + 3 * count > len {
+                return Err(format!("Invalid number of register values: {}", count));
+            }
+            for i in 0..count {
+                let start = registers_start + 1 + 3 * i;
+                let reg = word(start).ok_or("Truncated config header")?;
+                if reg == 0 || reg >= 32 {
+                    return Err(format!("Invalid register: {}", reg));
+                }
+                let low = word(start + 1).ok_or("Truncated config header")? as u64;
+                let high = word(start + 2).ok_or("Truncated config header")? as u64;
+                registers.push(RegisterInit {
+                    reg,
+                    value: (high << 32) | low,
+                });
+            }
+        }
+
+        Ok(Some((
+            InputConfig {
+                features,
+                privilege,
+                interrupts,
+                registers,
+            },
+            size,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        InputConfig, Interrupt, InterruptKind, PrivilegeLevel, RegisterInit, CONFIG_HEADER_MAGIC,
+    };
+
+    #[test]
+    fn encode_decode_config() {
+        let config = InputConfig {
+            features: 0xdead,
+            privilege: PrivilegeLevel::Supervisor,
+            interrupts: vec![
+                Interrupt {
+                    cycle: 10,
+                    kind: InterruptKind::Timer,
+                },
+                Interrupt {
+                    cycle: 500,
+                    kind: InterruptKind::External,
+                },
+            ],
+            registers: vec![
+                RegisterInit { reg: 2, value: 0 },
+                RegisterInit {
+                    reg: 31,
+                    value: 0x8000_0000_dead_beef,
+                },
+            ],
+        };
+        let mut bytes = config.encode();
+        let header_len = bytes.len();
+        bytes.extend_from_slice(&[0x13, 0, 0, 0]);
+
+        let (decoded, size) = InputConfig::decode(&bytes).unwrap().unwrap();
+        assert_eq!(decoded, config);
+        assert_eq!(size, header_len);
+    }
+
+    #[test]
+    fn decode_header_without_interrupts() {
+        // Headers only containing features and privilege are still valid.
+        let words = [CONFIG_HEADER_MAGIC, 2, 0x5, 1];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let (decoded, size) = InputConfig::decode(&bytes).unwrap().unwrap();
+        assert_eq!(decoded.features, 0x5);
+        assert_eq!(decoded.privilege, PrivilegeLevel::Supervisor);
+        assert!(decoded.interrupts.is_empty());
+        assert_eq!(size, bytes.len());
+
+        // Headers without register values are still valid.
+        let words = [CONFIG_HEADER_MAGIC, 5, 0, 3, 1, 7, 2];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let (decoded, _) = InputConfig::decode(&bytes).unwrap().unwrap();
+        assert_eq!(decoded.interrupts.len(), 1);
+        assert!(decoded.registers.is_empty());
+    }
+
+    #[test]
+    fn decode_invalid_register() {
+        let words = [CONFIG_HEADER_MAGIC, 6, 0, 3, 0, 1, 0, 0];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        assert!(InputConfig::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn decode_without_header() {
+        // A nop is not a header.
+        assert_eq!(InputConfig::decode(&[0x13, 0, 0, 0]), Ok(None));
+        assert_eq!(InputConfig::decode(&[]), Ok(None));
+    }
+
+    #[test]
+    fn parse_privilege_levels() {
+        assert_eq!(
+            PrivilegeLevel::parse_list("m, S,user"),
+            Ok(vec![
+                PrivilegeLevel::Machine,
+                PrivilegeLevel::Supervisor,
+                PrivilegeLevel::User
+            ])
+        );
+        assert!(PrivilegeLevel::parse_list("m,h").is_err());
+    }
+
+    #[test]
+    fn decode_truncated_header() {
+        let bytes = InputConfig::default().encode();
+        assert!(InputConfig::decode(&bytes[..bytes.len() - 4]).is_err());
+        // A huge length doesn't overflow the header size.
+        let mut bytes = bytes;
+        bytes[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(InputConfig::decode(&bytes).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use core::{fmt::Debug, marker::PhantomData, time::Duration};
+
+use libafl::{
+    events::{Event, EventFirer},
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::UsesInput,
+    monitors::UserStats,
+    observers::UsesObservers,
+    prelude::current_time,
+    state::UsesState,
+    Error,
+};
+
+use crate::{
+    instructions::{Instruction, InstructionTemplate},
+    program_input::HasProgramInput,
+};
+
+/// Decides whether a program should be executed at all.
+pub trait InputFilter {
+    /// Returns true if the program may be executed.
+    fn accepts(&mut self, insts: &[Instruction]) -> bool;
+
+    /// The name of this filter used in diagnostics.
+    fn name(&self) -> &str;
+}
+
+/// Rejects programs with more than the given number of instructions.
+pub struct MaxLengthFilter {
+    max_len: usize,
+}
+
+impl MaxLengthFilter {
+    pub fn new(max_len: usize) -> Self {
+        Self { max_len }
+    }
+}
+
+impl InputFilter for MaxLengthFilter {
+    fn accepts(&mut self, insts: &[Instruction]) -> bool {
+        insts.len() <= self.max_len
+    }
+
+    fn name(&self) -> &str {
+        "MaxLengthFilter"
+    }
+}
+
+/// Rejects programs containing any of the given instructions.
+pub struct ForbiddenInstsFilter {
+    forbidden: Vec<&'static InstructionTemplate>,
+}
+
+impl ForbiddenInstsFilter {
+    pub fn new(forbidden: Vec<&'static InstructionTemplate>) -> Self {
+        Self { forbidden }
+    }
+}
+
+impl InputFilter for ForbiddenInstsFilter {
+    fn accepts(&mut self, insts: &[Instruction]) -> bool {
+        !insts
+            .iter()
+            .any(|inst| self.forbidden.contains(&inst.template()))
+    }
+
+    fn name(&self) -> &str {
+        "ForbiddenInstsFilter"
+    }
+}
+
+// How often the number of rejected inputs is reported to the monitor.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Executor that runs all input filters before passing the input to the
+/// wrapped executor. Rejected inputs are not executed and reported as
+/// 'rejected' in the client stats.
+pub struct FilteringExecutor<E> {
+    executor: E,
+    filters: Vec<Box<dyn InputFilter>>,
+    rejected: u64,
+    last_report: Duration,
+}
+
+impl<E> FilteringExecutor<E> {
+    pub fn new(executor: E, filters: Vec<Box<dyn InputFilter>>) -> Self {
+        Self {
+            executor,
+            filters,
+            rejected: 0,
+            last_report: Duration::ZERO,
+        }
+    }
+
+    /// The number of inputs rejected so far.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    pub fn inner(&mut self) -> &mut E {
+        &mut self.executor
+    }
+}
+
+impl<E: Debug> Debug for FilteringExecutor<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let names: Vec<&str> = self.filters.iter().map(|f| f.name()).collect();
+        f.debug_struct("FilteringExecutor")
+            .field("executor", &self.executor)
+            .field("filters", &names)
+            .field("rejected", &self.rejected)
+            .finish()
+    }
+}
+
+impl<E> UsesState for FilteringExecutor<E>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E> UsesObservers for FilteringExecutor<E>
+where
+    E: UsesObservers,
+{
+    type Observers = E::Observers;
+}
+
+impl<E> HasObservers for FilteringExecutor<E>
+where
+    E: HasObservers,
+{
+    fn observers(&self) -> &Self::Observers {
+        self.executor.observers()
+    }
+
+    fn observers_mut(&mut self) -> &mut Self::Observers {
+        self.executor.observers_mut()
+    }
+}
+
+impl<E, EM, Z> Executor<EM, Z> for FilteringExecutor<E>
+where
+    E: Executor<EM, Z>,
+    EM: EventFirer<State = E::State>,
+    Z: UsesState<State = E::State>,
+    <E::State as UsesInput>::Input: HasProgramInput,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut Self::State,
+        mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        let insts = input.insts();
+        if self.filters.iter_mut().all(|filter| filter.accepts(insts)) {
+            return self.executor.run_target(fuzzer, state, mgr, input);
+        }
+
+        self.rejected += 1;
+        let now = current_time();
+        if now - self.last_report >= REPORT_INTERVAL {
+            self.last_report = now;
+            mgr.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: "rejected".to_string(),
+                    value: UserStats::Number(self.rejected),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+        // The observers were reset before the execution, so the input
+        // can't be interesting.
+        Ok(ExitKind::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::prelude::Xoshiro256StarRand;
+
+    use crate::generator::InstGenerator;
+    use crate::instructions::riscv::rv_i::{ADD, EBREAK};
+
+    use super::{ForbiddenInstsFilter, InputFilter, MaxLengthFilter};
+
+    #[test]
+    fn filter_programs() {
+        let mut rng = Xoshiro256StarRand::default();
+        let generator = InstGenerator::new();
+        let adds = generator.generate_instructions(&mut rng, &vec![&ADD], 4);
+        let ebreak = generator.generate_instructions(&mut rng, &vec![&EBREAK], 1);
+
+        let mut max_len = MaxLengthFilter::new(3);
+        assert!(max_len.accepts(&adds[..3]));
+        assert!(!max_len.accepts(&adds));
+
+        let mut forbidden = ForbiddenInstsFilter::new(vec![&EBREAK]);
+        assert!(forbidden.accepts(&adds));
+        assert!(!forbidden.accepts(&ebreak));
+    }
+}
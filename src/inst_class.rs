@@ -0,0 +1,204 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::instructions::InstructionTemplate;
+
+/// Coarse classes of RISC-V instructions, derived from the major opcode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InstClass {
+    /// Integer and FP loads.
+    Load,
+    /// Integer and FP stores.
+    Store,
+    /// Conditional branches and jumps.
+    Branch,
+    /// Floating point instructions (including FP loads/stores).
+    Fp,
+    /// Atomic memory operations and LR/SC.
+    Atomic,
+    /// Fences, environment calls and CSR accesses.
+    System,
+    /// Everything else (integer computation).
+    Alu,
+}
+
+impl InstClass {
+    pub const ALL: [InstClass; 7] = [
+        InstClass::Load,
+        InstClass::Store,
+        InstClass::Branch,
+        InstClass::Fp,
+        InstClass::Atomic,
+        InstClass::System,
+        InstClass::Alu,
+    ];
+
+    /// Returns all classes the given instruction belongs to.
+    pub fn of(template: &InstructionTemplate) -> Vec<InstClass> {
+        match template.opcode() {
+            0x03 => vec![InstClass::Load],
+            0x07 => vec![InstClass::Load, InstClass::Fp],
+            0x23 => vec![InstClass::Store],
+            0x27 => vec![InstClass::Store, InstClass::Fp],
+            0x2f => vec![InstClass::Atomic],
+            0x63 | 0x67 | 0x6f => vec![InstClass::Branch],
+            0x0f | 0x73 => vec![InstClass::System],
+            0x43 | 0x47 | 0x4b | 0x4f | 0x53 => vec![InstClass::Fp],
+            _ => vec![InstClass::Alu],
+        }
+    }
+
+    /// Parses the name of a class (e.g. 'loads' or 'load').
+    pub fn from_name(name: &str) -> Option<InstClass> {
+        match name.trim().to_lowercase().as_str() {
+            "load" | "loads" => Some(InstClass::Load),
+            "store" | "stores" => Some(InstClass::Store),
+            "branch" | "branches" => Some(InstClass::Branch),
+            "fp" => Some(InstClass::Fp),
+            "atomic" | "atomics" => Some(InstClass::Atomic),
+            "system" => Some(InstClass::System),
+            "alu" => Some(InstClass::Alu),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a comma separated list of class weights (e.g. 'alu=40,load=15').
+pub fn parse_class_weights(input: &str) -> Result<Vec<(InstClass, u32)>, String> {
+    let mut result = Vec::<(InstClass, u32)>::new();
+    for entry in input.split(',') {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let Some((name, weight)) = entry.split_once('=') else {
+            return Err(format!("Expected 'CLASS=WEIGHT' but got '{}'", entry));
+        };
+        let Some(class) = InstClass::from_name(name) else {
+            return Err(format!("Unknown instruction class: '{}'", name));
+        };
+        let weight = weight
+            .trim()
+            .parse::<u32>()
+            .map_err(|e| format!("Invalid weight '{}': {}", weight, e))?;
+        result.push((class, weight));
+    }
+    if result.is_empty() {
+        return Err("No class weights given".to_string());
+    }
+    Ok(result)
+}
+
+/// Restricts which instructions a mutator touches and a generator emits.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstClassFilter {
+    classes: Vec<InstClass>,
+}
+
+impl InstClassFilter {
+    pub fn new(classes: Vec<InstClass>) -> Self {
+        Self { classes }
+    }
+
+    /// A filter accepting all instructions that access memory.
+    pub fn memory() -> Self {
+        Self::new(vec![InstClass::Load, InstClass::Store, InstClass::Atomic])
+    }
+
+    /// Parses a comma separated list of class names (e.g. 'loads,stores').
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut classes = Vec::<InstClass>::new();
+        for name in input.split(',') {
+            if name.trim().is_empty() {
+                continue;
+            }
+            if name.trim() == "memory" {
+                classes.append(&mut Self::memory().classes);
+                continue;
+            }
+            match InstClass::from_name(name) {
+                Some(class) => classes.push(class),
+                None => return Err(format!("Unknown instruction class: '{}'", name)),
+            }
+        }
+        if classes.is_empty() {
+            return Err("No instruction classes given".to_string());
+        }
+        Ok(Self::new(classes))
+    }
+
+    pub fn classes(&self) -> &[InstClass] {
+        &self.classes
+    }
+
+    /// Whether the given instruction belongs to one of the accepted classes.
+    pub fn accepts(&self, template: &InstructionTemplate) -> bool {
+        InstClass::of(template)
+            .iter()
+            .any(|class| self.classes.contains(class))
+    }
+
+    /// Returns only the accepted instructions from the given list.
+    pub fn filter(
+        &self,
+        templates: &[&'static InstructionTemplate],
+    ) -> Vec<&'static InstructionTemplate> {
+        templates
+            .iter()
+            .filter(|t| self.accepts(t))
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instructions::riscv::{rv_a, rv_f, rv_i};
+    use crate::instructions::sets;
+
+    use super::{parse_class_weights, InstClass, InstClassFilter};
+
+    #[test]
+    fn classify_instructions() {
+        assert_eq!(InstClass::of(&rv_i::LB), vec![InstClass::Load]);
+        assert_eq!(InstClass::of(&rv_i::SW), vec![InstClass::Store]);
+        assert_eq!(InstClass::of(&rv_i::BEQ), vec![InstClass::Branch]);
+        assert_eq!(InstClass::of(&rv_i::JAL), vec![InstClass::Branch]);
+        assert_eq!(InstClass::of(&rv_i::ECALL), vec![InstClass::System]);
+        assert_eq!(InstClass::of(&rv_i::ADD), vec![InstClass::Alu]);
+        assert_eq!(InstClass::of(&rv_a::AMOADD_W), vec![InstClass::Atomic]);
+        assert_eq!(
+            InstClass::of(&rv_f::FLW),
+            vec![InstClass::Load, InstClass::Fp]
+        );
+    }
+
+    #[test]
+    fn filter_memory_instructions() {
+        let filter = InstClassFilter::parse("memory").unwrap();
+        let filtered = filter.filter(sets::riscv_g());
+        assert!(!filtered.is_empty());
+        assert!(filtered.contains(&&rv_i::LW));
+        assert!(!filtered.contains(&&rv_i::ADD));
+        assert!(InstClassFilter::parse("loads,bogus").is_err());
+    }
+
+    #[test]
+    fn parse_weights() {
+        assert_eq!(
+            parse_class_weights("alu=40, loads=30,branch=0").unwrap(),
+            vec![
+                (InstClass::Alu, 40),
+                (InstClass::Load, 30),
+                (InstClass::Branch, 0)
+            ]
+        );
+        assert!(parse_class_weights("alu").is_err());
+        assert!(parse_class_weights("alu=-1").is_err());
+        assert!(parse_class_weights("bogus=1").is_err());
+        assert!(parse_class_weights("").is_err());
+    }
+}
@@ -1,21 +1,61 @@
-use std::iter::{zip, Flatten};
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    fmt,
+    iter::{zip, Flatten},
+    ops::Deref,
+};
+
+use serde::{
+    de::Error as _, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer,
+};
+use smallvec::{smallvec, SmallVec};
+
+use crate::{objdump::format_objdump_inst, parser::DecodeTable, registers::RegisterStyle};
 
 pub type EncodedInstruction = u32;
 
+/// The semantic kind of an operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ArgKind {
+    /// An integer register.
+    Gpr,
+    /// A floating point register.
+    Fpr,
+    /// A CSR number.
+    Csr,
+    /// An immediate (or part of one).
+    Imm,
+    /// A shift amount.
+    Shamt,
+    /// A floating point rounding mode.
+    Rm,
+    /// Fence and memory ordering bits (fm, pred, succ, aq, rl).
+    Ordering,
+    /// Part of an opaque instruction word (see `RAW_WORD`).
+    Word,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct ArgumentSpec {
     name: &'static str,
     length: u32,
     offset: u32,
+    kind: ArgKind,
 }
 
 /// Specifies a single
 impl ArgumentSpec {
-    pub fn new(name: &'static str, length: u32, offset: u32) -> Self {
+    pub fn new(name: &'static str, length: u32, offset: u32, kind: ArgKind) -> Self {
         Self {
             name,
             length,
             offset,
+            kind,
         }
     }
 
@@ -29,6 +69,10 @@ impl ArgumentSpec {
         self.length
     }
 
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
     pub fn max_value(&self) -> u32 {
         2u32.pow(self.length)
     }
@@ -36,8 +80,102 @@ impl ArgumentSpec {
     pub fn name(&self) -> &str {
         self.name
     }
+
+    /// The kind of this field as generated by build.rs. The register fields
+    /// are shared by integer and FP instructions and reported as integer
+    /// registers, use `InstructionTemplate::arg_kind` to tell them apart.
+    pub fn kind(&self) -> ArgKind {
+        self.kind
+    }
+
+    /// Whether this argument selects an integer or floating point register.
+    pub fn is_register(&self) -> bool {
+        self.kind() == ArgKind::Gpr
+    }
+
+    /// Whether this argument is (part of) an immediate or shift amount.
+    pub fn is_immediate(&self) -> bool {
+        matches!(self.kind(), ArgKind::Imm | ArgKind::Shamt)
+    }
+
+    /// The logical immediate this field is a part of if the immediate is
+    /// split over several fields or its bits are scrambled.
+    pub fn split_immediate(&self) -> Option<&'static SplitImmediate> {
+        match self.name {
+            "imm12hi" | "imm12lo" => Some(&split_immediates::STORE_OFFSET),
+            "bimm12hi" | "bimm12lo" => Some(&split_immediates::BRANCH_OFFSET),
+            "jimm20" => Some(&split_immediates::JUMP_OFFSET),
+            _ => None,
+        }
+    }
 }
 
+/// Bits `field_lsb..field_lsb + length` of an argument that hold bits
+/// `value_lsb..value_lsb + length` of a split immediate.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImmediatePart {
+    field: &'static ArgumentSpec,
+    field_lsb: u32,
+    length: u32,
+    value_lsb: u32,
+}
+
+/// An immediate whose bits are spread over several fields of the encoding
+/// (S-type and B-type) or are stored in a different order (J-type).
+#[derive(Debug, PartialEq, Eq)]
+pub struct SplitImmediate {
+    name: &'static str,
+    // Number of bits of the logical value including implicit zero bits.
+    length: u32,
+    parts: &'static [ImmediatePart],
+}
+
+impl SplitImmediate {
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    /// The number of bits of the (signed) logical value.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// The lowest bit stored in the encoding. Branch and jump offsets are
+    /// multiples of two, so their bit 0 is implicitly zero.
+    pub fn lowest_bit(&self) -> u32 {
+        self.parts
+            .iter()
+            .map(|part| part.value_lsb)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Assembles the logical value from the fields of the instruction.
+    /// Returns None if the instruction doesn't have all fields.
+    pub fn extract(&self, inst: &Instruction) -> Option<u32> {
+        let mut value = 0;
+        for part in self.parts {
+            let field = inst.arg_value(part.field)?;
+            let bits = (field >> part.field_lsb) & ((1 << part.length) - 1);
+            value |= bits << part.value_lsb;
+        }
+        Some(value)
+    }
+
+    /// Distributes the logical value over the fields of the instruction.
+    pub fn apply(&self, inst: &mut Instruction, value: u32) {
+        for part in self.parts {
+            let mask = ((1 << part.length) - 1) << part.field_lsb;
+            let old = inst.arg_value(part.field).unwrap_or(0);
+            let bits = ((value >> part.value_lsb) << part.field_lsb) & mask;
+            inst.set_arg(Argument::new(part.field, (old & !mask) | bits));
+        }
+    }
+}
+
+/// The most operands an instruction template can have.
+pub const MAX_OPERANDS: usize = 5;
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct InstructionTemplate {
     name: &'static str,
@@ -74,7 +212,9 @@ impl InstructionTemplate {
         }
     }
 
-    pub fn operands(&self) -> Flatten<std::array::IntoIter<&Option<&'static ArgumentSpec>, 5>> {
+    pub fn operands(
+        &self,
+    ) -> Flatten<core::array::IntoIter<&Option<&'static ArgumentSpec>, MAX_OPERANDS>> {
         [
             &self.operand1,
             &self.operand2,
@@ -90,10 +230,50 @@ impl InstructionTemplate {
         self.match_pattern
     }
 
+    pub fn mask(&self) -> EncodedInstruction {
+        self.mask_pattern
+    }
+
+    /// The major opcode (lowest 7 bits) of this instruction.
+    pub fn opcode(&self) -> u32 {
+        self.match_pattern & 0x7f
+    }
+
     pub fn name(&self) -> &str {
         self.name
     }
 
+    /// The kind of the given operand of this instruction. In contrast to
+    /// `ArgumentSpec::kind`, this distinguishes integer and FP registers.
+    pub fn arg_kind(&self, spec: &ArgumentSpec) -> ArgKind {
+        let kind = spec.kind();
+        if kind != ArgKind::Gpr || !self.is_fp() {
+            return kind;
+        }
+        // Moves and conversions are named 'op.dest.src', where 'x' (fmv) or
+        // an integer format (fcvt) denotes an integer register.
+        let parts: Vec<&str> = self.name.split('.').collect();
+        let is_int = |idx: usize| match (parts[0], parts.get(idx)) {
+            ("fmv", Some(part)) => *part == "x",
+            ("fcvt", Some(part)) => ["w", "wu", "l", "lu"].contains(part),
+            _ => false,
+        };
+        let int_rd = ["feq", "flt", "fle", "fclass"].contains(&parts[0]) || is_int(1);
+        // FP loads and stores take an integer base register.
+        let int_rs1 = [0x07, 0x27].contains(&self.opcode()) || is_int(2);
+        match spec.name() {
+            "rd" if int_rd => ArgKind::Gpr,
+            "rs1" if int_rs1 => ArgKind::Gpr,
+            _ => ArgKind::Fpr,
+        }
+    }
+
+    // Whether this is a floating point instruction (including FP loads and
+    // stores).
+    fn is_fp(&self) -> bool {
+        [0x07, 0x27, 0x43, 0x47, 0x4b, 0x4f, 0x53].contains(&self.opcode())
+    }
+
     pub fn op_with_name(&self, name: String) -> Option<&'static ArgumentSpec> {
         for op in self.operands() {
             if op.name() == name {
@@ -112,43 +292,350 @@ impl InstructionTemplate {
             return None;
         }
 
-        let mut args = Vec::<Argument>::new();
-        for arg in self.operands() {
-            args.push(arg.extract(data))
-        }
-        Some(Instruction {
-            template: self,
-            arguments: args,
-        })
+        let args = self.operands().map(|arg| arg.extract(data)).collect();
+        Some(Instruction::from_parts(self, args))
     }
 }
 
 include!(concat!(env!("OUT_DIR"), "/raw_instructions.rs"));
 
+/// The lower half of the word of a `RAW_WORD`.
+pub static RAW_WORD_LO: ArgumentSpec = ArgumentSpec {
+    name: "word_lo",
+    length: 16,
+    offset: 0,
+    kind: ArgKind::Word,
+};
+
+/// The upper half of the word of a `RAW_WORD`.
+pub static RAW_WORD_HI: ArgumentSpec = ArgumentSpec {
+    name: "word_hi",
+    length: 16,
+    offset: 16,
+    kind: ArgKind::Word,
+};
+
+/// A word that is passed to the target as is, e.g. an illegal or reserved
+/// encoding inserted by the 'inject-illegal' mutation. It isn't part of any
+/// instruction set, so parsing only yields it for words that don't decode
+/// if asked to (see `InstDecoder::with_raw_words`). Deserializing always
+/// accepts it, as `Serialize` writes it like any other instruction.
+pub static RAW_WORD: InstructionTemplate = InstructionTemplate {
+    name: ".word",
+    match_pattern: 0,
+    mask_pattern: 0,
+    operand1: Some(&RAW_WORD_LO),
+    operand2: Some(&RAW_WORD_HI),
+    operand3: None,
+    operand4: None,
+    operand5: None,
+};
+
+/// The immediates that are split over several fields.
+pub mod split_immediates {
+    use super::riscv::args;
+    use super::{ImmediatePart, SplitImmediate};
+
+    /// imm[11:5] in imm12hi, imm[4:0] in imm12lo.
+    pub static STORE_OFFSET: SplitImmediate = SplitImmediate {
+        name: "store_offset",
+        length: 12,
+        parts: &[
+            ImmediatePart {
+                field: &args::IMM12HI,
+                field_lsb: 0,
+                length: 7,
+                value_lsb: 5,
+            },
+            ImmediatePart {
+                field: &args::IMM12LO,
+                field_lsb: 0,
+                length: 5,
+                value_lsb: 0,
+            },
+        ],
+    };
+
+    /// imm[12|10:5] in bimm12hi, imm[4:1|11] in bimm12lo.
+    pub static BRANCH_OFFSET: SplitImmediate = SplitImmediate {
+        name: "branch_offset",
+        length: 13,
+        parts: &[
+            ImmediatePart {
+                field: &args::BIMM12HI,
+                field_lsb: 6,
+                length: 1,
+                value_lsb: 12,
+            },
+            ImmediatePart {
+                field: &args::BIMM12HI,
+                field_lsb: 0,
+                length: 6,
+                value_lsb: 5,
+            },
+            ImmediatePart {
+                field: &args::BIMM12LO,
+                field_lsb: 1,
+                length: 4,
+                value_lsb: 1,
+            },
+            ImmediatePart {
+                field: &args::BIMM12LO,
+                field_lsb: 0,
+                length: 1,
+                value_lsb: 11,
+            },
+        ],
+    };
+
+    /// imm[20|10:1|11|19:12] in jimm20.
+    pub static JUMP_OFFSET: SplitImmediate = SplitImmediate {
+        name: "jump_offset",
+        length: 21,
+        parts: &[
+            ImmediatePart {
+                field: &args::JIMM20,
+                field_lsb: 19,
+                length: 1,
+                value_lsb: 20,
+            },
+            ImmediatePart {
+                field: &args::JIMM20,
+                field_lsb: 9,
+                length: 10,
+                value_lsb: 1,
+            },
+            ImmediatePart {
+                field: &args::JIMM20,
+                field_lsb: 8,
+                length: 1,
+                value_lsb: 11,
+            },
+            ImmediatePart {
+                field: &args::JIMM20,
+                field_lsb: 0,
+                length: 8,
+                value_lsb: 12,
+            },
+        ],
+    };
+}
+
 pub mod sets {
+    use alloc::{
+        boxed::Box,
+        format,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
+
+    use once_cell::race::OnceBox;
+
     use super::riscv::*;
     use super::InstructionTemplate;
 
-    pub fn riscv_g() -> Vec<&'static InstructionTemplate> {
+    /// An extension as generated from riscv-opcodes (see build.rs).
+    #[derive(Debug)]
+    pub struct RawExtension {
+        /// The riscv-opcodes name, e.g. 'rv_i' or 'rv64_m'.
+        pub name: &'static str,
+        pub insts: &'static [&'static InstructionTemplate],
+    }
+
+    /// All generated extensions, in the order of build.rs.
+    pub static REGISTRY: [RawExtension; 14] = [
+        RawExtension {
+            name: "rv_i",
+            insts: &rv_i::INSTS,
+        },
+        RawExtension {
+            name: "rv_m",
+            insts: &rv_m::INSTS,
+        },
+        RawExtension {
+            name: "rv_a",
+            insts: &rv_a::INSTS,
+        },
+        RawExtension {
+            name: "rv_f",
+            insts: &rv_f::INSTS,
+        },
+        RawExtension {
+            name: "rv_d",
+            insts: &rv_d::INSTS,
+        },
+        RawExtension {
+            name: "rv64_i",
+            insts: &rv64_i::INSTS,
+        },
+        RawExtension {
+            name: "rv64_m",
+            insts: &rv64_m::INSTS,
+        },
+        RawExtension {
+            name: "rv64_a",
+            insts: &rv64_a::INSTS,
+        },
+        RawExtension {
+            name: "rv64_f",
+            insts: &rv64_f::INSTS,
+        },
+        RawExtension {
+            name: "rv64_d",
+            insts: &rv64_d::INSTS,
+        },
+        RawExtension {
+            name: "rv_zicsr",
+            insts: &rv_zicsr::INSTS,
+        },
+        RawExtension {
+            name: "rv_zifencei",
+            insts: &rv_zifencei::INSTS,
+        },
+        RawExtension {
+            name: "rv_system",
+            insts: &rv_system::INSTS,
+        },
+        RawExtension {
+            name: "rv_s",
+            insts: &rv_s::INSTS,
+        },
+    ];
+
+    /// Looks up a generated extension by its riscv-opcodes name (ignoring
+    /// the case).
+    pub fn lookup(name: &str) -> Option<&'static RawExtension> {
+        REGISTRY
+            .iter()
+            .find(|ext| ext.name.eq_ignore_ascii_case(name))
+    }
+
+    // The templates of the generated extensions with the given names.
+    fn concat(names: &[&str]) -> Vec<&'static InstructionTemplate> {
+        names
+            .iter()
+            .flat_map(|name| lookup(name).expect("Extension not generated").insts)
+            .copied()
+            .collect()
+    }
+
+    /// The RV64G instructions. Built on first use.
+    pub fn riscv_g() -> &'static [&'static InstructionTemplate] {
+        static SET: OnceBox<Vec<&'static InstructionTemplate>> = OnceBox::new();
+        SET.get_or_init(|| {
+            // G also includes the Zicsr and Zifencei extensions.
+            Box::new(concat(&[
+                "rv64_i",
+                "rv64_a",
+                "rv64_d",
+                "rv64_f",
+                "rv64_m",
+                "rv_i",
+                "rv_a",
+                "rv_d",
+                "rv_f",
+                "rv_m",
+                "rv_zicsr",
+                "rv_zifencei",
+            ]))
+        })
+    }
+
+    /// The RV64I instructions. Built on first use.
+    pub fn riscv_base() -> &'static [&'static InstructionTemplate] {
+        static SET: OnceBox<Vec<&'static InstructionTemplate>> = OnceBox::new();
+        SET.get_or_init(|| Box::new(concat(&["rv64_i", "rv_i"])))
+    }
+
+    /// The instructions of every ISA extension. The RV64 additions are part
+    /// of their base extension.
+    pub fn extensions() -> &'static [(&'static str, Vec<&'static InstructionTemplate>)] {
+        static SETS: OnceBox<Vec<(&'static str, Vec<&'static InstructionTemplate>)>> =
+            OnceBox::new();
+        SETS.get_or_init(|| {
+            Box::new(vec![
+                ("I", concat(&["rv_i", "rv64_i"])),
+                ("M", concat(&["rv_m", "rv64_m"])),
+                ("A", concat(&["rv_a", "rv64_a"])),
+                ("F", concat(&["rv_f", "rv64_f"])),
+                ("D", concat(&["rv_d", "rv64_d"])),
+                ("Zicsr", concat(&["rv_zicsr"])),
+                ("Zifencei", concat(&["rv_zifencei"])),
+                ("System", concat(&["rv_system"])),
+                ("S", concat(&["rv_s"])),
+            ])
+        })
+    }
+
+    /// The name of the extension the instruction belongs to.
+    pub fn extension_of(template: &InstructionTemplate) -> Option<&'static str> {
+        extensions()
+            .iter()
+            .find(|(_, insts)| insts.contains(&template))
+            .map(|(name, _)| *name)
+    }
+
+    /// Parses a comma separated list of mnemonics (e.g. 'fence.i,ebreak').
+    pub fn parse_template_list(input: &str) -> Result<Vec<&'static InstructionTemplate>, String> {
         let mut result = Vec::<&'static InstructionTemplate>::new();
-        result.append(&mut rv64_i::INSTS.to_vec());
-        result.append(&mut rv64_a::INSTS.to_vec());
-        result.append(&mut rv64_d::INSTS.to_vec());
-        result.append(&mut rv64_f::INSTS.to_vec());
-        result.append(&mut rv64_m::INSTS.to_vec());
-        result.append(&mut rv_i::INSTS.to_vec());
-        result.append(&mut rv_a::INSTS.to_vec());
-        result.append(&mut rv_d::INSTS.to_vec());
-        result.append(&mut rv_f::INSTS.to_vec());
-        result.append(&mut rv_m::INSTS.to_vec());
-        result
+        for name in input.split(',').map(|name| name.trim().to_lowercase()) {
+            if name.is_empty() {
+                continue;
+            }
+            match all().iter().find(|t| t.name() == name) {
+                Some(template) => result.push(*template),
+                None => return Err(format!("Unknown instruction: '{}'", name)),
+            }
+        }
+        Ok(result)
     }
 
-    pub fn riscv_base() -> Vec<&'static InstructionTemplate> {
+    /// Parses a comma separated list of extensions (e.g. 'rv_i,rv_m,zicsr')
+    /// into the templates of all listed extensions. The 'rv_'/'rv64_'
+    /// prefix and the case are ignored, 'g' stands for `riscv_g`.
+    pub fn parse_extension_list(input: &str) -> Result<Vec<&'static InstructionTemplate>, String> {
         let mut result = Vec::<&'static InstructionTemplate>::new();
-        result.append(&mut rv64_i::INSTS.to_vec());
-        result.append(&mut rv_i::INSTS.to_vec());
-        result
+        for entry in input.split(',').map(|name| name.trim().to_lowercase()) {
+            let name = entry
+                .strip_prefix("rv64_")
+                .or_else(|| entry.strip_prefix("rv_"))
+                .unwrap_or(&entry);
+            if name.is_empty() {
+                continue;
+            }
+            let templates: &[&'static InstructionTemplate] = if name == "g" {
+                riscv_g()
+            } else {
+                match extensions()
+                    .iter()
+                    .find(|(ext, _)| ext.eq_ignore_ascii_case(name))
+                {
+                    Some((_, insts)) => insts,
+                    None => return Err(format!("Unknown extension: '{}'", entry)),
+                }
+            };
+            for template in templates {
+                if !result.contains(template) {
+                    result.push(template);
+                }
+            }
+        }
+        if result.is_empty() {
+            return Err("No extensions selected".to_string());
+        }
+        Ok(result)
+    }
+
+    /// Returns the set without the excluded templates.
+    pub fn without(
+        set: &[&'static InstructionTemplate],
+        excluded: &[&'static InstructionTemplate],
+    ) -> Vec<&'static InstructionTemplate> {
+        set.iter()
+            .filter(|t| !excluded.contains(t))
+            .copied()
+            .collect()
     }
 }
 
@@ -174,21 +661,111 @@ impl Argument {
     }
 }
 
+/// An argument of an instruction that can be changed in place, see
+/// `Instruction::arg_mut`.
+pub struct ArgumentMut<'a> {
+    inst: &'a mut Instruction,
+    idx: usize,
+}
+
+impl ArgumentMut<'_> {
+    /// Sets the raw value and updates the instruction's encoding.
+    pub fn set_value(&mut self, value: u32) {
+        self.inst.arguments[self.idx].value = value;
+        self.inst.update_encoding();
+    }
+}
+
+impl Deref for ArgumentMut<'_> {
+    type Target = Argument;
+
+    fn deref(&self) -> &Argument {
+        &self.inst.arguments[self.idx]
+    }
+}
+
+/// Why `Instruction::try_new` rejected the arguments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InstructionError {
+    /// The instruction has no operand of that name.
+    UnknownOperand { inst: String, operand: String },
+    /// No argument was given for the operand.
+    MissingOperand { inst: String, operand: String },
+    /// More than one argument was given for the operand.
+    DuplicateOperand { inst: String, operand: String },
+    /// The value doesn't fit into the bits of the operand's field.
+    OutOfRange {
+        inst: String,
+        operand: String,
+        value: u32,
+        bits: u32,
+    },
+}
+
+impl fmt::Display for InstructionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InstructionError::UnknownOperand { inst, operand } => {
+                write!(f, "{} has no operand {}", inst, operand)
+            }
+            InstructionError::MissingOperand { inst, operand } => {
+                write!(f, "Missing operand {} of {}", operand, inst)
+            }
+            InstructionError::DuplicateOperand { inst, operand } => {
+                write!(f, "Duplicate operand {} of {}", operand, inst)
+            }
+            InstructionError::OutOfRange {
+                inst,
+                operand,
+                value,
+                bits,
+            } => write!(
+                f,
+                "Value {} of operand {} of {} doesn't fit into {} bits",
+                value, operand, inst, bits
+            ),
+        }
+    }
+}
+
+/// The arguments of an instruction. They are stored inline, so that
+/// instructions can be cloned without allocating.
+type Arguments = SmallVec<[Argument; MAX_OPERANDS]>;
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Instruction {
     template: &'static InstructionTemplate,
-    arguments: Vec<Argument>,
+    arguments: Arguments,
+    // The encoding of the template and arguments, updated on every change.
+    encoded: EncodedInstruction,
 }
 
 impl Instruction {
     pub fn encode(&self) -> EncodedInstruction {
+        self.encoded
+    }
+
+    // Creates the instruction without checking the arguments.
+    fn from_parts(template: &'static InstructionTemplate, arguments: Arguments) -> Instruction {
+        let mut result = Instruction {
+            template,
+            arguments,
+            encoded: 0,
+        };
+        result.update_encoding();
+        result
+    }
+
+    fn update_encoding(&mut self) {
         let mut result: EncodedInstruction = self.template.base_pattern();
         for arg in &self.arguments {
             result |= arg.encode()
         }
-        result
+        self.encoded = result;
     }
 
+    /// Creates an instruction from arguments in the template's order. They
+    /// are only checked in debug builds, see `try_new`.
     pub fn new(template: &'static InstructionTemplate, arguments: Vec<Argument>) -> Instruction {
         // Check that the arguments match the template's arguments.
         debug_assert_eq!(template.operands().count(), arguments.len());
@@ -199,10 +776,78 @@ impl Instruction {
             debug_assert_eq!(i.0.spec.name, i.1.name);
         }
 
-        Instruction {
-            template,
-            arguments,
+        Instruction::from_parts(template, SmallVec::from_vec(arguments))
+    }
+
+    /// Like `new`, but fails instead of encoding garbage if an argument isn't
+    /// an operand of the template, is given twice or doesn't fit into its
+    /// field, or an operand is missing. The arguments may be in any order.
+    pub fn try_new(
+        template: &'static InstructionTemplate,
+        arguments: Vec<Argument>,
+    ) -> Result<Instruction, InstructionError> {
+        let names = |spec: &ArgumentSpec| (template.name().to_string(), spec.name.to_string());
+        for (i, arg) in arguments.iter().enumerate() {
+            let (inst, operand) = names(arg.spec);
+            if !template.operands().any(|spec| *spec == arg.spec) {
+                return Err(InstructionError::UnknownOperand { inst, operand });
+            }
+            if arguments[..i].iter().any(|other| other.spec == arg.spec) {
+                return Err(InstructionError::DuplicateOperand { inst, operand });
+            }
+            if arg.value >= arg.spec.max_value() {
+                return Err(InstructionError::OutOfRange {
+                    inst,
+                    operand,
+                    value: arg.value,
+                    bits: arg.spec.length,
+                });
+            }
+        }
+        let mut ordered = Arguments::new();
+        for spec in template.operands() {
+            let Some(arg) = arguments.iter().find(|arg| arg.spec == *spec) else {
+                let (inst, operand) = names(spec);
+                return Err(InstructionError::MissingOperand { inst, operand });
+            };
+            ordered.push(arg.clone());
+        }
+        Ok(Instruction::from_parts(template, ordered))
+    }
+
+    /// Checks that the arguments match the template's operands and fit into
+    /// their fields, like `try_new` does when creating the instruction. `new`
+    /// only checks the operands in debug builds.
+    pub fn check(&self) -> Result<(), String> {
+        let operands = self.template.operands().count();
+        if operands != self.arguments.len() {
+            return Err(format!(
+                "{} takes {} arguments, got {}",
+                self.template.name(),
+                operands,
+                self.arguments.len()
+            ));
         }
+        for (arg, spec) in zip(&self.arguments, self.template.operands()) {
+            if arg.spec.name != spec.name {
+                return Err(format!(
+                    "{} expects argument {}, got {}",
+                    self.template.name(),
+                    spec.name,
+                    arg.spec.name
+                ));
+            }
+            if arg.value >= spec.max_value() {
+                return Err(format!(
+                    "Value {} of operand {} of {} doesn't fit into {} bits",
+                    arg.value,
+                    spec.name,
+                    self.template.name(),
+                    spec.length
+                ));
+            }
+        }
+        Ok(())
     }
 
     pub fn arguments(&self) -> &[Argument] {
@@ -213,15 +858,194 @@ impl Instruction {
         self.template
     }
 
-    pub fn set_arg(&mut self, new_arg: Argument) {
-        // Delete the old argument if it exists.
+    /// Returns the value of the argument with the given spec.
+    pub fn arg_value(&self, spec: &ArgumentSpec) -> Option<u32> {
         self.arguments
-            .retain(|arg: &Argument| arg.spec != new_arg.spec);
-        // Add the new argument at the end.
-        self.arguments.push(new_arg);
+            .iter()
+            .find(|arg| arg.spec == spec)
+            .map(|arg| arg.value)
+    }
+
+    /// The argument at `idx` (in the template's order) for changing its
+    /// value in place.
+    pub fn arg_mut(&mut self, idx: usize) -> Option<ArgumentMut<'_>> {
+        if idx >= self.arguments.len() {
+            return None;
+        }
+        Some(ArgumentMut { inst: self, idx })
+    }
+
+    pub fn set_arg(&mut self, new_arg: Argument) {
+        // Replace the old argument in place to keep the template's order.
+        if let Some(arg) = self
+            .arguments
+            .iter_mut()
+            .find(|arg| arg.spec == new_arg.spec)
+        {
+            *arg = new_arg;
+        } else {
+            // Add the new argument at the end.
+            self.arguments.push(new_arg);
+        }
+        self.update_encoding();
+    }
+
+    /// A `RAW_WORD` with the given encoding.
+    pub fn raw_word(word: EncodedInstruction) -> Instruction {
+        Instruction::from_parts(
+            &RAW_WORD,
+            smallvec![RAW_WORD_LO.extract(word), RAW_WORD_HI.extract(word)],
+        )
+    }
+
+    /// Whether this is a `RAW_WORD` instead of an instruction.
+    pub fn is_raw_word(&self) -> bool {
+        core::ptr::eq(self.template, &RAW_WORD)
+    }
+
+    /// Starts building an instruction from named operands. See also `inst!`.
+    pub fn builder(template: &'static InstructionTemplate) -> InstructionBuilder {
+        InstructionBuilder {
+            template,
+            arguments: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+/// Formats the instruction like the disassembler (e.g. 'addi\tsp,sp,-16'),
+/// with branch targets relative to address 0.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_objdump_inst(self, 0, RegisterStyle::Abi))
     }
 }
 
+// The operands of an instruction as a map from their names to their values,
+// in the template's order.
+struct NamedOperands<'a>(&'a Instruction);
+
+impl Serialize for NamedOperands<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(
+            self.0
+                .arguments
+                .iter()
+                .map(|arg| (arg.spec.name, arg.value)),
+        )
+    }
+}
+
+/// Human-readable formats (e.g. JSON or TOML) get the mnemonic and the
+/// operands by name, like `{"mnemonic":"addi","operands":{"rd":1,...}}`.
+/// Binary formats (e.g. postcard) get the encoded instruction, so words that
+/// don't decode are read back as `RAW_WORD`s.
+impl Serialize for Instruction {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if !serializer.is_human_readable() {
+            return serializer.serialize_u32(self.encode());
+        }
+        let mut inst = serializer.serialize_struct("Instruction", 2)?;
+        inst.serialize_field("mnemonic", self.template.name())?;
+        inst.serialize_field("operands", &NamedOperands(self))?;
+        inst.end()
+    }
+}
+
+// The human-readable form of an instruction, see `Serialize for Instruction`.
+#[derive(Deserialize)]
+#[serde(rename = "Instruction")]
+struct StructuredInstruction {
+    mnemonic: String,
+    operands: BTreeMap<String, u32>,
+}
+
+impl<'de> Deserialize<'de> for Instruction {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if !deserializer.is_human_readable() {
+            let word = u32::deserialize(deserializer)?;
+            let inst = DecodeTable::cached(riscv::all())
+                .decode(word)
+                .unwrap_or_else(|| Instruction::raw_word(word));
+            inst.check().map_err(D::Error::custom)?;
+            return Ok(inst);
+        }
+        let inst = StructuredInstruction::deserialize(deserializer)?;
+        let template = riscv::all()
+            .iter()
+            .copied()
+            .chain([&RAW_WORD])
+            .find(|template| template.name() == inst.mnemonic)
+            .ok_or_else(|| D::Error::custom(format!("Unknown instruction {}", inst.mnemonic)))?;
+        let mut builder = Instruction::builder(template);
+        for (name, value) in &inst.operands {
+            builder = builder.arg(name, *value);
+        }
+        builder.build().map_err(D::Error::custom)
+    }
+}
+
+/// Builds an instruction from operands given by name, validating the names,
+/// the value ranges and that every operand is set when calling `build`.
+#[derive(Clone, Debug)]
+pub struct InstructionBuilder {
+    template: &'static InstructionTemplate,
+    arguments: Vec<Argument>,
+    // The first error, reported by `build`.
+    error: Option<InstructionError>,
+}
+
+impl InstructionBuilder {
+    /// Sets the raw value of the operand with the given name.
+    #[must_use]
+    pub fn arg(mut self, name: &str, value: u32) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        match self.template.op_with_name(name.to_string()) {
+            Some(spec) => self.arguments.push(Argument::new(spec, value)),
+            None => {
+                self.error = Some(InstructionError::UnknownOperand {
+                    inst: self.template.name().to_string(),
+                    operand: name.to_string(),
+                })
+            }
+        }
+        self
+    }
+
+    /// Returns the instruction with the arguments in the template's order.
+    pub fn build(self) -> Result<Instruction, InstructionError> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Instruction::try_new(self.template, self.arguments),
+        }
+    }
+}
+
+/// Builds an instruction from a template and its operands by name, e.g.
+/// `inst!(ADDI, rd = 1, rs1 = 0, imm12 = 42)`. Values are the raw field
+/// values. Panics with the `InstructionError`, use `Instruction::builder` to
+/// handle it.
+#[macro_export]
+macro_rules! inst {
+    ($template:expr $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::instructions::Instruction::builder(&$template)
+            $(.arg(stringify!($name), $value))*
+            .build()
+            .unwrap_or_else(|err| panic!("{}", err))
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use crate::instructions::riscv::args;
@@ -277,4 +1101,200 @@ mod tests {
         // Do a whole decode-encode roundabout with this instruction.
         assert_eq!(ADD.decode(inst.encode()).unwrap(), inst);
     }
+
+    #[test]
+    fn check_arguments() {
+        let inst = Instruction::new(
+            &ADD,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 2),
+                Argument::new(&args::RS2, 4),
+            ],
+        );
+        assert_eq!(inst.check(), Ok(()));
+
+        // Bypass the debug assertions of `new`.
+        let missing = Instruction::from_parts(
+            &ADD,
+            smallvec![Argument::new(&args::RD, 1), Argument::new(&args::RS1, 2)],
+        );
+        assert!(missing.check().is_err());
+        let wrong = Instruction::from_parts(
+            &ADD,
+            smallvec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 2),
+                Argument::new(&args::IMM12, 4),
+            ],
+        );
+        assert!(wrong.check().is_err());
+    }
+
+    #[test]
+    fn change_argument_in_place() {
+        let mut inst = inst!(ADDI, rd = 1, rs1 = 2, imm12 = 3);
+        let mut arg = inst.arg_mut(2).unwrap();
+        assert_eq!(arg.spec(), &args::IMM12);
+        arg.set_value(0xfff);
+        assert_eq!(inst.arg_value(&args::IMM12), Some(0xfff));
+        assert_eq!(
+            inst.encode(),
+            inst!(ADDI, rd = 1, rs1 = 2, imm12 = 0xfff).encode()
+        );
+        assert!(inst.arg_mut(3).is_none());
+    }
+
+    #[test]
+    fn raw_word() {
+        let inst = Instruction::raw_word(0xdead_beef);
+        assert!(inst.is_raw_word());
+        assert_eq!(inst.encode(), 0xdead_beef);
+        assert_eq!(inst.check(), Ok(()));
+        assert!(!inst!(ADDI, rd = 1, rs1 = 2, imm12 = 3).is_raw_word());
+    }
+
+    #[test]
+    fn build_instruction() {
+        let inst = inst!(ADDI, imm12 = 42, rd = 1, rs1 = 0);
+        assert_eq!(inst.check(), Ok(()));
+        assert_eq!(inst.arg_value(&args::RD), Some(1));
+        assert_eq!(inst.arg_value(&args::IMM12), Some(42));
+        assert_eq!(ADDI.decode(inst.encode()).unwrap(), inst);
+
+        let build = |builder: InstructionBuilder| builder.build();
+        assert!(build(Instruction::builder(&ADDI).arg("rs2", 1)).is_err());
+        assert!(build(Instruction::builder(&ADDI).arg("rd", 32)).is_err());
+        assert!(build(Instruction::builder(&ADDI).arg("rd", 1).arg("rd", 2)).is_err());
+        assert!(build(Instruction::builder(&ADDI).arg("rd", 1).arg("rs1", 2)).is_err());
+    }
+
+    #[test]
+    fn try_new_checks_arguments() {
+        let inst = Instruction::try_new(
+            &ADDI,
+            vec![
+                Argument::new(&args::IMM12, 42),
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 0),
+            ],
+        )
+        .unwrap();
+        assert_eq!(inst.arguments()[0].spec(), &args::RD);
+
+        let rd = |value| Argument::new(&args::RD, value);
+        let rs1 = || Argument::new(&args::RS1, 0);
+        let imm12 = || Argument::new(&args::IMM12, 0);
+        assert_eq!(
+            Instruction::try_new(&ADDI, vec![rd(32), rs1(), imm12()]),
+            Err(InstructionError::OutOfRange {
+                inst: "addi".to_string(),
+                operand: "rd".to_string(),
+                value: 32,
+                bits: 5
+            })
+        );
+        assert!(matches!(
+            Instruction::try_new(&ADDI, vec![rd(1), rs1()]),
+            Err(InstructionError::MissingOperand { .. })
+        ));
+        assert!(matches!(
+            Instruction::try_new(&ADDI, vec![rd(1), rd(2), rs1(), imm12()]),
+            Err(InstructionError::DuplicateOperand { .. })
+        ));
+        assert!(matches!(
+            Instruction::try_new(
+                &ADDI,
+                vec![rd(1), rs1(), imm12(), Argument::new(&args::RS2, 0)]
+            ),
+            Err(InstructionError::UnknownOperand { .. })
+        ));
+        // `check` finds the same errors in instructions created by `new`.
+        assert!(Instruction::new(&ADDI, vec![rd(32), rs1(), imm12()])
+            .check()
+            .is_err());
+    }
+
+    #[test]
+    fn display_instruction() {
+        let inst = inst!(ADDI, rd = 2, rs1 = 2, imm12 = 0xff0);
+        assert_eq!(inst.to_string(), "addi\tsp,sp,-16");
+    }
+
+    #[test]
+    fn split_immediates() {
+        // beq x1, x2, -8
+        let mut beq = BEQ.decode(0xfe208ce3).unwrap();
+        let split = args::BIMM12LO.split_immediate().unwrap();
+        assert_eq!(split.extract(&beq), Some(0x1ff8));
+        assert_eq!(split.lowest_bit(), 1);
+        split.apply(&mut beq, 0x800);
+        assert_eq!(beq.arg_value(&args::BIMM12HI), Some(0));
+        assert_eq!(beq.arg_value(&args::BIMM12LO), Some(1));
+
+        // jal x0, 2048 and jal x1, -2
+        let split = args::JIMM20.split_immediate().unwrap();
+        assert_eq!(split.extract(&JAL.decode(0x0010006f).unwrap()), Some(0x800));
+        let mut jal = JAL.decode(0x0010006f).unwrap();
+        split.apply(&mut jal, 0x1ffffe);
+        jal.set_arg(Argument::new(&args::RD, 1));
+        assert_eq!(jal.encode(), 0xfffff0ef);
+
+        // sw x2, -4(x1)
+        let split = args::IMM12HI.split_immediate().unwrap();
+        assert_eq!(split.extract(&SW.decode(0xfe20ae23).unwrap()), Some(0xffc));
+        assert!(args::IMM12.split_immediate().is_none());
+    }
+
+    #[test]
+    fn parse_extension_lists() {
+        use crate::instructions::riscv::rv_m::MUL;
+
+        let set = sets::parse_extension_list("rv_i, RV64_M,i").unwrap();
+        assert!(set.contains(&&ADD) && set.contains(&&MUL));
+        assert_eq!(
+            set.len(),
+            sets::extensions()[0].1.len() + sets::extensions()[1].1.len()
+        );
+        assert_eq!(
+            sets::parse_extension_list("g").unwrap().len(),
+            sets::riscv_g().len()
+        );
+        assert!(sets::parse_extension_list("rv_q").is_err());
+        assert!(sets::parse_extension_list(",").is_err());
+    }
+
+    #[test]
+    fn extension_registry() {
+        assert_eq!(sets::lookup("RV64_M").unwrap().name, "rv64_m");
+        assert!(sets::lookup("m").is_none());
+        assert!(sets::lookup("rv_i").unwrap().insts.contains(&&ADD));
+        let total: usize = sets::REGISTRY.iter().map(|ext| ext.insts.len()).sum();
+        assert_eq!(total, riscv::all().len());
+    }
+
+    #[test]
+    fn argument_kinds() {
+        use crate::instructions::riscv::{
+            rv64_f::FCVT_L_S,
+            rv_f::{FADD_S, FLW, FSW},
+        };
+
+        assert_eq!(args::RD.kind(), ArgKind::Gpr);
+        assert_eq!(args::CSR.kind(), ArgKind::Csr);
+        assert_eq!(args::ZIMM.kind(), ArgKind::Imm);
+        assert_eq!(args::SHAMTW.kind(), ArgKind::Shamt);
+        assert_eq!(args::RM.kind(), ArgKind::Rm);
+        assert_eq!(args::PRED.kind(), ArgKind::Ordering);
+        assert_eq!(RAW_WORD_LO.kind(), ArgKind::Word);
+
+        assert_eq!(ADD.arg_kind(&args::RD), ArgKind::Gpr);
+        assert_eq!(FADD_S.arg_kind(&args::RS2), ArgKind::Fpr);
+        assert_eq!(FADD_S.arg_kind(&args::RM), ArgKind::Rm);
+        assert_eq!(FLW.arg_kind(&args::RD), ArgKind::Fpr);
+        assert_eq!(FLW.arg_kind(&args::RS1), ArgKind::Gpr);
+        assert_eq!(FSW.arg_kind(&args::RS2), ArgKind::Fpr);
+        assert_eq!(FCVT_L_S.arg_kind(&args::RD), ArgKind::Gpr);
+        assert_eq!(FCVT_L_S.arg_kind(&args::RS1), ArgKind::Fpr);
+    }
 }
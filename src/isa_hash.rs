@@ -0,0 +1,91 @@
+use std::{fs, path::Path};
+
+use crate::instructions::{self, InstructionTemplate};
+
+/// Name of the file storing the ISA hash in an output directory.
+pub const ISA_HASH_FILE: &str = "isa_hash";
+
+// FNV-1a parameters. Used instead of std's hasher as the result needs to be
+// stable across Rust versions.
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv_update(hash: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(hash, |h, b| (h ^ (*b as u64)).wrapping_mul(FNV_PRIME))
+}
+
+/// Computes a stable hash over the names, encodings and operands of the given
+/// instruction templates. The order of the templates does not matter.
+pub fn hash_templates(templates: &[&'static InstructionTemplate]) -> u64 {
+    let mut sorted = templates.to_vec();
+    sorted.sort_by_key(|t| t.name());
+
+    let mut hash = FNV_OFFSET;
+    for template in sorted {
+        hash = fnv_update(hash, template.name().as_bytes());
+        hash = fnv_update(hash, &template.base_pattern().to_le_bytes());
+        hash = fnv_update(hash, &template.mask().to_le_bytes());
+        for op in template.operands() {
+            hash = fnv_update(hash, op.name().as_bytes());
+            hash = fnv_update(hash, &op.length().to_le_bytes());
+            hash = fnv_update(hash, &op.offset().to_le_bytes());
+        }
+    }
+    hash
+}
+
+/// The hash of all instructions this crate was compiled with.
+pub fn isa_hash() -> u64 {
+    hash_templates(instructions::riscv::all())
+}
+
+/// Writes the current ISA hash into the given directory.
+pub fn write_isa_hash_file(dir: &Path) -> std::io::Result<()> {
+    fs::write(dir.join(ISA_HASH_FILE), format!("{:016x}\n", isa_hash()))
+}
+
+/// Checks the ISA hash stored in the given directory or any of its parents
+/// against the current ISA hash. Directories without hash file are accepted.
+pub fn check_isa_hash_file(path: &Path) -> Result<(), String> {
+    for dir in path.ancestors() {
+        let hash_file = dir.join(ISA_HASH_FILE);
+        let Ok(content) = fs::read_to_string(&hash_file) else {
+            continue;
+        };
+        let expected = format!("{:016x}", isa_hash());
+        if content.trim() != expected {
+            return Err(format!(
+                "Instruction set hash in {:?} is {} but this build uses {}. \
+                 Inputs were created with different opcode tables and may be misparsed.",
+                hash_file,
+                content.trim(),
+                expected
+            ));
+        }
+        return Ok(());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instructions::riscv::rv_i::{ADD, ADDI, SUB};
+
+    use super::hash_templates;
+
+    #[test]
+    fn hash_is_order_independent() {
+        assert_eq!(
+            hash_templates(&[&ADD, &ADDI]),
+            hash_templates(&[&ADDI, &ADD])
+        );
+    }
+
+    #[test]
+    fn hash_depends_on_templates() {
+        assert_ne!(hash_templates(&[&ADD, &ADDI]), hash_templates(&[&ADD]));
+        assert_ne!(hash_templates(&[&ADD]), hash_templates(&[&SUB]));
+    }
+}
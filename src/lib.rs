@@ -1,10 +1,91 @@
+//! Without the default 'std' feature, only the instruction model, the
+//! assembler and parser and the generator are available, for `no_std`
+//! targets with `alloc` (e.g. a bare-metal stimulus generator).
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod address_pool;
+#[cfg(feature = "std")]
+pub mod arch_state;
 pub mod assembler;
+#[cfg(feature = "std")]
+pub mod bisect;
+#[cfg(feature = "std")]
+pub mod budget;
+pub mod cache;
+#[cfg(feature = "std")]
 pub mod calibration;
+#[cfg(feature = "std")]
 pub mod causes;
+#[cfg(feature = "std")]
+pub mod dependence;
+#[cfg(feature = "std")]
+pub mod differential;
+#[cfg(feature = "std")]
+pub mod elf;
+#[cfg(feature = "std")]
+pub mod experiment;
+#[cfg(feature = "std")]
+pub mod extension_stats;
+#[cfg(feature = "fuzzer")]
 pub mod fuzz_ui;
 pub mod generator;
+#[cfg(feature = "std")]
+pub mod http_stats;
+#[cfg(feature = "fuzzer")]
+pub mod in_process;
+pub mod inst_class;
+pub mod input_config;
+#[cfg(feature = "std")]
+pub mod input_filter;
 pub mod instructions;
+#[cfg(feature = "std")]
+pub mod isa_hash;
+#[cfg(feature = "std")]
+pub mod map_regions;
+#[cfg(feature = "std")]
+pub mod memory_image;
+pub mod memory_ops;
+#[cfg(feature = "fuzzer")]
 pub mod monitor;
+#[cfg(feature = "std")]
+pub mod mutation_stats;
+#[cfg(feature = "std")]
 pub mod mutator;
+pub mod objdump;
+pub mod paging;
 pub mod parser;
+pub mod pmp;
+#[cfg(feature = "std")]
 pub mod program_input;
+pub mod provenance;
+pub mod registers;
+#[cfg(feature = "std")]
+pub mod retirement;
+pub mod rewrites;
+#[cfg(feature = "std")]
+pub mod seeds;
+#[cfg(feature = "std")]
+pub mod snippets;
+#[cfg(feature = "std")]
+pub mod state_feedback;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod storage;
+pub mod system_snippets;
+#[cfg(feature = "std")]
+pub mod target_profile;
+#[cfg(feature = "std")]
+pub mod template_coverage;
+#[cfg(feature = "std")]
+pub mod text_format;
+#[cfg(feature = "std")]
+pub mod timeline;
+#[cfg(feature = "std")]
+pub mod triage;
+#[cfg(feature = "std")]
+pub mod trimming;
+#[cfg(feature = "std")]
+pub mod virgin_bits;
@@ -0,0 +1,346 @@
+//! Named regions of the coverage map (e.g. one per RTL module) and a
+//! feedback that tracks which regions an input found new coverage in.
+
+use core::{fmt::Debug, marker::PhantomData};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use libafl::{
+    bolts::tuples::Named,
+    corpus::Testcase,
+    events::{Event, EventFirer},
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::UsesInput,
+    monitors::UserStats,
+    observers::{MapObserver, ObserversTuple},
+    state::HasClientPerfMonitor,
+    Error,
+};
+
+/// The harness writes the region manifest to the path in this variable.
+pub const FUZZING_MAP_REGIONS_VAR: &str = "FUZZING_MAP_REGIONS";
+/// The prefix of the user stats that report the coverage of a region.
+pub const REGION_STAT_PREFIX: &str = "region:";
+
+/// A named, contiguous range of coverage map entries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapRegion {
+    name: String,
+    start: usize,
+    len: usize,
+}
+
+impl MapRegion {
+    pub fn new(name: &str, start: usize, len: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            start,
+            len,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the map index lies in this region.
+    pub fn contains(&self, idx: usize) -> bool {
+        idx >= self.start && idx - self.start < self.len
+    }
+}
+
+/// The list of regions published by the harness.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RegionManifest {
+    regions: Vec<MapRegion>,
+}
+
+impl RegionManifest {
+    /// Parses one 'NAME START LEN' line per region. START and LEN are
+    /// decimal or hex (with '0x'), '#' starts a comment.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let parse_num = |s: &str| -> Result<usize, String> {
+            let result = match s.strip_prefix("0x") {
+                Some(hex) => usize::from_str_radix(hex, 16),
+                None => s.parse::<usize>(),
+            };
+            result.map_err(|e| format!("Invalid number '{}': {}", s, e))
+        };
+
+        let mut regions = Vec::<MapRegion>::new();
+        for line in input.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() != 3 {
+                return Err(format!("Expected 'NAME START LEN' but got '{}'", line));
+            }
+            let region = MapRegion::new(parts[0], parse_num(parts[1])?, parse_num(parts[2])?);
+            if regions.iter().any(|r| r.name() == region.name()) {
+                return Err(format!("Duplicate region '{}'", region.name()));
+            }
+            let overlaps = regions.iter().any(|r| {
+                region.start() < r.start() + r.len() && r.start() < region.start() + region.len()
+            });
+            if overlaps {
+                return Err(format!(
+                    "Region '{}' overlaps another region",
+                    region.name()
+                ));
+            }
+            regions.push(region);
+        }
+        Ok(Self { regions })
+    }
+
+    /// Reads and parses the manifest at the given path.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read region manifest {:?}: {}", path, e))?;
+        Self::parse(&text)
+    }
+
+    pub fn regions(&self) -> &[MapRegion] {
+        &self.regions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Returns the region with the given name.
+    pub fn find(&self, name: &str) -> Option<&MapRegion> {
+        self.regions.iter().find(|r| r.name() == name)
+    }
+
+    /// Returns the index of the region containing the map index.
+    pub fn region_of(&self, idx: usize) -> Option<usize> {
+        self.regions.iter().position(|r| r.contains(idx))
+    }
+}
+
+/// Parses the value of a region user stat ('COVERED/LEN (P%)').
+pub fn parse_region_stat(value: &str) -> Option<(u64, u64)> {
+    let mut parts = value.split('/');
+    let covered = parts.next()?.trim().parse::<u64>().ok()?;
+    let len = parts
+        .next()?
+        .split(" (")
+        .next()?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some((covered, len))
+}
+
+libafl::impl_serdeany!(RegionNoveltyMetadata);
+/// The map regions in which a corpus entry found new coverage.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RegionNoveltyMetadata {
+    regions: Vec<String>,
+    focused: bool,
+}
+
+impl RegionNoveltyMetadata {
+    pub fn regions(&self) -> &[String] {
+        &self.regions
+    }
+
+    /// Whether the entry found new coverage in the focus region.
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+}
+
+/// Feedback that is never interesting by itself, but records in which map
+/// regions an input found new coverage and reports the coverage of every
+/// region to the monitor. The manifest is loaded once the harness wrote it.
+#[derive(Clone, Debug)]
+pub struct RegionNoveltyFeedback<O, S> {
+    observer_name: String,
+    manifest_path: PathBuf,
+    manifest: Option<RegionManifest>,
+    focus: Option<String>,
+    // The map entries that were hit by any input so far.
+    seen: Vec<bool>,
+    // The number of seen entries in every region.
+    covered: Vec<usize>,
+    // The regions with new coverage in the last execution.
+    novel: Vec<usize>,
+    phantom: PhantomData<(O, S)>,
+}
+
+impl<O, S> RegionNoveltyFeedback<O, S>
+where
+    O: MapObserver<Entry = u8>,
+{
+    #[must_use]
+    pub fn new(observer: &O, manifest_path: PathBuf, focus: Option<String>) -> Self {
+        Self {
+            observer_name: observer.name().to_string(),
+            manifest_path,
+            manifest: None,
+            focus,
+            seen: Vec::new(),
+            covered: Vec::new(),
+            novel: Vec::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    fn try_load_manifest(&mut self) {
+        if self.manifest.is_some() || !self.manifest_path.exists() {
+            return;
+        }
+        let manifest = match RegionManifest::load(&self.manifest_path) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                log::warn!("{}", err);
+                RegionManifest::default()
+            }
+        };
+        if let Some(focus) = &self.focus {
+            if manifest.find(focus).is_none() {
+                log::warn!("Focus region '{}' is not in the region manifest", focus);
+            }
+        }
+        self.covered = vec![0; manifest.regions().len()];
+        self.manifest = Some(manifest);
+    }
+}
+
+impl<O, S> Named for RegionNoveltyFeedback<O, S> {
+    fn name(&self) -> &str {
+        "RegionNoveltyFeedback"
+    }
+}
+
+impl<O, S> Feedback<S> for RegionNoveltyFeedback<O, S>
+where
+    O: MapObserver<Entry = u8>,
+    S: UsesInput + HasClientPerfMonitor + Debug,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        _input: &S::Input,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.novel.clear();
+        self.try_load_manifest();
+        let Some(manifest) = &self.manifest else {
+            return Ok(false);
+        };
+        let observer = observers
+            .match_name::<O>(&self.observer_name)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found".to_string()))?;
+
+        let initial = observer.initial();
+        if self.seen.len() < observer.usable_count() {
+            self.seen.resize(observer.usable_count(), false);
+        }
+        for (region_idx, region) in manifest.regions().iter().enumerate() {
+            let end = (region.start() + region.len()).min(observer.usable_count());
+            for idx in region.start()..end {
+                if self.seen[idx] || *observer.get(idx) == initial {
+                    continue;
+                }
+                self.seen[idx] = true;
+                self.covered[region_idx] += 1;
+                if !self.novel.contains(&region_idx) {
+                    self.novel.push(region_idx);
+                }
+            }
+        }
+
+        for region_idx in &self.novel {
+            let region = &manifest.regions()[*region_idx];
+            manager.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: format!("{}{}", REGION_STAT_PREFIX, region.name()),
+                    value: UserStats::Ratio(self.covered[*region_idx] as u64, region.len() as u64),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error> {
+        let Some(manifest) = &self.manifest else {
+            return Ok(());
+        };
+        if self.novel.is_empty() {
+            return Ok(());
+        }
+        let regions: Vec<String> = self
+            .novel
+            .iter()
+            .map(|idx| manifest.regions()[*idx].name().to_string())
+            .collect();
+        let focused = matches!(&self.focus, Some(focus) if regions.contains(focus));
+        testcase.add_metadata(RegionNoveltyMetadata { regions, focused });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_region_stat, RegionManifest};
+
+    #[test]
+    fn parse_manifest() {
+        let manifest = RegionManifest::parse(
+            "# name start len\n\
+             decode 0 0x100\n\
+             \n\
+             lsu 256 128 # load/store unit\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.regions().len(), 2);
+        let lsu = manifest.find("lsu").unwrap();
+        assert_eq!((lsu.start(), lsu.len()), (256, 128));
+        assert_eq!(manifest.region_of(0xff), Some(0));
+        assert_eq!(manifest.region_of(383), Some(1));
+        assert_eq!(manifest.region_of(384), None);
+
+        assert!(RegionManifest::parse("decode 0").is_err());
+        assert!(RegionManifest::parse("decode 0 zz").is_err());
+        assert!(RegionManifest::parse("a 0 16\na 16 16").is_err());
+        assert!(RegionManifest::parse("a 0 16\nb 8 16").is_err());
+    }
+
+    #[test]
+    fn parse_stats() {
+        assert_eq!(parse_region_stat("12/256 (4%)"), Some((12, 256)));
+        assert_eq!(parse_region_stat("3/0"), Some((3, 0)));
+        assert_eq!(parse_region_stat("abc"), None);
+    }
+}
@@ -0,0 +1,124 @@
+//! Optional data-memory image that is placed between the configuration header
+//! and the instructions. The harness writes the bytes to the given address
+//! before running the program (see `readMemoryImage` in FuzzerAPI.h).
+//!
+//! Layout (all words are little-endian u32):
+//!   MEMORY_IMAGE_MAGIC, low address word, high address word, number of
+//!   bytes, the bytes padded with zeros to a multiple of four
+
+/// Marks the start of a memory image. The lowest two bits are not 0b11, so
+/// this word can never be confused with a 32-bit instruction.
+pub const MEMORY_IMAGE_MAGIC: u32 = 0x4d45_4d52;
+
+/// The maximum number of bytes in a memory image.
+pub const MAX_MEMORY_IMAGE_SIZE: usize = 4096;
+
+/// Bytes the harness writes to data memory before running the program.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MemoryImage {
+    /// The address of the first byte.
+    pub address: u64,
+    pub bytes: Vec<u8>,
+}
+
+impl MemoryImage {
+    /// Creates an empty image at the given address.
+    pub fn new(address: u64) -> Self {
+        Self {
+            address,
+            bytes: Vec::new(),
+        }
+    }
+
+    /// Encodes the image in the format described in the module docs.
+    pub fn encode(&self) -> Vec<u8> {
+        let words = [
+            MEMORY_IMAGE_MAGIC,
+            self.address as u32,
+            (self.address >> 32) as u32,
+            self.bytes.len() as u32,
+        ];
+        let mut result: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        result.extend_from_slice(&self.bytes);
+        result.resize(result.len() + padding(self.bytes.len()), 0);
+        result
+    }
+
+    /// Decodes an image at the start of the given bytes. Returns the image
+    /// and its encoded size in bytes, or None if there is no image.
+    pub fn decode(bytes: &[u8]) -> Result<Option<(MemoryImage, usize)>, String> {
+        let word = |i: usize| -> Option<u32> {
+            let chunk = bytes.get(i * 4..i * 4 + 4)?;
+            Some(u32::from_le_bytes(chunk.try_into().unwrap()))
+        };
+
+        if word(0) != Some(MEMORY_IMAGE_MAGIC) {
+            return Ok(None);
+        }
+        let low = word(1).ok_or("Truncated memory image")? as u64;
+        let high = word(2).ok_or("Truncated memory image")? as u64;
+        let len = word(3).ok_or("Truncated memory image")? as usize;
+        if len > MAX_MEMORY_IMAGE_SIZE {
+            return Err(format!("Memory image too large: {} bytes", len));
+        }
+        let size = 16 + len + padding(len);
+        if size > bytes.len() {
+            return Err("Truncated memory image".to_string());
+        }
+        Ok(Some((
+            MemoryImage {
+                address: (high << 32) | low,
+                bytes: bytes[16..16 + len].to_vec(),
+            },
+            size,
+        )))
+    }
+}
+
+// The number of zero bytes that pad an image of the given length.
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryImage, MAX_MEMORY_IMAGE_SIZE, MEMORY_IMAGE_MAGIC};
+
+    #[test]
+    fn encode_decode_image() {
+        let image = MemoryImage {
+            address: 0x1_8000_1000,
+            bytes: vec![1, 2, 3, 4, 5],
+        };
+        let mut bytes = image.encode();
+        assert_eq!(bytes.len(), 24);
+        let image_len = bytes.len();
+        bytes.extend_from_slice(&[0x13, 0, 0, 0]);
+
+        let (decoded, size) = MemoryImage::decode(&bytes).unwrap().unwrap();
+        assert_eq!(decoded, image);
+        assert_eq!(size, image_len);
+
+        let empty = MemoryImage::new(0x8000_0000);
+        let (decoded, size) = MemoryImage::decode(&empty.encode()).unwrap().unwrap();
+        assert_eq!(decoded, empty);
+        assert_eq!(size, 16);
+    }
+
+    #[test]
+    fn decode_invalid_image() {
+        // A nop is not an image.
+        assert_eq!(MemoryImage::decode(&[0x13, 0, 0, 0]), Ok(None));
+
+        let bytes = MemoryImage {
+            address: 0,
+            bytes: vec![0; 8],
+        }
+        .encode();
+        assert!(MemoryImage::decode(&bytes[..bytes.len() - 4]).is_err());
+
+        let words = [MEMORY_IMAGE_MAGIC, 0, 0, MAX_MEMORY_IMAGE_SIZE as u32 + 1];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        assert!(MemoryImage::decode(&bytes).is_err());
+    }
+}
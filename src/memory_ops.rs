@@ -0,0 +1,472 @@
+//! Helpers for creating and inspecting integer loads and stores.
+
+use alloc::{vec, vec::Vec};
+
+use libafl::prelude::Rand;
+
+use crate::{
+    address_pool::AddressPool,
+    inst_class::InstClass,
+    instructions::{
+        riscv::{
+            args,
+            rv64_i::{LD, LWU, SD},
+            rv_i::{BEQ, JALR, LB, LBU, LH, LHU, LW, SB, SH, SW},
+        },
+        split_immediates, Argument, Instruction, InstructionTemplate,
+    },
+    pmp::PmpConfig,
+    rewrites::{sign_extend, truncate},
+    system_snippets::{load_address, load_constant, SCRATCH_REG},
+};
+
+pub const CACHE_LINE_SIZE: u32 = 64;
+pub const PAGE_SIZE: u32 = 1 << 12;
+
+/// Registers that hold the stored (t1) and the loaded value (t2) in
+/// `store_load_check`.
+const CHECK_VALUE_REG: u32 = 6;
+const CHECK_LOADED_REG: u32 = 7;
+
+/// Low-bit patterns of effective addresses. Alignment corner cases are hard to
+/// reach with uniformly random immediates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressPattern {
+    /// Naturally aligned to the access width.
+    Aligned,
+    /// One byte after an aligned address.
+    PlusOne,
+    /// Two bytes after an aligned address.
+    PlusTwo,
+    /// The access straddles two cache lines.
+    CrossLine,
+    /// The access straddles two pages.
+    CrossPage,
+}
+
+impl AddressPattern {
+    pub const ALL: [AddressPattern; 5] = [
+        AddressPattern::Aligned,
+        AddressPattern::PlusOne,
+        AddressPattern::PlusTwo,
+        AddressPattern::CrossLine,
+        AddressPattern::CrossPage,
+    ];
+
+    /// Moves the address so that an access of `width` bytes follows the
+    /// pattern. The result is at most one page above the address.
+    pub fn apply(self, address: u32, width: u32) -> u32 {
+        let aligned = address & !(width - 1);
+        // Places the access so that half of it lies in the next block.
+        let straddle = |block: u32| (aligned | (block - 1)).wrapping_add(1) - width / 2;
+        match self {
+            AddressPattern::Aligned => aligned,
+            AddressPattern::PlusOne => aligned + 1,
+            AddressPattern::PlusTwo => aligned + 2,
+            AddressPattern::CrossLine => straddle(CACHE_LINE_SIZE),
+            AddressPattern::CrossPage => straddle(PAGE_SIZE),
+        }
+    }
+
+    /// Picks one of the misaligned patterns with the given chance (0-100)
+    /// and the aligned pattern otherwise.
+    pub fn pick<R: Rand>(rng: &mut R, misaligned_chance: u64) -> Self {
+        if rng.below(100) < misaligned_chance {
+            *rng.choose(&AddressPattern::ALL[1..])
+        } else {
+            AddressPattern::Aligned
+        }
+    }
+}
+
+/// All integer loads.
+pub fn loads() -> Vec<&'static InstructionTemplate> {
+    vec![&LB, &LH, &LW, &LD, &LBU, &LHU, &LWU]
+}
+
+/// All integer stores.
+pub fn stores() -> Vec<&'static InstructionTemplate> {
+    vec![&SB, &SH, &SW, &SD]
+}
+
+/// Returns the number of bytes accessed by an integer load or store.
+pub fn access_width(template: &InstructionTemplate) -> Option<u32> {
+    if [&LB, &LBU, &SB].contains(&template) {
+        Some(1)
+    } else if [&LH, &LHU, &SH].contains(&template) {
+        Some(2)
+    } else if [&LW, &LWU, &SW].contains(&template) {
+        Some(4)
+    } else if [&LD, &SD].contains(&template) {
+        Some(8)
+    } else {
+        None
+    }
+}
+
+/// Returns the base register and the signed offset of an integer load or
+/// store.
+pub fn access_address(inst: &Instruction) -> Option<(u32, i32)> {
+    let template = inst.template();
+    let rs1 = inst.arg_value(&args::RS1)?;
+    if loads().contains(&template) {
+        return Some((rs1, sign_extend(inst.arg_value(&args::IMM12)?, 12)));
+    }
+    if stores().contains(&template) {
+        let imm = split_immediates::STORE_OFFSET.extract(inst)?;
+        return Some((rs1, sign_extend(imm, 12)));
+    }
+    None
+}
+
+/// Creates a load of the given type from `offset(rs1)`.
+pub fn make_load(
+    template: &'static InstructionTemplate,
+    rd: u32,
+    rs1: u32,
+    offset: i32,
+) -> Instruction {
+    Instruction::new(
+        template,
+        vec![
+            Argument::new(&args::RD, rd),
+            Argument::new(&args::RS1, rs1),
+            Argument::new(&args::IMM12, truncate(offset, 12)),
+        ],
+    )
+}
+
+/// Creates a store of the given type of `rs2` to `offset(rs1)`.
+pub fn make_store(
+    template: &'static InstructionTemplate,
+    rs1: u32,
+    rs2: u32,
+    offset: i32,
+) -> Instruction {
+    let mut inst = Instruction::new(
+        template,
+        vec![
+            Argument::new(&args::IMM12HI, 0),
+            Argument::new(&args::RS1, rs1),
+            Argument::new(&args::RS2, rs2),
+            Argument::new(&args::IMM12LO, 0),
+        ],
+    );
+    split_immediates::STORE_OFFSET.apply(&mut inst, truncate(offset, 12));
+    inst
+}
+
+/// Creates a `beq rs1, rs2, offset`.
+fn make_beq(rs1: u32, rs2: u32, offset: i32) -> Instruction {
+    let mut inst = Instruction::new(
+        &BEQ,
+        vec![
+            Argument::new(&args::BIMM12HI, 0),
+            Argument::new(&args::RS1, rs1),
+            Argument::new(&args::RS2, rs2),
+            Argument::new(&args::BIMM12LO, 0),
+        ],
+    );
+    split_immediates::BRANCH_OFFSET.apply(&mut inst, truncate(offset, 13));
+    inst
+}
+
+/// Stores a random value with a random width to an address in the region,
+/// loads it back and jumps to the failure routine if the loaded value
+/// differs. This makes memory-consistency violations of the target visible
+/// as control flow without a golden model. The address is relative to a
+/// register in `pool` if possible. Clobbers t0, t1 and t2.
+pub fn store_load_check<R: Rand>(
+    rng: &mut R,
+    region: &PmpConfig,
+    failure_routine: u32,
+    pool: &AddressPool,
+) -> Vec<Instruction> {
+    let (load, store, width) =
+        *rng.choose(&[(&LB, &SB, 1), (&LH, &SH, 2), (&LW, &SW, 4), (&LD, &SD, 8)]);
+    let address = region.base() + (rng.below(region.size() as u64 / 8) * 8) as u32;
+    // The loads sign-extend, so the value is sign-extended from the width
+    // (and from 32 bits by lui/addi for doublewords).
+    let bits = 8 * width.min(4);
+    let value = sign_extend(rng.below(1 << bits) as u32, bits) as u32;
+
+    let (mut result, base, offset) = pool.address_operand(address);
+    result.append(&mut load_constant(CHECK_VALUE_REG, value));
+    result.push(make_store(store, base, CHECK_VALUE_REG, offset));
+    result.push(make_load(load, CHECK_LOADED_REG, base, offset));
+
+    let mut failure = load_address(SCRATCH_REG, failure_routine);
+    failure.push(Instruction::new(
+        &JALR,
+        vec![
+            Argument::new(&args::RD, 0),
+            Argument::new(&args::RS1, SCRATCH_REG),
+            Argument::new(&args::IMM12, 0),
+        ],
+    ));
+    // Skips the jump to the failure routine if the values match.
+    let skip = 4 * (failure.len() as i32 + 1);
+    result.push(make_beq(CHECK_VALUE_REG, CHECK_LOADED_REG, skip));
+    result.append(&mut failure);
+    result
+}
+
+/// Creates one to three loads/stores of random width and signedness that
+/// access the same address as the given load or store. Returns None if the
+/// instruction isn't an integer load or store.
+///
+/// The accesses reuse the data register of the original instruction, but
+/// never overwrite the base register so that all of them hit the same address.
+/// They have to be placed in front of the original instruction, which may be
+/// a load overwriting the base register.
+pub fn same_address_accesses<R: Rand>(rng: &mut R, inst: &Instruction) -> Option<Vec<Instruction>> {
+    let (base, offset) = access_address(inst)?;
+    let data = inst
+        .arg_value(&args::RD)
+        .or_else(|| inst.arg_value(&args::RS2))?;
+    let load_rd = if data == base { 0 } else { data };
+
+    let mut result = Vec::<Instruction>::new();
+    for _ in 0..rng.between(1, 3) {
+        let access = if rng.below(2) == 0 {
+            make_load(rng.choose(loads()), load_rd, base, offset)
+        } else {
+            make_store(rng.choose(stores()), base, data, offset)
+        };
+        result.push(access);
+    }
+    Some(result)
+}
+
+/// Rewrites an atomic instruction to stress the atomic unit. Either aliases
+/// rd with rs1 or rs2, adds a copy with different aq/rl bits, or adds a plain
+/// load or store to the same cache line. Returns the instructions replacing
+/// the original one or None if it isn't an atomic instruction.
+///
+/// If the atomic overwrites its own base register, the added instructions
+/// are placed in front of it so they still target the same line.
+pub fn alias_amo<R: Rand>(rng: &mut R, inst: &Instruction) -> Option<Vec<Instruction>> {
+    if InstClass::of(inst.template()) != [InstClass::Atomic] {
+        return None;
+    }
+    let base = inst.arg_value(&args::RS1)?;
+    let rd = inst.arg_value(&args::RD)?;
+    let data = inst.arg_value(&args::RS2);
+
+    let alias_targets: Vec<u32> = [Some(base), data]
+        .into_iter()
+        .flatten()
+        .filter(|reg| *reg != rd)
+        .collect();
+    match rng.below(3) {
+        0 if !alias_targets.is_empty() => {
+            let mut aliased = inst.clone();
+            aliased.set_arg(Argument::new(&args::RD, *rng.choose(&alias_targets)));
+            Some(vec![aliased])
+        }
+        1 => {
+            let ordering = (inst.arg_value(&args::AQ)? << 1) | inst.arg_value(&args::RL)?;
+            let new_ordering = (ordering + rng.between(1, 3) as u32) % 4;
+            let mut copy = inst.clone();
+            copy.set_arg(Argument::new(&args::AQ, new_ordering >> 1));
+            copy.set_arg(Argument::new(&args::RL, new_ordering & 1));
+            if rd == base {
+                // The copy must leave the base intact for the original.
+                copy.set_arg(Argument::new(&args::RD, 0));
+                Some(vec![copy, inst.clone()])
+            } else {
+                Some(vec![inst.clone(), copy])
+            }
+        }
+        _ => {
+            // A doubleword offset that stays in the cache line if the base is
+            // line aligned.
+            let offset = (rng.below((CACHE_LINE_SIZE / 8) as u64) * 8) as i32;
+            let access = if rng.below(2) == 0 {
+                let load_rd = if rd == base { 0 } else { rd };
+                make_load(rng.choose(loads()), load_rd, base, offset)
+            } else {
+                make_store(rng.choose(stores()), base, data.unwrap_or(rd), offset)
+            };
+            if rd == base || rng.below(2) == 0 {
+                Some(vec![access, inst.clone()])
+            } else {
+                Some(vec![inst.clone(), access])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::prelude::Xoshiro256StarRand;
+
+    use crate::{
+        address_pool::AddressPool,
+        assembler::assemble_instructions,
+        instructions::{
+            self,
+            riscv::{
+                args,
+                rv_a::AMOADD_W,
+                rv_i::{ADD, BEQ, JALR, LW, SH},
+            },
+            split_immediates::BRANCH_OFFSET,
+            Argument, Instruction,
+        },
+        parser::parse_instructions,
+        pmp::PmpConfig,
+    };
+
+    use super::{
+        access_address, access_width, alias_amo, make_load, make_store, same_address_accesses,
+        store_load_check, AddressPattern, CACHE_LINE_SIZE, PAGE_SIZE,
+    };
+
+    #[test]
+    fn address_of_accesses() {
+        assert_eq!(access_address(&make_load(&LW, 1, 2, -8)), Some((2, -8)));
+        assert_eq!(
+            access_address(&make_store(&SH, 3, 4, 2047)),
+            Some((3, 2047))
+        );
+        assert_eq!(access_address(&make_store(&SH, 3, 4, -33)), Some((3, -33)));
+        let add = Instruction::new(
+            &ADD,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 2),
+                Argument::new(&args::RS2, 3),
+            ],
+        );
+        assert_eq!(access_address(&add), None);
+        assert_eq!(access_width(&SH), Some(2));
+    }
+
+    #[test]
+    fn address_patterns() {
+        let address = 0x8000_1238;
+        assert_eq!(AddressPattern::Aligned.apply(address + 3, 8), address);
+        assert_eq!(AddressPattern::PlusOne.apply(address, 4), address + 1);
+        assert_eq!(AddressPattern::PlusTwo.apply(address, 2), address + 2);
+        for width in [2, 4, 8] {
+            let line = AddressPattern::CrossLine.apply(address, width);
+            assert_ne!(line / CACHE_LINE_SIZE, (line + width - 1) / CACHE_LINE_SIZE);
+            let page = AddressPattern::CrossPage.apply(address, width);
+            assert_ne!(page / PAGE_SIZE, (page + width - 1) / PAGE_SIZE);
+            assert!(page - address <= PAGE_SIZE);
+        }
+    }
+
+    #[test]
+    fn accesses_share_address() {
+        let mut rng = Xoshiro256StarRand::default();
+        for inst in [make_load(&LW, 5, 5, 12), make_store(&SH, 6, 7, -4)] {
+            for _ in 0..100 {
+                let accesses = same_address_accesses(&mut rng, &inst).unwrap();
+                assert!((1..=3).contains(&accesses.len()));
+                for access in &accesses {
+                    assert_eq!(access_address(access), access_address(&inst));
+                    // The base register must never be overwritten.
+                    assert_ne!(
+                        access.arg_value(&args::RD),
+                        inst.arg_value(&args::RS1),
+                        "{:?}",
+                        access
+                    );
+                }
+                let bytes = assemble_instructions(&accesses);
+                assert_eq!(
+                    parse_instructions(&bytes, instructions::riscv::all()).unwrap(),
+                    accesses
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn store_load_checks() {
+        let mut rng = Xoshiro256StarRand::default();
+        let region = PmpConfig::new(0x8000_1000, 0x1000).unwrap();
+        let (pool, _) = AddressPool::create(&mut rng, &region);
+        for pool in [AddressPool::default(), pool] {
+            for _ in 0..100 {
+                let check = store_load_check(&mut rng, &region, 0x8000_0100, &pool);
+                let bytes = assemble_instructions(&check);
+                assert_eq!(
+                    parse_instructions(&bytes, instructions::riscv::all()).unwrap(),
+                    check
+                );
+                // The branch skips exactly the jump to the failure routine.
+                let beq = check
+                    .iter()
+                    .position(|inst| inst.template() == &BEQ)
+                    .unwrap();
+                let skip = BRANCH_OFFSET.extract(&check[beq]).unwrap() as usize;
+                assert_eq!(beq + skip / 4, check.len());
+                assert_eq!(check.last().unwrap().template(), &JALR);
+                // The store and the load access the same address.
+                let store = &check[beq - 2];
+                let load = &check[beq - 1];
+                assert_eq!(access_address(store), access_address(load));
+                assert_eq!(
+                    access_width(store.template()),
+                    access_width(load.template())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn amo_aliasing() {
+        let mut rng = Xoshiro256StarRand::default();
+        let amo = Instruction::new(
+            &AMOADD_W,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 2),
+                Argument::new(&args::RS2, 3),
+                Argument::new(&args::AQ, 0),
+                Argument::new(&args::RL, 1),
+            ],
+        );
+        assert!(alias_amo(&mut rng, &make_load(&LW, 1, 2, 0)).is_none());
+        for _ in 0..100 {
+            let insts = alias_amo(&mut rng, &amo).unwrap();
+            let amos: Vec<&Instruction> = insts
+                .iter()
+                .filter(|inst| inst.template() == &AMOADD_W)
+                .collect();
+            match (insts.len(), amos.len()) {
+                (1, 1) => {
+                    let rd = amos[0].arg_value(&args::RD);
+                    assert!(rd == Some(2) || rd == Some(3));
+                }
+                (2, 2) => {
+                    assert_eq!(amos[0], &amo);
+                    assert_ne!(amos[1], &amo);
+                    assert_eq!(amos[1].arg_value(&args::RS1), Some(2));
+                }
+                (2, 1) => {
+                    let access = insts.iter().find(|inst| *inst != &amo).unwrap();
+                    assert_eq!(access_address(access).unwrap().0, 2);
+                }
+                _ => panic!("Unexpected rewrite: {:?}", insts),
+            }
+        }
+
+        // An atomic overwriting its base comes last, so that the added
+        // instructions see the original base.
+        let mut amo_on_base = amo.clone();
+        amo_on_base.set_arg(Argument::new(&args::RD, 2));
+        for _ in 0..100 {
+            let insts = alias_amo(&mut rng, &amo_on_base).unwrap();
+            let (last, others) = insts.split_last().unwrap();
+            assert_eq!(last.arg_value(&args::RS1), Some(2));
+            for inst in others {
+                assert_ne!(inst.arg_value(&args::RD), Some(2), "{:?}", insts);
+                assert_eq!(inst.arg_value(&args::RS1), Some(2));
+            }
+        }
+    }
+}
@@ -1,21 +1,131 @@
 use core::time::Duration;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::OpenOptions;
 use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use libafl::prelude::current_time;
 use libafl::prelude::{format_duration_hms, ClientId, ClientStats, Monitor};
 
-use crate::fuzz_ui::FuzzUI;
+use crate::budget::{request_stop, stop_requested, Budget, SUMMARY_FILE};
+use crate::causes::{read_causes, FUZZING_CAUSE_DIR_VAR};
+use crate::fuzz_ui::{ClientRow, FuzzUI, RegionRow, UiAction};
+use crate::http_stats::StatsSnapshot;
+use crate::map_regions::{parse_region_stat, REGION_STAT_PREFIX};
+use crate::mutation_stats::{MutationCounts, MUTATION_STAT_PREFIX};
+use crate::stats::{
+    coverage_line, ClientRecord, StatsRecord, COVERAGE_FILE, COVERAGE_HEADER, PLOT_DATA_FILE,
+    PLOT_DATA_HEADER, STATS_FILE,
+};
+use crate::timeline::{TimelineEvent, TimelineLog};
+
+// The maximum number of data points kept per series and client.
+const MAX_SERIES_LEN: usize = 1000;
+// How often a record is appended to the stats file and the plot data.
+const STATS_INTERVAL: Duration = Duration::from_secs(10);
+// How long a client can go without executions before it is shown as stalled.
+const STALL_TIMEOUT: Duration = Duration::from_secs(60);
+// How long the clients get to stop before the broker exits.
+const STOP_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// The coverage and execution history of a single fuzzing client.
+#[derive(Clone, Debug, Default)]
+pub struct ClientSeries {
+    /// Covered map bits over time as (seconds since start, bits).
+    pub coverage: Vec<(f64, f64)>,
+    /// Total executions over time as (seconds since start, execs).
+    pub execs: Vec<(f64, f64)>,
+    /// The size of the coverage map as reported by the client.
+    pub map_size: u64,
+    /// Covered entries and size of every named map region.
+    pub regions: HashMap<String, (u64, u64)>,
+    /// The counts of every mutation, by mutation name.
+    pub mutations: HashMap<String, MutationCounts>,
+}
+
+impl ClientSeries {
+    /// The last reported number of covered map bits.
+    pub fn last_coverage(&self) -> u64 {
+        self.coverage.last().map_or(0, |c| c.1 as u64)
+    }
+
+    /// The last reported number of executions.
+    pub fn last_execs(&self) -> u64 {
+        self.execs.last().map_or(0, |e| e.1 as u64)
+    }
+
+    /// When (in seconds since start) the coverage of the client last changed.
+    pub fn last_new_coverage(&self) -> Option<f64> {
+        self.coverage.last().map(|c| c.0)
+    }
+
+    /// When (in seconds since start) the executions of the client last
+    /// changed.
+    pub fn last_progress(&self) -> Option<f64> {
+        self.execs.last().map(|e| e.0)
+    }
+
+    fn add_coverage(&mut self, time: f64, bits: u64) {
+        if self.last_coverage() != bits || self.coverage.is_empty() {
+            push_bounded(&mut self.coverage, (time, bits as f64));
+        }
+    }
+
+    fn add_execs(&mut self, time: f64, execs: u64) {
+        if self.last_execs() != execs || self.execs.is_empty() {
+            push_bounded(&mut self.execs, (time, execs as f64));
+        }
+    }
+}
+
+fn push_bounded<T>(series: &mut Vec<T>, value: T) {
+    if series.len() >= MAX_SERIES_LEN {
+        series.remove(0);
+    }
+    series.push(value);
+}
+
+/// Parses the value of the 'shared_mem' user stat into the covered bits and
+/// the total map size.
+fn parse_map_stat(value: &str) -> Option<(u64, u64)> {
+    // shared_mem has the form:
+    //  BITS/MAX_BITS (PERCENTAGE%)
+    // For example:
+    //  0/2201728 (0%)
+    //  ^ ^^^^^^^
+    // We return these two parts.
+    let mut parts = value.split('/');
+    let bits = parts.next()?.trim().parse::<u64>().ok()?;
+    let max_str = parts.next()?.split(" (").next()?;
+    let max = max_str.trim().parse::<u64>().ok()?;
+    Some((bits, max))
+}
 
 /// Tracking monitor during fuzzing.
 #[derive(Clone)]
 pub struct HWFuzzMonitor {
     start_time: Duration,
     client_stats: Vec<ClientStats>,
+    /// The history of every client that reported to this monitor.
+    client_series: HashMap<ClientId, ClientSeries>,
     ui: Arc<Mutex<FuzzUI>>,
     iterations_log_path: String,
     last_iterations_logged: u64,
+    timeline: TimelineLog,
+    // The totals that were last recorded in the timeline.
+    timeline_coverage: u64,
+    timeline_objectives: u64,
+    // The causes that were already recorded in the timeline.
+    known_causes: HashSet<String>,
+    out_dir: PathBuf,
+    // When the last record was appended to the stats file.
+    last_stats: Option<Duration>,
+    budget: Budget,
+    // Whether the campaign is already being stopped.
+    stopping: bool,
+    // Served by the stats server, if one is running.
+    http_stats: Option<Arc<Mutex<StatsSnapshot>>>,
 }
 
 impl Monitor for HWFuzzMonitor {
@@ -37,44 +147,204 @@ impl Monitor for HWFuzzMonitor {
     fn display(&mut self, _event_msg: String, sender_id: ClientId) {
         let execs = self.total_execs();
         let execs_per_sec = self.execs_per_sec_pretty();
-        {
-            let client = self.client_stats_mut_for(sender_id).clone();
+        let time_since_start = current_time() - self.start_time;
 
-            let mut ui = self.ui.lock().unwrap();
-            let data = ui.data();
+        // Only update the series of the client that sent this event.
+        let client = self.client_stats_mut_for(sender_id).clone();
+        match self.client_series.get(&sender_id) {
+            None => self.timeline.record(TimelineEvent::ClientStart {
+                client: sender_id.0,
+            }),
+            // Restarted clients start counting from zero again.
+            Some(series) if client.executions < series.last_execs() => {
+                self.timeline.record(TimelineEvent::ClientRestart {
+                    client: sender_id.0,
+                    execs: series.last_execs(),
+                })
+            }
+            Some(_) => {}
+        }
+        let series = self.client_series.entry(sender_id).or_default();
+        series.add_execs(time_since_start.as_secs_f64(), client.executions);
+        for (key, val) in &client.user_monitor {
+            if let Some(region) = key.strip_prefix(REGION_STAT_PREFIX) {
+                if let Some(stat) = parse_region_stat(&val.to_string()) {
+                    series.regions.insert(region.to_string(), stat);
+                }
+                continue;
+            }
+            if let Some(mutation) = key.strip_prefix(MUTATION_STAT_PREFIX) {
+                if let Some(counts) = MutationCounts::parse_stat(&val.to_string()) {
+                    series.mutations.insert(mutation.to_string(), counts);
+                }
+                continue;
+            }
+            if key != "shared_mem" {
+                continue;
+            }
+            if let Some((bits, map_size)) = parse_map_stat(&val.to_string()) {
+                series.add_coverage(time_since_start.as_secs_f64(), bits);
+                // This should be constant during the execution, but changes
+                // depending on the used coverage, so we log it.
+                series.map_size = map_size;
+            }
+        }
 
-            data.add_corpus_size(self.corpus_size());
+        // Each client has its own coverage map, so the best client is the
+        // closest approximation to the total coverage we have.
+        let total_coverage = self
+            .client_series
+            .values()
+            .map(|s| s.last_coverage())
+            .max()
+            .unwrap_or(0);
+        let map_size = self
+            .client_series
+            .values()
+            .map(|s| s.map_size)
+            .max()
+            .unwrap_or(0);
 
-            let mut max_coverage : u64 = 0;
-            for (key, val) in &client.user_monitor {
-                if key == "shared_mem" {
-                    // shared_mem has the form:
-                    //  BITS/MAX_BITS (PERCENTAGE%)
-                    // For example:
-                    //  0/2201728 (0%)
-                    //  ^ ^^^^^^^
-                    // We store these two parts.
-                    let str = val.to_string();
-                    let bit_str = str.split("/").nth(0).unwrap();
-                    let bits = u64::from_str_radix(bit_str, 10).unwrap();
-                    data.add_max_coverage(bits as f64);
-
-                    // The second half is the maximum coverage.
-                    // This should be constant during the execution.
-                    // Changes depending on used coverage, so we log it.
-                    let right_side = str.split("/").nth(1).unwrap();
-                    let max_str = right_side.split(" (").nth(0).unwrap();
-                    max_coverage = u64::from_str_radix(max_str, 10).unwrap();
+        if total_coverage > self.timeline_coverage {
+            self.timeline_coverage = total_coverage;
+            self.append_coverage(time_since_start.as_secs_f64(), total_coverage);
+            if let Some(snapshot) = &self.http_stats {
+                push_bounded(
+                    &mut snapshot.lock().unwrap().coverage,
+                    (time_since_start.as_secs_f64(), total_coverage),
+                );
+            }
+            self.timeline.record(TimelineEvent::NewCoverage {
+                client: sender_id.0,
+                coverage: total_coverage,
+            });
+        }
+        let objectives = self.objective_size();
+        if objectives > self.timeline_objectives {
+            self.timeline_objectives = objectives;
+            self.timeline.record(TimelineEvent::Objective {
+                client: sender_id.0,
+                objectives,
+            });
+            self.record_new_causes();
+        }
+
+        // Like the total coverage, the best client per region approximates
+        // the coverage of the region.
+        let mut regions = HashMap::<String, RegionRow>::new();
+        for series in self.client_series.values() {
+            for (name, (covered, len)) in &series.regions {
+                let row = regions.entry(name.clone()).or_insert(RegionRow {
+                    name: name.clone(),
+                    covered: 0,
+                    len: *len,
+                });
+                row.covered = row.covered.max(*covered);
+            }
+        }
+        let mut regions: Vec<RegionRow> = regions.into_values().collect();
+        regions.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut ids: Vec<ClientId> = self.client_series.keys().copied().collect();
+        ids.sort_by_key(|id| id.0);
+
+        let mut records = Vec::<ClientRecord>::new();
+        for id in &ids {
+            let series_execs = self.client_series[id].last_execs();
+            let series_coverage = self.client_series[id].last_coverage();
+            let stats = self.client_stats_mut_for(*id);
+            records.push(ClientRecord {
+                id: id.0,
+                execs: series_execs,
+                execs_per_sec: stats.execs_per_sec(current_time()),
+                corpus: stats.corpus_size,
+                objectives: stats.objective_size,
+                coverage: series_coverage,
+            });
+        }
+        let now = time_since_start.as_secs_f64();
+        let rows: Vec<ClientRow> = records
+            .iter()
+            .zip(&ids)
+            .map(|(record, id)| {
+                let series = &self.client_series[id];
+                let last_progress = series.last_progress().unwrap_or(now);
+                ClientRow {
+                    id: record.id,
+                    execs: record.execs,
+                    execs_per_sec: format!("{}", record.execs_per_sec),
+                    corpus_size: record.corpus,
+                    objective_size: record.objectives,
+                    coverage: record.coverage,
+                    since_new_coverage: series
+                        .last_new_coverage()
+                        .map(|time| Duration::from_secs_f64((now - time).max(0.0))),
+                    stalled: now - last_progress >= STALL_TIMEOUT.as_secs_f64(),
+                }
+            })
+            .collect();
+
+        // The keys pressed since the last event.
+        let actions = self.ui.lock().unwrap().take_actions();
+        let snapshot = actions.contains(&UiAction::Snapshot);
+
+        let stats_due = match self.last_stats {
+            Some(last) => time_since_start >= last + STATS_INTERVAL,
+            None => true,
+        };
+        // The stats server always shows the current stats.
+        if stats_due || snapshot || self.http_stats.is_some() {
+            // Unlike the coverage, the clients count the mutations of their
+            // own executions, so the counts add up.
+            let mut mutations = BTreeMap::<String, MutationCounts>::new();
+            for series in self.client_series.values() {
+                for (name, counts) in &series.mutations {
+                    mutations.entry(name.clone()).or_default().add(counts);
                 }
             }
+            let record = StatsRecord {
+                time: current_time().as_secs(),
+                run_time: time_since_start.as_secs(),
+                execs,
+                execs_per_sec: self.execs_per_sec(),
+                corpus: self.corpus_size(),
+                objectives: self.objective_size(),
+                coverage: total_coverage,
+                map_size,
+                clients: records,
+                mutations,
+            };
+            if stats_due || snapshot {
+                self.last_stats = Some(time_since_start);
+                self.append_stats(&record);
+            }
+            if let Some(http_stats) = &self.http_stats {
+                http_stats.lock().unwrap().stats = Some(record);
+            }
+        }
+        if snapshot {
+            // The corpus and objectives are always on disk.
+            self.record_new_causes();
+            self.write_summary("snapshot", total_coverage, map_size);
+            let msg = format!("Snapshot written to {:?}", self.out_dir);
+            self.ui.lock().unwrap().data().add_message(msg);
+        }
 
-            let time_since_start = current_time() - self.start_time;
+        {
+            let mut ui = self.ui.lock().unwrap();
+            let data = ui.data();
+
+            data.add_corpus_size(self.corpus_size());
+            data.add_max_coverage(total_coverage as f64);
+            data.add_execs_per_sec(self.execs_per_sec());
 
             // Only log every few hundred iterations the time to avoid creating
             // a too large log file.
             let log_every_n_iterations = 500;
             if execs > self.last_iterations_logged + log_every_n_iterations {
                 self.last_iterations_logged = execs;
+                // Causes can show up after the objective was reported.
+                self.record_new_causes();
 
                 // Write the current time and iterations to a log file. This can
                 // be used to find infer iterations-to-exposure from the
@@ -86,38 +356,45 @@ impl Monitor for HWFuzzMonitor {
                     .open(&self.iterations_log_path)
                     .expect("Failed to open iterations log file");
 
+                // The aggregated totals come first, followed by one
+                // 'id:execs:coverage' triple for every client.
+                let mut line = format!(
+                    "{} {} {} {} {}",
+                    time_since_start.as_secs(),
+                    execs,
+                    self.corpus_size(),
+                    data.get_max_coverage() as u64,
+                    map_size
+                );
+                for row in &rows {
+                    line += format!(" {}:{}:{}", row.id, row.execs, row.coverage).as_str();
+                }
+                line += "\n";
+
                 iterations_log
-                    .write_all(
-                        format!(
-                            "{} {} {} {} {}\n",
-                            time_since_start.as_secs(),
-                            execs,
-                            self.corpus_size(),
-                            data.get_max_coverage() as u64,
-                            max_coverage
-                        )
-                        .as_bytes(),
-                    )
+                    .write_all(line.as_bytes())
                     .expect("Failed to update iterations log file");
             }
 
-            let mut msg = format!(
-                "time: {}, corpus: {}, found: {}, execs: {}, exec/sec: {}",
-                format_duration_hms(&(current_time() - self.start_time)),
+            let msg = format!(
+                "time: {}, corpus: {}, found: {}, execs: {}, exec/sec: {}, coverage: {}/{}, clients: {}",
+                format_duration_hms(&time_since_start),
                 self.corpus_size(),
                 self.objective_size(),
                 execs,
                 execs_per_sec,
+                total_coverage,
+                map_size,
+                rows.len(),
             );
-            for (key, val) in &client.user_monitor {
-                msg += format!(", {key}: {val}").as_str();
-            }
-            data.add_message(msg.to_string());
+            data.add_message(msg);
+            data.set_clients(rows);
+            data.set_regions(regions);
 
-            if msg.contains("shared_mem") {
+            if client.user_monitor.contains_key("shared_mem") {
                 let mut log_msg = format!(
                     "STATUS: {} {} {} {} {} ",
-                    (current_time() - self.start_time).as_secs(),
+                    time_since_start.as_secs(),
                     self.corpus_size(),
                     self.objective_size(),
                     execs,
@@ -137,21 +414,175 @@ impl Monitor for HWFuzzMonitor {
             }
         }
 
-        let mut ui = self.ui.lock().unwrap();
-        ui.try_tick();
+        {
+            let mut ui = self.ui.lock().unwrap();
+            ui.try_tick();
+        }
+
+        if !self.stopping {
+            let reason = self
+                .budget
+                .exceeded(time_since_start, execs)
+                .or_else(|| stop_requested(&self.out_dir))
+                .or_else(|| {
+                    actions
+                        .contains(&UiAction::Quit)
+                        .then(|| "stopped from the UI".to_string())
+                });
+            if let Some(reason) = reason {
+                self.stop(&reason, total_coverage, map_size);
+            }
+        }
     }
 }
 
 impl HWFuzzMonitor {
     /// Creates the monitor, using the `current_time` as `start_time`.
     pub fn new(ui: Arc<Mutex<FuzzUI>>, out_dir: String) -> Self {
+        let timeline = TimelineLog::new(Path::new(&out_dir));
+        let out_path = PathBuf::from(&out_dir);
         let log_path = out_dir + "/iterations_time";
         Self {
             start_time: current_time(),
             client_stats: vec![],
+            client_series: HashMap::new(),
             ui,
             iterations_log_path: log_path,
             last_iterations_logged: 0,
+            timeline,
+            timeline_coverage: 0,
+            timeline_objectives: 0,
+            known_causes: HashSet::new(),
+            out_dir: out_path,
+            last_stats: None,
+            budget: Budget::default(),
+            stopping: false,
+            http_stats: None,
+        }
+    }
+
+    /// Stops the campaign once the given budget is used up.
+    #[must_use]
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Keeps the given snapshot up to date (see `http_stats::serve`).
+    #[must_use]
+    pub fn with_http_stats(mut self, snapshot: Arc<Mutex<StatsSnapshot>>) -> Self {
+        self.http_stats = Some(snapshot);
+        self
+    }
+
+    // Asks the clients to stop, writes the summary and exits the broker once
+    // the clients had time to stop.
+    fn stop(&mut self, reason: &str, coverage: u64, map_size: u64) {
+        self.stopping = true;
+        if let Err(err) = request_stop(&self.out_dir, reason) {
+            log::error!("Failed to ask the clients to stop: {}", err);
+        }
+        self.record_new_causes();
+        let execs = self.total_execs();
+        self.timeline.record(TimelineEvent::CampaignStop {
+            reason: reason.to_string(),
+            execs,
+        });
+
+        let summary = self.write_summary(reason, coverage, map_size);
+
+        let ui = self.ui.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(STOP_GRACE_PERIOD);
+            ui.lock().unwrap().restore_terminal();
+            println!("\nCampaign stopped.\n{}", summary);
+            std::process::exit(0);
+        });
+    }
+
+    // Writes the summary of the campaign so far to the out dir and returns
+    // it.
+    fn write_summary(&self, reason: &str, coverage: u64, map_size: u64) -> String {
+        let execs = self.total_execs();
+        let lines = [
+            format!("reason: {}", reason),
+            format!(
+                "run time: {}",
+                format_duration_hms(&(current_time() - self.start_time))
+            ),
+            format!("execs: {}", execs),
+            format!("execs/sec: {}", self.execs_per_sec_pretty()),
+            format!("corpus: {}", self.corpus_size()),
+            format!("objectives: {}", self.objective_size()),
+            format!("coverage: {}/{}", coverage, map_size),
+            format!("causes: {}", self.known_causes.len()),
+        ];
+        let summary: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+        let summary_path = self.out_dir.join(SUMMARY_FILE);
+        if let Err(err) = std::fs::write(&summary_path, &summary) {
+            log::error!("Failed to write {:?}: {}", summary_path, err);
+        }
+        summary
+    }
+
+    // Appends the record to the stats file and the AFL plot data. Failures
+    // are only logged.
+    fn append_stats(&self, record: &StatsRecord) {
+        let plot_path = self.out_dir.join(PLOT_DATA_FILE);
+        let mut plot_line = record.to_plot_line() + "\n";
+        if !plot_path.exists() {
+            plot_line = format!("{}\n{}", PLOT_DATA_HEADER, plot_line);
         }
+        let files = [
+            (self.out_dir.join(STATS_FILE), record.to_line() + "\n"),
+            (plot_path, plot_line),
+        ];
+        for (path, line) in files {
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .and_then(|mut file| file.write_all(line.as_bytes()));
+            if let Err(err) = written {
+                log::warn!("Failed to update {:?}: {}", path, err);
+            }
+        }
+    }
+
+    // Appends a point to the coverage file. Unlike the chart in the UI, it
+    // keeps the full history. Failures are only logged.
+    fn append_coverage(&self, seconds: f64, coverage: u64) {
+        let path = self.out_dir.join(COVERAGE_FILE);
+        let mut line = coverage_line(seconds, coverage) + "\n";
+        if !path.exists() {
+            line = format!("{}\n{}", COVERAGE_HEADER, line);
+        }
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+        if let Err(err) = written {
+            log::warn!("Failed to update {:?}: {}", path, err);
+        }
+    }
+
+    // Records the causes in the cause dir that weren't recorded before.
+    fn record_new_causes(&mut self) {
+        let Ok(cause_dir) = std::env::var(FUZZING_CAUSE_DIR_VAR) else {
+            return;
+        };
+        // Sorted by time, so the first record of a cause is its first input.
+        for (_, record) in read_causes(Path::new(&cause_dir)) {
+            if self.known_causes.insert(record.cause.clone()) {
+                self.timeline
+                    .record(TimelineEvent::Cause { name: record.cause });
+            }
+        }
+    }
+
+    /// The history of every client that reported so far.
+    pub fn client_series(&self) -> &HashMap<ClientId, ClientSeries> {
+        &self.client_series
     }
 }
@@ -0,0 +1,286 @@
+//! Counts what every mutation achieved in a campaign, to see which of them
+//! matter for a target. The mutators record their applications in the state
+//! and the `MutationStatsFeedback`s credit the mutations of an input once it
+//! becomes a corpus entry or objective.
+
+use core::{fmt::Debug, marker::PhantomData};
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use libafl::{
+    bolts::tuples::Named,
+    corpus::Testcase,
+    events::{Event, EventFirer},
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::UsesInput,
+    monitors::UserStats,
+    mutators::MutationResult,
+    observers::ObserversTuple,
+    state::{HasClientPerfMonitor, HasMetadata},
+    Error,
+};
+
+use crate::mutator::Mutation;
+
+/// Prefix of the user stats with the counts of a mutation. The name of the
+/// mutation follows it.
+pub const MUTATION_STAT_PREFIX: &str = "mutation:";
+
+// How many executions pass between two reports of the counts.
+const REPORT_INTERVAL: u64 = 1000;
+
+/// What a single mutation achieved.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MutationCounts {
+    /// How often the mutation changed an input.
+    pub applied: u64,
+    /// How often the mutation couldn't be applied to an input.
+    pub skipped: u64,
+    /// The corpus entries the mutation was applied to.
+    pub corpus: u64,
+    /// The objectives the mutation was applied to.
+    pub objectives: u64,
+}
+
+impl MutationCounts {
+    /// Formats the counts as an 'APPLIED/SKIPPED/CORPUS/OBJECTIVES' stat.
+    pub fn to_stat(&self) -> String {
+        format!(
+            "{}/{}/{}/{}",
+            self.applied, self.skipped, self.corpus, self.objectives
+        )
+    }
+
+    /// Parses the output of `to_stat`.
+    pub fn parse_stat(value: &str) -> Option<MutationCounts> {
+        let mut parts = value.trim().split('/').map(|part| part.parse::<u64>().ok());
+        let counts = MutationCounts {
+            applied: parts.next()??,
+            skipped: parts.next()??,
+            corpus: parts.next()??,
+            objectives: parts.next()??,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(counts)
+    }
+
+    /// Adds the counts of `other`, e.g. those of another client.
+    pub fn add(&mut self, other: &MutationCounts) {
+        self.applied += other.applied;
+        self.skipped += other.skipped;
+        self.corpus += other.corpus;
+        self.objectives += other.objectives;
+    }
+}
+
+libafl::impl_serdeany!(MutationStatsMetadata);
+/// The counts of every mutation of a client, by mutation name.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MutationStatsMetadata {
+    counts: BTreeMap<String, MutationCounts>,
+    // The mutations applied to the input of the next execution.
+    pending: Vec<String>,
+    // The mutations applied to the input of the last execution.
+    last: Vec<String>,
+}
+
+impl MutationStatsMetadata {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an application of the mutation to the next input.
+    pub fn record(&mut self, mutation: Mutation, result: MutationResult) {
+        let counts = self.counts.entry(mutation.name().to_string()).or_default();
+        match result {
+            MutationResult::Mutated => {
+                counts.applied += 1;
+                if !self.pending.iter().any(|name| name == mutation.name()) {
+                    self.pending.push(mutation.name().to_string());
+                }
+            }
+            MutationResult::Skipped => counts.skipped += 1,
+        }
+    }
+
+    /// Marks the pending mutations as those of the executed input.
+    pub fn finish_execution(&mut self) {
+        self.last = std::mem::take(&mut self.pending);
+    }
+
+    /// Credits the mutations of the executed input with a corpus entry.
+    pub fn credit_corpus(&mut self) {
+        for name in &self.last {
+            self.counts.entry(name.clone()).or_default().corpus += 1;
+        }
+    }
+
+    /// Credits the mutations of the executed input with an objective.
+    pub fn credit_objective(&mut self) {
+        for name in &self.last {
+            self.counts.entry(name.clone()).or_default().objectives += 1;
+        }
+    }
+
+    /// The counts by mutation name.
+    pub fn counts(&self) -> &BTreeMap<String, MutationCounts> {
+        &self.counts
+    }
+}
+
+/// Feedback that is never interesting, but credits the mutations of new
+/// corpus entries or objectives in the `MutationStatsMetadata`. One instance
+/// belongs to the feedback and one to the objective, which LibAFL evaluates
+/// first for every execution. The objective instance also reports the counts
+/// as user stats every few executions.
+#[derive(Clone, Debug)]
+pub struct MutationStatsFeedback<S> {
+    objective: bool,
+    execs: u64,
+    phantom: PhantomData<S>,
+}
+
+impl<S> MutationStatsFeedback<S> {
+    /// The instance for the feedback.
+    #[must_use]
+    pub fn corpus() -> Self {
+        Self {
+            objective: false,
+            execs: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The instance for the objective.
+    #[must_use]
+    pub fn objective() -> Self {
+        Self {
+            objective: true,
+            execs: 0,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for MutationStatsFeedback<S> {
+    fn name(&self) -> &str {
+        if self.objective {
+            "MutationStatsObjectiveFeedback"
+        } else {
+            "MutationStatsFeedback"
+        }
+    }
+}
+
+impl<S> Feedback<S> for MutationStatsFeedback<S>
+where
+    S: UsesInput + HasClientPerfMonitor + HasMetadata + Debug,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        _input: &S::Input,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if !self.objective {
+            return Ok(false);
+        }
+        let Some(stats) = state.metadata_map_mut().get_mut::<MutationStatsMetadata>() else {
+            return Ok(false);
+        };
+        stats.finish_execution();
+
+        self.execs += 1;
+        if self.execs % REPORT_INTERVAL != 0 {
+            return Ok(false);
+        }
+        let counts = stats.counts().clone();
+        for (name, counts) in counts {
+            manager.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: format!("{}{}", MUTATION_STAT_PREFIX, name),
+                    value: UserStats::String(counts.to_stat()),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error> {
+        if let Some(stats) = state.metadata_map_mut().get_mut::<MutationStatsMetadata>() {
+            if self.objective {
+                stats.credit_objective();
+            } else {
+                stats.credit_corpus();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::mutators::MutationResult;
+
+    use crate::mutator::Mutation;
+
+    use super::{MutationCounts, MutationStatsMetadata};
+
+    #[test]
+    fn count_mutations() {
+        let mut stats = MutationStatsMetadata::new();
+        stats.record(Mutation::Add, MutationResult::Mutated);
+        stats.record(Mutation::Add, MutationResult::Mutated);
+        stats.record(Mutation::Remove, MutationResult::Skipped);
+        stats.finish_execution();
+        stats.credit_corpus();
+        // Inputs without mutations (e.g. seeds) credit nothing.
+        stats.finish_execution();
+        stats.credit_objective();
+
+        let add = stats.counts()["add"];
+        assert_eq!(
+            add,
+            MutationCounts {
+                applied: 2,
+                skipped: 0,
+                corpus: 1,
+                objectives: 0,
+            }
+        );
+        assert_eq!(stats.counts()["remove"].skipped, 1);
+        assert_eq!(stats.counts()["remove"].corpus, 0);
+    }
+
+    #[test]
+    fn parse_counts_stat() {
+        let counts = MutationCounts {
+            applied: 10,
+            skipped: 2,
+            corpus: 3,
+            objectives: 1,
+        };
+        assert_eq!(counts.to_stat(), "10/2/3/1");
+        assert_eq!(MutationCounts::parse_stat("10/2/3/1"), Some(counts));
+        assert_eq!(MutationCounts::parse_stat("10/2/3"), None);
+        assert_eq!(MutationCounts::parse_stat("10/2/3/1/5"), None);
+        assert_eq!(MutationCounts::parse_stat("10/x/3/1"), None);
+    }
+}
@@ -1,25 +1,43 @@
-use std::cmp::max;
+use std::{cmp::max, fmt, str::FromStr};
 
 use libafl::prelude::*;
 
 use crate::{
-    generator::InstGenerator,
+    address_pool::AddressPool,
+    cache::eviction_snippet,
+    dependence::movable_range,
+    generator::{InstGenerator, InstGeneratorConfig},
+    input_config::{
+        InputConfig, Interrupt, InterruptKind, PrivilegeLevel, RegisterInit, MAX_INTERRUPTS,
+    },
     instructions::{
         self,
         riscv::{
             args,
             rv_i::{ADDI, AUIPC, JALR},
         },
-        Argument, Instruction,
+        Argument, ArgumentSpec, Instruction,
     },
+    memory_image::{MemoryImage, MAX_MEMORY_IMAGE_SIZE},
+    memory_ops::{alias_amo, same_address_accesses, store_load_check},
+    mutation_stats::MutationStatsMetadata,
+    paging::{perturb_pte, sv39_setup},
+    parser::DecodeTable,
+    pmp::pmp_snippet,
     program_input::HasProgramInput,
+    provenance::{Provenance, ProvenanceMetadata},
+    rewrites::{equivalent_forms, permute_registers, swap_registers},
+    snippets::SnippetLibraryMetadata,
+    system_snippets::{privilege_transition, random_fence},
 };
 
+#[cfg(feature = "strict-checks")]
+use crate::parser::check_instructions;
 #[cfg(test)]
-use crate::{assembler::assemble_instructions, parser::parse_instructions};
+use crate::{assembler::assemble_instructions, parser::parse_instructions_with_raw_words};
 
 /// Supported mutation strategies.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Mutation {
     // Adds a new instruction.
     Add,
@@ -36,6 +54,196 @@ pub enum Mutation {
     // Replaces an instruction with a nop.
     ReplaceWithNop,
     Snippet,
+    // Rewrites an instruction into a semantically equivalent form.
+    Equivalent,
+    // Adds/subtracts a small delta to an immediate or flips one of its bits.
+    TweakImm,
+    // Swaps all uses of two registers in the whole program.
+    RenameRegs,
+    // Applies one permutation of register numbers to all uses of a register
+    // argument (e.g. rd) in the program.
+    PermuteArgsGlobally,
+    // Moves an instruction past instructions it doesn't depend on.
+    Reorder,
+    // Inserts a FENCE with random pred/succ sets or a FENCE.I.
+    InjectFence,
+    // Flips a flag bit of a PTE written by a page-table setup snippet.
+    PerturbPte,
+    // Inserts loads/stores of varying width to the address of a memory access.
+    SameAddress,
+    // Aliases registers of an atomic or adds accesses to the same address.
+    AmoAlias,
+    // Flips a feature bit in the configuration header.
+    FlipFeature,
+    // Changes the privilege level in the configuration header.
+    ChangePrivilege,
+    // Adds, removes or moves an interrupt in the configuration header.
+    MutateInterrupts,
+    // Adds, removes or changes an initial register value in the
+    // configuration header.
+    MutateRegisters,
+    // Grows, shrinks or changes bytes of the data-memory image.
+    MutateMemory,
+    // Inserts a word that isn't an instruction (an illegal or reserved
+    // encoding). Only applies if raw words are allowed, see
+    // `InstGeneratorConfig::allow_raw_words`.
+    InjectIllegal,
+}
+
+impl Mutation {
+    pub const ALL: [Mutation; 23] = [
+        Mutation::Add,
+        Mutation::Replace,
+        Mutation::ReplaceArg,
+        Mutation::RepeatSeveral,
+        Mutation::SwapTwo,
+        Mutation::Remove,
+        Mutation::ReplaceWithNop,
+        Mutation::Snippet,
+        Mutation::Equivalent,
+        Mutation::TweakImm,
+        Mutation::RenameRegs,
+        Mutation::PermuteArgsGlobally,
+        Mutation::Reorder,
+        Mutation::InjectFence,
+        Mutation::PerturbPte,
+        Mutation::SameAddress,
+        Mutation::AmoAlias,
+        Mutation::FlipFeature,
+        Mutation::ChangePrivilege,
+        Mutation::MutateInterrupts,
+        Mutation::MutateRegisters,
+        Mutation::MutateMemory,
+        Mutation::InjectIllegal,
+    ];
+
+    /// The name used on the command line and in reports (e.g. 'replace-arg').
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mutation::Add => "add",
+            Mutation::Replace => "replace",
+            Mutation::ReplaceArg => "replace-arg",
+            Mutation::RepeatSeveral => "repeat-several",
+            Mutation::SwapTwo => "swap-two",
+            Mutation::Remove => "remove",
+            Mutation::ReplaceWithNop => "replace-with-nop",
+            Mutation::Snippet => "snippet",
+            Mutation::Equivalent => "equivalent",
+            Mutation::TweakImm => "tweak-imm",
+            Mutation::RenameRegs => "rename-regs",
+            Mutation::PermuteArgsGlobally => "permute-args-globally",
+            Mutation::Reorder => "reorder",
+            Mutation::InjectFence => "inject-fence",
+            Mutation::PerturbPte => "perturb-pte",
+            Mutation::SameAddress => "same-address",
+            Mutation::AmoAlias => "amo-alias",
+            Mutation::FlipFeature => "flip-feature",
+            Mutation::ChangePrivilege => "change-privilege",
+            Mutation::MutateInterrupts => "mutate-interrupts",
+            Mutation::MutateRegisters => "mutate-registers",
+            Mutation::MutateMemory => "mutate-memory",
+            Mutation::InjectIllegal => "inject-illegal",
+        }
+    }
+
+    /// A one-line description of what the mutation does.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Mutation::Add => "Adds a new instruction",
+            Mutation::Replace => "Replaces an instruction with a different new instruction",
+            Mutation::ReplaceArg => "Replaces an argument of an instruction with a different one",
+            Mutation::RepeatSeveral => "Repeats one instruction several times",
+            Mutation::SwapTwo => "Swaps two single instructions",
+            Mutation::Remove => "Removes a single instruction",
+            Mutation::ReplaceWithNop => "Replaces an instruction with a nop",
+            Mutation::Snippet => "Inserts a builtin or learned snippet",
+            Mutation::Equivalent => "Rewrites an instruction into a semantically equivalent form",
+            Mutation::TweakImm => "Adds a small delta to an immediate or flips one of its bits",
+            Mutation::RenameRegs => "Swaps all uses of two registers in the whole program",
+            Mutation::PermuteArgsGlobally => {
+                "Permutes the registers of one register argument in the whole program"
+            }
+            Mutation::Reorder => "Moves an instruction past instructions it doesn't depend on",
+            Mutation::InjectFence => "Inserts a FENCE with random pred/succ sets or a FENCE.I",
+            Mutation::PerturbPte => "Flips a flag bit of a PTE written by a page-table setup",
+            Mutation::SameAddress => {
+                "Inserts accesses of varying width to the address of an access"
+            }
+            Mutation::AmoAlias => "Aliases registers of an atomic or adds accesses to its address",
+            Mutation::FlipFeature => "Flips a feature bit in the configuration header",
+            Mutation::ChangePrivilege => "Changes the privilege level in the configuration header",
+            Mutation::MutateInterrupts => "Adds, removes or moves an interrupt in the header",
+            Mutation::MutateRegisters => "Adds, removes or changes an initial register value",
+            Mutation::MutateMemory => "Grows, shrinks or changes bytes of the data-memory image",
+            Mutation::InjectIllegal => "Inserts an illegal or reserved encoding as a raw word",
+        }
+    }
+
+    /// The minimum number of instructions the program needs for the
+    /// mutation to apply.
+    pub fn arity(&self) -> usize {
+        match self {
+            Mutation::Add
+            | Mutation::Snippet
+            | Mutation::InjectFence
+            | Mutation::FlipFeature
+            | Mutation::ChangePrivilege
+            | Mutation::MutateInterrupts
+            | Mutation::MutateRegisters
+            | Mutation::MutateMemory
+            | Mutation::InjectIllegal => 0,
+            Mutation::SwapTwo | Mutation::Reorder => 2,
+            _ => 1,
+        }
+    }
+
+    /// Whether the mutation only replaces single instructions and keeps the
+    /// others at their positions. These are applied with
+    /// `HasProgramInput::set_inst`, so inputs can patch their encoding.
+    pub fn replaces_in_place(&self) -> bool {
+        matches!(
+            self,
+            Mutation::Replace
+                | Mutation::ReplaceArg
+                | Mutation::SwapTwo
+                | Mutation::ReplaceWithNop
+                | Mutation::TweakImm
+        )
+    }
+}
+
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Mutation {
+    type Err = String;
+
+    /// Parses the name of a mutation (e.g. 'replace-arg' or 'replace_arg').
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.trim().to_lowercase().replace('_', "-");
+        Mutation::ALL
+            .iter()
+            .find(|mutation| mutation.name() == name)
+            .copied()
+            .ok_or(format!("Unknown mutation: '{}'", name))
+    }
+}
+
+/// Derives the seed of the RNG for one mutation from the campaign seed, the
+/// id of the mutator and the number of mutations it did before. The parts
+/// are mixed with the splitmix64 finalizer so that neighboring ids and
+/// iterations give unrelated streams.
+pub fn stream_seed(seed: u64, mutator_id: u64, iteration: u64) -> u64 {
+    let mix = |mut z: u64| {
+        z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    mix(seed ^ mix(mutator_id ^ mix(iteration)))
 }
 
 /// Mutator for RISC-V instructions.
@@ -44,11 +252,19 @@ pub enum Mutation {
 pub struct RiscVInstructionMutator {
     /// This should be a const generic argument but Rust doesn't support that.
     mutation: Mutation,
+    /// The generator settings used for creating new instructions/arguments.
+    generator: InstGenerator,
+    // Identifies the RNG stream of this mutator. Derived from the mutation
+    // and how many mutators with the same mutation came before, so it
+    // doesn't change if other mutations are disabled.
+    stream_id: u64,
+    // The number of mutations done with the RNG stream.
+    iteration: u64,
 }
 
 impl<I, S> Mutator<I, S> for RiscVInstructionMutator
 where
-    S: HasRand,
+    S: HasRand + HasMetadata,
     I: HasProgramInput,
 {
     fn mutate(
@@ -57,8 +273,211 @@ where
         input: &mut I,
         _stage_idx: i32,
     ) -> Result<MutationResult, Error> {
-        self.mutate_impl(state.rand_mut(), input.insts_mut())
+        let result = self.mutate_input(state, input)?;
+        // Credited by the MutationStatsFeedback after the execution.
+        if !state.has_metadata::<MutationStatsMetadata>() {
+            state.add_metadata(MutationStatsMetadata::new());
+        }
+        if let Some(stats) = state.metadata_map_mut().get_mut::<MutationStatsMetadata>() {
+            stats.record(self.mutation, result);
+        }
+        Ok(result)
+    }
+}
+
+// Upper bound for the cycle of newly added interrupts.
+const MAX_INTERRUPT_CYCLE: u64 = 10_000;
+
+// Initial register values that often hit corner cases.
+const INTERESTING_REGISTER_VALUES: [u64; 8] = [
+    0,
+    1,
+    u64::MAX,
+    0x8000_0000_0000_0000,
+    0x7fff_ffff_ffff_ffff,
+    0x0000_0000_ffff_ffff,
+    0x0000_0000_8000_0000,
+    0xffff_ffff_8000_0000,
+];
+
+fn random_register_value<Rng: Rand>(rng: &mut Rng) -> u64 {
+    if rng.below(2) == 0 {
+        *rng.choose(&INTERESTING_REGISTER_VALUES)
+    } else {
+        rng.next()
+    }
+}
+
+/// Applies a configuration mutation to the config header of an input.
+/// Returns None if the input has no config header.
+fn mutate_config<Rng: Rand>(
+    rng: &mut Rng,
+    mutation: Mutation,
+    config: &mut Option<InputConfig>,
+) -> Option<()> {
+    let config = config.as_mut()?;
+    match mutation {
+        Mutation::FlipFeature => {
+            config.features ^= 1 << rng.below(u32::BITS as u64);
+        }
+        Mutation::ChangePrivilege => {
+            let others: Vec<PrivilegeLevel> = PrivilegeLevel::ALL
+                .iter()
+                .copied()
+                .filter(|level| *level != config.privilege)
+                .collect();
+            config.privilege = *rng.choose(&others);
+        }
+        Mutation::MutateInterrupts => {
+            let interrupts = &mut config.interrupts;
+            let can_add = interrupts.len() < MAX_INTERRUPTS;
+            // Always add if there is nothing to modify.
+            if interrupts.is_empty() || (can_add && rng.below(4) == 0) {
+                interrupts.push(Interrupt {
+                    cycle: rng.below(MAX_INTERRUPT_CYCLE) as u32,
+                    kind: *rng.choose(&InterruptKind::ALL),
+                });
+            } else {
+                let idx = rng.below(interrupts.len() as u64) as usize;
+                match rng.below(3) {
+                    0 => {
+                        interrupts.remove(idx);
+                    }
+                    1 => interrupts[idx].kind = *rng.choose(&InterruptKind::ALL),
+                    _ => {
+                        // Move the interrupt by a small number of cycles.
+                        let delta = rng.between(1, 64) as u32;
+                        let cycle = &mut interrupts[idx].cycle;
+                        *cycle = if rng.below(2) == 0 {
+                            cycle.saturating_sub(delta)
+                        } else {
+                            cycle.saturating_add(delta)
+                        };
+                    }
+                }
+            }
+            interrupts.sort_by_key(|interrupt| interrupt.cycle);
+        }
+        Mutation::MutateRegisters => {
+            let registers = &mut config.registers;
+            let free: Vec<u32> = (1..32)
+                .filter(|reg| registers.iter().all(|init| init.reg != *reg))
+                .collect();
+            // Always add if there is nothing to modify.
+            if registers.is_empty() || (!free.is_empty() && rng.below(4) == 0) {
+                registers.push(RegisterInit {
+                    reg: *rng.choose(&free),
+                    value: random_register_value(rng),
+                });
+            } else {
+                let idx = rng.below(registers.len() as u64) as usize;
+                match rng.below(3) {
+                    0 => {
+                        registers.remove(idx);
+                    }
+                    1 => registers[idx].value = random_register_value(rng),
+                    _ => registers[idx].value ^= 1 << rng.below(u64::BITS as u64),
+                }
+            }
+            registers.sort_by_key(|init| init.reg);
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+// How often Replace and ReplaceArg try to generate something different
+// from what they replace before skipping.
+const REPLACE_RETRIES: usize = 16;
+
+// Upper bound for the number of bytes a memory image grows or shrinks by.
+const MAX_MEMORY_RESIZE: u64 = 16;
+
+/// Grows, shrinks or changes the bytes of a data-memory image.
+/// Returns None if the input has no memory image.
+fn mutate_memory<Rng: Rand>(rng: &mut Rng, memory: &mut Option<MemoryImage>) -> Option<()> {
+    let bytes = &mut memory.as_mut()?.bytes;
+    let can_grow = bytes.len() < MAX_MEMORY_IMAGE_SIZE;
+    // Always grow if there is nothing to modify.
+    if bytes.is_empty() || (can_grow && rng.below(4) == 0) {
+        let count = rng.between(1, MAX_MEMORY_RESIZE) as usize;
+        for _ in 0..count.min(MAX_MEMORY_IMAGE_SIZE - bytes.len()) {
+            bytes.push(rng.below(256) as u8);
+        }
+    } else {
+        let idx = rng.below(bytes.len() as u64) as usize;
+        match rng.below(3) {
+            0 => {
+                let count = rng.between(1, MAX_MEMORY_RESIZE) as usize;
+                bytes.truncate(bytes.len().saturating_sub(count));
+            }
+            1 => bytes[idx] ^= 1 << rng.below(u8::BITS as u64),
+            _ => {
+                // Place a (possibly cut off) interesting value.
+                let value = random_register_value(rng).to_le_bytes();
+                let end = bytes.len().min(idx + value.len());
+                bytes[idx..end].copy_from_slice(&value[..end - idx]);
+            }
+        }
     }
+    Some(())
+}
+
+/// Major opcodes that no standard 32-bit instruction uses: custom-0 to
+/// custom-3 and the two reserved ones. OP-V (0x57) is taken by the vector
+/// extension.
+const UNUSED_OPCODES: [u32; 6] = [0x0b, 0x2b, 0x5b, 0x6b, 0x77, 0x7b];
+
+/// Whether the word has the length encoding of a 32-bit instruction. Other
+/// words start with a compressed instruction or the prefix of a longer one,
+/// which would swallow the following instructions.
+fn is_32bit_word(word: u32) -> bool {
+    word & 0b11 == 0b11 && word & 0b11100 != 0b11100
+}
+
+/// Picks a 32-bit word that no instruction of the known extensions decodes:
+/// The defined illegal instructions (all zeros or ones), an unused major
+/// opcode, an instruction with a flipped bit in its fixed fields or random
+/// bits.
+fn illegal_word<Rng: Rand>(rng: &mut Rng) -> u32 {
+    let templates = instructions::riscv::all();
+    let table = DecodeTable::cached(templates);
+    loop {
+        let word = match rng.below(4) {
+            0 => return *rng.choose(&[0, u32::MAX]),
+            1 => (rng.next() as u32 & !0x7f) | *rng.choose(&UNUSED_OPCODES),
+            2 => {
+                let template = rng.choose(templates);
+                let flip = (1 << rng.below(32)) & template.mask();
+                (template.base_pattern() ^ flip) | (rng.next() as u32 & !template.mask()) | 0b11
+            }
+            _ => rng.next() as u32 | 0b11,
+        };
+        if is_32bit_word(word) && table.decode(word).is_none() {
+            return word;
+        }
+    }
+}
+
+/// Picks a random snippet from the snippet library (if there is one).
+/// Returns None in half of the cases so that the builtin snippets are still
+/// used.
+fn pick_learned_snippet<Rng: Rand, S: HasMetadata>(
+    rng: &mut Rng,
+    state: &S,
+) -> Option<Vec<Instruction>> {
+    let library_len = state
+        .metadata_map()
+        .get::<SnippetLibraryMetadata>()
+        .map_or(0, |library| library.len());
+    if library_len == 0 || rng.below(2) == 0 {
+        return None;
+    }
+    let idx = rng.below(library_len as u64) as usize;
+    state
+        .metadata_map()
+        .get::<SnippetLibraryMetadata>()?
+        .get(idx)
 }
 
 impl Named for RiscVInstructionMutator {
@@ -70,19 +489,117 @@ impl Named for RiscVInstructionMutator {
 pub struct EmptyProgramNotSupported;
 
 impl RiscVInstructionMutator {
-    pub fn new(mutation: Mutation) -> Self {
-        Self { mutation }
+    /// Creates a mutator that generates new instructions with the given
+    /// generator settings.
+    pub fn new(mutation: Mutation, config: InstGeneratorConfig) -> Self {
+        Self {
+            mutation,
+            generator: InstGenerator::with_config(config),
+            stream_id: (mutation as u64) << 32,
+            iteration: 0,
+        }
+    }
+
+    /// Distinguishes the RNG streams of several mutators with the same
+    /// mutation.
+    fn with_instance(mut self, instance: u64) -> Self {
+        self.stream_id = ((self.mutation as u64) << 32) | instance;
+        self
+    }
+
+    // Applies the mutation to the input.
+    fn mutate_input<S, I>(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error>
+    where
+        S: HasRand + HasMetadata,
+        I: HasProgramInput,
+    {
+        // Every mutation draws from its own RNG so that it can be derived
+        // from the mutator's stream when a mutation seed is set.
+        let seed = match self.generator.mutation_seed() {
+            Some(seed) => {
+                self.iteration += 1;
+                stream_seed(seed, self.stream_id, self.iteration)
+            }
+            None => state.rand_mut().next(),
+        };
+        let mut rng = StdRand::with_seed(seed);
+
+        let learned = match self.mutation {
+            Mutation::Snippet => pick_learned_snippet(&mut rng, state),
+            Mutation::FlipFeature
+            | Mutation::ChangePrivilege
+            | Mutation::MutateInterrupts
+            | Mutation::MutateRegisters => {
+                let config = input.config_mut();
+                if mutate_config(&mut rng, self.mutation, config).is_none() {
+                    return Ok(MutationResult::Skipped);
+                }
+                return Ok(MutationResult::Mutated);
+            }
+            Mutation::MutateMemory => {
+                let memory = input.memory_mut();
+                if memory.is_none() {
+                    *memory = self.generator.memory_image().map(MemoryImage::new);
+                }
+                if mutate_memory(&mut rng, memory).is_none() {
+                    return Ok(MutationResult::Skipped);
+                }
+                return Ok(MutationResult::Mutated);
+            }
+            _ => None,
+        };
+        let mut generated = ProvenanceMetadata::new();
+        let result = if self.mutation.replaces_in_place() {
+            match self.replacements(input.insts(), &mut rng, self.mutation, &mut generated) {
+                Some(replacements) => {
+                    for (pos, inst) in replacements {
+                        input.set_inst(pos, inst);
+                    }
+                    Ok(MutationResult::Mutated)
+                }
+                None => Ok(MutationResult::Skipped),
+            }
+        } else {
+            self.mutate_impl(&mut rng, input.insts_mut(), learned, &mut generated)
+        };
+        // Collected by the ProvenanceFeedback after the execution.
+        if !generated.is_empty() {
+            if !state.has_metadata::<ProvenanceMetadata>() {
+                state.add_metadata(ProvenanceMetadata::new());
+            }
+            if let Some(pending) = state.metadata_map_mut().get_mut::<ProvenanceMetadata>() {
+                pending.extend(generated);
+            }
+        }
+        // Catch broken programs before they are executed and end up in the
+        // corpus.
+        #[cfg(feature = "strict-checks")]
+        if let Err(err) = check_instructions(input.insts(), instructions::riscv::all()) {
+            return Err(Error::illegal_state(format!(
+                "{} produced an invalid program: {}",
+                self.mutation, err
+            )));
+        }
+        result
     }
 
-    /// Generates a random instruction.
-    fn gen_inst<Rng: Rand>(&self, program: &Vec<Instruction>, rng: &mut Rng) -> Instruction {
-        let mut generator = InstGenerator::new();
+    /// Generates a random instruction and records its operand provenance.
+    fn gen_inst<Rng: Rand>(
+        &self,
+        program: &[Instruction],
+        rng: &mut Rng,
+        generated: &mut ProvenanceMetadata,
+    ) -> Instruction {
+        let mut generator = self.generator.clone();
 
         for inst in program {
-            generator.forward_args(inst.arguments())
+            generator.forward_inst(inst)
         }
 
-        generator.generate_instruction::<Rng>(rng, &instructions::sets::riscv_base())
+        let (inst, provenance) =
+            generator.generate_instruction_traced::<Rng>(rng, self.generator.instruction_set());
+        generated.add(&inst, provenance);
+        inst
     }
 
     /// Interprets the input bytes as RISC-V opcodes and mutates them.
@@ -90,8 +607,13 @@ impl RiscVInstructionMutator {
         &self,
         rng: &mut Rng,
         program: &mut Vec<Instruction>,
+        learned_snippet: Option<Vec<Instruction>>,
+        generated: &mut ProvenanceMetadata,
     ) -> Result<MutationResult, Error> {
-        if self.mutate_with(program, rng, self.mutation).is_none() {
+        if self
+            .mutate_with(program, rng, self.mutation, learned_snippet, generated)
+            .is_none()
+        {
             return Ok(MutationResult::Skipped);
         }
 
@@ -105,13 +627,26 @@ impl RiscVInstructionMutator {
         rng: &mut Rng,
         input: &mut Vec<u8>,
     ) -> Result<MutationResult, Error> {
-        let program_or_err = parse_instructions(input, &instructions::sets::riscv_g());
+        let program_or_err = parse_instructions_with_raw_words(
+            input,
+            instructions::sets::riscv_g(),
+            self.generator.raw_words_allowed(),
+        );
         if program_or_err.is_err() {
             return Err(Error::illegal_argument(program_or_err.err().unwrap()));
         }
         let mut program = program_or_err.unwrap();
 
-        if self.mutate_with(&mut program, rng, self.mutation).is_none() {
+        if self
+            .mutate_with(
+                &mut program,
+                rng,
+                self.mutation,
+                None,
+                &mut ProvenanceMetadata::new(),
+            )
+            .is_none()
+        {
             return Ok(MutationResult::Skipped);
         }
 
@@ -119,7 +654,9 @@ impl RiscVInstructionMutator {
         Ok(MutationResult::Mutated)
     }
 
-    fn make_snippet<Rng: Rand>(&self, rng: &mut Rng) -> Vec<Instruction> {
+    /// Creates a builtin snippet. Memory snippets address relative to the
+    /// registers in `pool` where possible.
+    fn make_snippet<Rng: Rand>(&self, rng: &mut Rng, pool: &AddressPool) -> Vec<Instruction> {
         // Creates:
         //   auipc x2, 0
         //   jalr x1, random_offset(x2)
@@ -158,17 +695,189 @@ impl RiscVInstructionMutator {
             )]
         };
 
+        // Privilege transitions are only an option if the target supports
+        // several privilege levels.
+        if rng.below(3) == 0 {
+            if let Some(snippet) = privilege_transition(rng, self.generator.privilege_levels()) {
+                return snippet;
+            }
+        }
+
+        if let Some(config) = self.generator.page_table() {
+            if rng.below(3) == 0 {
+                return sv39_setup(rng, config, self.generator.misaligned_chance(), pool);
+            }
+        }
+
+        if let (Some(geometry), Some(region)) =
+            (self.generator.cache_geometry(), self.generator.pmp_region())
+        {
+            if rng.below(3) == 0 {
+                return eviction_snippet(rng, geometry, region, pool);
+            }
+        }
+
+        if let (Some(failure_routine), Some(region)) = (
+            self.generator.failure_routine(),
+            self.generator.pmp_region(),
+        ) {
+            if rng.below(3) == 0 {
+                return store_load_check(rng, region, failure_routine, pool);
+            }
+        }
+
+        if let Some(config) = self.generator.pmp_region() {
+            if rng.below(3) == 0 {
+                return pmp_snippet(rng, config, self.generator.misaligned_chance(), pool);
+            }
+        }
+
         let options = [make_call, make_ret];
         let selected: usize = rng.below(options.len() as u64) as usize;
         return options[selected](rng);
     }
 
-    ///
+    /// The positions of all instructions we're allowed to mutate.
+    fn mutable_positions(&self, program: &[Instruction]) -> Vec<usize> {
+        match self.generator.class_filter() {
+            Some(filter) => (0..program.len())
+                .filter(|i| filter.accepts(program[*i].template()))
+                .collect(),
+            None => (0..program.len()).collect(),
+        }
+    }
+
+    /// Computes a mutation that replaces instructions in place (see
+    /// `Mutation::replaces_in_place`) without applying it. Returns the
+    /// positions with their new instructions or None if the mutation doesn't
+    /// apply.
+    fn replacements<Rng: Rand>(
+        &self,
+        program: &[Instruction],
+        rng: &mut Rng,
+        mutation: Mutation,
+        generated: &mut ProvenanceMetadata,
+    ) -> Option<Vec<(usize, Instruction)>> {
+        let mutable_positions = self.mutable_positions(program);
+        let valid_pos = |rng: &mut Rng| -> Option<usize> {
+            if mutable_positions.is_empty() {
+                return None;
+            }
+            Some(*rng.choose(&mutable_positions))
+        };
+
+        match mutation {
+            Mutation::Replace => {
+                // Keep replacing until we actually changed something. Give up
+                // if the generator can only produce the same instruction
+                // (e.g. a single template and a single register).
+                for _ in 0..REPLACE_RETRIES {
+                    let pos = valid_pos(rng)?;
+                    let new_inst = self.gen_inst(program, rng, generated);
+                    if new_inst != program[pos] {
+                        return Some(vec![(pos, new_inst)]);
+                    }
+                }
+                None
+            }
+            Mutation::ReplaceArg => {
+                let pos = valid_pos(rng)?;
+                let mut inst = program[pos].clone();
+                if inst.arguments().is_empty() {
+                    return None;
+                }
+                let template = inst.template();
+                let arg_idx = rng.below(inst.arguments().len() as u64) as usize;
+                let mut arg = inst.arg_mut(arg_idx)?;
+                // Keep generating arguments until we find a new one. A
+                // one-register subset never yields a different register.
+                let (new_arg, origin) = (0..REPLACE_RETRIES)
+                    .map(|_| {
+                        self.generator
+                            .generate_argument_traced(rng, template, arg.spec())
+                    })
+                    .find(|(new_arg, _)| new_arg.value() != arg.value())?;
+                arg.set_value(new_arg.value());
+                // The origin of the other operands is unknown here.
+                let mut provenance = vec![Provenance::Unknown; inst.arguments().len()];
+                provenance[arg_idx] = origin;
+                generated.add(&inst, provenance);
+                Some(vec![(pos, inst)])
+            }
+            Mutation::SwapTwo => {
+                let pos = valid_pos(rng)?;
+                let pos2 = valid_pos(rng)?;
+                Some(vec![
+                    (pos, program[pos2].clone()),
+                    (pos2, program[pos].clone()),
+                ])
+            }
+            Mutation::ReplaceWithNop => {
+                let pos = valid_pos(rng)?;
+                let nop = Instruction::new(
+                    &ADDI,
+                    vec![
+                        Argument::new(&args::RD, 0),
+                        Argument::new(&args::RS1, 0),
+                        Argument::new(&args::IMM12, 0),
+                    ],
+                );
+                Some(vec![(pos, nop)])
+            }
+            Mutation::TweakImm => {
+                let pos = valid_pos(rng)?;
+                let mut inst = program[pos].clone();
+                let imms: Vec<Argument> = inst
+                    .arguments()
+                    .iter()
+                    .filter(|arg| arg.spec().is_immediate())
+                    .cloned()
+                    .collect();
+                if imms.is_empty() {
+                    return None;
+                }
+                let old_arg = rng.choose(imms);
+                let spec = old_arg.spec();
+                // Immediates split over several fields are tweaked as a whole.
+                let split = spec.split_immediate();
+                let (old_value, length, lowest_bit) = match split {
+                    Some(split) => (split.extract(&inst)?, split.length(), split.lowest_bit()),
+                    None => (old_arg.value(), spec.length(), 0),
+                };
+                let mask = (u32::MAX >> (32 - length)) & !((1 << lowest_bit) - 1);
+                let new_value = if rng.below(2) == 0 {
+                    // Add or subtract a small delta and wrap around.
+                    let delta = (rng.between(1, 16) as u32) << lowest_bit;
+                    if rng.below(2) == 0 {
+                        old_value.wrapping_add(delta) & mask
+                    } else {
+                        old_value.wrapping_sub(delta) & mask
+                    }
+                } else {
+                    old_value ^ (1 << rng.between(lowest_bit as u64, length as u64 - 1))
+                };
+                if new_value == old_value {
+                    return None;
+                }
+                match split {
+                    Some(split) => split.apply(&mut inst, new_value),
+                    None => inst.set_arg(Argument::new(spec, new_value)),
+                }
+                Some(vec![(pos, inst)])
+            }
+            _ => unreachable!("{} doesn't replace instructions in place", mutation),
+        }
+    }
+
+    /// Applies the given mutation to the program. `learned_snippet` is
+    /// inserted instead of a builtin snippet for `Mutation::Snippet`.
     fn mutate_with<Rng: Rand>(
         &self,
         program: &mut Vec<Instruction>,
         rng: &mut Rng,
         mutation: Mutation,
+        learned_snippet: Option<Vec<Instruction>>,
+        generated: &mut ProvenanceMetadata,
     ) -> Option<()> {
         let program_empty = program.is_empty();
         let program_len = program.len();
@@ -179,54 +888,32 @@ impl RiscVInstructionMutator {
             rng.below(max(program_len as u64, 1)) as usize
         };
 
+        let mutable_positions = self.mutable_positions(program);
         let valid_pos = |rng: &mut Rng| -> Option<usize> {
-            if program_empty {
+            if mutable_positions.is_empty() {
                 return None;
             }
-            Some(rng.below(program_len as u64) as usize)
+            Some(*rng.choose(&mutable_positions))
         };
 
         match mutation {
             Mutation::Add => {
-                program.insert(add_pos(rng), self.gen_inst(program, rng));
+                program.insert(add_pos(rng), self.gen_inst(program, rng, generated));
             }
-            Mutation::Replace => {
-                // Keep replacing until we actually changed something.
-                loop {
-                    let pos = valid_pos(rng)?;
-                    let old_inst = program[pos].clone();
-                    let new_inst = self.gen_inst(program, rng);
-                    if new_inst != old_inst {
-                        program[pos] = new_inst;
-                        break;
-                    }
-                }
-            }
-            Mutation::ReplaceArg => {
-                let pos = valid_pos(rng)?;
-                let mut inst = program[pos].clone();
-                if inst.arguments().is_empty() {
+            Mutation::InjectIllegal => {
+                if !self.generator.raw_words_allowed() {
                     return None;
                 }
-                let old_arg = rng.choose(inst.arguments());
-                let arg_spec = old_arg.spec();
-                // Keep generating arguments until we find a new one.
-                loop {
-                    let new_arg = InstGenerator::new().generate_argument(rng, arg_spec);
-                    if &new_arg == old_arg {
-                        continue;
-                    }
-                    inst.set_arg(new_arg);
-                    break;
-                }
-                program[pos] = inst;
+                program.insert(add_pos(rng), Instruction::raw_word(illegal_word(rng)));
             }
-            Mutation::SwapTwo => {
-                let pos = valid_pos(rng)?;
-                let pos2 = valid_pos(rng)?;
-                let backup = program[pos].clone();
-                program[pos] = program[pos2].clone();
-                program[pos2] = backup;
+            Mutation::Replace
+            | Mutation::ReplaceArg
+            | Mutation::SwapTwo
+            | Mutation::ReplaceWithNop
+            | Mutation::TweakImm => {
+                for (pos, inst) in self.replacements(program, rng, mutation, generated)? {
+                    program[pos] = inst;
+                }
             }
             Mutation::RepeatSeveral => {
                 let pos = valid_pos(rng)?;
@@ -237,21 +924,170 @@ impl RiscVInstructionMutator {
             Mutation::Remove => {
                 program.remove(valid_pos(rng)?);
             }
-            Mutation::ReplaceWithNop => {
+            Mutation::Equivalent => {
+                // Start at a random instruction and rewrite the first one that
+                // has an equivalent form.
+                let start = valid_pos(rng)?;
+                let forms = (0..program_len)
+                    .map(|i| (start + i) % program_len)
+                    .filter(|i| mutable_positions.contains(i))
+                    .map(|i| {
+                        let mut forms = equivalent_forms(rng, &program[i]);
+                        forms.retain(|form| {
+                            !form
+                                .iter()
+                                .any(|inst| self.generator.is_excluded(inst.template()))
+                        });
+                        (i, forms)
+                    })
+                    .find(|(_, forms)| !forms.is_empty());
+                let (pos, forms) = forms?;
+                let form = rng.choose(forms);
+                program.splice(pos..pos + 1, form);
+            }
+            Mutation::RenameRegs => {
+                // x0 is hardwired to zero, so renaming it changes data flow.
+                let mut used: Vec<u32> = program
+                    .iter()
+                    .flat_map(|inst| inst.arguments().iter())
+                    .filter(|arg| arg.spec().is_register() && arg.value() != 0)
+                    .map(|arg| arg.value())
+                    .collect();
+                used.sort_unstable();
+                used.dedup();
+                if used.is_empty() {
+                    return None;
+                }
+                let a = *rng.choose(&used);
+                let candidates: Vec<u32> = match self.generator.register_subset() {
+                    Some(subset) => subset.to_vec(),
+                    None => (1..32).collect(),
+                };
+                let candidates: Vec<u32> = candidates
+                    .into_iter()
+                    .filter(|reg| *reg != 0 && *reg != a)
+                    .collect();
+                if candidates.is_empty() {
+                    return None;
+                }
+                let b = *rng.choose(&candidates);
+                swap_registers(program, a, b);
+            }
+            Mutation::PermuteArgsGlobally => {
+                let mut specs: Vec<&'static ArgumentSpec> = Vec::new();
+                for arg in program.iter().flat_map(|inst| inst.arguments().iter()) {
+                    if arg.spec().is_register() && !specs.contains(&arg.spec()) {
+                        specs.push(arg.spec());
+                    }
+                }
+                if specs.is_empty() {
+                    return None;
+                }
+                let spec = *rng.choose(&specs);
+                // x0 stays in place as it is hardwired to zero.
+                let mut registers: Vec<u32> = match self.generator.register_subset() {
+                    Some(subset) => subset.iter().copied().filter(|reg| *reg != 0).collect(),
+                    None => (1..32).collect(),
+                };
+                registers.sort_unstable();
+                registers.dedup();
+                let mut shuffled = registers.clone();
+                for i in (1..shuffled.len()).rev() {
+                    shuffled.swap(i, rng.below(i as u64 + 1) as usize);
+                }
+                let mut permutation: Vec<u32> = (0..32).collect();
+                for (from, to) in registers.iter().zip(shuffled.iter()) {
+                    permutation[*from as usize] = *to;
+                }
+                let changed = program
+                    .iter()
+                    .filter_map(|inst| inst.arg_value(spec))
+                    .any(|value| permutation[value as usize] != value);
+                if !changed {
+                    return None;
+                }
+                permute_registers(program, spec, &permutation);
+            }
+            Mutation::Reorder => {
                 let pos = valid_pos(rng)?;
-                let nop = Instruction::new(
-                    &ADDI,
-                    vec![
-                        Argument::new(&args::RD, 0),
-                        Argument::new(&args::RS1, 0),
-                        Argument::new(&args::IMM12, 0),
-                    ],
-                );
-                program[pos] = nop;
+                let range = movable_range(program, pos);
+                if range.len() <= 1 {
+                    return None;
+                }
+                let targets: Vec<usize> = range.filter(|i| *i != pos).collect();
+                let new_pos = *rng.choose(&targets);
+                let inst = program.remove(pos);
+                program.insert(new_pos, inst);
             }
+            Mutation::InjectFence => {
+                let fence = random_fence(rng);
+                if !self.generator.accepts(fence.template()) {
+                    return None;
+                }
+                program.insert(add_pos(rng), fence);
+            }
+            Mutation::PerturbPte => perturb_pte(rng, program)?,
+            Mutation::SameAddress => {
+                // Start at a random instruction and add accesses in front of
+                // the first load or store. Behind it, a load could already
+                // have overwritten its own base register.
+                let start = valid_pos(rng)?;
+                let (pos, accesses) = (0..program_len)
+                    .map(|i| (start + i) % program_len)
+                    .filter(|i| mutable_positions.contains(i))
+                    .find_map(|i| Some((i, same_address_accesses(rng, &program[i])?)))?;
+                if !accesses
+                    .iter()
+                    .all(|inst| self.generator.accepts(inst.template()))
+                {
+                    return None;
+                }
+                program.splice(pos..pos, accesses);
+            }
+            Mutation::AmoAlias => {
+                let start = valid_pos(rng)?;
+                let (pos, insts) = (0..program_len)
+                    .map(|i| (start + i) % program_len)
+                    .filter(|i| mutable_positions.contains(i))
+                    .find_map(|i| Some((i, alias_amo(rng, &program[i])?)))?;
+                if !insts
+                    .iter()
+                    .all(|inst| self.generator.accepts(inst.template()))
+                {
+                    return None;
+                }
+                program.splice(pos..pos + 1, insts);
+            }
+            // Only change the config header, see `mutate_config`.
+            Mutation::FlipFeature
+            | Mutation::ChangePrivilege
+            | Mutation::MutateInterrupts
+            | Mutation::MutateRegisters
+            | Mutation::MutateMemory => return None,
             Mutation::Snippet => {
                 let pos = add_pos(rng);
-                let mut snippet = self.make_snippet(rng);
+                let mut snippet = match learned_snippet {
+                    Some(snippet) => snippet,
+                    None => {
+                        // Materialize a pool of base addresses in front of
+                        // the snippet if there isn't one yet.
+                        let mut pool = AddressPool::of(&program[..pos]);
+                        let mut result = Vec::<Instruction>::new();
+                        if let Some(region) = self.generator.pmp_region() {
+                            if pool.is_empty() {
+                                (pool, result) = AddressPool::create(rng, region);
+                            }
+                        }
+                        result.append(&mut self.make_snippet(rng, &pool));
+                        result
+                    }
+                };
+                if !snippet
+                    .iter()
+                    .all(|inst| self.generator.accepts(inst.template()))
+                {
+                    return None;
+                }
                 while !snippet.is_empty() {
                     program.insert(pos, snippet.pop().unwrap());
                 }
@@ -277,27 +1113,203 @@ pub type RiscVMutationList = tuple_list_type!(
     RiscVInstructionMutator,
     RiscVInstructionMutator,
     RiscVInstructionMutator,
+    RiscVInstructionMutator,
+    RiscVInstructionMutator,
+    RiscVInstructionMutator,
+    RiscVInstructionMutator,
+    RiscVInstructionMutator,
+    RiscVInstructionMutator,
+    RiscVInstructionMutator,
+    RiscVInstructionMutator,
+    RiscVInstructionMutator,
+    RiscVInstructionMutator,
+    RiscVInstructionMutator,
+    RiscVInstructionMutator,
+    RiscVInstructionMutator,
+    RiscVInstructionMutator,
 );
 
 /// Provides a list of all supported RISC-V instruction mutators.
 pub fn all_riscv_mutations() -> RiscVMutationList {
+    all_riscv_mutations_with(&InstGeneratorConfig::default())
+}
+
+/// Provides a list of all supported RISC-V instruction mutators that use the
+/// given generator settings.
+pub fn all_riscv_mutations_with(config: &InstGeneratorConfig) -> RiscVMutationList {
+    // The number of mutators created so far for every mutation.
+    let mut instances = Vec::<Mutation>::new();
+    let mut m = |mutation: Mutation| {
+        let instance = instances
+            .iter()
+            .filter(|other| **other as u64 == mutation as u64)
+            .count();
+        instances.push(mutation);
+        RiscVInstructionMutator::new(mutation, config.clone()).with_instance(instance as u64)
+    };
     tuple_list!(
-        RiscVInstructionMutator::new(Mutation::Add),
-        RiscVInstructionMutator::new(Mutation::Add),
-        RiscVInstructionMutator::new(Mutation::Remove),
-        RiscVInstructionMutator::new(Mutation::Remove),
-        RiscVInstructionMutator::new(Mutation::ReplaceArg),
-        RiscVInstructionMutator::new(Mutation::ReplaceArg),
-        RiscVInstructionMutator::new(Mutation::Replace),
-        RiscVInstructionMutator::new(Mutation::Replace),
-        RiscVInstructionMutator::new(Mutation::RepeatSeveral),
-        RiscVInstructionMutator::new(Mutation::RepeatSeveral),
-        RiscVInstructionMutator::new(Mutation::SwapTwo),
-        RiscVInstructionMutator::new(Mutation::SwapTwo),
-        RiscVInstructionMutator::new(Mutation::Snippet),
+        m(Mutation::Add),
+        m(Mutation::Add),
+        m(Mutation::Remove),
+        m(Mutation::Remove),
+        m(Mutation::ReplaceArg),
+        m(Mutation::ReplaceArg),
+        m(Mutation::Replace),
+        m(Mutation::Replace),
+        m(Mutation::RepeatSeveral),
+        m(Mutation::RepeatSeveral),
+        m(Mutation::SwapTwo),
+        m(Mutation::SwapTwo),
+        m(Mutation::Snippet),
+        m(Mutation::Equivalent),
+        m(Mutation::TweakImm),
+        m(Mutation::RenameRegs),
+        m(Mutation::PermuteArgsGlobally),
+        m(Mutation::Reorder),
+        m(Mutation::InjectFence),
+        m(Mutation::PerturbPte),
+        m(Mutation::SameAddress),
+        m(Mutation::AmoAlias),
+        m(Mutation::FlipFeature),
+        m(Mutation::ChangePrivilege),
+        m(Mutation::MutateInterrupts),
+        m(Mutation::MutateRegisters),
+        m(Mutation::MutateMemory),
     )
 }
 
+/// The mutations of `all_riscv_mutations` with the number of mutators each
+/// has in the list.
+pub const DEFAULT_MUTATIONS: &str = "add:2,remove:2,replace-arg:2,replace:2,repeat-several:2,\
+swap-two:2,snippet,equivalent,tweak-imm,rename-regs,permute-args-globally,reorder,inject-fence,\
+perturb-pte,same-address,amo-alias,flip-feature,change-privilege,mutate-interrupts,\
+mutate-registers,mutate-memory";
+
+/// Parses a comma separated list of mutations with optional weights (e.g.
+/// 'add,replace,snippet:3'). 'default' selects `DEFAULT_MUTATIONS`.
+pub fn parse_mutation_list(list: &str) -> Result<Vec<(Mutation, usize)>, String> {
+    let list = if list.trim() == "default" {
+        DEFAULT_MUTATIONS
+    } else {
+        list
+    };
+    let mut result = Vec::<(Mutation, usize)>::new();
+    for entry in list.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (name, weight) = match entry.split_once(':') {
+            Some((name, weight)) => {
+                let weight = weight
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid weight in '{}'", entry))?;
+                (name, weight)
+            }
+            None => (entry, 1),
+        };
+        let mutation = name.parse::<Mutation>()?;
+        if weight == 0 {
+            return Err(format!("Weight of '{}' must be positive", mutation));
+        }
+        if result.iter().any(|(other, _)| *other == mutation) {
+            return Err(format!("Mutation '{}' is listed twice", mutation));
+        }
+        result.push((mutation, weight));
+    }
+    if result.is_empty() {
+        return Err("No mutations selected".to_string());
+    }
+    Ok(result)
+}
+
+/// Mutators for a selection of mutations (see `parse_mutation_list`). Each
+/// call picks a mutation with a probability proportional to its weight.
+pub struct SelectedMutations {
+    // One mutator per unit of weight, like the repeated entries of
+    // `all_riscv_mutations`.
+    mutators: Vec<RiscVInstructionMutator>,
+}
+
+impl SelectedMutations {
+    pub fn new(selection: &[(Mutation, usize)], config: &InstGeneratorConfig) -> Self {
+        let mutators = selection
+            .iter()
+            .flat_map(|(mutation, weight)| {
+                (0..*weight).map(|instance| {
+                    RiscVInstructionMutator::new(*mutation, config.clone())
+                        .with_instance(instance as u64)
+                })
+            })
+            .collect();
+        Self { mutators }
+    }
+
+    /// The selected mutations, once for every unit of their weight.
+    pub fn mutations(&self) -> Vec<Mutation> {
+        self.mutators.iter().map(|m| m.mutation).collect()
+    }
+}
+
+impl<I, S> Mutator<I, S> for SelectedMutations
+where
+    S: HasRand + HasMetadata,
+    I: HasProgramInput,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut I,
+        stage_idx: i32,
+    ) -> Result<MutationResult, Error> {
+        let idx = state.rand_mut().below(self.mutators.len() as u64) as usize;
+        self.mutators[idx].mutate(state, input, stage_idx)
+    }
+}
+
+impl Named for SelectedMutations {
+    fn name(&self) -> &str {
+        "SelectedMutations"
+    }
+}
+
+/// A minimal state for using the mutators outside of a LibAFL fuzzer, e.g.
+/// in the AFL++ custom mutator or the Python bindings.
+#[derive(Debug)]
+pub struct StandaloneState {
+    rand: StdRand,
+    metadata: SerdeAnyMap,
+}
+
+impl StandaloneState {
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rand: StdRand::with_seed(seed),
+            metadata: SerdeAnyMap::new(),
+        }
+    }
+}
+
+impl HasRand for StandaloneState {
+    type Rand = StdRand;
+
+    fn rand(&self) -> &StdRand {
+        &self.rand
+    }
+
+    fn rand_mut(&mut self) -> &mut StdRand {
+        &mut self.rand
+    }
+}
+
+impl HasMetadata for StandaloneState {
+    fn metadata_map(&self) -> &SerdeAnyMap {
+        &self.metadata
+    }
+
+    fn metadata_map_mut(&mut self) -> &mut SerdeAnyMap {
+        &mut self.metadata
+    }
+}
+
 /// All reducing mutations
 pub type RiscVReducingMutationList = tuple_list_type!(
     RiscVInstructionMutator,
@@ -307,10 +1319,11 @@ pub type RiscVReducingMutationList = tuple_list_type!(
 
 /// All mutations used to minimize test cases.
 pub fn reducing_mutations() -> RiscVReducingMutationList {
+    let m = |mutation| RiscVInstructionMutator::new(mutation, InstGeneratorConfig::default());
     tuple_list!(
-        RiscVInstructionMutator::new(Mutation::Remove),
-        RiscVInstructionMutator::new(Mutation::Remove),
-        RiscVInstructionMutator::new(Mutation::ReplaceWithNop),
+        m(Mutation::Remove),
+        m(Mutation::Remove),
+        m(Mutation::ReplaceWithNop),
     )
 }
 
@@ -319,20 +1332,39 @@ mod tests {
     use std::cmp::min;
 
     use libafl::prelude::MutationResult;
+    use libafl::prelude::Mutator;
     use libafl::prelude::Rand;
     use libafl::prelude::Xoshiro256StarRand;
 
     use crate::assembler::assemble_instructions;
-    use crate::generator::InstGenerator;
+    use crate::generator::{InstGenerator, InstGeneratorConfig};
     use crate::instructions;
+    use crate::instructions::riscv::args;
     use crate::instructions::riscv::rv_i::AUIPC;
+    use crate::instructions::riscv::rv_i::BEQ;
+    use crate::instructions::riscv::rv_i::FENCE;
     use crate::instructions::riscv::rv_i::JALR;
+    use crate::instructions::riscv::rv_i::LW;
+    use crate::instructions::riscv::rv_zifencei::FENCE_I;
     use crate::instructions::Instruction;
     use crate::instructions::InstructionTemplate;
-    use crate::parser::parse_instructions;
+    use crate::parser::{parse_instructions, InstDecoder};
 
+    use super::all_riscv_mutations_with;
+    use super::is_32bit_word;
+    use super::mutate_config;
+    use super::mutate_memory;
+    use super::parse_mutation_list;
+    use super::stream_seed;
     use super::Mutation;
     use super::RiscVInstructionMutator;
+    use super::SelectedMutations;
+    use super::StandaloneState;
+    use crate::input_config::{InputConfig, MAX_INTERRUPTS, MAX_REGISTER_INITS};
+    use crate::inst_class::{InstClass, InstClassFilter};
+    use crate::memory_image::{MemoryImage, MAX_MEMORY_IMAGE_SIZE};
+    use crate::memory_ops::{access_address, make_load};
+    use crate::program_input::HasProgramInput;
 
     /// The test harness.
     /// Contains all the data for the tests below and some utility code.
@@ -351,9 +1383,13 @@ mod tests {
 
     impl TestSetup {
         fn new(mutation: Mutation) -> Self {
+            Self::with_config(mutation, InstGeneratorConfig::default())
+        }
+
+        fn with_config(mutation: Mutation, config: InstGeneratorConfig) -> Self {
             Self {
                 rng: Xoshiro256StarRand::default(),
-                mutator: RiscVInstructionMutator::new(mutation),
+                mutator: RiscVInstructionMutator::new(mutation, config),
                 data: Vec::<u8>::new(),
                 old_data: Vec::<u8>::new(),
                 changed_insts: 0,
@@ -363,9 +1399,9 @@ mod tests {
         /// Calculates how many instructions have changed.
         fn update_changed(&mut self) {
             self.changed_insts = 0;
-            let new_insts = parse_instructions(&self.data, &instructions::sets::riscv_g()).unwrap();
+            let new_insts = parse_instructions(&self.data, instructions::sets::riscv_g()).unwrap();
             let old_insts =
-                parse_instructions(&self.old_data, &instructions::sets::riscv_g()).unwrap();
+                parse_instructions(&self.old_data, instructions::sets::riscv_g()).unwrap();
             for i in 0..min(new_insts.len(), old_insts.len()) {
                 if new_insts[i] != old_insts[i] {
                     self.changed_insts += 1;
@@ -389,8 +1425,8 @@ mod tests {
 
         /// Fill the byte vector with one specific encoded instruction.
         fn fill_one_inst(&mut self, template: &'static InstructionTemplate) {
-            let inst = InstGenerator::new().generate_instruction(&mut self.rng, &vec![template]);
-            self.data = assemble_instructions(&vec![inst]);
+            let inst = InstGenerator::new().generate_instruction(&mut self.rng, &[template]);
+            self.data = assemble_instructions(&[inst]);
         }
 
         /// Fill the byte vector with random instructions.
@@ -400,14 +1436,14 @@ mod tests {
             let num_insts = self.rng.below(40) as u32;
             self.data = assemble_instructions(&generator.generate_instructions(
                 &mut self.rng,
-                &instructions::sets::riscv_g(),
+                instructions::sets::riscv_g(),
                 num_insts,
             ));
         }
 
         /// Returns the parsed instructions in the current buffer.
         fn parsed_insts(&self) -> Vec<Instruction> {
-            parse_instructions(&self.data, &instructions::sets::riscv_g()).unwrap()
+            parse_instructions(&self.data, instructions::sets::riscv_g()).unwrap()
         }
     }
 
@@ -520,6 +1556,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn mutate_replace_single_register() {
+        // Test that replacing gives up instead of hanging when the generator
+        // can't produce anything different.
+        for mutation in [Mutation::Replace, Mutation::ReplaceArg] {
+            let config = InstGeneratorConfig::new()
+                .instruction_set(vec![&instructions::riscv::rv_i::ADD])
+                .register_subset(vec![5]);
+            let mut setup = TestSetup::with_config(mutation, config);
+            let add = Instruction::new(
+                &instructions::riscv::rv_i::ADD,
+                vec![
+                    instructions::Argument::new(&args::RD, 5),
+                    instructions::Argument::new(&args::RS1, 5),
+                    instructions::Argument::new(&args::RS2, 5),
+                ],
+            );
+            setup.data = assemble_instructions(&[add]);
+            for _ in 0..10 {
+                assert!(!setup.mutate());
+            }
+        }
+    }
+
     #[test]
     fn mutate_repeat() {
         // Test that 'RepeatOne' only adds instructions.
@@ -563,4 +1623,545 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn mutate_replace_with_class_filter() {
+        // Test that a class filter restricts which instructions are replaced
+        // and which ones are inserted.
+        let config = InstGeneratorConfig::new().class_filter(InstClassFilter::memory());
+        let mut setup = TestSetup::with_config(Mutation::Replace, config);
+        let filter = InstClassFilter::memory();
+
+        for _ in 0..TRIES {
+            setup.fill_random_inst();
+            let old_insts = setup.parsed_insts();
+            if setup.mutate() {
+                let new_insts = setup.parsed_insts();
+                for (old, new) in old_insts.iter().zip(new_insts.iter()) {
+                    if old != new {
+                        assert!(filter.accepts(old.template()));
+                        assert!(filter.accepts(new.template()));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mutate_with_excluded_templates() {
+        // Test that excluded templates are never inserted, but existing ones
+        // are kept.
+        let excluded = instructions::sets::parse_template_list("fence.i,EBREAK").unwrap();
+        assert_eq!(excluded.len(), 2);
+        let count = |insts: &Vec<Instruction>| {
+            insts
+                .iter()
+                .filter(|inst| excluded.contains(&inst.template()))
+                .count()
+        };
+        for mutation in [Mutation::Add, Mutation::InjectFence] {
+            let config = InstGeneratorConfig::new().exclude_templates(excluded.clone());
+            let mut setup = TestSetup::with_config(mutation, config);
+            for _ in 0..TRIES {
+                setup.fill_random_inst();
+                let old_insts = setup.parsed_insts();
+                if setup.mutate() {
+                    assert!(count(&setup.parsed_insts()) <= count(&old_insts));
+                }
+            }
+        }
+        assert!(instructions::sets::parse_template_list("fence.i,bogus").is_err());
+    }
+
+    #[test]
+    fn mutate_equivalent() {
+        // Test that 'Equivalent' only rewrites a single instruction into at
+        // most two instructions.
+        let mut setup = TestSetup::new(Mutation::Equivalent);
+
+        for _ in 0..TRIES {
+            setup.fill_random_inst();
+            if setup.mutate() {
+                assert!(setup.data.len() >= setup.old_data.len());
+                assert!(setup.data.len() <= setup.old_data.len() + 4);
+            }
+        }
+    }
+
+    #[test]
+    fn mutate_equivalent_add() {
+        let mut setup = TestSetup::new(Mutation::Equivalent);
+        let nop = InstGenerator::new().generate_instructions(
+            &mut setup.rng,
+            &[&instructions::riscv::rv_i::ADDI],
+            1,
+        );
+        let mut nop = nop[0].clone();
+        nop.set_arg(instructions::Argument::new(
+            &instructions::riscv::args::IMM12,
+            0,
+        ));
+        setup.data = assemble_instructions(&[nop]);
+        assert!(setup.mutate());
+        assert_eq!(
+            setup.parsed_insts()[0].template(),
+            &instructions::riscv::rv_i::ADD
+        );
+    }
+
+    #[test]
+    fn mutate_inject_illegal() {
+        // The injected words don't decode, so they are only parsed as raw
+        // words.
+        let config = InstGeneratorConfig::new().allow_raw_words(true);
+        let mut setup = TestSetup::with_config(Mutation::InjectIllegal, config);
+        let set = instructions::riscv::all();
+        for _ in 0..TRIES {
+            setup.fill_random_inst();
+            let old_len = setup.data.len() / 4;
+            let result = setup.mutator.mutate_bytes(&mut setup.rng, &mut setup.data);
+            assert_eq!(result.unwrap(), MutationResult::Mutated);
+            assert!(parse_instructions(&setup.data, set).is_err());
+            let insts = InstDecoder::new(&setup.data, set)
+                .with_raw_words(true)
+                .collect::<Result<Vec<Instruction>, _>>()
+                .unwrap();
+            assert_eq!(insts.len(), old_len + 1);
+            let raw_words: Vec<u32> = insts
+                .iter()
+                .filter(|inst| inst.is_raw_word())
+                .map(Instruction::encode)
+                .collect();
+            assert_eq!(raw_words.len(), 1);
+            // Either defined illegal or a full 32-bit word.
+            let word = raw_words[0];
+            assert!(word == 0 || word == u32::MAX || is_32bit_word(word));
+            // Inputs with raw words can be mutated again.
+            let result = setup.mutator.mutate_bytes(&mut setup.rng, &mut setup.data);
+            assert_eq!(result.unwrap(), MutationResult::Mutated);
+        }
+
+        // Without raw words, the mutation never applies.
+        let mut setup = TestSetup::new(Mutation::InjectIllegal);
+        setup.fill_random_inst();
+        assert!(!setup.mutate());
+    }
+
+    #[test]
+    fn mutate_tweak_imm() {
+        // Test that 'TweakImm' only changes a single immediate.
+        let mut setup = TestSetup::new(Mutation::TweakImm);
+
+        for _ in 0..TRIES {
+            setup.fill_one_inst(&instructions::riscv::rv_i::ADDI);
+            let original_inst = setup.parsed_insts()[0].clone();
+            assert!(setup.mutate());
+
+            let new_inst = setup.parsed_insts()[0].clone();
+            assert_eq!(new_inst.template(), &instructions::riscv::rv_i::ADDI);
+            for (old, new) in original_inst
+                .arguments()
+                .iter()
+                .zip(new_inst.arguments().iter())
+            {
+                if old.spec().is_immediate() {
+                    assert_ne!(old, new);
+                } else {
+                    assert_eq!(old, new);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mutate_tweak_split_imm() {
+        // Branch offsets are tweaked as one value that stays a multiple of 2.
+        let mut setup = TestSetup::new(Mutation::TweakImm);
+        for _ in 0..TRIES {
+            setup.fill_one_inst(&BEQ);
+            let split = args::BIMM12HI.split_immediate().unwrap();
+            let old = split.extract(&setup.parsed_insts()[0]).unwrap();
+            assert!(setup.mutate());
+            let new = split.extract(&setup.parsed_insts()[0]).unwrap();
+            assert_ne!(old, new);
+            assert_eq!(new & 1, 0);
+            let diff = old ^ new;
+            let distance = min(old.wrapping_sub(new), new.wrapping_sub(old)) & 0x1fff;
+            assert!(diff.count_ones() == 1 || distance <= 32);
+        }
+    }
+
+    #[test]
+    fn mutate_tweak_imm_no_imm() {
+        // Instructions without immediates can't be tweaked.
+        let mut setup = TestSetup::new(Mutation::TweakImm);
+        setup.fill_one_inst(&instructions::riscv::rv_i::ADD);
+        assert!(!setup.mutate());
+    }
+
+    #[test]
+    fn mutate_rename_regs() {
+        let mut setup = TestSetup::new(Mutation::RenameRegs);
+        for _ in 0..TRIES {
+            setup.fill_random_inst();
+            let old_insts = setup.parsed_insts();
+            let mutated = setup.mutate();
+            let new_insts = setup.parsed_insts();
+            assert_eq!(old_insts.len(), new_insts.len());
+            if !mutated {
+                continue;
+            }
+            // Only registers were renamed.
+            for (old, new) in old_insts.iter().zip(new_insts.iter()) {
+                assert_eq!(old.template(), new.template());
+                for (old_arg, new_arg) in old.arguments().iter().zip(new.arguments().iter()) {
+                    if !old_arg.spec().is_register() {
+                        assert_eq!(old_arg, new_arg);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mutate_permute_args_globally() {
+        let mut setup = TestSetup::new(Mutation::PermuteArgsGlobally);
+        for _ in 0..TRIES {
+            setup.fill_random_inst();
+            let old_insts = setup.parsed_insts();
+            let mutated = setup.mutate();
+            let new_insts = setup.parsed_insts();
+            assert_eq!(old_insts.len(), new_insts.len());
+            if !mutated {
+                continue;
+            }
+            // The same register is always renamed to the same register.
+            let mut mapping = std::collections::HashMap::<(&str, u32), u32>::new();
+            for (old, new) in old_insts.iter().zip(new_insts.iter()) {
+                assert_eq!(old.template(), new.template());
+                for (old_arg, new_arg) in old.arguments().iter().zip(new.arguments().iter()) {
+                    if !old_arg.spec().is_register() {
+                        assert_eq!(old_arg, new_arg);
+                        continue;
+                    }
+                    let key = (old_arg.spec().name(), old_arg.value());
+                    let renamed = *mapping.entry(key).or_insert(new_arg.value());
+                    assert_eq!(renamed, new_arg.value());
+                    if old_arg.value() == 0 {
+                        assert_eq!(new_arg.value(), 0);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mutate_reorder() {
+        let mut setup = TestSetup::new(Mutation::Reorder);
+        for _ in 0..TRIES {
+            setup.fill_random_inst();
+            let old_insts = setup.parsed_insts();
+            if !setup.mutate() {
+                continue;
+            }
+            // Same instructions, just in a different order.
+            let mut new_insts = setup.parsed_insts();
+            assert_ne!(old_insts, new_insts);
+            for inst in &old_insts {
+                let idx = new_insts.iter().position(|i| i == inst).unwrap();
+                new_insts.remove(idx);
+            }
+            assert!(new_insts.is_empty());
+        }
+    }
+
+    #[test]
+    fn mutate_inject_fence() {
+        let mut setup = TestSetup::new(Mutation::InjectFence);
+        for _ in 0..TRIES {
+            setup.fill_random_inst();
+            let old_insts = setup.parsed_insts();
+            assert!(setup.mutate());
+            let new_insts = setup.parsed_insts();
+            assert_eq!(old_insts.len() + 1, new_insts.len());
+            assert!(new_insts
+                .iter()
+                .any(|inst| inst.template() == &FENCE || inst.template() == &FENCE_I));
+        }
+    }
+
+    #[test]
+    fn mutate_same_address() {
+        let mut setup = TestSetup::new(Mutation::SameAddress);
+        for _ in 0..TRIES {
+            setup.fill_random_inst();
+            let old_insts = setup.parsed_insts();
+            let has_access = old_insts.iter().any(|inst| access_address(inst).is_some());
+            assert_eq!(setup.mutate(), has_access);
+            if !has_access {
+                continue;
+            }
+            let new_insts = setup.parsed_insts();
+            let added = new_insts.len() - old_insts.len();
+            assert!((1..=3).contains(&added));
+            // The accesses are inserted directly in front of a load or store
+            // and use the same address.
+            let inserted_before = |pos: usize| {
+                let address = access_address(&old_insts[pos]);
+                address.is_some()
+                    && new_insts[..pos] == old_insts[..pos]
+                    && new_insts[pos + added..] == old_insts[pos..]
+                    && new_insts[pos..pos + added]
+                        .iter()
+                        .all(|inst| access_address(inst) == address)
+            };
+            assert!((0..old_insts.len()).any(inserted_before));
+        }
+    }
+
+    #[test]
+    fn mutate_same_address_load_overwriting_base() {
+        // Test that the accesses still hit the address of a load that
+        // overwrites its own base register (lw a0, 0(a0)).
+        let mut setup = TestSetup::new(Mutation::SameAddress);
+        let load = make_load(&LW, 10, 10, 8);
+        for _ in 0..100 {
+            setup.data = assemble_instructions(&[load.clone()]);
+            assert!(setup.mutate());
+            let insts = setup.parsed_insts();
+            // The base register holds the same value for every access.
+            let (base, offset) = access_address(&load).unwrap();
+            assert_eq!(insts.last(), Some(&load));
+            for inst in &insts[..insts.len() - 1] {
+                assert_eq!(access_address(inst), Some((base, offset)));
+                assert_ne!(inst.arg_value(&args::RD), Some(base));
+            }
+        }
+    }
+
+    #[test]
+    fn mutate_amo_alias() {
+        let mut setup = TestSetup::new(Mutation::AmoAlias);
+        for _ in 0..TRIES {
+            setup.fill_random_inst();
+            let old_insts = setup.parsed_insts();
+            let has_amo = old_insts
+                .iter()
+                .any(|inst| InstClass::of(inst.template()) == [InstClass::Atomic]);
+            assert_eq!(setup.mutate(), has_amo);
+            if has_amo {
+                let added = setup.parsed_insts().len() - old_insts.len();
+                assert!(added <= 1);
+                assert!(setup.changed_insts > 0 || added == 1);
+            }
+        }
+    }
+
+    #[test]
+    fn mutate_config_header() {
+        let mut rng = Xoshiro256StarRand::default();
+        let mut config = Some(InputConfig::default());
+        for _ in 0..TRIES {
+            let old = config.clone().unwrap();
+            assert!(mutate_config(&mut rng, Mutation::FlipFeature, &mut config).is_some());
+            let new = config.clone().unwrap();
+            assert_eq!((old.features ^ new.features).count_ones(), 1);
+            assert_eq!(old.privilege, new.privilege);
+
+            assert!(mutate_config(&mut rng, Mutation::ChangePrivilege, &mut config).is_some());
+            assert_ne!(config.as_ref().unwrap().privilege, new.privilege);
+        }
+    }
+
+    #[test]
+    fn mutate_interrupt_schedule() {
+        let mut rng = Xoshiro256StarRand::default();
+        let mut config = Some(InputConfig::default());
+        for _ in 0..TRIES {
+            assert!(mutate_config(&mut rng, Mutation::MutateInterrupts, &mut config).is_some());
+            let interrupts = &config.as_ref().unwrap().interrupts;
+            assert!(interrupts.len() <= MAX_INTERRUPTS);
+            assert!(interrupts.windows(2).all(|w| w[0].cycle <= w[1].cycle));
+            // The schedule must survive the header encoding.
+            let bytes = config.as_ref().unwrap().encode();
+            assert_eq!(
+                InputConfig::decode(&bytes).unwrap().unwrap().0,
+                *config.as_ref().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn mutate_initial_registers() {
+        let mut rng = Xoshiro256StarRand::default();
+        let mut config = Some(InputConfig::default());
+        for _ in 0..TRIES {
+            assert!(mutate_config(&mut rng, Mutation::MutateRegisters, &mut config).is_some());
+            let registers = &config.as_ref().unwrap().registers;
+            assert!(registers.len() <= MAX_REGISTER_INITS);
+            // Sorted, without duplicates and never x0.
+            assert!(registers.windows(2).all(|w| w[0].reg < w[1].reg));
+            assert!(registers.iter().all(|init| (1..32).contains(&init.reg)));
+            let bytes = config.as_ref().unwrap().encode();
+            assert_eq!(
+                InputConfig::decode(&bytes).unwrap().unwrap().0,
+                *config.as_ref().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn parse_mutation_names() {
+        for mutation in Mutation::ALL {
+            assert_eq!(mutation.to_string().parse::<Mutation>(), Ok(mutation));
+            assert!(!mutation.description().is_empty());
+        }
+        assert_eq!("Replace_Arg".parse::<Mutation>(), Ok(Mutation::ReplaceArg));
+        assert!("frobnicate".parse::<Mutation>().is_err());
+        assert_eq!(Mutation::SwapTwo.arity(), 2);
+        assert_eq!(Mutation::FlipFeature.arity(), 0);
+    }
+
+    #[test]
+    fn parse_mutation_lists() {
+        assert_eq!(
+            parse_mutation_list("add, replace_arg,snippet:3"),
+            Ok(vec![
+                (Mutation::Add, 1),
+                (Mutation::ReplaceArg, 1),
+                (Mutation::Snippet, 3)
+            ])
+        );
+        assert!(parse_mutation_list("add,add").is_err());
+        assert!(parse_mutation_list("add:0").is_err());
+        assert!(parse_mutation_list("add:x").is_err());
+        assert!(parse_mutation_list("").is_err());
+
+        // The default list has the same mutators as `all_riscv_mutations`.
+        let default = parse_mutation_list("default").unwrap();
+        let selected = SelectedMutations::new(&default, &InstGeneratorConfig::default());
+        assert_eq!(selected.mutations().len(), 27);
+        assert!(Mutation::ALL.iter().all(|m| {
+            matches!(m, Mutation::ReplaceWithNop | Mutation::InjectIllegal)
+                || selected.mutations().contains(m)
+        }));
+    }
+
+    #[test]
+    fn mutate_memory_image() {
+        let mut rng = Xoshiro256StarRand::default();
+        let mut memory = Some(MemoryImage::new(0x8000_1000));
+        for _ in 0..TRIES {
+            assert!(mutate_memory(&mut rng, &mut memory).is_some());
+            let image = memory.as_ref().unwrap();
+            assert!(image.bytes.len() <= MAX_MEMORY_IMAGE_SIZE);
+            assert_eq!(image.address, 0x8000_1000);
+            assert_eq!(
+                MemoryImage::decode(&image.encode()).unwrap().unwrap().0,
+                *image
+            );
+        }
+
+        let mut memory = None;
+        assert!(mutate_memory(&mut rng, &mut memory).is_none());
+    }
+
+    #[test]
+    fn mutate_config_without_header() {
+        let mut rng = Xoshiro256StarRand::default();
+        let mut config = None;
+        assert!(mutate_config(&mut rng, Mutation::FlipFeature, &mut config).is_none());
+        assert!(mutate_config(&mut rng, Mutation::ChangePrivilege, &mut config).is_none());
+        assert_eq!(config, None);
+
+        // Config mutations never touch the instructions.
+        let mut setup = TestSetup::new(Mutation::FlipFeature);
+        setup.fill_random_inst();
+        assert!(!setup.mutate());
+    }
+
+    /// An input that only allows replacing single instructions.
+    #[derive(Default)]
+    struct PatchOnlyInput {
+        insts: Vec<Instruction>,
+        config: Option<InputConfig>,
+        memory: Option<MemoryImage>,
+        patched: usize,
+    }
+
+    impl HasProgramInput for PatchOnlyInput {
+        fn insts(&self) -> &[Instruction] {
+            &self.insts
+        }
+
+        fn insts_mut(&mut self) -> &mut Vec<Instruction> {
+            panic!("Only set_inst is allowed");
+        }
+
+        fn config_mut(&mut self) -> &mut Option<InputConfig> {
+            &mut self.config
+        }
+
+        fn memory_mut(&mut self) -> &mut Option<MemoryImage> {
+            &mut self.memory
+        }
+
+        fn set_inst(&mut self, idx: usize, inst: Instruction) {
+            self.patched += 1;
+            self.insts[idx] = inst;
+        }
+    }
+
+    #[test]
+    fn mutate_in_place_with_set_inst() {
+        // Test that the mutations replacing single instructions patch them
+        // instead of borrowing the whole program.
+        let mut state = StandaloneState::new(1);
+        let mut rng = Xoshiro256StarRand::default();
+        for mutation in Mutation::ALL.iter().filter(|m| m.replaces_in_place()) {
+            let mut mutator = RiscVInstructionMutator::new(*mutation, InstGeneratorConfig::new());
+            for _ in 0..100 {
+                let mut input = PatchOnlyInput {
+                    insts: InstGenerator::new().generate_instructions(
+                        &mut rng,
+                        instructions::sets::riscv_g(),
+                        8,
+                    ),
+                    ..Default::default()
+                };
+                let old_insts = input.insts.clone();
+                let result = mutator.mutate(&mut state, &mut input, 0).unwrap();
+                if result == MutationResult::Mutated {
+                    assert!((1..=2).contains(&input.patched));
+                    assert_eq!(input.insts.len(), old_insts.len());
+                } else {
+                    assert_eq!(input.insts, old_insts);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mutation_streams() {
+        assert_eq!(stream_seed(1, 2, 3), stream_seed(1, 2, 3));
+        let mut seeds = Vec::new();
+        for id in 0..4 {
+            for iteration in 0..4 {
+                seeds.push(stream_seed(42, id, iteration));
+            }
+        }
+        seeds.sort();
+        seeds.dedup();
+        assert_eq!(seeds.len(), 16);
+
+        // Mutators with the same mutation get different streams.
+        let config = InstGeneratorConfig::default().mutation_seed(42);
+        let list = all_riscv_mutations_with(&config);
+        assert_eq!(list.0.generator.mutation_seed(), Some(42));
+        assert_ne!(list.0.stream_id, list.1 .0.stream_id);
+        assert_eq!(
+            list.0.stream_id,
+            RiscVInstructionMutator::new(Mutation::Add, config).stream_id
+        );
+    }
 }
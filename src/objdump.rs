@@ -0,0 +1,259 @@
+//! Prints instructions like `objdump -d -M no-aliases` so that disassembly
+//! can be diffed against the GNU tools.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::{
+    instructions::{
+        riscv::args,
+        split_immediates::{BRANCH_OFFSET, JUMP_OFFSET, STORE_OFFSET},
+        ArgKind, Instruction,
+    },
+    registers::{register_name, RegisterStyle},
+};
+
+/// The names objdump prints for common CSRs. Other CSRs are printed as hex.
+const CSR_NAMES: [(u32, &str); 40] = [
+    (0x001, "fflags"),
+    (0x002, "frm"),
+    (0x003, "fcsr"),
+    (0x100, "sstatus"),
+    (0x104, "sie"),
+    (0x105, "stvec"),
+    (0x106, "scounteren"),
+    (0x140, "sscratch"),
+    (0x141, "sepc"),
+    (0x142, "scause"),
+    (0x143, "stval"),
+    (0x144, "sip"),
+    (0x180, "satp"),
+    (0x300, "mstatus"),
+    (0x301, "misa"),
+    (0x302, "medeleg"),
+    (0x303, "mideleg"),
+    (0x304, "mie"),
+    (0x305, "mtvec"),
+    (0x306, "mcounteren"),
+    (0x340, "mscratch"),
+    (0x341, "mepc"),
+    (0x342, "mcause"),
+    (0x343, "mtval"),
+    (0x344, "mip"),
+    (0x3a0, "pmpcfg0"),
+    (0x3a2, "pmpcfg2"),
+    (0x3b0, "pmpaddr0"),
+    (0x3b1, "pmpaddr1"),
+    (0x3b2, "pmpaddr2"),
+    (0x3b3, "pmpaddr3"),
+    (0xb00, "mcycle"),
+    (0xb02, "minstret"),
+    (0xc00, "cycle"),
+    (0xc01, "time"),
+    (0xc02, "instret"),
+    (0xf11, "mvendorid"),
+    (0xf12, "marchid"),
+    (0xf13, "mimpid"),
+    (0xf14, "mhartid"),
+];
+
+/// The names of the static rounding modes. 'dyn' (7) is not printed.
+const ROUNDING_MODES: [&str; 5] = ["rne", "rtz", "rdn", "rup", "rmm"];
+
+fn sign_extend(value: u32, bits: u32) -> i64 {
+    ((value as i64) << (64 - bits)) >> (64 - bits)
+}
+
+/// The objdump name of a CSR (e.g. 'mstatus'), or its number in hex.
+pub fn csr_name(csr: u32) -> String {
+    match CSR_NAMES.iter().find(|(num, _)| *num == csr) {
+        Some((_, name)) => name.to_string(),
+        None => format!("{:#x}", csr),
+    }
+}
+
+/// The number of a CSR given by its objdump name (e.g. 'mstatus') or as a
+/// number (e.g. '0x300').
+pub fn csr_number(name: &str) -> Option<u32> {
+    let name = name.trim().to_lowercase();
+    if let Some((num, _)) = CSR_NAMES.iter().find(|(_, n)| *n == name) {
+        return Some(*num);
+    }
+    match name.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => name.parse::<u32>().ok(),
+    }
+    .filter(|num| *num < 0x1000)
+}
+
+/// Formats the fence ordering bits (e.g. 'iorw').
+fn ordering(bits: u32) -> String {
+    let result: String = "iorw"
+        .chars()
+        .enumerate()
+        .filter(|(idx, _)| bits & (8 >> idx) != 0)
+        .map(|(_, c)| c)
+        .collect();
+    if result.is_empty() {
+        "0".to_string()
+    } else {
+        result
+    }
+}
+
+/// Formats the mnemonic and operands of an instruction at the given
+/// address (e.g. 'addi\tsp,sp,-16'). Branch and jump targets are printed as
+/// absolute addresses.
+pub fn format_objdump_inst(inst: &Instruction, addr: u64, style: RegisterStyle) -> String {
+    if inst.is_raw_word() {
+        return format!(".word\t{:#010x}", inst.encode());
+    }
+    let template = inst.template();
+    let reg = |spec| {
+        let value = inst.arg_value(spec).unwrap_or(0);
+        register_name(template.arg_kind(spec), value, style)
+    };
+    let imm12 = || sign_extend(inst.arg_value(&args::IMM12).unwrap_or(0), 12);
+    let target = |offset: i64| format!("{:x}", addr.wrapping_add(offset as u64));
+
+    let mut mnemonic = template.name().to_string();
+    let operands: Vec<String> = match template.opcode() {
+        // Loads and jalr.
+        0x03 | 0x07 | 0x67 => vec![reg(&args::RD), format!("{}({})", imm12(), reg(&args::RS1))],
+        // Stores.
+        0x23 | 0x27 => {
+            let offset = STORE_OFFSET.extract(inst).unwrap_or(0);
+            let offset = sign_extend(offset, STORE_OFFSET.length());
+            vec![reg(&args::RS2), format!("{}({})", offset, reg(&args::RS1))]
+        }
+        // Branches.
+        0x63 => {
+            let offset = BRANCH_OFFSET.extract(inst).unwrap_or(0);
+            let offset = sign_extend(offset, BRANCH_OFFSET.length());
+            vec![reg(&args::RS1), reg(&args::RS2), target(offset)]
+        }
+        // jal
+        0x6f => {
+            let offset = JUMP_OFFSET.extract(inst).unwrap_or(0);
+            let offset = sign_extend(offset, JUMP_OFFSET.length());
+            vec![reg(&args::RD), target(offset)]
+        }
+        // lui and auipc
+        0x37 | 0x17 => {
+            let imm = inst.arg_value(&args::IMM20).unwrap_or(0);
+            vec![reg(&args::RD), format!("{:#x}", imm)]
+        }
+        // Atomics take the ordering as a suffix and the address in parentheses.
+        0x2f => {
+            let aq = inst.arg_value(&args::AQ).unwrap_or(0) != 0;
+            let rl = inst.arg_value(&args::RL).unwrap_or(0) != 0;
+            mnemonic += match (aq, rl) {
+                (true, true) => ".aqrl",
+                (true, false) => ".aq",
+                (false, true) => ".rl",
+                (false, false) => "",
+            };
+            let mut operands = vec![reg(&args::RD)];
+            if inst.arg_value(&args::RS2).is_some() {
+                operands.push(reg(&args::RS2));
+            }
+            operands.push(format!("({})", reg(&args::RS1)));
+            operands
+        }
+        // fence
+        0x0f if inst.arg_value(&args::PRED).is_some() => vec![
+            ordering(inst.arg_value(&args::PRED).unwrap()),
+            ordering(inst.arg_value(&args::SUCC).unwrap_or(0)),
+        ],
+        // CSR accesses.
+        0x73 if inst.arg_value(&args::CSR).is_some() => {
+            let csr = csr_name(inst.arg_value(&args::CSR).unwrap());
+            let source = match inst.arg_value(&args::ZIMM) {
+                Some(zimm) => zimm.to_string(),
+                None => reg(&args::RS1),
+            };
+            vec![reg(&args::RD), csr, source]
+        }
+        // Everything else has its registers in order, then the immediate and
+        // the rounding mode.
+        _ => {
+            let mut operands = Vec::<String>::new();
+            for spec in [&args::RD, &args::RS1, &args::RS2, &args::RS3] {
+                if inst.arg_value(spec).is_some() {
+                    operands.push(reg(spec));
+                }
+            }
+            for arg in inst.arguments() {
+                match template.arg_kind(arg.spec()) {
+                    ArgKind::Imm => {
+                        let imm = sign_extend(arg.value(), arg.spec().length());
+                        operands.push(imm.to_string())
+                    }
+                    ArgKind::Shamt => operands.push(format!("{:#x}", arg.value())),
+                    ArgKind::Rm if (arg.value() as usize) < ROUNDING_MODES.len() => {
+                        operands.push(ROUNDING_MODES[arg.value() as usize].to_string())
+                    }
+                    _ => {}
+                }
+            }
+            operands
+        }
+    };
+    if operands.is_empty() {
+        mnemonic
+    } else {
+        format!("{}\t{}", mnemonic, operands.join(","))
+    }
+}
+
+/// Formats a line of objdump output ('addr:\tencoding\tmnemonic\toperands').
+pub fn format_objdump_line(inst: &Instruction, addr: u64, style: RegisterStyle) -> String {
+    format!(
+        "{:8x}:\t{:08x}          \t{}",
+        addr,
+        inst.encode(),
+        format_objdump_inst(inst, addr, style)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{instructions::riscv::all, parser::parse_instructions, registers::RegisterStyle};
+
+    use super::{format_objdump_inst, format_objdump_line};
+
+    fn disassemble(encoding: u32, addr: u64) -> String {
+        let insts = parse_instructions(&encoding.to_le_bytes(), all()).unwrap();
+        format_objdump_inst(&insts[0], addr, RegisterStyle::Abi)
+    }
+
+    #[test]
+    fn objdump_syntax() {
+        assert_eq!(disassemble(0xff010113, 0), "addi\tsp,sp,-16");
+        assert_eq!(disassemble(0x00813023, 0), "sd\ts0,0(sp)");
+        assert_eq!(disassemble(0xff843403, 0), "ld\ts0,-8(s0)");
+        assert_eq!(disassemble(0x000000e7, 0), "jalr\tra,0(zero)");
+        assert_eq!(disassemble(0x00b50463, 0x1000), "beq\ta0,a1,1008");
+        assert_eq!(disassemble(0xffdff06f, 0x1000), "jal\tzero,ffc");
+        assert_eq!(disassemble(0x000105b7, 0), "lui\ta1,0x10");
+        assert_eq!(disassemble(0x02051513, 0), "slli\ta0,a0,0x20");
+        assert_eq!(disassemble(0x00b50533, 0), "add\ta0,a0,a1");
+        assert_eq!(disassemble(0x34102573, 0), "csrrs\ta0,mepc,zero");
+        assert_eq!(disassemble(0x0ff0000f, 0), "fence\tiorw,iorw");
+        assert_eq!(disassemble(0x00000073, 0), "ecall");
+        assert_eq!(disassemble(0x06b5352f, 0), "amoadd.d.aqrl\ta0,a1,(a0)");
+        assert_eq!(disassemble(0x1005252f, 0), "lr.w\ta0,(a0)");
+        assert_eq!(disassemble(0x00b57553, 0), "fadd.s\tfa0,fa0,fa1");
+        assert_eq!(disassemble(0x00b51553, 0), "fadd.s\tfa0,fa0,fa1,rtz");
+
+        let insts = parse_instructions(&0x00100093u32.to_le_bytes(), all()).unwrap();
+        assert_eq!(
+            format_objdump_line(&insts[0], 0x80000000, RegisterStyle::Abi),
+            "80000000:\t00100093          \taddi\tra,zero,1"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Minimal Sv39 page-table setup for fuzzing the MMU and TLBs.
+//!
+//! The setup maps the gigapage containing the physical region twice: once
+//! with an identity mapping (so the code keeps running once translation is
+//! active) and once at a random alias through which the memory accesses go.
+//! Translation only applies outside of machine mode, so the snippets are
+//! meant to be combined with the privilege transition snippets.
+
+use alloc::{format, string::String, vec, vec::Vec};
+
+use libafl::prelude::Rand;
+
+use crate::{
+    address_pool::AddressPool,
+    instructions::{
+        riscv::{
+            args,
+            rv64_i::{LD, SD, SLLI},
+            rv_i::{ADDI, OR},
+            rv_s::SFENCE_VMA,
+            rv_zicsr::CSRRW,
+        },
+        Argument, Instruction,
+    },
+    memory_ops::{make_load, make_store, AddressPattern},
+    system_snippets::{csr_inst, load_address, load_constant, SCRATCH_REG},
+};
+
+pub const CSR_SATP: u32 = 0x180;
+const SATP_MODE_SV39: u32 = 8;
+const PAGE_SIZE: u32 = 1 << 12;
+const GIGAPAGE_SHIFT: u32 = 30;
+
+/// Register holding the PTE value before it is stored (t1).
+pub const PTE_REG: u32 = 6;
+
+// PTE flag bits.
+pub const PTE_V: u32 = 1 << 0;
+pub const PTE_R: u32 = 1 << 1;
+pub const PTE_W: u32 = 1 << 2;
+pub const PTE_X: u32 = 1 << 3;
+pub const PTE_U: u32 = 1 << 4;
+pub const PTE_G: u32 = 1 << 5;
+pub const PTE_A: u32 = 1 << 6;
+pub const PTE_D: u32 = 1 << 7;
+// The lowest bits of a PTE that contain flags.
+const PTE_FLAG_BITS: u64 = 8;
+
+/// Where the page table and the mapped memory are located.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageTableConfig {
+    /// Physical address of the root page table (4 KiB aligned).
+    table_base: u32,
+    /// Physical address of the mapped gigapage (1 GiB aligned).
+    phys_base: u32,
+}
+
+impl PageTableConfig {
+    pub fn new(table_base: u32, phys_base: u32) -> Result<Self, String> {
+        if table_base & (PAGE_SIZE - 1) != 0 {
+            return Err(format!("Page table {:#x} is not page aligned", table_base));
+        }
+        if phys_base & ((1 << GIGAPAGE_SHIFT) - 1) != 0 {
+            return Err(format!(
+                "Mapped region {:#x} is not 1 GiB aligned",
+                phys_base
+            ));
+        }
+        Ok(Self {
+            table_base,
+            phys_base,
+        })
+    }
+
+    /// Parses 'TABLE,PHYS' where both are hex addresses.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let parse_hex = |s: &str| -> Result<u32, String> {
+            u32::from_str_radix(s.trim().trim_start_matches("0x"), 16)
+                .map_err(|e| format!("Invalid address '{}': {}", s, e))
+        };
+        match input.split_once(',') {
+            Some((table, phys)) => Self::new(parse_hex(table)?, parse_hex(phys)?),
+            None => Err(format!("Expected 'TABLE,PHYS' but got '{}'", input)),
+        }
+    }
+
+    pub fn table_base(&self) -> u32 {
+        self.table_base
+    }
+
+    pub fn phys_base(&self) -> u32 {
+        self.phys_base
+    }
+}
+
+/// Stores a leaf PTE mapping the gigapage `vpn2` to the physical region.
+fn write_gigapage_pte(config: &PageTableConfig, vpn2: u32, flags: u32) -> Vec<Instruction> {
+    let pte = ((config.phys_base >> 12) << 10) | flags;
+    let mut result = load_address(SCRATCH_REG, config.table_base + vpn2 * 8);
+    result.append(&mut load_constant(PTE_REG, pte));
+    result.push(Instruction::new(
+        &SD,
+        vec![
+            Argument::new(&args::IMM12HI, 0),
+            Argument::new(&args::RS1, SCRATCH_REG),
+            Argument::new(&args::RS2, PTE_REG),
+            Argument::new(&args::IMM12LO, 0),
+        ],
+    ));
+    result
+}
+
+/// Enables Sv39 translation with the configured root page table.
+fn enable_sv39(config: &PageTableConfig) -> Vec<Instruction> {
+    let mut result = load_address(SCRATCH_REG, config.table_base >> 12);
+    result.append(&mut vec![
+        Instruction::new(
+            &ADDI,
+            vec![
+                Argument::new(&args::RD, PTE_REG),
+                Argument::new(&args::RS1, 0),
+                Argument::new(&args::IMM12, SATP_MODE_SV39),
+            ],
+        ),
+        Instruction::new(
+            &SLLI,
+            vec![
+                Argument::new(&args::RD, PTE_REG),
+                Argument::new(&args::RS1, PTE_REG),
+                Argument::new(&args::SHAMTD, 60),
+            ],
+        ),
+        Instruction::new(
+            &OR,
+            vec![
+                Argument::new(&args::RD, SCRATCH_REG),
+                Argument::new(&args::RS1, SCRATCH_REG),
+                Argument::new(&args::RS2, PTE_REG),
+            ],
+        ),
+        csr_inst(&CSRRW, 0, SCRATCH_REG, CSR_SATP),
+        Instruction::new(
+            &SFENCE_VMA,
+            vec![Argument::new(&args::RS1, 0), Argument::new(&args::RS2, 0)],
+        ),
+    ]);
+    result
+}
+
+/// Creates the page-table setup followed by a few loads/stores through the
+/// aliased mapping. `misaligned_chance` (0-100) is the chance of an access
+/// being misaligned. Accesses are relative to the registers in `pool` where
+/// possible. Clobbers t0 and t1.
+pub fn sv39_setup<R: Rand>(
+    rng: &mut R,
+    config: &PageTableConfig,
+    misaligned_chance: u64,
+    pool: &AddressPool,
+) -> Vec<Instruction> {
+    let flags = PTE_V | PTE_R | PTE_W | PTE_X | PTE_A | PTE_D;
+    let identity_vpn2 = config.phys_base >> GIGAPAGE_SHIFT;
+    // Keep the alias in the lower 4 GiB so its address fits in 32 bits.
+    let alias_vpn2 = rng.below(4) as u32;
+
+    let mut result = write_gigapage_pte(config, identity_vpn2, flags);
+    if alias_vpn2 != identity_vpn2 {
+        result.append(&mut write_gigapage_pte(config, alias_vpn2, flags));
+    }
+    result.append(&mut enable_sv39(config));
+
+    for _ in 0..rng.between(1, 4) {
+        let offset = (rng.below(PAGE_SIZE as u64 / 8) * 8) as u32;
+        let pattern = AddressPattern::pick(rng, misaligned_chance);
+        let address = pattern.apply((alias_vpn2 << GIGAPAGE_SHIFT) + offset, 8);
+        let (mut setup, base, offset) = pool.address_operand(address);
+        result.append(&mut setup);
+        let access = if rng.below(2) == 0 {
+            make_load(&LD, PTE_REG, base, offset)
+        } else {
+            make_store(&SD, base, PTE_REG, offset)
+        };
+        result.push(access);
+    }
+    result
+}
+
+/// Returns the positions of the ADDI instructions that set the flags of a
+/// PTE created by `sv39_setup`.
+pub fn pte_flag_positions(program: &[Instruction]) -> Vec<usize> {
+    (1..program.len())
+        .filter(|i| {
+            let (addi, store) = (&program[i - 1], &program[*i]);
+            addi.template() == &ADDI
+                && addi.arg_value(&args::RD) == Some(PTE_REG)
+                && addi.arg_value(&args::RS1) == Some(PTE_REG)
+                && store.template() == &SD
+                && store.arg_value(&args::RS2) == Some(PTE_REG)
+        })
+        .map(|i| i - 1)
+        .collect()
+}
+
+/// Flips a random flag bit in one of the PTEs written by the program.
+/// Returns None if the program doesn't write any PTEs.
+pub fn perturb_pte<R: Rand>(rng: &mut R, program: &mut [Instruction]) -> Option<()> {
+    let positions = pte_flag_positions(program);
+    if positions.is_empty() {
+        return None;
+    }
+    let inst = &mut program[*rng.choose(&positions)];
+    let imm = inst.arg_value(&args::IMM12)?;
+    let bit = 1 << rng.below(PTE_FLAG_BITS);
+    inst.set_arg(Argument::new(&args::IMM12, imm ^ bit));
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::prelude::Xoshiro256StarRand;
+
+    use crate::{
+        address_pool::AddressPool,
+        assembler::assemble_instructions,
+        instructions::{self, riscv::args},
+        parser::parse_instructions,
+    };
+
+    use super::{perturb_pte, pte_flag_positions, sv39_setup, PageTableConfig};
+
+    #[test]
+    fn parse_config() {
+        let config = PageTableConfig::parse("0x80010000,80000000").unwrap();
+        assert_eq!(config.table_base(), 0x8001_0000);
+        assert_eq!(config.phys_base(), 0x8000_0000);
+        assert!(PageTableConfig::parse("0x80010004,0x80000000").is_err());
+        assert!(PageTableConfig::parse("0x80010000,0x80001000").is_err());
+        assert!(PageTableConfig::parse("0x80010000").is_err());
+    }
+
+    #[test]
+    fn setup_and_perturb() {
+        let mut rng = Xoshiro256StarRand::default();
+        let config = PageTableConfig::new(0x8001_0000, 0x8000_0000).unwrap();
+        for _ in 0..100 {
+            let mut program = sv39_setup(&mut rng, &config, 50, &AddressPool::default());
+            let bytes = assemble_instructions(&program);
+            assert_eq!(
+                parse_instructions(&bytes, instructions::riscv::all()).unwrap(),
+                program
+            );
+
+            let positions = pte_flag_positions(&program);
+            assert!(!positions.is_empty());
+            let old = program.clone();
+            perturb_pte(&mut rng, &mut program).unwrap();
+            let changed: Vec<usize> = (0..program.len())
+                .filter(|i| program[*i] != old[*i])
+                .collect();
+            assert_eq!(changed.len(), 1);
+            assert!(positions.contains(&changed[0]));
+            let diff = program[changed[0]].arg_value(&args::IMM12).unwrap()
+                ^ old[changed[0]].arg_value(&args::IMM12).unwrap();
+            assert!(diff.count_ones() == 1 && diff < 0x100);
+        }
+    }
+}
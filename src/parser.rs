@@ -1,38 +1,280 @@
-use crate::instructions::{Instruction, InstructionTemplate};
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+
+use once_cell::race::OnceBox;
+
+use crate::{
+    assembler::{assemble_instructions, ByteOrder},
+    instructions::{riscv, sets, Instruction, InstructionTemplate},
+};
+
+// Buckets are selected by the major opcode and funct3.
+const BUCKET_BITS: u32 = 0x7f | (0x7 << 12);
+
+fn bucket_of(data: u32) -> usize {
+    ((data & 0x7f) | (((data >> 12) & 0x7) << 7)) as usize
+}
+
+// Whether the two sets have the same templates in the same order. Compares
+// the addresses, which is cheap compared to building a table.
+fn same_set(
+    mut set: impl Iterator<Item = &'static InstructionTemplate>,
+    insts: &[&'static InstructionTemplate],
+) -> bool {
+    insts
+        .iter()
+        .all(|inst| set.next().is_some_and(|other| core::ptr::eq(other, *inst)))
+        && set.next().is_none()
+}
+
+/// The templates of an instruction set grouped by the major opcode and
+/// funct3 they match, so decoding only tries the templates that can match.
+#[derive(Debug)]
+pub struct DecodeTable {
+    buckets: Vec<Vec<&'static InstructionTemplate>>,
+}
+
+impl DecodeTable {
+    pub fn new(insts: &[&'static InstructionTemplate]) -> Self {
+        let mut buckets = vec![Vec::new(); 1 << 10];
+        for (idx, bucket) in buckets.iter_mut().enumerate() {
+            let data = (idx as u32 & 0x7f) | ((idx as u32 >> 7) << 12);
+            // Templates that don't fix funct3 are in all buckets of their
+            // opcode. The order of the set is kept, so the first matching
+            // template still wins.
+            for inst in insts {
+                if (data ^ inst.base_pattern()) & inst.mask() & BUCKET_BITS == 0 {
+                    bucket.push(*inst);
+                }
+            }
+        }
+        Self { buckets }
+    }
+
+    /// Returns the table for the instruction set. The tables of
+    /// `riscv::all()`, `sets::riscv_g()` and `sets::riscv_base()` are built
+    /// once and shared, other sets get a new table.
+    pub fn cached(insts: &[&'static InstructionTemplate]) -> Arc<DecodeTable> {
+        static ALL: OnceBox<Arc<DecodeTable>> = OnceBox::new();
+        static RISCV_G: OnceBox<Arc<DecodeTable>> = OnceBox::new();
+        static RISCV_BASE: OnceBox<Arc<DecodeTable>> = OnceBox::new();
+
+        let all = riscv::all().iter().copied();
+        let table = if same_set(sets::riscv_g().iter().copied(), insts) {
+            &RISCV_G
+        } else if same_set(sets::riscv_base().iter().copied(), insts) {
+            &RISCV_BASE
+        } else if same_set(all, insts) {
+            &ALL
+        } else {
+            return Arc::new(DecodeTable::new(insts));
+        };
+        table
+            .get_or_init(|| Box::new(Arc::new(DecodeTable::new(insts))))
+            .clone()
+    }
+
+    /// Decodes the word with the first matching template of the set.
+    pub fn decode(&self, data: u32) -> Option<Instruction> {
+        self.buckets[bucket_of(data)]
+            .iter()
+            .find_map(|inst| inst.decode(data))
+    }
+}
+
+/// Bytes that `parse_instructions_lossy` couldn't decode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UndecodableWord {
+    /// The byte offset in the input.
+    pub offset: usize,
+    /// The number of instructions decoded before this word.
+    pub position: usize,
+    /// The word, or the trailing bytes if the input length isn't a multiple
+    /// of four.
+    pub bytes: Vec<u8>,
+}
+
+/// Why `InstDecoder` couldn't decode a word.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The word at the byte offset isn't an instruction of the set.
+    Unknown { offset: usize, word: u32 },
+    /// The input ends with fewer than four bytes at the byte offset.
+    Truncated { offset: usize, len: usize },
+}
+
+impl DecodeError {
+    /// The byte offset of the word in the input.
+    pub fn offset(&self) -> usize {
+        match self {
+            DecodeError::Unknown { offset, .. } | DecodeError::Truncated { offset, .. } => *offset,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Unknown { offset, word } => write!(
+                f,
+                "Failed to parse bytes as instruction: {:x} (at offset {})",
+                word, offset
+            ),
+            DecodeError::Truncated { offset, len } => write!(
+                f,
+                "Tailing garbage in instructions: {} bytes at offset {}",
+                len, offset
+            ),
+        }
+    }
+}
+
+/// Decodes the instructions of a byte slice one word at a time.
+pub struct InstDecoder<'a> {
+    input: &'a [u8],
+    offset: usize,
+    table: Arc<DecodeTable>,
+    order: ByteOrder,
+    raw_words: bool,
+}
+
+impl<'a> InstDecoder<'a> {
+    /// Decodes little-endian words with the given instruction set.
+    pub fn new(input: &'a [u8], insts: &[&'static InstructionTemplate]) -> Self {
+        Self {
+            input,
+            offset: 0,
+            table: DecodeTable::cached(insts),
+            order: ByteOrder::Little,
+            raw_words: false,
+        }
+    }
+
+    /// Decodes words that aren't instructions of the set as `RAW_WORD`s
+    /// instead of failing.
+    pub fn with_raw_words(mut self, allow: bool) -> Self {
+        self.raw_words = allow;
+        self
+    }
+
+    /// Reads the words in the given order instead.
+    pub fn with_order(mut self, order: ByteOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// The byte offset of the next word.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for InstDecoder<'a> {
+    type Item = Result<Instruction, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.input[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        let offset = self.offset;
+        let Ok(bytes) = rest[..rest.len().min(4)].try_into() else {
+            self.offset = self.input.len();
+            return Some(Err(DecodeError::Truncated {
+                offset,
+                len: rest.len(),
+            }));
+        };
+        self.offset += 4;
+        let word = self.order.word_from_bytes(bytes);
+        let decoded = self.table.decode(word);
+        let decoded = decoded.or_else(|| self.raw_words.then(|| Instruction::raw_word(word)));
+        Some(decoded.ok_or(DecodeError::Unknown { offset, word }))
+    }
+}
 
 pub fn parse_instructions(
-    input: &Vec<u8>,
-    insts: &Vec<&'static InstructionTemplate>,
+    input: &[u8],
+    insts: &[&'static InstructionTemplate],
 ) -> Result<Vec<Instruction>, String> {
-    let mut result = Vec::<Instruction>::new();
+    parse_instructions_with_order(input, insts, ByteOrder::Little)
+}
 
+/// Like `parse_instructions`, but reads the words in the given order.
+pub fn parse_instructions_with_order(
+    input: &[u8],
+    insts: &[&'static InstructionTemplate],
+    order: ByteOrder,
+) -> Result<Vec<Instruction>, String> {
     if input.len() % 4 != 0 {
         return Err(format!("Tailing garbage in instructions: {:?}", input));
     }
+    InstDecoder::new(input, insts)
+        .with_order(order)
+        .collect::<Result<Vec<Instruction>, DecodeError>>()
+        .map_err(|err| err.to_string())
+}
 
-    for i in (0..input.len()).step_by(4) {
-        if i + 4 > input.len() {
-            continue;
-        }
-        let data = u32::from_ne_bytes(input[i..i + 4].try_into().unwrap());
-
-        let mut found = false;
-        for inst in insts {
-            let maybe_parsed = inst.decode(data);
-            if let Some(..) = maybe_parsed {
-                found = true;
-                result.push(maybe_parsed.unwrap());
-                break;
-            }
-        }
-        if found {
-            continue;
-        }
+/// Like `parse_instructions`, but decodes words that aren't instructions of
+/// the set as `RAW_WORD`s if `raw_words` is set, e.g. for inputs of the
+/// 'inject-illegal' mutation.
+pub fn parse_instructions_with_raw_words(
+    input: &[u8],
+    insts: &[&'static InstructionTemplate],
+    raw_words: bool,
+) -> Result<Vec<Instruction>, String> {
+    InstDecoder::new(input, insts)
+        .with_raw_words(raw_words)
+        .collect::<Result<Vec<Instruction>, DecodeError>>()
+        .map_err(|err| err.to_string())
+}
 
-        return Err(format!("Failed to parse bytes as instruction: {:x}", data));
+/// Checks that the instructions match their templates and decode again
+/// after assembling them. `Instruction::new` and `ProgramInput::target_bytes`
+/// only check this in debug builds.
+pub fn check_instructions(
+    insts: &[Instruction],
+    templates: &[&'static InstructionTemplate],
+) -> Result<(), String> {
+    for (i, inst) in insts.iter().enumerate() {
+        inst.check()
+            .map_err(|err| format!("Instruction {}: {}", i, err))?;
     }
+    let raw_words = insts.iter().any(Instruction::is_raw_word);
+    parse_instructions_with_raw_words(&assemble_instructions(insts), templates, raw_words)?;
+    Ok(())
+}
 
-    Ok(result)
+/// Like `parse_instructions`, but skips words that aren't instructions
+/// (e.g. literal pools or unknown extensions) and trailing bytes instead of
+/// failing. Returns the decoded instructions and the skipped words.
+pub fn parse_instructions_lossy(
+    input: &[u8],
+    insts: &[&'static InstructionTemplate],
+) -> (Vec<Instruction>, Vec<UndecodableWord>) {
+    let mut result = Vec::<Instruction>::new();
+    let mut skipped = Vec::<UndecodableWord>::new();
+    for decoded in InstDecoder::new(input, insts) {
+        match decoded {
+            Ok(inst) => result.push(inst),
+            Err(err) => {
+                let offset = err.offset();
+                skipped.push(UndecodableWord {
+                    offset,
+                    position: result.len(),
+                    bytes: input[offset..(offset + 4).min(input.len())].to_vec(),
+                })
+            }
+        }
+    }
+    (result, skipped)
 }
 
 #[cfg(test)]
@@ -41,7 +283,10 @@ mod tests {
 
     use crate::instructions;
 
-    use super::parse_instructions;
+    use super::{
+        check_instructions, parse_instructions, parse_instructions_lossy,
+        parse_instructions_with_raw_words, DecodeError, DecodeTable, InstDecoder, UndecodableWord,
+    };
 
     #[test]
     fn parse_random_bytes() {
@@ -54,11 +299,144 @@ mod tests {
                 input.push((rng.next() % 256) as u8);
             }
 
-            let parsed = parse_instructions(&input, &instructions::sets::riscv_g());
+            let parsed = parse_instructions(&input, instructions::sets::riscv_g());
             if parsed.is_err() {
                 continue;
             }
             assert_eq!(parsed.unwrap().len() * 4, input.len());
         }
     }
+
+    #[test]
+    fn check_parsed_instructions() {
+        // addi x1, x1, 1 and lw x2, 8(x1)
+        let input: Vec<u8> = [0x00108093u32, 0x0080a103]
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect();
+        let set = instructions::sets::riscv_g();
+        let insts = parse_instructions(&input, set).unwrap();
+        assert_eq!(check_instructions(&insts, set), Ok(()));
+        // Instructions outside the set don't decode again.
+        assert!(check_instructions(&insts, &set[..0]).is_err());
+    }
+
+    #[test]
+    fn decode_table_matches_scan() {
+        let set = instructions::riscv::all();
+        let table = DecodeTable::new(set);
+        let mut rng = Xoshiro256StarRand::default();
+        rng.set_seed(0);
+        for _ in 0..100000 {
+            let data = rng.next() as u32;
+            let scanned = set.iter().find_map(|inst| inst.decode(data));
+            assert_eq!(table.decode(data), scanned, "{:#x}", data);
+        }
+        // Every template is found by its own encoding.
+        for inst in set {
+            let data = inst.base_pattern();
+            let scanned = set.iter().find_map(|inst| inst.decode(data));
+            assert_eq!(table.decode(data), scanned, "{}", inst.name());
+        }
+    }
+
+    #[test]
+    fn decode_tables_are_shared() {
+        use alloc::sync::Arc;
+
+        let all = DecodeTable::cached(instructions::riscv::all());
+        assert!(Arc::ptr_eq(
+            &all,
+            &DecodeTable::cached(instructions::riscv::all())
+        ));
+        let set = instructions::sets::riscv_g();
+        let g = DecodeTable::cached(set);
+        assert!(Arc::ptr_eq(&g, &DecodeTable::cached(set)));
+        assert!(!Arc::ptr_eq(&g, &all));
+        // Other sets get their own table.
+        let subset = DecodeTable::cached(&set[1..]);
+        assert!(!Arc::ptr_eq(&subset, &g));
+        assert!(subset.decode(0x00108093).is_some());
+    }
+
+    #[test]
+    fn parse_lossy() {
+        let mut input = Vec::<u8>::new();
+        // addi x1, x1, 1
+        input.extend(0x00108093u32.to_ne_bytes());
+        input.extend([0; 4]);
+        input.extend(0x00108093u32.to_ne_bytes());
+        input.extend([0x13, 0x00]);
+
+        let set = instructions::sets::riscv_g();
+        assert!(parse_instructions(&input, set).is_err());
+        let (insts, skipped) = parse_instructions_lossy(&input, set);
+        assert_eq!(insts.len(), 2);
+        assert_eq!(
+            skipped,
+            vec![
+                UndecodableWord {
+                    offset: 4,
+                    position: 1,
+                    bytes: vec![0; 4],
+                },
+                UndecodableWord {
+                    offset: 12,
+                    position: 2,
+                    bytes: vec![0x13, 0x00],
+                },
+            ]
+        );
+        assert_eq!(
+            parse_instructions_lossy(&input[..4], set).0,
+            parse_instructions(&input[..4], set).unwrap()
+        );
+    }
+
+    #[test]
+    fn decode_iteratively() {
+        let mut input = Vec::<u8>::new();
+        input.extend(0x00108093u32.to_le_bytes());
+        input.extend([0xff; 4]);
+        input.extend([0x13, 0x00]);
+
+        let mut decoder = InstDecoder::new(&input, instructions::sets::riscv_g());
+        assert!(decoder.next().unwrap().is_ok());
+        assert_eq!(decoder.offset(), 4);
+        assert_eq!(
+            decoder.next(),
+            Some(Err(DecodeError::Unknown {
+                offset: 4,
+                word: 0xffffffff
+            }))
+        );
+        assert_eq!(
+            decoder.next(),
+            Some(Err(DecodeError::Truncated { offset: 8, len: 2 }))
+        );
+        assert_eq!(decoder.next(), None);
+    }
+
+    #[test]
+    fn decode_raw_words() {
+        let mut input = Vec::<u8>::new();
+        input.extend(0x00108093u32.to_le_bytes());
+        input.extend([0xff; 4]);
+
+        let set = instructions::sets::riscv_g();
+        let insts: Vec<_> = InstDecoder::new(&input, set)
+            .with_raw_words(true)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(!insts[0].is_raw_word());
+        assert_eq!(insts[1], instructions::Instruction::raw_word(0xffffffff));
+        assert_eq!(
+            parse_instructions_with_raw_words(&input, set, true).unwrap(),
+            insts
+        );
+        assert!(parse_instructions_with_raw_words(&input, set, false).is_err());
+        assert!(check_instructions(&insts, set).is_ok());
+        // Lossy parsing still skips them.
+        assert_eq!(parse_instructions_lossy(&input, set).1.len(), 1);
+    }
 }
@@ -0,0 +1,269 @@
+//! Snippets that program the physical memory protection (PMP) unit and then
+//! access memory around the boundaries of the configured region.
+//!
+//! PMP checks only apply to machine mode for locked entries, so the snippets
+//! are most useful together with the privilege transition snippets.
+
+use alloc::{format, string::String, vec::Vec};
+
+use libafl::prelude::Rand;
+
+use crate::{
+    address_pool::AddressPool,
+    instructions::{
+        riscv::{
+            rv64_i::{LD, SD},
+            rv_i::{LW, SW},
+            rv_zicsr::{CSRRC, CSRRS, CSRRW},
+        },
+        Instruction,
+    },
+    memory_ops::{access_width, make_load, make_store, AddressPattern},
+    system_snippets::{csr_inst, load_address, SCRATCH_REG},
+};
+
+pub const CSR_PMPCFG0: u32 = 0x3a0;
+pub const CSR_PMPADDR0: u32 = 0x3b0;
+// Only the first entries are used, all of them are configured in pmpcfg0.
+const PMP_ENTRIES: u64 = 4;
+
+// pmpcfg bits.
+pub const PMP_R: u32 = 1 << 0;
+pub const PMP_W: u32 = 1 << 1;
+pub const PMP_X: u32 = 1 << 2;
+pub const PMP_A_NAPOT: u32 = 3 << 3;
+pub const PMP_L: u32 = 1 << 7;
+
+// The smallest region NAPOT can describe.
+const MIN_NAPOT_SIZE: u32 = 8;
+
+/// The memory region in which the snippets may place PMP regions and perform
+/// accesses without breaking the harness.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PmpConfig {
+    base: u32,
+    size: u32,
+}
+
+impl PmpConfig {
+    pub fn new(base: u32, size: u32) -> Result<Self, String> {
+        // Guarantees that an aligned minimal region fits regardless of the
+        // alignment of the base.
+        if size < 2 * MIN_NAPOT_SIZE {
+            return Err(format!("PMP region of {} bytes is too small", size));
+        }
+        if base.checked_add(size).is_none() {
+            return Err(format!(
+                "PMP region {:#x}+{:#x} exceeds 32 bit addresses",
+                base, size
+            ));
+        }
+        Ok(Self { base, size })
+    }
+
+    /// Parses 'BASE,SIZE' where both are hex numbers.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let parse_hex = |s: &str| -> Result<u32, String> {
+            u32::from_str_radix(s.trim().trim_start_matches("0x"), 16)
+                .map_err(|e| format!("Invalid number '{}': {}", s, e))
+        };
+        match input.split_once(',') {
+            Some((base, size)) => Self::new(parse_hex(base)?, parse_hex(size)?),
+            None => Err(format!("Expected 'BASE,SIZE' but got '{}'", input)),
+        }
+    }
+
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    fn end(&self) -> u32 {
+        self.base + self.size
+    }
+}
+
+/// Picks a random naturally aligned power-of-two region inside the safe
+/// region. Returns the base and size of the region.
+pub fn pick_napot_region<R: Rand>(rng: &mut R, config: &PmpConfig) -> (u32, u32) {
+    let max_bits = 31 - config.size.leading_zeros();
+    let mut bits = rng.between(MIN_NAPOT_SIZE.trailing_zeros() as u64, max_bits as u64) as u32;
+    loop {
+        let size = 1u32 << bits;
+        // First aligned address inside the safe region.
+        let first = config.base.wrapping_add(size - 1) & !(size - 1);
+        if first >= config.base && first as u64 + size as u64 <= config.end() as u64 {
+            let slots = (config.end() - first - size) / size + 1;
+            let base = first + (rng.below(slots as u64) as u32) * size;
+            return (base, size);
+        }
+        // Alignment doesn't fit, try a smaller region.
+        bits -= 1;
+    }
+}
+
+/// Encodes a NAPOT region for a pmpaddr register.
+pub fn napot_address(base: u32, size: u32) -> u32 {
+    (base | (size / 2 - 1)) >> 2
+}
+
+/// Sets the config byte of the given PMP entry in pmpcfg0.
+fn write_pmpcfg(entry: u32, cfg: u32) -> Vec<Instruction> {
+    let shift = 8 * entry;
+    let mut result = load_address(SCRATCH_REG, 0xff << shift);
+    result.push(csr_inst(&CSRRC, 0, SCRATCH_REG, CSR_PMPCFG0));
+    if cfg != 0 {
+        result.append(&mut load_address(SCRATCH_REG, cfg << shift));
+        result.push(csr_inst(&CSRRS, 0, SCRATCH_REG, CSR_PMPCFG0));
+    }
+    result
+}
+
+/// Creates a load or store of a random width at the given address. With
+/// `misaligned_chance` (0-100) the address is moved to a misaligned one if it
+/// stays inside the safe region.
+fn access<R: Rand>(
+    rng: &mut R,
+    config: &PmpConfig,
+    address: u32,
+    misaligned_chance: u64,
+    pool: &AddressPool,
+) -> Vec<Instruction> {
+    let (load, store) = if rng.below(2) == 0 {
+        (&LW, &SW)
+    } else {
+        (&LD, &SD)
+    };
+    let width = access_width(load).unwrap();
+    let moved = AddressPattern::pick(rng, misaligned_chance).apply(address, width);
+    let address = if (moved as u64) + (width as u64) <= config.end() as u64 {
+        moved
+    } else {
+        address
+    };
+    let (mut result, base, offset) = pool.address_operand(address);
+    let inst = if rng.below(2) == 0 {
+        make_load(load, SCRATCH_REG, base, offset)
+    } else {
+        make_store(store, base, SCRATCH_REG, offset)
+    };
+    result.push(inst);
+    result
+}
+
+/// Returns the addresses around the boundaries of the given PMP region that
+/// still lie inside the safe region. Accesses at `boundary - 4` straddle the
+/// boundary for 8 byte accesses.
+pub fn boundary_addresses(config: &PmpConfig, base: u32, size: u32) -> Vec<u32> {
+    let end = base + size;
+    [base.wrapping_sub(4), base, end.wrapping_sub(4), end]
+        .into_iter()
+        .filter(|addr| *addr >= config.base && (*addr as u64) + 8 <= config.end() as u64)
+        .collect()
+}
+
+/// Configures a random PMP entry with random permissions for a region inside
+/// the safe region and then accesses memory around its boundaries.
+/// `misaligned_chance` (0-100) is the chance of an access being misaligned.
+/// Accesses are relative to the registers in `pool` where possible.
+/// Clobbers t0.
+pub fn pmp_snippet<R: Rand>(
+    rng: &mut R,
+    config: &PmpConfig,
+    misaligned_chance: u64,
+    pool: &AddressPool,
+) -> Vec<Instruction> {
+    let entry = rng.below(PMP_ENTRIES) as u32;
+    let (base, size) = pick_napot_region(rng, config);
+
+    // Random permissions, including the reserved W without R combination.
+    let mut cfg = PMP_A_NAPOT | (rng.below(8) as u32);
+    // Locking makes the entry apply to machine mode, but it can't be changed
+    // afterwards, so only do it occasionally.
+    if rng.below(8) == 0 {
+        cfg |= PMP_L;
+    }
+
+    let mut result = load_address(SCRATCH_REG, napot_address(base, size));
+    result.push(csr_inst(&CSRRW, 0, SCRATCH_REG, CSR_PMPADDR0 + entry));
+    result.append(&mut write_pmpcfg(entry, cfg));
+
+    for address in boundary_addresses(config, base, size) {
+        if rng.below(2) == 0 {
+            result.append(&mut access(rng, config, address, misaligned_chance, pool));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::prelude::Xoshiro256StarRand;
+
+    use crate::{
+        address_pool::{AddressPool, POOL_REGS},
+        assembler::assemble_instructions,
+        instructions,
+        memory_ops::access_address,
+        parser::parse_instructions,
+        system_snippets::load_address,
+    };
+
+    use super::{boundary_addresses, napot_address, pick_napot_region, pmp_snippet, PmpConfig};
+
+    #[test]
+    fn napot_encoding() {
+        assert_eq!(napot_address(0x8000_0000, 8), 0x2000_0000);
+        assert_eq!(napot_address(0x8000_0000, 0x1000), 0x2000_01ff);
+    }
+
+    #[test]
+    fn regions_stay_in_safe_region() {
+        let mut rng = Xoshiro256StarRand::default();
+        let config = PmpConfig::parse("0x80001008,0x3000").unwrap();
+        for _ in 0..1000 {
+            let (base, size) = pick_napot_region(&mut rng, &config);
+            assert!(size.is_power_of_two());
+            assert_eq!(base % size, 0);
+            assert!(base >= config.base());
+            assert!(base + size <= config.base() + config.size());
+            for addr in boundary_addresses(&config, base, size) {
+                assert!(addr >= config.base());
+                assert!(addr + 8 <= config.base() + config.size());
+            }
+        }
+    }
+
+    #[test]
+    fn snippet_roundtrip() {
+        let mut rng = Xoshiro256StarRand::default();
+        let config = PmpConfig::new(0x8000_0000, 0x10000).unwrap();
+        let (pool, _) = AddressPool::create(&mut rng, &config);
+        for _ in 0..100 {
+            let snippet = pmp_snippet(&mut rng, &config, 50, &pool);
+            let bytes = assemble_instructions(&snippet);
+            assert_eq!(
+                parse_instructions(&bytes, instructions::riscv::all()).unwrap(),
+                snippet
+            );
+        }
+    }
+
+    #[test]
+    fn snippet_uses_pool() {
+        let mut rng = Xoshiro256StarRand::default();
+        let config = PmpConfig::new(0x8000_0000, 0x1000).unwrap();
+        // All addresses in the region are reachable from the pool register.
+        let pool = AddressPool::of(&load_address(POOL_REGS[0], 0x8000_0800));
+        for _ in 0..100 {
+            for inst in pmp_snippet(&mut rng, &config, 0, &pool) {
+                if let Some((base, _)) = access_address(&inst) {
+                    assert_eq!(base, POOL_REGS[0]);
+                }
+            }
+        }
+    }
+}
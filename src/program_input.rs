@@ -1,36 +1,130 @@
 //! The gramatron grammar fuzzer
-use core::hash::{BuildHasher, Hasher};
+use core::hash::{BuildHasher, Hash, Hasher};
 use libafl::{
     prelude::{HasLen, HasTargetBytes, Input, OwnedSlice},
     Error,
 };
-use std::fmt;
+use std::{fmt, ops::Range, sync::OnceLock};
 
 use ahash::RandomState;
-use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
+use serde::{
+    de::{Error as _, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 
 use crate::{
-    assembler::assemble_instructions,
+    assembler::{assemble_instructions, ByteOrder},
+    input_config::InputConfig,
     instructions::{self, Instruction},
-    parser::parse_instructions,
+    memory_image::MemoryImage,
+    objdump::format_objdump_inst,
+    parser::parse_instructions_with_raw_words,
+    registers::RegisterStyle,
+    storage::{decode_envelope, encode_envelope, SectionKind, StorageFormat},
+    text_format::{format_directives, format_inst, parse_directives, parse_source, Directives},
 };
 
+// The assembled epilogue, see `set_epilogue`.
+static EPILOGUE: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Pins instructions (e.g. a result dump and exit sequence) that are
+/// appended to every program passed to the harness. They aren't part of the
+/// inputs, so mutations never touch them and they aren't stored in the
+/// corpus. Can only be set once per process.
+pub fn set_epilogue(insts: &[Instruction]) -> Result<(), String> {
+    EPILOGUE
+        .set(assemble_instructions(insts))
+        .map_err(|_| "The epilogue is already set".to_string())
+}
+
+/// The assembled epilogue (empty if none is set).
+pub fn epilogue() -> &'static [u8] {
+    match EPILOGUE.get() {
+        Some(bytes) => bytes,
+        None => &[],
+    }
+}
+
 pub trait HasProgramInput {
     fn insts(&self) -> &[Instruction];
     fn insts_mut(&mut self) -> &mut Vec<Instruction>;
+    fn config_mut(&mut self) -> &mut Option<InputConfig>;
+    fn memory_mut(&mut self) -> &mut Option<MemoryImage>;
+
+    /// Replaces the instruction at `idx`. Inputs that cache their encoding
+    /// can patch it instead of assembling everything again.
+    fn set_inst(&mut self, idx: usize, inst: Instruction) {
+        self.insts_mut()[idx] = inst;
+    }
+}
+
+// The output of `ProgramInput::to_bytes`, assembled on first use. It only
+// depends on the other fields, so it is ignored when comparing and hashing
+// inputs.
+#[derive(Clone, Default)]
+struct AssemblyCache(OnceLock<Vec<u8>>);
+
+impl PartialEq for AssemblyCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for AssemblyCache {}
+
+impl Hash for AssemblyCache {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+impl fmt::Debug for AssemblyCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "AssemblyCache({} bytes)",
+            self.0.get().map_or(0, Vec::len)
+        )
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct ProgramInput {
     insts: Vec<Instruction>,
+    /// Configuration for the harness that is placed before the instructions.
+    config: Option<InputConfig>,
+    /// Data memory the harness initializes before running the instructions.
+    memory: Option<MemoryImage>,
+    // Cleared whenever one of the other fields is borrowed mutably.
+    assembled: AssemblyCache,
 }
 
+// The human-readable form of an input: The directives of the text format
+// for the config header and memory image, and the instructions by mnemonic
+// and operand names.
+#[derive(Serialize, Deserialize)]
+struct StructuredProgram {
+    directives: Vec<String>,
+    instructions: Vec<Instruction>,
+}
+
+/// Human-readable formats (e.g. JSON or TOML) get a `StructuredProgram`,
+/// binary formats (e.g. postcard for the corpus) get the storage bytes.
 impl Serialize for ProgramInput {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_bytes(assemble_instructions(&self.insts).as_slice())
+        if !serializer.is_human_readable() {
+            return serializer.serialize_bytes(self.to_storage_bytes().as_slice());
+        }
+        let directives = Directives {
+            config: self.config.clone(),
+            memory: self.memory.clone(),
+        };
+        StructuredProgram {
+            directives: format_directives(&directives, RegisterStyle::Abi),
+            instructions: self.insts.clone(),
+        }
+        .serialize(serializer)
     }
 }
 
@@ -39,15 +133,29 @@ impl<'de> Deserialize<'de> for ProgramInput {
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_bytes(ProgramInputVisitor)
+        if !deserializer.is_human_readable() {
+            return deserializer.deserialize_bytes(ProgramInputVisitor);
+        }
+        let program = StructuredProgram::deserialize(deserializer)?;
+        let (directives, _) =
+            parse_directives(&program.directives.join("\n")).map_err(D::Error::custom)?;
+        Ok(Self {
+            insts: program.instructions,
+            config: directives.config,
+            memory: directives.memory,
+            assembled: AssemblyCache::default(),
+        })
     }
 }
 
 impl HasTargetBytes for ProgramInput {
     fn target_bytes(&self) -> OwnedSlice<u8> {
-        let bytes = assemble_instructions(&self.insts);
-        debug_assert!(parse_instructions(&bytes.to_vec(), &instructions::riscv::all()).is_ok());
-        OwnedSlice::<u8>::from(bytes.to_vec())
+        if epilogue().is_empty() {
+            return OwnedSlice::<u8>::from(self.bytes());
+        }
+        let mut bytes = self.to_bytes();
+        bytes.extend_from_slice(epilogue());
+        OwnedSlice::<u8>::from(bytes)
     }
 }
 
@@ -63,9 +171,7 @@ impl<'de> Visitor<'de> for ProgramInputVisitor {
     where
         E: serde::de::Error,
     {
-        Ok(ProgramInput {
-            insts: parse_instructions(&v.to_vec(), &instructions::riscv::all()).unwrap(),
-        })
+        ProgramInput::from_storage_bytes(v).map_err(E::custom)
     }
 }
 
@@ -74,11 +180,23 @@ impl Input for ProgramInput {
     #[must_use]
     fn generate_name(&self, _idx: usize) -> String {
         let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
-        hasher.write(assemble_instructions(&self.insts).as_slice());
+        hasher.write(self.bytes());
         format!("size:{}-hash:{:016x}", self.insts().len(), hasher.finish())
     }
 }
 
+/// Formats the instructions like the disassembler, one per line, with the
+/// program starting at address 0.
+impl fmt::Display for ProgramInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, inst) in self.insts.iter().enumerate() {
+            let addr = i as u64 * 4;
+            writeln!(f, "{}", format_objdump_inst(inst, addr, RegisterStyle::Abi))?;
+        }
+        Ok(())
+    }
+}
+
 impl HasLen for ProgramInput {
     fn len(&self) -> usize {
         self.insts.len()
@@ -91,15 +209,197 @@ impl HasProgramInput for ProgramInput {
     }
 
     fn insts_mut(&mut self) -> &mut Vec<Instruction> {
+        self.invalidate();
         &mut self.insts
     }
+
+    fn config_mut(&mut self) -> &mut Option<InputConfig> {
+        self.invalidate();
+        &mut self.config
+    }
+
+    fn memory_mut(&mut self) -> &mut Option<MemoryImage> {
+        self.invalidate();
+        &mut self.memory
+    }
+
+    fn set_inst(&mut self, idx: usize, inst: Instruction) {
+        ProgramInput::set_inst(self, idx, inst)
+    }
 }
 
 impl ProgramInput {
     /// Creates a new codes input using the given terminals
     #[must_use]
     pub fn new(insts: Vec<Instruction>) -> Self {
-        Self { insts }
+        Self {
+            insts,
+            config: None,
+            memory: None,
+            assembled: AssemblyCache::default(),
+        }
+    }
+
+    /// Creates a new input that also carries a configuration header.
+    #[must_use]
+    pub fn with_config(insts: Vec<Instruction>, config: InputConfig) -> Self {
+        Self {
+            insts,
+            config: Some(config),
+            memory: None,
+            assembled: AssemblyCache::default(),
+        }
+    }
+
+    /// Adds a data-memory image to the input.
+    #[must_use]
+    pub fn with_memory(mut self, memory: MemoryImage) -> Self {
+        self.memory = Some(memory);
+        self.invalidate();
+        self
+    }
+
+    pub fn config(&self) -> Option<&InputConfig> {
+        self.config.as_ref()
+    }
+
+    pub fn memory(&self) -> Option<&MemoryImage> {
+        self.memory.as_ref()
+    }
+
+    /// The bytes passed to the harness: The config header (if any), the
+    /// memory image (if any) and the assembled instructions.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bytes().to_vec()
+    }
+
+    /// Like `to_bytes`, but assembles the input only once. The result is
+    /// cached until the input changes.
+    pub fn bytes(&self) -> &[u8] {
+        self.assembled.0.get_or_init(|| {
+            let bytes = self.assemble();
+            let raw_words = self.insts.iter().any(Instruction::is_raw_word);
+            debug_assert!(ProgramInput::from_bytes_with(&bytes, raw_words).is_ok());
+            bytes
+        })
+    }
+
+    // Drops the cached encoding after a change.
+    fn invalidate(&mut self) {
+        self.assembled.0.take();
+    }
+
+    fn assemble(&self) -> Vec<u8> {
+        let mut bytes = match &self.config {
+            Some(config) => config.encode(),
+            None => Vec::<u8>::new(),
+        };
+        if let Some(memory) = &self.memory {
+            bytes.append(&mut memory.encode());
+        }
+        bytes.append(&mut assemble_instructions(&self.insts));
+        bytes
+    }
+
+    /// Parses the output of `to_bytes`. Words that aren't instructions are
+    /// rejected, see `from_bytes_with`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        Self::from_bytes_with(bytes, false)
+    }
+
+    /// Like `from_bytes`, but keeps words that aren't instructions as
+    /// `RAW_WORD`s if `raw_words` is set.
+    pub fn from_bytes_with(bytes: &[u8], raw_words: bool) -> Result<Self, String> {
+        let (config, header_size) = match InputConfig::decode(bytes)? {
+            Some((config, size)) => (Some(config), size),
+            None => (None, 0),
+        };
+        let (memory, image_size) = match MemoryImage::decode(&bytes[header_size..])? {
+            Some((memory, size)) => (Some(memory), size),
+            None => (None, 0),
+        };
+        let insts = parse_instructions_with_raw_words(
+            &bytes[header_size + image_size..],
+            instructions::riscv::all(),
+            raw_words,
+        )?;
+        insts.iter().try_for_each(Instruction::check)?;
+        Ok(Self {
+            insts,
+            config,
+            memory,
+            assembled: AssemblyCache::default(),
+        })
+    }
+
+    /// The bytes stored in the corpus: The parts of the input in the
+    /// versioned envelope described in `storage`.
+    pub fn to_storage_bytes(&self) -> Vec<u8> {
+        let mut sections = Vec::<(SectionKind, Vec<u8>)>::new();
+        if let Some(config) = &self.config {
+            sections.push((SectionKind::Config, config.encode()));
+        }
+        if let Some(memory) = &self.memory {
+            sections.push((SectionKind::Memory, memory.encode()));
+        }
+        sections.push((
+            SectionKind::Instructions,
+            assemble_instructions(&self.insts),
+        ));
+        encode_envelope(&sections)
+    }
+
+    /// Parses the output of `to_storage_bytes`. Inputs stored before the
+    /// envelope existed are parsed with `from_bytes`. Stored inputs were
+    /// written by the fuzzer, so words that aren't instructions are kept as
+    /// `RAW_WORD`s.
+    pub fn from_storage_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if StorageFormat::of(bytes) == StorageFormat::Legacy {
+            return ProgramInput::from_bytes_with(bytes, true);
+        }
+        let mut result = ProgramInput::default();
+        for (kind, data) in decode_envelope(bytes)? {
+            match kind {
+                SectionKind::Instructions => {
+                    result.insts =
+                        parse_instructions_with_raw_words(data, instructions::riscv::all(), true)?;
+                    result.insts.iter().try_for_each(Instruction::check)?;
+                }
+                SectionKind::Config => {
+                    let (config, _) = InputConfig::decode(data)?.ok_or("Invalid config section")?;
+                    result.config = Some(config);
+                }
+                SectionKind::Memory => {
+                    let (memory, _) = MemoryImage::decode(data)?.ok_or("Invalid memory section")?;
+                    result.memory = Some(memory);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Formats the input in the text format of `inst-assembler`. The config
+    /// header and memory image are written as directives in front of the
+    /// instructions.
+    pub fn to_text(&self, style: RegisterStyle) -> String {
+        let directives = Directives {
+            config: self.config.clone(),
+            memory: self.memory.clone(),
+        };
+        let mut lines = format_directives(&directives, style);
+        lines.extend(self.insts.iter().map(|inst| format_inst(inst, style)));
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    /// Parses the output of `to_text` (or any other `inst-assembler` source).
+    pub fn from_text(source: &str) -> Result<Self, String> {
+        let (directives, code) = parse_directives(source)?;
+        Ok(Self {
+            insts: parse_source(&code)?,
+            config: directives.config,
+            memory: directives.memory,
+            assembled: AssemblyCache::default(),
+        })
     }
 
     pub fn insts(&self) -> &[Instruction] {
@@ -107,23 +407,200 @@ impl ProgramInput {
     }
 
     pub fn insts_mut(&mut self) -> &mut Vec<Instruction> {
+        self.invalidate();
         &mut self.insts
     }
 
+    /// Replaces the instruction at `idx`. If the input is already
+    /// assembled, only its encoding is patched.
+    pub fn set_inst(&mut self, idx: usize, inst: Instruction) {
+        let count = self.insts.len();
+        debug_assert!(idx < count, "Instruction {} of {}", idx, count);
+        if let Some(bytes) = self.assembled.0.get_mut() {
+            // The instructions are at the end of the bytes.
+            let offset = bytes.len() - (count - idx) * 4;
+            bytes[offset..offset + 4]
+                .copy_from_slice(&ByteOrder::Little.word_to_bytes(inst.encode()));
+        }
+        self.insts[idx] = inst;
+    }
+
     /// Create a bytes representation of this input
     pub fn unparse(&self, bytes: &mut Vec<u8>) {
         bytes.clear();
-        bytes.extend_from_slice(assemble_instructions(&self.insts).as_slice());
+        bytes.extend_from_slice(self.bytes());
     }
 
     /// Crop the value to the given length
     pub fn crop(&self, from: usize, to: usize) -> Result<Self, Error> {
-        if from < to && to <= self.insts.len() {
-            let mut insts = vec![];
-            insts.clone_from_slice(&self.insts[from..to]);
-            Ok(Self { insts })
+        if from < to {
+            self.slice(from..to)
         } else {
             Err(Error::illegal_argument("Invalid from or to argument"))
         }
     }
+
+    /// The input with only the instructions in the given range. The config
+    /// header and memory image are kept.
+    pub fn slice(&self, range: Range<usize>) -> Result<Self, Error> {
+        let insts = self
+            .insts
+            .get(range)
+            .ok_or_else(|| Error::illegal_argument("Invalid instruction range"))?;
+        Ok(Self {
+            insts: insts.to_vec(),
+            config: self.config.clone(),
+            memory: self.memory.clone(),
+            assembled: AssemblyCache::default(),
+        })
+    }
+
+    /// Splits the instructions into the ones before and starting at `mid`.
+    /// Both halves keep the config header and memory image.
+    pub fn split_at(&self, mid: usize) -> Result<(Self, Self), Error> {
+        if mid > self.insts.len() {
+            return Err(Error::illegal_argument("Split point out of bounds"));
+        }
+        Ok((self.slice(0..mid)?, self.slice(mid..self.insts.len())?))
+    }
+
+    /// Shortens the input to the first `len` instructions. Has no effect if
+    /// it has fewer instructions.
+    pub fn truncate(&mut self, len: usize) {
+        self.insts_mut().truncate(len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{input_config::InputConfig, instructions::Instruction, text_format::parse_source};
+
+    use super::{HasProgramInput, ProgramInput};
+
+    #[test]
+    fn structured_serde_roundtrip() {
+        let insts = parse_source("addi a0, a0, -1\nsw a1, 8(sp)\nfence\n").unwrap();
+        let mut input = ProgramInput::new(insts);
+        *input.config_mut() = Some(InputConfig::default());
+
+        let json = serde_json::to_string(&input).unwrap();
+        assert!(json.contains(r#"{"mnemonic":"addi","operands":{"rd":10,"rs1":10"#));
+        assert_eq!(serde_json::from_str::<ProgramInput>(&json).unwrap(), input);
+
+        let toml = toml::to_string(&input).unwrap();
+        assert_eq!(toml::from_str::<ProgramInput>(&toml).unwrap(), input);
+
+        // Binary formats still store the bytes of the corpus.
+        let stored = postcard::to_allocvec(&input).unwrap();
+        assert_eq!(
+            postcard::from_bytes::<ProgramInput>(&stored).unwrap(),
+            input
+        );
+
+        let unknown = json.replace("addi", "addx");
+        assert!(serde_json::from_str::<ProgramInput>(&unknown).is_err());
+    }
+
+    #[test]
+    fn raw_words() {
+        let mut insts = parse_source("addi a0, a0, -1\n").unwrap();
+        insts.push(Instruction::raw_word(0xffff_ffff));
+        let input = ProgramInput::with_config(insts, InputConfig::default());
+
+        // Harness inputs only keep them if asked to.
+        let bytes = input.to_bytes();
+        assert!(ProgramInput::from_bytes(&bytes).is_err());
+        assert_eq!(ProgramInput::from_bytes_with(&bytes, true).unwrap(), input);
+
+        // The fuzzer's own formats always keep them.
+        let stored = postcard::to_allocvec(&input).unwrap();
+        assert_eq!(
+            postcard::from_bytes::<ProgramInput>(&stored).unwrap(),
+            input
+        );
+        let json = serde_json::to_string(&input).unwrap();
+        assert_eq!(serde_json::from_str::<ProgramInput>(&json).unwrap(), input);
+        let word = postcard::to_allocvec(&input.insts()[1]).unwrap();
+        assert_eq!(
+            postcard::from_bytes::<Instruction>(&word).unwrap(),
+            input.insts()[1]
+        );
+    }
+
+    #[test]
+    fn cached_bytes() {
+        let insts = parse_source("addi a0, a0, -1\nsw a1, 8(sp)\nfence\n").unwrap();
+        let mut input = ProgramInput::with_config(insts, InputConfig::default());
+        let expected = input.assemble();
+        assert_eq!(input.bytes(), expected);
+
+        // Replacing an instruction patches the cached bytes.
+        let nop = parse_source("nop\n").unwrap().remove(0);
+        input.set_inst(1, nop.clone());
+        assert_eq!(input.insts()[1], nop);
+        assert_eq!(input.bytes(), input.assemble());
+        assert_ne!(input.bytes(), expected);
+
+        // Any other change invalidates them.
+        input.insts_mut().push(nop);
+        assert_eq!(input.bytes(), input.assemble());
+        *input.config_mut() = None;
+        assert_eq!(input.bytes(), input.assemble());
+        input.truncate(1);
+        assert_eq!(input.to_bytes(), input.assemble());
+
+        // The cache doesn't affect comparisons.
+        let fresh = ProgramInput::new(input.insts().to_vec());
+        assert_eq!(fresh, input);
+    }
+
+    #[test]
+    fn crop_and_slice() {
+        let insts = parse_source("addi a0, a0, -1\nsw a1, 8(sp)\nfence\nnop\n").unwrap();
+        let input = ProgramInput::with_config(insts.clone(), InputConfig::default());
+
+        let sliced = input.slice(1..3).unwrap();
+        assert_eq!(sliced.insts(), &insts[1..3]);
+        assert_eq!(sliced.config, input.config);
+        assert_eq!(sliced.bytes(), sliced.assemble());
+        assert!(input.slice(0..0).unwrap().insts().is_empty());
+        assert!(input.slice(4..4).unwrap().insts().is_empty());
+        assert!(input.slice(2..5).is_err());
+        assert!(input.slice(5..5).is_err());
+
+        assert_eq!(input.crop(1, 3).unwrap(), sliced);
+        assert_eq!(input.crop(0, 4).unwrap(), input);
+        assert!(input.crop(2, 2).is_err());
+        assert!(input.crop(3, 1).is_err());
+        assert!(input.crop(0, 5).is_err());
+    }
+
+    #[test]
+    fn split_and_truncate() {
+        let insts = parse_source("addi a0, a0, -1\nsw a1, 8(sp)\nfence\n").unwrap();
+        let input = ProgramInput::with_config(insts.clone(), InputConfig::default());
+
+        let (head, tail) = input.split_at(1).unwrap();
+        assert_eq!(head.insts(), &insts[..1]);
+        assert_eq!(tail.insts(), &insts[1..]);
+        assert_eq!(head.config, input.config);
+        assert_eq!(tail.config, input.config);
+
+        let (head, tail) = input.split_at(0).unwrap();
+        assert!(head.insts().is_empty());
+        assert_eq!(tail, input);
+        let (head, tail) = input.split_at(3).unwrap();
+        assert_eq!(head, input);
+        assert!(tail.insts().is_empty());
+        assert!(input.split_at(4).is_err());
+
+        let mut truncated = input.clone();
+        truncated.truncate(5);
+        assert_eq!(truncated, input);
+        truncated.truncate(2);
+        assert_eq!(truncated.insts(), &insts[..2]);
+        assert_eq!(truncated.bytes(), truncated.assemble());
+        truncated.truncate(0);
+        assert!(truncated.insts().is_empty());
+    }
 }
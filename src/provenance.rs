@@ -0,0 +1,297 @@
+//! Tracks how the generator chose the operand values of new instructions so
+//! that the disassembler can show whether the generator biases work. Only
+//! `Provenance` is available without the 'std' feature.
+
+#[cfg(feature = "std")]
+use core::{fmt::Debug, marker::PhantomData};
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "std")]
+use libafl::{
+    bolts::tuples::Named,
+    corpus::{Corpus, Testcase},
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::{Input, UsesInput},
+    observers::ObserversTuple,
+    state::{HasClientPerfMonitor, HasCorpus, HasMetadata},
+    Error,
+};
+
+#[cfg(feature = "std")]
+use crate::{instructions::Instruction, program_input::ProgramInput};
+
+/// How the value of an operand was chosen.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Provenance {
+    /// Reused from an operand of the program.
+    Reused,
+    /// Taken from the immediate pool.
+    Dictionary,
+    /// A random power of two.
+    PowerOfTwo,
+    /// Uniformly random.
+    Random,
+    /// Not created by the generator or the origin was lost.
+    Unknown,
+}
+
+impl Provenance {
+    pub const ALL: [Provenance; 5] = [
+        Provenance::Reused,
+        Provenance::Dictionary,
+        Provenance::PowerOfTwo,
+        Provenance::Random,
+        Provenance::Unknown,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provenance::Reused => "reused",
+            Provenance::Dictionary => "dict",
+            Provenance::PowerOfTwo => "pow2",
+            Provenance::Random => "random",
+            Provenance::Unknown => "unknown",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Provenance> {
+        Provenance::ALL.into_iter().find(|p| p.name() == name)
+    }
+}
+
+#[cfg(feature = "std")]
+libafl::impl_serdeany!(ProvenanceMetadata);
+/// The operand provenance of generated instructions, keyed by their
+/// encoding. As state metadata it holds the instructions generated in the
+/// current mutation round, as testcase metadata those of the entry.
+#[cfg(feature = "std")]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProvenanceMetadata {
+    // (encoding, provenance of every operand) pairs.
+    entries: Vec<(u32, Vec<Provenance>)>,
+}
+
+#[cfg(feature = "std")]
+impl ProvenanceMetadata {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the provenance of the operands of the instruction.
+    pub fn add(&mut self, inst: &Instruction, provenance: Vec<Provenance>) {
+        self.insert(inst.encode(), provenance);
+    }
+
+    fn insert(&mut self, encoding: u32, provenance: Vec<Provenance>) {
+        match self.entries.iter_mut().find(|(e, _)| *e == encoding) {
+            Some(entry) => entry.1 = provenance,
+            None => self.entries.push((encoding, provenance)),
+        }
+    }
+
+    /// Returns the provenance of the operands of the instruction.
+    pub fn get(&self, inst: &Instruction) -> Option<&[Provenance]> {
+        let encoding = inst.encode();
+        self.entries
+            .iter()
+            .find(|(e, _)| *e == encoding)
+            .map(|(_, provenance)| provenance.as_slice())
+    }
+
+    /// Adds all entries of `other`, replacing existing ones.
+    pub fn extend(&mut self, other: ProvenanceMetadata) {
+        for (encoding, provenance) in other.entries {
+            self.insert(encoding, provenance);
+        }
+    }
+
+    /// Adds the entries of `parent` that aren't known yet.
+    pub fn inherit(&mut self, parent: &ProvenanceMetadata) {
+        for (encoding, provenance) in &parent.entries {
+            if !self.entries.iter().any(|(e, _)| e == encoding) {
+                self.entries.push((*encoding, provenance.clone()));
+            }
+        }
+    }
+
+    /// Drops the entries of instructions that aren't in the program.
+    pub fn retain(&mut self, program: &[Instruction]) {
+        let encodings: Vec<u32> = program.iter().map(|inst| inst.encode()).collect();
+        self.entries.retain(|(e, _)| encodings.contains(e));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// One 'ENCODING PROVENANCE,...' line per instruction.
+    pub fn to_text(&self) -> String {
+        let mut result = String::new();
+        for (encoding, provenance) in &self.entries {
+            let names: Vec<&str> = provenance.iter().map(|p| p.name()).collect();
+            result += &format!("{:08x} {}\n", encoding, names.join(","));
+        }
+        result
+    }
+
+    /// Parses the output of `to_text`.
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut result = Self::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let (encoding, names) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            let encoding = u32::from_str_radix(encoding, 16)
+                .map_err(|_| format!("Invalid encoding '{}'", encoding))?;
+            let provenance = names
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(|name| {
+                    Provenance::from_name(name).ok_or(format!("Unknown provenance '{}'", name))
+                })
+                .collect::<Result<Vec<Provenance>, String>>()?;
+            result.insert(encoding, provenance);
+        }
+        Ok(result)
+    }
+}
+
+/// Feedback that is never interesting by itself, but attaches the operand
+/// provenance of the generated instructions (and those inherited from the
+/// parent) to new corpus entries. Optionally also writes them to a file named
+/// like the entry in the given directory.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct ProvenanceFeedback<S> {
+    dir: Option<PathBuf>,
+    // The instructions generated for the last execution.
+    last: ProvenanceMetadata,
+    phantom: PhantomData<S>,
+}
+
+#[cfg(feature = "std")]
+impl<S> ProvenanceFeedback<S> {
+    #[must_use]
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            last: ProvenanceMetadata::new(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S> Named for ProvenanceFeedback<S> {
+    fn name(&self) -> &str {
+        "ProvenanceFeedback"
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S> Feedback<S> for ProvenanceFeedback<S>
+where
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor + HasMetadata + HasCorpus + Debug,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &ProgramInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        // The mutators collect the provenance of one round in the state.
+        self.last = state
+            .metadata_map_mut()
+            .get_mut::<ProvenanceMetadata>()
+            .map(std::mem::take)
+            .unwrap_or_default();
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        let Some(child) = testcase.input().as_ref() else {
+            return Ok(());
+        };
+        let mut provenance = std::mem::take(&mut self.last);
+        if let Some(parent_id) = testcase.parent_id().or(*state.corpus().current()) {
+            let parent = state.corpus().get(parent_id)?.borrow();
+            if let Some(parent_provenance) = parent.metadata_map().get::<ProvenanceMetadata>() {
+                provenance.inherit(parent_provenance);
+            }
+        }
+        provenance.retain(child.insts());
+        if provenance.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(dir) = &self.dir {
+            let path = dir.join(child.generate_name(0));
+            if let Err(err) = std::fs::write(&path, provenance.to_text()) {
+                log::warn!("Failed to write provenance to {:?}: {}", path, err);
+            }
+        }
+        testcase.add_metadata(provenance);
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::instructions::{
+        riscv::{args, rv_i::ADDI},
+        Argument, Instruction,
+    };
+
+    use super::{Provenance, ProvenanceMetadata};
+
+    fn addi(imm: u32) -> Instruction {
+        Instruction::new(
+            &ADDI,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 2),
+                Argument::new(&args::IMM12, imm),
+            ],
+        )
+    }
+
+    #[test]
+    fn track_provenance() {
+        let mut parent = ProvenanceMetadata::new();
+        parent.add(&addi(1), vec![Provenance::Random; 3]);
+        parent.add(&addi(2), vec![Provenance::Random; 3]);
+
+        let mut child = ProvenanceMetadata::new();
+        let origin = vec![
+            Provenance::Reused,
+            Provenance::Random,
+            Provenance::Dictionary,
+        ];
+        child.add(&addi(2), origin.clone());
+        child.inherit(&parent);
+        assert_eq!(child.get(&addi(2)), Some(origin.as_slice()));
+        assert_eq!(child.get(&addi(1)).unwrap()[0], Provenance::Random);
+
+        child.retain(&[addi(2)]);
+        assert_eq!(child.get(&addi(1)), None);
+
+        let text = child.to_text();
+        assert_eq!(ProvenanceMetadata::from_text(&text).unwrap(), child);
+        assert!(ProvenanceMetadata::from_text("zz reused").is_err());
+        assert!(ProvenanceMetadata::from_text("00000013 bogus").is_err());
+    }
+}
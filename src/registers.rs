@@ -0,0 +1,145 @@
+//! Names of integer and floating point registers for printing and parsing
+//! instructions in text form.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+
+use crate::instructions::{ArgKind, Argument, InstructionTemplate};
+
+/// The ABI names of the integer registers.
+pub const GPR_ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// The ABI names of the floating point registers.
+pub const FPR_ABI_NAMES: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2",
+    "fa3", "fa4", "fa5", "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9",
+    "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+/// How register operands are printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterStyle {
+    /// The raw operand value in hex (e.g. '0xa').
+    Hex,
+    /// Architectural names (e.g. 'x10' or 'f10').
+    Arch,
+    /// ABI names (e.g. 'a0' or 'fa0').
+    Abi,
+}
+
+impl RegisterStyle {
+    /// Parses the name of a style ('hex', 'arch' or 'abi').
+    pub fn from_name(name: &str) -> Option<RegisterStyle> {
+        match name.trim().to_lowercase().as_str() {
+            "hex" => Some(RegisterStyle::Hex),
+            "arch" => Some(RegisterStyle::Arch),
+            "abi" => Some(RegisterStyle::Abi),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the name of the register in the given style.
+pub fn register_name(kind: ArgKind, reg: u32, style: RegisterStyle) -> String {
+    let fp = kind == ArgKind::Fpr;
+    match style {
+        RegisterStyle::Hex => format!("{:#x}", reg),
+        RegisterStyle::Arch => format!("{}{}", if fp { "f" } else { "x" }, reg),
+        RegisterStyle::Abi if fp => FPR_ABI_NAMES[reg as usize].to_string(),
+        RegisterStyle::Abi => GPR_ABI_NAMES[reg as usize].to_string(),
+    }
+}
+
+/// Parses an architectural or ABI register name of the given kind. Also
+/// accepts 'fp' as an alias for s0.
+pub fn parse_register(kind: ArgKind, name: &str) -> Option<u32> {
+    let (prefix, abi_names) = match kind {
+        ArgKind::Gpr => ("x", &GPR_ABI_NAMES),
+        ArgKind::Fpr => ("f", &FPR_ABI_NAMES),
+        _ => return None,
+    };
+    if kind == ArgKind::Gpr && name == "fp" {
+        return Some(8);
+    }
+    if let Some(reg) = abi_names.iter().position(|abi| *abi == name) {
+        return Some(reg as u32);
+    }
+    let number = name.strip_prefix(prefix)?;
+    // Reject leading zeros and signs so that every register has one name.
+    if (number.starts_with('0') && number != "0") || number.starts_with('+') {
+        return None;
+    }
+    number.parse::<u32>().ok().filter(|reg| *reg < 32)
+}
+
+/// Formats the value of an operand of the given instruction. Registers are
+/// printed in the given style, all other operands in hex.
+pub fn format_argument(
+    template: &InstructionTemplate,
+    arg: &Argument,
+    style: RegisterStyle,
+) -> String {
+    match template.arg_kind(arg.spec()) {
+        kind @ (ArgKind::Gpr | ArgKind::Fpr) => register_name(kind, arg.value(), style),
+        _ => format!("{:#x}", arg.value()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instructions::{
+        riscv::{
+            args,
+            rv_f::{FADD_S, FLW},
+            rv_i::ADDI,
+        },
+        ArgKind, Argument,
+    };
+
+    use super::{format_argument, parse_register, register_name, RegisterStyle};
+
+    #[test]
+    fn register_names() {
+        assert_eq!(register_name(ArgKind::Gpr, 2, RegisterStyle::Abi), "sp");
+        assert_eq!(register_name(ArgKind::Fpr, 10, RegisterStyle::Abi), "fa0");
+        assert_eq!(register_name(ArgKind::Fpr, 10, RegisterStyle::Arch), "f10");
+        assert_eq!(register_name(ArgKind::Gpr, 31, RegisterStyle::Hex), "0x1f");
+
+        for reg in 0..32 {
+            for kind in [ArgKind::Gpr, ArgKind::Fpr] {
+                for style in [RegisterStyle::Arch, RegisterStyle::Abi] {
+                    let name = register_name(kind, reg, style);
+                    assert_eq!(parse_register(kind, &name), Some(reg), "{}", name);
+                }
+            }
+        }
+        assert_eq!(parse_register(ArgKind::Gpr, "fp"), Some(8));
+        assert_eq!(parse_register(ArgKind::Gpr, "x32"), None);
+        assert_eq!(parse_register(ArgKind::Gpr, "x01"), None);
+        assert_eq!(parse_register(ArgKind::Gpr, "fa0"), None);
+        assert_eq!(parse_register(ArgKind::Fpr, "a0"), None);
+        assert_eq!(parse_register(ArgKind::Imm, "a0"), None);
+    }
+
+    #[test]
+    fn format_operands() {
+        let style = RegisterStyle::Abi;
+        let rd = Argument::new(&args::RD, 10);
+        assert_eq!(format_argument(&ADDI, &rd, style), "a0");
+        assert_eq!(format_argument(&FADD_S, &rd, style), "fa0");
+        assert_eq!(
+            format_argument(&FLW, &Argument::new(&args::RS1, 2), style),
+            "sp"
+        );
+        assert_eq!(
+            format_argument(&ADDI, &Argument::new(&args::IMM12, 10), style),
+            "0xa"
+        );
+    }
+}
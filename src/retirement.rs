@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use libafl::{
+    corpus::{Corpus, CorpusId},
+    fuzzer::HasScheduler,
+    inputs::{Input, UsesInput},
+    schedulers::{powersched::SchedulerMetadata, Scheduler},
+    stages::Stage,
+    state::{HasCorpus, HasMetadata, UsesState},
+    Error,
+};
+
+libafl::impl_serdeany!(RetirementMetadata);
+/// Remembers in which queue cycle a corpus entry last produced an offspring
+/// that was added to the corpus.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RetirementMetadata {
+    last_productive_cycle: u64,
+}
+
+impl RetirementMetadata {
+    /// Create a new [`struct@RetirementMetadata`] for an entry first seen in
+    /// the given queue cycle.
+    #[must_use]
+    pub fn new(cycle: u64) -> Self {
+        Self {
+            last_productive_cycle: cycle,
+        }
+    }
+
+    /// Records the outcome of fuzzing the entry once and returns the number of
+    /// queue cycles that passed since it was last productive.
+    pub fn record(&mut self, cycle: u64, had_offspring: bool) -> u64 {
+        if had_offspring {
+            self.last_productive_cycle = cycle;
+        }
+        cycle.saturating_sub(self.last_productive_cycle)
+    }
+}
+
+/// Wraps a mutational stage and retires the fuzzed entry once it hasn't
+/// produced any new corpus entries for the given number of queue cycles.
+///
+/// Retired entries are written to the archive directory and removed from the
+/// corpus, so the scheduler no longer has to consider them.
+#[derive(Clone, Debug)]
+pub struct CorpusRetirement<ST> {
+    inner: ST,
+    // None disables retirement.
+    max_barren_cycles: Option<u64>,
+    archive_dir: PathBuf,
+}
+
+impl<ST> CorpusRetirement<ST> {
+    /// Create a new [`CorpusRetirement`] around the given stage.
+    pub fn new(inner: ST, max_barren_cycles: Option<u64>, archive_dir: PathBuf) -> Self {
+        Self {
+            inner,
+            max_barren_cycles,
+            archive_dir,
+        }
+    }
+
+    fn retire<S, Z>(&self, fuzzer: &mut Z, state: &mut S, corpus_idx: CorpusId) -> Result<(), Error>
+    where
+        S: HasCorpus,
+        S::Input: Input,
+        Z: HasScheduler<State = S>,
+    {
+        let input = state
+            .corpus()
+            .get(corpus_idx)?
+            .borrow_mut()
+            .load_input(state.corpus())?
+            .clone();
+
+        std::fs::create_dir_all(&self.archive_dir)?;
+        input.to_file(
+            self.archive_dir
+                .join(input.generate_name(usize::from(corpus_idx))),
+        )?;
+
+        let testcase = state.corpus_mut().remove(corpus_idx)?;
+        fuzzer
+            .scheduler_mut()
+            .on_remove(state, corpus_idx, &Some(testcase))?;
+        *state.corpus_mut().current_mut() = None;
+        Ok(())
+    }
+}
+
+impl<ST> UsesState for CorpusRetirement<ST>
+where
+    ST: UsesState,
+{
+    type State = ST::State;
+}
+
+impl<E, EM, ST, Z> Stage<E, EM, Z> for CorpusRetirement<ST>
+where
+    ST: Stage<E, EM, Z>,
+    E: UsesState<State = ST::State>,
+    EM: UsesState<State = ST::State>,
+    Z: UsesState<State = ST::State> + HasScheduler,
+    ST::State: HasCorpus + HasMetadata,
+    <ST::State as UsesInput>::Input: Input,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut ST::State,
+        mgr: &mut EM,
+        corpus_idx: CorpusId,
+    ) -> Result<(), Error> {
+        let count_before = state.corpus().count();
+        self.inner
+            .perform(fuzzer, executor, state, mgr, corpus_idx)?;
+
+        let Some(max_barren_cycles) = self.max_barren_cycles else {
+            return Ok(());
+        };
+
+        let had_offspring = state.corpus().count() > count_before;
+        let cycle = state
+            .metadata_map()
+            .get::<SchedulerMetadata>()
+            .map_or(0, |meta| meta.queue_cycles());
+
+        let barren_cycles = {
+            let mut testcase = state.corpus().get(corpus_idx)?.borrow_mut();
+            if !testcase.has_metadata::<RetirementMetadata>() {
+                testcase.add_metadata(RetirementMetadata::new(cycle));
+            }
+            testcase
+                .metadata_map_mut()
+                .get_mut::<RetirementMetadata>()
+                .unwrap()
+                .record(cycle, had_offspring)
+        };
+
+        // Never retire the last entry, the scheduler needs something to pick.
+        if barren_cycles < max_barren_cycles || state.corpus().count() <= 1 {
+            return Ok(());
+        }
+        self.retire(fuzzer, state, corpus_idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_barren_cycles() {
+        let mut meta = RetirementMetadata::new(3);
+        assert_eq!(meta.record(3, false), 0);
+        assert_eq!(meta.record(5, false), 2);
+        assert_eq!(meta.record(6, true), 0);
+        assert_eq!(meta.record(9, false), 3);
+    }
+}
@@ -0,0 +1,231 @@
+use alloc::{vec, vec::Vec};
+
+use libafl::prelude::Rand;
+
+use crate::instructions::{
+    riscv::{
+        args,
+        rv64_i::ADDW,
+        rv64_m::MULW,
+        rv_i::{ADD, ADDI, AND, OR, XOR},
+        rv_m::{MUL, MULH, MULHU},
+    },
+    Argument, ArgumentSpec, Instruction, InstructionTemplate,
+};
+
+/// Instructions where rs1 and rs2 can be swapped without changing the result.
+fn is_commutative(template: &InstructionTemplate) -> bool {
+    [&ADD, &AND, &OR, &XOR, &MUL, &MULH, &MULHU, &ADDW, &MULW].contains(&template)
+}
+
+/// Interprets the lowest `bits` bits of `value` as a signed number.
+pub fn sign_extend(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((value << shift) as i32) >> shift
+}
+
+/// Truncates a signed number to an unsigned field with `bits` bits.
+pub fn truncate(value: i32, bits: u32) -> u32 {
+    (value as u32) & (u32::MAX >> (32 - bits))
+}
+
+fn make_addi(rd: u32, rs1: u32, imm: i32) -> Instruction {
+    Instruction::new(
+        &ADDI,
+        vec![
+            Argument::new(&args::RD, rd),
+            Argument::new(&args::RS1, rs1),
+            Argument::new(&args::IMM12, truncate(imm, 12)),
+        ],
+    )
+}
+
+fn make_add(rd: u32, rs1: u32, rs2: u32) -> Instruction {
+    Instruction::new(
+        &ADD,
+        vec![
+            Argument::new(&args::RD, rd),
+            Argument::new(&args::RS1, rs1),
+            Argument::new(&args::RS2, rs2),
+        ],
+    )
+}
+
+/// Returns all semantically equivalent forms of the given instruction that
+/// we know how to create. Each form can consist of several instructions.
+pub fn equivalent_forms<R: Rand>(rng: &mut R, inst: &Instruction) -> Vec<Vec<Instruction>> {
+    let mut result = Vec::<Vec<Instruction>>::new();
+    let template = inst.template();
+
+    if template == &ADD {
+        let (rd, rs1, rs2) = (
+            inst.arg_value(&args::RD).unwrap(),
+            inst.arg_value(&args::RS1).unwrap(),
+            inst.arg_value(&args::RS2).unwrap(),
+        );
+        // add rd, rs, x0 -> addi rd, rs, 0
+        if rs2 == 0 {
+            result.push(vec![make_addi(rd, rs1, 0)]);
+        } else if rs1 == 0 {
+            result.push(vec![make_addi(rd, rs2, 0)]);
+        }
+    }
+
+    if template == &ADDI {
+        let (rd, rs1, imm) = (
+            inst.arg_value(&args::RD).unwrap(),
+            inst.arg_value(&args::RS1).unwrap(),
+            sign_extend(inst.arg_value(&args::IMM12).unwrap(), 12),
+        );
+        // addi rd, rs, 0 -> add rd, rs, x0
+        if imm == 0 {
+            result.push(vec![make_add(rd, rs1, 0)]);
+        }
+        // addi rd, rs, imm -> addi rd, rs, a; addi rd, rd, imm - a
+        // Splitting is only correct if the first instruction actually writes
+        // its result somewhere.
+        if imm != 0 && rd != 0 {
+            let min = (imm - 2047).max(-2048);
+            let max = (imm + 2048).min(2047);
+            let first = min + rng.below((max - min + 1) as u64) as i32;
+            result.push(vec![
+                make_addi(rd, rs1, first),
+                make_addi(rd, rd, imm - first),
+            ]);
+        }
+    }
+
+    if is_commutative(template) {
+        let (rs1, rs2) = (
+            inst.arg_value(&args::RS1).unwrap(),
+            inst.arg_value(&args::RS2).unwrap(),
+        );
+        if rs1 != rs2 {
+            let mut swapped = inst.clone();
+            swapped.set_arg(Argument::new(&args::RS1, rs2));
+            swapped.set_arg(Argument::new(&args::RS2, rs1));
+            result.push(vec![swapped]);
+        }
+    }
+
+    result
+}
+
+/// Swaps all uses of the registers `a` and `b` in the program. As every
+/// use is renamed, the data flow of the program is preserved.
+/// Integer and floating point registers with the same index are swapped
+/// together, which keeps the renaming consistent within each register file.
+pub fn swap_registers(program: &mut [Instruction], a: u32, b: u32) {
+    for inst in program.iter_mut() {
+        let renamed: Vec<Argument> = inst
+            .arguments()
+            .iter()
+            .filter(|arg| arg.spec().is_register())
+            .filter_map(|arg| match arg.value() {
+                v if v == a => Some(Argument::new(arg.spec(), b)),
+                v if v == b => Some(Argument::new(arg.spec(), a)),
+                _ => None,
+            })
+            .collect();
+        for arg in renamed {
+            inst.set_arg(arg);
+        }
+    }
+}
+
+/// Replaces every value `v` of the argument `spec` in the program with
+/// `permutation[v]`. Values outside of the permutation are kept. Unlike
+/// `swap_registers`, only the one argument is renamed, so the data flow
+/// between instructions changes but stays consistent across the program.
+pub fn permute_registers(
+    program: &mut [Instruction],
+    spec: &'static ArgumentSpec,
+    permutation: &[u32],
+) {
+    for inst in program.iter_mut() {
+        let Some(value) = inst.arg_value(spec) else {
+            continue;
+        };
+        if let Some(new_value) = permutation.get(value as usize) {
+            inst.set_arg(Argument::new(spec, *new_value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::prelude::Xoshiro256StarRand;
+
+    use crate::instructions::riscv::{args, rv_i::ADDI};
+
+    use super::{
+        equivalent_forms, make_add, make_addi, permute_registers, sign_extend, swap_registers,
+        truncate,
+    };
+
+    #[test]
+    fn sign_extension() {
+        assert_eq!(sign_extend(0xfff, 12), -1);
+        assert_eq!(sign_extend(0x7ff, 12), 2047);
+        assert_eq!(truncate(-1, 12), 0xfff);
+        assert_eq!(truncate(-2048, 12), 0x800);
+    }
+
+    #[test]
+    fn add_with_zero_register() {
+        let mut rng = Xoshiro256StarRand::default();
+        let forms = equivalent_forms(&mut rng, &make_add(3, 4, 0));
+        assert!(forms.contains(&vec![make_addi(3, 4, 0)]));
+        // Commutative swap.
+        assert!(forms.contains(&vec![make_add(3, 0, 4)]));
+
+        let forms = equivalent_forms(&mut rng, &make_addi(3, 4, 0));
+        assert_eq!(forms, vec![vec![make_add(3, 4, 0)]]);
+    }
+
+    #[test]
+    fn split_immediate() {
+        let mut rng = Xoshiro256StarRand::default();
+        for imm in [-2048, -1, 1, 5, 2047] {
+            let forms = equivalent_forms(&mut rng, &make_addi(3, 4, imm));
+            assert_eq!(forms.len(), 1);
+            let split = &forms[0];
+            assert_eq!(split.len(), 2);
+            assert!(split.iter().all(|inst| inst.template() == &ADDI));
+            let sum: i32 = split
+                .iter()
+                .map(|inst| sign_extend(inst.arg_value(&args::IMM12).unwrap(), 12))
+                .sum();
+            assert_eq!(sum, imm);
+            assert_eq!(split[1].arg_value(&args::RS1), Some(3));
+        }
+    }
+
+    #[test]
+    fn swap_register_uses() {
+        let mut program = vec![make_addi(3, 0, 5), make_add(4, 3, 3), make_add(5, 4, 6)];
+        swap_registers(&mut program, 3, 4);
+        assert_eq!(
+            program,
+            vec![make_addi(4, 0, 5), make_add(3, 4, 4), make_add(5, 3, 6)]
+        );
+
+        // Swapping back restores the original program.
+        swap_registers(&mut program, 4, 3);
+        assert_eq!(program[1], make_add(4, 3, 3));
+    }
+
+    #[test]
+    fn permute_register_uses() {
+        let mut program = vec![make_addi(3, 0, 5), make_add(4, 3, 3), make_add(7, 4, 6)];
+        // Swaps x3 and x7, keeps the others.
+        let permutation = [0, 1, 2, 7, 4, 5, 6, 3];
+        permute_registers(&mut program, &args::RD, &permutation);
+        assert_eq!(
+            program,
+            vec![make_addi(7, 0, 5), make_add(4, 3, 3), make_add(3, 4, 6)]
+        );
+        permute_registers(&mut program, &args::RS1, &permutation);
+        assert_eq!(program[1], make_add(4, 7, 3));
+    }
+}
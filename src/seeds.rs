@@ -0,0 +1,146 @@
+//! Loads the initial seeds of a campaign. A seed directory may mix corpus
+//! entries of earlier campaigns, reproducers from a 'causes' directory, raw
+//! machine code ('.bin') and text assembly ('.s').
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use libafl::inputs::Input;
+
+use crate::{
+    instructions, isa_hash::ISA_HASH_FILE, parser::parse_instructions_with_raw_words,
+    program_input::ProgramInput,
+};
+
+/// How a seed file is parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeedFormat {
+    /// A serialized `ProgramInput` (e.g. from a 'queue' directory) or the
+    /// bytes passed to the harness (e.g. from a 'causes' directory).
+    Input,
+    /// Little-endian instruction words without any header.
+    Raw,
+    /// The text format of `inst-assembler`.
+    Text,
+}
+
+impl SeedFormat {
+    /// The format of a seed file, based on its extension.
+    pub fn of(path: &Path) -> SeedFormat {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("bin") => SeedFormat::Raw,
+            Some("s" | "S" | "asm") => SeedFormat::Text,
+            _ => SeedFormat::Input,
+        }
+    }
+}
+
+/// Parses the seed in the given file. Words in machine code that aren't
+/// instructions are kept as `RAW_WORD`s if `raw_words` is set and rejected
+/// otherwise.
+pub fn load_seed(path: &Path, raw_words: bool) -> Result<ProgramInput, String> {
+    match SeedFormat::of(path) {
+        SeedFormat::Raw => {
+            let bytes = fs::read(path).map_err(|e| e.to_string())?;
+            Ok(ProgramInput::new(parse_instructions_with_raw_words(
+                &bytes,
+                instructions::riscv::all(),
+                raw_words,
+            )?))
+        }
+        SeedFormat::Text => {
+            let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+            ProgramInput::from_text(&source)
+        }
+        SeedFormat::Input => match ProgramInput::from_file(path) {
+            Ok(input) => Ok(input),
+            Err(err) => {
+                let bytes = fs::read(path).map_err(|e| e.to_string())?;
+                ProgramInput::from_bytes_with(&bytes, raw_words).map_err(|raw_err| {
+                    format!(
+                        "Neither a corpus entry ({}) nor a harness input ({})",
+                        err, raw_err
+                    )
+                })
+            }
+        },
+    }
+}
+
+/// The seeds in a directory and the files that couldn't be parsed with the
+/// reason. Hidden files (e.g. LibAFL metadata) and the ISA hash file are
+/// skipped. See `load_seed` for `raw_words`.
+pub fn load_seed_dir(
+    dir: &Path,
+    raw_words: bool,
+) -> Result<(Vec<ProgramInput>, Vec<(PathBuf, String)>), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read {:?}: {}", dir, e))?;
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            !name.starts_with('.') && name != ISA_HASH_FILE
+        })
+        .collect();
+    paths.sort();
+
+    let mut seeds = Vec::<ProgramInput>::new();
+    let mut failures = Vec::<(PathBuf, String)>::new();
+    for path in paths {
+        match load_seed(&path, raw_words) {
+            Ok(seed) if seed.insts().is_empty() => {
+                failures.push((path, "No instructions".to_string()));
+            }
+            Ok(seed) => seeds.push(seed),
+            Err(err) => failures.push((path, err)),
+        }
+    }
+    Ok((seeds, failures))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use libafl::inputs::Input;
+
+    use super::{load_seed_dir, SeedFormat};
+    use crate::{
+        isa_hash::write_isa_hash_file, program_input::ProgramInput, text_format::parse_source,
+    };
+
+    #[test]
+    fn load_mixed_seed_dir() {
+        let dir = std::env::temp_dir().join(format!("seeds-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        write_isa_hash_file(&dir).unwrap();
+
+        let insts = parse_source("addi x1, x0, 5\nadd x2, x1, x1\n").unwrap();
+        let input = ProgramInput::new(insts.clone());
+        input.to_file(dir.join("queue-entry")).unwrap();
+        fs::write(dir.join("reproducer"), input.to_bytes()).unwrap();
+        fs::write(dir.join("raw.bin"), input.to_bytes()).unwrap();
+        fs::write(dir.join("text.s"), "addi x1, x0, 5\nadd x2, x1, x1\n").unwrap();
+        fs::write(dir.join("broken.bin"), [1, 2, 3]).unwrap();
+        fs::write(dir.join(".queue-entry.metadata"), [0]).unwrap();
+
+        let (seeds, failures) = load_seed_dir(&dir, false).unwrap();
+        assert_eq!(seeds.len(), 4);
+        assert!(seeds.iter().all(|seed| seed.insts() == insts));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, dir.join("broken.bin"));
+
+        // Words that aren't instructions are only kept if asked to.
+        fs::write(dir.join("illegal.bin"), [0xff; 4]).unwrap();
+        let (_, failures) = load_seed_dir(&dir, false).unwrap();
+        assert_eq!(failures.len(), 2);
+        let (seeds, _) = load_seed_dir(&dir, true).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(seeds.len(), 5);
+        assert_eq!(SeedFormat::of(&dir.join("text.s")), SeedFormat::Text);
+    }
+}
@@ -0,0 +1,246 @@
+use core::{fmt::Debug, marker::PhantomData, ops::Range};
+
+use serde::{Deserialize, Serialize};
+
+use libafl::{
+    bolts::tuples::Named,
+    corpus::{Corpus, Testcase},
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::UsesInput,
+    observers::ObserversTuple,
+    state::{HasClientPerfMonitor, HasCorpus, HasMetadata},
+    Error,
+};
+
+use crate::{
+    assembler::assemble_instructions,
+    instructions::{self, Instruction},
+    parser::parse_instructions,
+    program_input::ProgramInput,
+};
+
+// The maximum number of snippets kept in the library.
+const MAX_LIBRARY_SIZE: usize = 256;
+// Changed windows longer than this are not worth remembering as a snippet.
+const MAX_SNIPPET_LEN: usize = 16;
+
+libafl::impl_serdeany!(SnippetLibraryMetadata);
+/// Instruction snippets that were part of coverage increasing mutations.
+/// The snippets are stored as assembled machine code.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SnippetLibraryMetadata {
+    snippets: Vec<Vec<u8>>,
+}
+
+impl SnippetLibraryMetadata {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a snippet to the library. Drops the oldest snippet if the
+    /// library is full and ignores snippets that are already known.
+    pub fn add(&mut self, snippet: &Vec<Instruction>) {
+        let bytes = assemble_instructions(snippet);
+        if bytes.is_empty() || self.snippets.contains(&bytes) {
+            return;
+        }
+        if self.snippets.len() >= MAX_LIBRARY_SIZE {
+            self.snippets.remove(0);
+        }
+        self.snippets.push(bytes);
+    }
+
+    /// Returns the snippet at the given index.
+    pub fn get(&self, idx: usize) -> Option<Vec<Instruction>> {
+        let bytes = self.snippets.get(idx)?;
+        parse_instructions(bytes, instructions::riscv::all()).ok()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.snippets.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.snippets.is_empty()
+    }
+}
+
+/// Returns the range of instructions in `child` that differ from `parent`.
+/// Returns None if the child only removed instructions.
+pub fn changed_window(parent: &[Instruction], child: &[Instruction]) -> Option<Range<usize>> {
+    let prefix = parent
+        .iter()
+        .zip(child.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    // The common suffix must not overlap with the common prefix.
+    let max_suffix = parent.len().min(child.len()) - prefix;
+    let suffix = parent
+        .iter()
+        .rev()
+        .zip(child.iter().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let window = prefix..(child.len() - suffix);
+    if window.is_empty() {
+        return None;
+    }
+    Some(window)
+}
+
+/// Feedback that never rates an input as interesting by itself, but adds the
+/// instructions in which a new corpus entry differs from its parent to the
+/// [`SnippetLibraryMetadata`].
+#[derive(Clone, Debug)]
+pub struct SnippetExtractionFeedback<S> {
+    phantom: PhantomData<S>,
+}
+
+impl<S> SnippetExtractionFeedback<S> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Default for SnippetExtractionFeedback<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Named for SnippetExtractionFeedback<S> {
+    fn name(&self) -> &str {
+        "SnippetExtractionFeedback"
+    }
+}
+
+impl<S> Feedback<S> for SnippetExtractionFeedback<S>
+where
+    S: UsesInput<Input = ProgramInput>
+        + HasClientPerfMonitor
+        + HasMetadata
+        + HasCorpus
+        + Debug,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        if !state.has_metadata::<SnippetLibraryMetadata>() {
+            state.add_metadata(SnippetLibraryMetadata::new());
+        }
+        Ok(())
+    }
+
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &ProgramInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        let Some(child) = testcase.input().as_ref() else {
+            return Ok(());
+        };
+        // New entries are derived from the entry currently being fuzzed.
+        let Some(parent_id) = testcase.parent_id().or(*state.corpus().current()) else {
+            return Ok(());
+        };
+
+        let parent = state
+            .corpus()
+            .get(parent_id)?
+            .borrow_mut()
+            .load_input(state.corpus())?
+            .clone();
+
+        let Some(window) = changed_window(parent.insts(), child.insts()) else {
+            return Ok(());
+        };
+        if window.len() > MAX_SNIPPET_LEN {
+            return Ok(());
+        }
+
+        let snippet = child.insts()[window].to_vec();
+        if let Some(library) = state
+            .metadata_map_mut()
+            .get_mut::<SnippetLibraryMetadata>()
+        {
+            library.add(&snippet);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::prelude::Xoshiro256StarRand;
+
+    use crate::generator::InstGenerator;
+    use crate::instructions;
+
+    use super::{changed_window, SnippetLibraryMetadata};
+
+    #[test]
+    fn find_changed_windows() {
+        let mut rng = Xoshiro256StarRand::default();
+        let insts =
+            InstGenerator::new().generate_instructions(&mut rng, instructions::sets::riscv_g(), 10);
+        let parent = insts[0..6].to_vec();
+
+        // Instructions inserted in the middle.
+        let mut child = parent.clone();
+        child.insert(2, insts[8].clone());
+        child.insert(3, insts[9].clone());
+        assert_eq!(changed_window(&parent, &child), Some(2..4));
+
+        // Appended instructions.
+        let mut child = parent.clone();
+        child.push(insts[7].clone());
+        assert_eq!(changed_window(&parent, &child), Some(6..7));
+
+        // Removed instruction.
+        let mut child = parent.clone();
+        child.remove(1);
+        assert_eq!(changed_window(&parent, &child), None);
+
+        // Unchanged program.
+        assert_eq!(changed_window(&parent, &parent), None);
+    }
+
+    #[test]
+    fn library_ignores_duplicates() {
+        let mut rng = Xoshiro256StarRand::default();
+        let insts = InstGenerator::new().generate_instructions(
+            &mut rng,
+            instructions::sets::riscv_g(),
+            3,
+        );
+        let mut library = SnippetLibraryMetadata::new();
+        library.add(&insts);
+        library.add(&insts);
+        assert_eq!(library.len(), 1);
+        assert_eq!(library.get(0).unwrap(), insts);
+    }
+}
@@ -0,0 +1,156 @@
+//! Rewards inputs that end in a new architectural state (see `sim-fuzzer
+//! --state-feedback`). The coverage map misses data-dependent bugs, e.g. a
+//! wrong result that takes the same paths through the RTL as the right one.
+//!
+//! The harness dumps the final state (see `ArchState::from_dump`) to the
+//! path in `FUZZING_STATE_DUMP_VAR` after every run.
+
+use core::{fmt::Debug, marker::PhantomData};
+use std::{collections::HashSet, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use libafl::{
+    bolts::tuples::Named,
+    events::{Event, EventFirer},
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::UsesInput,
+    monitors::UserStats,
+    observers::{Observer, ObserversTuple},
+    state::HasClientPerfMonitor,
+    Error,
+};
+
+use crate::arch_state::ArchState;
+
+/// The harness writes the final state to the path in this variable.
+pub const FUZZING_STATE_DUMP_VAR: &str = "FUZZING_STATE_DUMP";
+
+/// Reads the state dump of the harness after every run. Without a path, it
+/// never observes a state.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ArchStateObserver {
+    name: String,
+    path: Option<PathBuf>,
+    // The signature of the state of the last run, if the harness dumped one.
+    signature: Option<u64>,
+}
+
+impl ArchStateObserver {
+    pub fn new(name: &str, path: Option<PathBuf>) -> Self {
+        Self {
+            name: name.to_string(),
+            path,
+            signature: None,
+        }
+    }
+
+    /// The `ArchState::signature` of the state of the last run.
+    pub fn signature(&self) -> Option<u64> {
+        self.signature
+    }
+}
+
+impl Named for ArchStateObserver {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<S> Observer<S> for ArchStateObserver
+where
+    S: UsesInput,
+{
+    fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        self.signature = None;
+        // A stale dump would be attributed to the wrong input.
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+        Ok(())
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _input: &S::Input,
+        _exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        // The harness didn't dump a state (e.g. it crashed).
+        let Ok(text) = fs::read_to_string(path) else {
+            return Ok(());
+        };
+        match ArchState::from_dump(&text) {
+            Ok(state) => self.signature = Some(state.signature()),
+            Err(err) => log::warn!("{}", err),
+        }
+        Ok(())
+    }
+}
+
+/// Feedback that is interesting if the input ended in a state with a new
+/// signature. Reports the number of signatures as 'states' to the monitor.
+#[derive(Clone, Debug)]
+pub struct NovelStateFeedback<S> {
+    observer_name: String,
+    seen: HashSet<u64>,
+    phantom: PhantomData<S>,
+}
+
+impl<S> NovelStateFeedback<S> {
+    #[must_use]
+    pub fn new(observer: &ArchStateObserver) -> Self {
+        Self {
+            observer_name: observer.name().to_string(),
+            seen: HashSet::new(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for NovelStateFeedback<S> {
+    fn name(&self) -> &str {
+        "NovelStateFeedback"
+    }
+}
+
+impl<S> Feedback<S> for NovelStateFeedback<S>
+where
+    S: UsesInput + HasClientPerfMonitor + Debug,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        _input: &S::Input,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let observer = observers
+            .match_name::<ArchStateObserver>(&self.observer_name)
+            .ok_or_else(|| Error::key_not_found("ArchStateObserver not found".to_string()))?;
+        let Some(signature) = observer.signature() else {
+            return Ok(false);
+        };
+        if !self.seen.insert(signature) {
+            return Ok(false);
+        }
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: "states".to_string(),
+                value: UserStats::Number(self.seen.len() as u64),
+                phantom: PhantomData,
+            },
+        )?;
+        Ok(true)
+    }
+}
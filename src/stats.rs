@@ -0,0 +1,179 @@
+//! Periodic snapshots of the campaign statistics (one JSON object per line),
+//! for plotting campaigns and comparing runs.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::mutation_stats::MutationCounts;
+
+/// The name of the stats file in the out dir.
+pub const STATS_FILE: &str = "stats.jsonl";
+
+/// The name of the AFL-style plot data file in the out dir (see `afl-plot`).
+pub const PLOT_DATA_FILE: &str = "plot_data";
+
+/// The first line of the plot data file, as written by AFL++.
+pub const PLOT_DATA_HEADER: &str = "# relative_time, cycles_done, cur_item, corpus_count, \
+pending_total, pending_favs, map_size, saved_crashes, saved_hangs, max_depth, \
+execs_per_sec, total_execs, edges_found";
+
+/// The name of the file in the out dir with the full coverage history.
+pub const COVERAGE_FILE: &str = "coverage.csv";
+
+/// The first line of the coverage file.
+pub const COVERAGE_HEADER: &str = "seconds,coverage";
+
+/// Formats a line of the coverage file (without the newline).
+pub fn coverage_line(seconds: f64, coverage: u64) -> String {
+    format!("{:.3},{}", seconds, coverage)
+}
+
+/// Parses a coverage file into (seconds since start, covered map entries)
+/// pairs, skipping the header and malformed lines.
+pub fn parse_coverage(text: &str) -> Vec<(f64, u64)> {
+    text.lines()
+        .filter_map(|line| {
+            let (seconds, coverage) = line.split_once(',')?;
+            Some((seconds.parse().ok()?, coverage.parse().ok()?))
+        })
+        .collect()
+}
+
+/// The statistics of a single client.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClientRecord {
+    pub id: u32,
+    pub execs: u64,
+    pub execs_per_sec: f64,
+    pub corpus: u64,
+    pub objectives: u64,
+    /// Covered map entries of the client's map.
+    pub coverage: u64,
+}
+
+/// The statistics of the whole campaign at one point in time.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StatsRecord {
+    /// Seconds since the UNIX epoch.
+    pub time: u64,
+    /// Seconds since the campaign started.
+    pub run_time: u64,
+    pub execs: u64,
+    pub execs_per_sec: f64,
+    pub corpus: u64,
+    pub objectives: u64,
+    /// Covered map entries of the best client.
+    pub coverage: u64,
+    pub map_size: u64,
+    pub clients: Vec<ClientRecord>,
+    /// The counts of every mutation (by name), summed over the clients.
+    #[serde(default)]
+    pub mutations: BTreeMap<String, MutationCounts>,
+}
+
+impl StatsRecord {
+    /// Formats the record as a JSON line (without the newline).
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).expect("Stats records are always serializable")
+    }
+
+    /// Formats the record as a line of AFL's plot data (without the newline).
+    /// Columns sim-fuzzer has no equivalent for (e.g. cycles and hangs) are
+    /// always 0.
+    pub fn to_plot_line(&self) -> String {
+        let map_percent = if self.map_size == 0 {
+            0.0
+        } else {
+            self.coverage as f64 * 100.0 / self.map_size as f64
+        };
+        format!(
+            "{}, 0, 0, {}, 0, 0, {:.2}%, {}, 0, 0, {:.2}, {}, {}",
+            self.run_time,
+            self.corpus,
+            map_percent,
+            self.objectives,
+            self.execs_per_sec,
+            self.execs,
+            self.coverage
+        )
+    }
+
+    /// Parses the output of `to_line`.
+    pub fn parse_line(line: &str) -> Option<StatsRecord> {
+        serde_json::from_str(line).ok()
+    }
+}
+
+/// Parses a stats file, skipping malformed (e.g. partially written) lines.
+pub fn parse_stats(text: &str) -> Vec<StatsRecord> {
+    text.lines().filter_map(StatsRecord::parse_line).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::mutation_stats::MutationCounts;
+
+    use super::{
+        coverage_line, parse_coverage, parse_stats, ClientRecord, StatsRecord, COVERAGE_HEADER,
+        PLOT_DATA_HEADER,
+    };
+
+    #[test]
+    fn parse_stats_lines() {
+        let record = StatsRecord {
+            time: 1_700_000_000,
+            run_time: 60,
+            execs: 1200,
+            execs_per_sec: 20.0,
+            corpus: 7,
+            objectives: 1,
+            coverage: 345,
+            map_size: 65536,
+            clients: vec![ClientRecord {
+                id: 1,
+                execs: 1200,
+                execs_per_sec: 20.0,
+                corpus: 7,
+                objectives: 1,
+                coverage: 345,
+            }],
+            mutations: BTreeMap::from([(
+                "add".to_string(),
+                MutationCounts {
+                    applied: 50,
+                    skipped: 3,
+                    corpus: 2,
+                    objectives: 0,
+                },
+            )]),
+        };
+        let mut text = record.to_line() + "\n";
+        assert!(text.starts_with(r#"{"time":1700000000,"run_time":60,"execs":1200"#));
+        text += r#"{"time":17"#;
+        assert_eq!(parse_stats(&text), vec![record.clone()]);
+        // Records written before the mutation counts were added.
+        let old = r#"{"time":1,"run_time":0,"execs":0,"execs_per_sec":0.0,"corpus":0,"objectives":0,"coverage":0,"map_size":0,"clients":[]}"#;
+        assert!(parse_stats(old)[0].mutations.is_empty());
+
+        assert_eq!(
+            record.to_plot_line(),
+            "60, 0, 0, 7, 0, 0, 0.53%, 1, 0, 0, 20.00, 1200, 345"
+        );
+        assert_eq!(PLOT_DATA_HEADER.split(", ").count(), 13);
+    }
+
+    #[test]
+    fn coverage_lines() {
+        assert_eq!(coverage_line(1.5, 300), "1.500,300");
+        let text = format!(
+            "{}\n{}\n{}\n12.0",
+            COVERAGE_HEADER,
+            coverage_line(0.25, 10),
+            coverage_line(3.0, 20)
+        );
+        assert_eq!(parse_coverage(&text), vec![(0.25, 10), (3.0, 20)]);
+    }
+}
@@ -0,0 +1,168 @@
+//! Versioned envelope in which inputs are stored (e.g. in the corpus). Every
+//! part of an input is a separate section, so new parts can be added without
+//! breaking older files.
+//!
+//! Layout (all words are little-endian u32):
+//!   STORAGE_MAGIC, version, number of sections, (kind, length in bytes) per
+//!   section, the contents of the sections in the same order
+
+/// Marks the start of a stored input. Different from the configuration
+/// header and memory image magics and never a valid 32-bit instruction, so
+/// it can't be confused with the inputs stored before the envelope existed.
+pub const STORAGE_MAGIC: u32 = 0x5652_5352;
+
+/// The version written by `encode_envelope`. Readers reject newer versions.
+pub const STORAGE_VERSION: u32 = 1;
+
+/// The part of an input a section contains.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SectionKind {
+    /// The assembled (little-endian) instructions.
+    Instructions = 1,
+    /// An encoded `InputConfig`.
+    Config = 2,
+    /// An encoded `MemoryImage`.
+    Memory = 3,
+}
+
+impl SectionKind {
+    pub const ALL: [SectionKind; 3] = [
+        SectionKind::Instructions,
+        SectionKind::Config,
+        SectionKind::Memory,
+    ];
+
+    pub fn from_u32(value: u32) -> Option<SectionKind> {
+        SectionKind::ALL
+            .iter()
+            .find(|kind| **kind as u32 == value)
+            .copied()
+    }
+}
+
+/// How stored input bytes are encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageFormat {
+    /// The envelope described in the module docs.
+    Envelope { version: u32 },
+    /// The bytes passed to the harness, as stored before the envelope
+    /// existed.
+    Legacy,
+}
+
+impl StorageFormat {
+    /// Determines the format of the given stored bytes.
+    pub fn of(bytes: &[u8]) -> StorageFormat {
+        match (word(bytes, 0), word(bytes, 1)) {
+            (Some(STORAGE_MAGIC), Some(version)) => StorageFormat::Envelope { version },
+            _ => StorageFormat::Legacy,
+        }
+    }
+}
+
+fn word(bytes: &[u8], i: usize) -> Option<u32> {
+    let chunk = bytes.get(i * 4..i * 4 + 4)?;
+    Some(u32::from_le_bytes(chunk.try_into().unwrap()))
+}
+
+/// Wraps the given sections in an envelope of the current version.
+pub fn encode_envelope(sections: &[(SectionKind, Vec<u8>)]) -> Vec<u8> {
+    let mut words = vec![STORAGE_MAGIC, STORAGE_VERSION, sections.len() as u32];
+    for (kind, data) in sections {
+        words.push(*kind as u32);
+        words.push(data.len() as u32);
+    }
+    let mut result: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+    for (_, data) in sections {
+        result.extend_from_slice(data);
+    }
+    result
+}
+
+/// Returns the sections of an envelope in their stored order. Sections of
+/// kinds this version doesn't know are skipped.
+pub fn decode_envelope(bytes: &[u8]) -> Result<Vec<(SectionKind, &[u8])>, String> {
+    let version = match StorageFormat::of(bytes) {
+        StorageFormat::Envelope { version } => version,
+        StorageFormat::Legacy => return Err("Not a stored input envelope".to_string()),
+    };
+    if version == 0 || version > STORAGE_VERSION {
+        return Err(format!("Unsupported storage version: {}", version));
+    }
+    let count = word(bytes, 2).ok_or("Truncated envelope")? as usize;
+
+    let mut result = Vec::<(SectionKind, &[u8])>::new();
+    let mut offset = 4 * (3 + 2 * count);
+    for i in 0..count {
+        let kind = word(bytes, 3 + 2 * i).ok_or("Truncated envelope")?;
+        let len = word(bytes, 4 + 2 * i).ok_or("Truncated envelope")? as usize;
+        let data = bytes
+            .get(offset..offset + len)
+            .ok_or(format!("Truncated section {}", i))?;
+        offset += len;
+        if let Some(kind) = SectionKind::from_u32(kind) {
+            result.push((kind, data));
+        }
+    }
+    if offset != bytes.len() {
+        return Err(format!(
+            "{} trailing bytes after the last section",
+            bytes.len() - offset
+        ));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_envelope, encode_envelope, SectionKind, StorageFormat, STORAGE_MAGIC,
+        STORAGE_VERSION,
+    };
+
+    #[test]
+    fn encode_decode_envelope() {
+        let sections = vec![
+            (SectionKind::Config, vec![1, 2, 3, 4]),
+            (SectionKind::Instructions, vec![0x13, 0, 0, 0]),
+            (SectionKind::Memory, vec![]),
+        ];
+        let bytes = encode_envelope(&sections);
+        assert_eq!(
+            StorageFormat::of(&bytes),
+            StorageFormat::Envelope {
+                version: STORAGE_VERSION
+            }
+        );
+        let decoded = decode_envelope(&bytes).unwrap();
+        assert_eq!(decoded.len(), sections.len());
+        for ((kind, data), (expected_kind, expected_data)) in decoded.iter().zip(&sections) {
+            assert_eq!(kind, expected_kind);
+            assert_eq!(data, expected_data);
+        }
+
+        assert!(decode_envelope(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn skip_unknown_sections() {
+        let words = [STORAGE_MAGIC, 1, 2, 99, 4, 1, 4, 0, 0x13];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let decoded = decode_envelope(&bytes).unwrap();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].0, SectionKind::Instructions);
+        assert_eq!(decoded[0].1, 0x13u32.to_le_bytes());
+    }
+
+    #[test]
+    fn reject_other_formats() {
+        // A nop is the start of an input from before the envelope existed.
+        assert_eq!(StorageFormat::of(&[0x13, 0, 0, 0]), StorageFormat::Legacy);
+        assert_eq!(StorageFormat::of(&[]), StorageFormat::Legacy);
+        assert!(decode_envelope(&[0x13, 0, 0, 0]).is_err());
+
+        let words = [STORAGE_MAGIC, STORAGE_VERSION + 1, 0];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        assert!(decode_envelope(&bytes).is_err());
+    }
+}
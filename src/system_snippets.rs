@@ -0,0 +1,320 @@
+//! Snippets for privileged architecture features that random instructions
+//! practically never reach on their own.
+
+use alloc::{vec, vec::Vec};
+
+use libafl::prelude::Rand;
+
+use crate::{
+    input_config::PrivilegeLevel,
+    instructions::{
+        riscv::{
+            args,
+            rv64_i::{ADDIW, SLLI, SRLI},
+            rv_i::{ADDI, AUIPC, ECALL, FENCE, LUI},
+            rv_s::SRET,
+            rv_system::MRET,
+            rv_zicsr::{CSRRC, CSRRS, CSRRW},
+            rv_zifencei::FENCE_I,
+        },
+        Argument, Instruction, InstructionTemplate,
+    },
+};
+
+// CSR numbers used by the snippets.
+pub const CSR_SSTATUS: u32 = 0x100;
+pub const CSR_SEPC: u32 = 0x141;
+pub const CSR_MSTATUS: u32 = 0x300;
+pub const CSR_MEPC: u32 = 0x341;
+
+// Location of the previous privilege fields in mstatus/sstatus.
+const MSTATUS_MPP_SHIFT: u32 = 11;
+const MSTATUS_MPP_MASK: u32 = 0b11 << MSTATUS_MPP_SHIFT;
+const SSTATUS_SPP: u32 = 1 << 8;
+
+/// Scratch register clobbered by the snippets (t0).
+pub const SCRATCH_REG: u32 = 5;
+
+/// Creates a CSR instruction (csrrw, csrrs, csrrc) with a register source.
+pub fn csr_inst(
+    template: &'static InstructionTemplate,
+    rd: u32,
+    rs1: u32,
+    csr: u32,
+) -> Instruction {
+    Instruction::new(
+        template,
+        vec![
+            Argument::new(&args::RD, rd),
+            Argument::new(&args::RS1, rs1),
+            Argument::new(&args::CSR, csr),
+        ],
+    )
+}
+
+/// Loads a 32-bit constant into the given register (lui + addi). On RV64 the
+/// value is sign-extended from bit 31.
+pub fn load_constant(rd: u32, value: u32) -> Vec<Instruction> {
+    // addi sign-extends its immediate, so round the upper part up if the
+    // lower 12 bits are negative.
+    let upper = value.wrapping_add(0x800) >> 12;
+    let lower = value & 0xfff;
+    // For 0x7ffff800..=0x7fffffff the rounding makes lui's result negative,
+    // which a 64-bit addi can't undo. addiw wraps at 32 bits instead.
+    let add = if (upper ^ (value >> 12)) & 0x80000 != 0 {
+        &ADDIW
+    } else {
+        &ADDI
+    };
+    vec![
+        Instruction::new(
+            &LUI,
+            vec![
+                Argument::new(&args::RD, rd),
+                Argument::new(&args::IMM20, upper & 0xfffff),
+            ],
+        ),
+        Instruction::new(
+            add,
+            vec![
+                Argument::new(&args::RD, rd),
+                Argument::new(&args::RS1, rd),
+                Argument::new(&args::IMM12, lower),
+            ],
+        ),
+    ]
+}
+
+/// Loads a 32-bit address into the given register. In contrast to
+/// `load_constant`, the value is zero-extended on RV64.
+pub fn load_address(rd: u32, address: u32) -> Vec<Instruction> {
+    let mut result = load_constant(rd, address);
+    if address & 0x8000_0000 != 0 {
+        let shift = |template: &'static InstructionTemplate| {
+            Instruction::new(
+                template,
+                vec![
+                    Argument::new(&args::RD, rd),
+                    Argument::new(&args::RS1, rd),
+                    Argument::new(&args::SHAMTD, 32),
+                ],
+            )
+        };
+        result.push(shift(&SLLI));
+        result.push(shift(&SRLI));
+    }
+    result
+}
+
+/// Writes the address of the instruction after the following return
+/// instruction into the given epc CSR.
+/// Creates:
+///   auipc t0, 0
+///   addi t0, t0, 16
+///   csrrw x0, epc, t0
+fn set_epc_to_next(epc: u32) -> Vec<Instruction> {
+    vec![
+        Instruction::new(
+            &AUIPC,
+            vec![
+                Argument::new(&args::RD, SCRATCH_REG),
+                Argument::new(&args::IMM20, 0),
+            ],
+        ),
+        Instruction::new(
+            &ADDI,
+            vec![
+                Argument::new(&args::RD, SCRATCH_REG),
+                Argument::new(&args::RS1, SCRATCH_REG),
+                Argument::new(&args::IMM12, 16),
+            ],
+        ),
+        csr_inst(&CSRRW, 0, SCRATCH_REG, epc),
+    ]
+}
+
+/// Drops from machine mode into the given privilege level via MRET.
+/// Execution continues at the instruction after the snippet.
+pub fn mret_to(target: PrivilegeLevel) -> Vec<Instruction> {
+    let mut result = load_constant(SCRATCH_REG, MSTATUS_MPP_MASK);
+    result.push(csr_inst(&CSRRC, 0, SCRATCH_REG, CSR_MSTATUS));
+    let mpp = (target as u32) << MSTATUS_MPP_SHIFT;
+    if mpp != 0 {
+        result.append(&mut load_constant(SCRATCH_REG, mpp));
+        result.push(csr_inst(&CSRRS, 0, SCRATCH_REG, CSR_MSTATUS));
+    }
+    result.append(&mut set_epc_to_next(CSR_MEPC));
+    result.push(Instruction::new(&MRET, vec![]));
+    result
+}
+
+/// Drops from supervisor mode into user mode via SRET.
+/// Execution continues at the instruction after the snippet.
+pub fn sret_to_user() -> Vec<Instruction> {
+    let mut result = load_constant(SCRATCH_REG, SSTATUS_SPP);
+    result.push(csr_inst(&CSRRC, 0, SCRATCH_REG, CSR_SSTATUS));
+    result.append(&mut set_epc_to_next(CSR_SEPC));
+    result.push(Instruction::new(&SRET, vec![]));
+    result
+}
+
+/// Creates a FENCE with the given predecessor and successor sets
+/// (each a combination of the I, O, R and W bits).
+pub fn fence(pred: u32, succ: u32) -> Instruction {
+    Instruction::new(
+        &FENCE,
+        vec![
+            Argument::new(&args::FM, 0),
+            Argument::new(&args::PRED, pred & 0xf),
+            Argument::new(&args::SUCC, succ & 0xf),
+            Argument::new(&args::RS1, 0),
+            Argument::new(&args::RD, 0),
+        ],
+    )
+}
+
+/// Creates a FENCE.I that synchronizes the instruction and data streams.
+pub fn fence_i() -> Instruction {
+    Instruction::new(
+        &FENCE_I,
+        vec![
+            Argument::new(&args::RD, 0),
+            Argument::new(&args::RS1, 0),
+            Argument::new(&args::IMM12, 0),
+        ],
+    )
+}
+
+/// Creates either a FENCE with random non-empty pred/succ sets or a FENCE.I.
+pub fn random_fence<R: Rand>(rng: &mut R) -> Instruction {
+    if rng.below(4) == 0 {
+        return fence_i();
+    }
+    fence(rng.between(1, 15) as u32, rng.between(1, 15) as u32)
+}
+
+/// Creates a random privilege transition between the given supported
+/// privilege levels. Dropping privilege uses MRET/SRET, raising it uses
+/// ECALL, which relies on the harness' trap handler to return to a higher
+/// privilege level. Returns None if only one level is supported.
+pub fn privilege_transition<R: Rand>(
+    rng: &mut R,
+    supported: &[PrivilegeLevel],
+) -> Option<Vec<Instruction>> {
+    if supported.len() < 2 {
+        return None;
+    }
+    let mut options = Vec::<Vec<Instruction>>::new();
+    if supported.contains(&PrivilegeLevel::Machine) {
+        for target in supported.iter().filter(|l| **l != PrivilegeLevel::Machine) {
+            options.push(mret_to(*target));
+        }
+    }
+    if supported.contains(&PrivilegeLevel::Supervisor) && supported.contains(&PrivilegeLevel::User)
+    {
+        options.push(sret_to_user());
+    }
+    options.push(vec![Instruction::new(&ECALL, vec![])]);
+    Some(rng.choose(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::prelude::Xoshiro256StarRand;
+
+    use crate::{
+        assembler::assemble_instructions,
+        input_config::PrivilegeLevel,
+        instructions::{
+            self,
+            riscv::{
+                args,
+                rv64_i::{ADDIW, SLLI, SRLI},
+                rv_i::{ADDI, LUI},
+                rv_s::SRET,
+                rv_system::MRET,
+            },
+            Instruction,
+        },
+        parser::parse_instructions,
+        rewrites::sign_extend,
+    };
+
+    use super::{
+        fence, fence_i, load_address, load_constant, mret_to, privilege_transition, random_fence,
+        sret_to_user,
+    };
+
+    /// Computes the register value the snippet leaves behind on RV64.
+    fn run_rv64(insts: &[Instruction]) -> u64 {
+        let mut reg = 0u64;
+        for inst in insts {
+            let template = inst.template();
+            if template == &LUI {
+                let imm = inst.arg_value(&args::IMM20).unwrap() << 12;
+                reg = imm as i32 as i64 as u64;
+            } else if template == &ADDI || template == &ADDIW {
+                let imm = sign_extend(inst.arg_value(&args::IMM12).unwrap(), 12) as i64;
+                reg = reg.wrapping_add(imm as u64);
+                if template == &ADDIW {
+                    reg = reg as i32 as i64 as u64;
+                }
+            } else if template == &SLLI {
+                reg <<= inst.arg_value(&args::SHAMTD).unwrap();
+            } else if template == &SRLI {
+                reg >>= inst.arg_value(&args::SHAMTD).unwrap();
+            } else {
+                panic!("unexpected instruction {}", template.name());
+            }
+        }
+        reg
+    }
+
+    #[test]
+    fn load_constants() {
+        for value in [
+            0, 0x800, 0x1800, 0x7ff, 0x12345678, 0x7ffff7ff, 0x7ffff800, 0x7fffffff, 0x80000000,
+            0xfffff800, 0xffffffff,
+        ] {
+            let sign_extended = value as i32 as i64 as u64;
+            assert_eq!(run_rv64(&load_constant(5, value)), sign_extended);
+            assert_eq!(run_rv64(&load_address(5, value)), value as u64);
+        }
+    }
+
+    #[test]
+    fn transitions_end_with_return() {
+        for target in [PrivilegeLevel::User, PrivilegeLevel::Supervisor] {
+            let snippet = mret_to(target);
+            assert_eq!(snippet.last().unwrap().template(), &MRET);
+            // The snippet must survive a roundtrip through the fuzzer input.
+            let bytes = assemble_instructions(&snippet);
+            assert_eq!(
+                parse_instructions(&bytes, instructions::riscv::all()).unwrap(),
+                snippet
+            );
+        }
+        assert_eq!(sret_to_user().last().unwrap().template(), &SRET);
+    }
+
+    #[test]
+    fn transitions_need_two_levels() {
+        let mut rng = Xoshiro256StarRand::default();
+        assert!(privilege_transition(&mut rng, &[PrivilegeLevel::Machine]).is_none());
+        assert!(privilege_transition(&mut rng, &PrivilegeLevel::ALL).is_some());
+    }
+
+    #[test]
+    fn fences_roundtrip() {
+        let mut rng = Xoshiro256StarRand::default();
+        let mut insts = vec![fence(0b0011, 0b1100), fence_i()];
+        for _ in 0..100 {
+            insts.push(random_fence(&mut rng));
+        }
+        let bytes = assemble_instructions(&insts);
+        assert_eq!(
+            parse_instructions(&bytes, instructions::riscv::all()).unwrap(),
+            insts
+        );
+    }
+}
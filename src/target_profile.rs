@@ -0,0 +1,337 @@
+//! Describes the core under test in one TOML file instead of many command
+//! line options (see `sim-fuzzer --profile` and the examples in 'profiles').
+//!
+//! ```toml
+//! name = "rocket"
+//! extensions = ["I", "M", "A", "F", "D", "Zicsr", "Zifencei", "System", "S"]
+//! privilege_levels = ["m", "s", "u"]
+//! reserved_registers = ["x2", "x3", "x4"]
+//!
+//! [pmp_region]
+//! base = 0x80100000
+//! size = 0x10000
+//!
+//! [csr_init]
+//! mstatus = 0x1800
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache::CacheGeometry,
+    generator::{parse_register_list, InstGeneratorConfig},
+    input_config::PrivilegeLevel,
+    instructions::{sets, InstructionTemplate},
+    objdump::csr_number,
+    paging::PageTableConfig,
+    pmp::PmpConfig,
+};
+
+/// Environment variable through which the fuzzer passes the CSR values of
+/// the profile to the harness (see `readCsrInit` in FuzzerAPI.h).
+pub const FUZZING_CSR_INIT_VAR: &str = "FUZZING_CSR_INIT";
+
+/// The memory region PMP and memory snippets may use.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RegionProfile {
+    pub base: u32,
+    pub size: u32,
+}
+
+/// The location of the Sv39 page table and the mapped gigapage.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PageTableProfile {
+    pub table_base: u32,
+    pub phys_base: u32,
+}
+
+/// The geometry of the data cache.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CacheProfile {
+    pub line_size: u32,
+    pub sets: u32,
+    pub ways: u32,
+}
+
+/// Everything the fuzzer needs to know about a target. All settings are
+/// optional, missing ones keep the defaults of the command line options.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct TargetProfile {
+    /// A short name of the target (e.g. 'ibex').
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// The ISA extensions the target implements (see `sets::extensions`).
+    /// Instructions of other extensions are never generated.
+    pub extensions: Option<Vec<String>>,
+    /// The privilege levels the target supports (e.g. ["m", "u"]).
+    pub privilege_levels: Option<Vec<String>>,
+    /// Registers the harness relies on (e.g. the stack pointer), which are
+    /// never used as operands. Entries are registers or ranges ('x5-x7').
+    pub reserved_registers: Option<Vec<String>>,
+    /// See `--pmp-region`.
+    pub pmp_region: Option<RegionProfile>,
+    /// See `--page-table`.
+    pub page_table: Option<PageTableProfile>,
+    /// See `--cache-geometry`. Requires `pmp_region`.
+    pub cache: Option<CacheProfile>,
+    /// See `--memory-image`.
+    pub memory_image: Option<u64>,
+    /// See `--failure-routine`. Requires `pmp_region`.
+    pub failure_routine: Option<u32>,
+    /// Text format file with the exit sequence (see `--epilogue`). Relative
+    /// paths are relative to the profile.
+    pub epilogue: Option<PathBuf>,
+    /// Values the harness writes to CSRs before running a program, by CSR
+    /// name (e.g. 'mtvec') or number (e.g. "0x7c0").
+    #[serde(default)]
+    pub csr_init: BTreeMap<String, u64>,
+}
+
+impl TargetProfile {
+    /// Parses and validates a profile.
+    pub fn parse(text: &str) -> Result<TargetProfile, String> {
+        let profile: TargetProfile =
+            toml::from_str(text).map_err(|e| format!("Invalid target profile: {}", e))?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    /// Loads a profile and makes the epilogue path relative to it.
+    pub fn from_file(path: &Path) -> Result<TargetProfile, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        let mut profile = TargetProfile::parse(&text).map_err(|e| format!("{:?}: {}", path, e))?;
+        if let Some(dir) = path.parent() {
+            profile.epilogue = profile.epilogue.map(|epilogue| dir.join(epilogue));
+        }
+        Ok(profile)
+    }
+
+    /// Checks the settings and their combinations. The errors name the
+    /// offending setting.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name: The profile needs a name".to_string());
+        }
+        self.excluded_templates()?;
+        self.levels()?;
+        self.register_subset()?;
+        self.pmp_config()?;
+        self.page_table_config()?;
+        self.cache_geometry()?;
+        if self.cache.is_some() && self.pmp_region.is_none() {
+            return Err("cache: Requires pmp_region".to_string());
+        }
+        if self.failure_routine.is_some() && self.pmp_region.is_none() {
+            return Err("failure_routine: Requires pmp_region".to_string());
+        }
+        self.csr_values()?;
+        Ok(())
+    }
+
+    /// The templates of the extensions the target doesn't implement.
+    pub fn excluded_templates(&self) -> Result<Vec<&'static InstructionTemplate>, String> {
+        let Some(names) = &self.extensions else {
+            return Ok(Vec::new());
+        };
+        let known: Vec<&str> = sets::extensions().iter().map(|(name, _)| *name).collect();
+        for name in names {
+            if !known.iter().any(|k| k.eq_ignore_ascii_case(name)) {
+                return Err(format!(
+                    "extensions: Unknown extension '{}' (known: {})",
+                    name,
+                    known.join(", ")
+                ));
+            }
+        }
+        Ok(sets::extensions()
+            .iter()
+            .filter(|(ext, _)| !names.iter().any(|name| name.eq_ignore_ascii_case(ext)))
+            .flat_map(|(_, insts)| insts.iter().copied())
+            .collect())
+    }
+
+    fn levels(&self) -> Result<Option<Vec<PrivilegeLevel>>, String> {
+        self.privilege_levels
+            .as_ref()
+            .map(|levels| {
+                PrivilegeLevel::parse_list(&levels.join(","))
+                    .map_err(|e| format!("privilege_levels: {}", e))
+            })
+            .transpose()
+    }
+
+    /// The registers that may be used as operands, or None if all may.
+    pub fn register_subset(&self) -> Result<Option<Vec<u32>>, String> {
+        let Some(reserved) = &self.reserved_registers else {
+            return Ok(None);
+        };
+        if reserved.is_empty() {
+            return Ok(None);
+        }
+        let reserved = parse_register_list(&reserved.join(","))
+            .map_err(|e| format!("reserved_registers: {}", e))?;
+        if reserved.contains(&0) {
+            return Err("reserved_registers: x0 can't be reserved".to_string());
+        }
+        Ok(Some(
+            (0..32).filter(|reg| !reserved.contains(reg)).collect(),
+        ))
+    }
+
+    fn pmp_config(&self) -> Result<Option<PmpConfig>, String> {
+        self.pmp_region
+            .map(|region| {
+                PmpConfig::new(region.base, region.size).map_err(|e| format!("pmp_region: {}", e))
+            })
+            .transpose()
+    }
+
+    fn page_table_config(&self) -> Result<Option<PageTableConfig>, String> {
+        self.page_table
+            .map(|table| {
+                PageTableConfig::new(table.table_base, table.phys_base)
+                    .map_err(|e| format!("page_table: {}", e))
+            })
+            .transpose()
+    }
+
+    fn cache_geometry(&self) -> Result<Option<CacheGeometry>, String> {
+        self.cache
+            .map(|cache| {
+                CacheGeometry::new(cache.line_size, cache.sets, cache.ways)
+                    .map_err(|e| format!("cache: {}", e))
+            })
+            .transpose()
+    }
+
+    /// The initial CSR values by CSR number, ordered by their name in the
+    /// profile.
+    pub fn csr_values(&self) -> Result<Vec<(u32, u64)>, String> {
+        self.csr_init
+            .iter()
+            .map(|(name, value)| match csr_number(name) {
+                Some(csr) => Ok((csr, *value)),
+                None => Err(format!("csr_init: Unknown CSR '{}'", name)),
+            })
+            .collect()
+    }
+
+    /// Formats the CSR values for `FUZZING_CSR_INIT_VAR` as 'CSR=VALUE'
+    /// pairs of hex numbers separated by commas (e.g. '300=1800,305=0').
+    pub fn csr_init_var(&self) -> Result<String, String> {
+        Ok(self
+            .csr_values()?
+            .iter()
+            .map(|(csr, value)| format!("{:x}={:x}", csr, value))
+            .collect::<Vec<String>>()
+            .join(","))
+    }
+
+    /// Applies the profile to a generator config. Options applied afterwards
+    /// (e.g. from the command line) override the profile.
+    pub fn apply(&self, mut config: InstGeneratorConfig) -> Result<InstGeneratorConfig, String> {
+        let excluded = self.excluded_templates()?;
+        if !excluded.is_empty() {
+            config = config.exclude_templates(excluded);
+        }
+        if let Some(levels) = self.levels()? {
+            config = config.privilege_levels(levels);
+        }
+        if let Some(registers) = self.register_subset()? {
+            config = config.register_subset(registers);
+        }
+        if let Some(region) = self.pmp_config()? {
+            config = config.pmp_region(region);
+        }
+        if let Some(table) = self.page_table_config()? {
+            config = config.page_table(table);
+        }
+        if let Some(geometry) = self.cache_geometry()? {
+            config = config.cache_geometry(geometry);
+        }
+        if let Some(address) = self.failure_routine {
+            config = config.failure_routine(address);
+        }
+        if let Some(address) = self.memory_image {
+            config = config.memory_image(address);
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TargetProfile;
+    use crate::{
+        generator::InstGenerator,
+        instructions::riscv::{rv_i::ADD, rv_m::MUL},
+    };
+
+    const SHIPPED: [&str; 3] = [
+        include_str!("../profiles/ibex.toml"),
+        include_str!("../profiles/rocket.toml"),
+        include_str!("../profiles/cva6.toml"),
+    ];
+
+    #[test]
+    fn parse_shipped_profiles() {
+        for text in SHIPPED {
+            let profile = TargetProfile::parse(text).unwrap();
+            let config = profile.apply(Default::default()).unwrap();
+            let generator = InstGenerator::with_config(config);
+            assert!(generator.pmp_region().is_some(), "{}", profile.name);
+        }
+    }
+
+    #[test]
+    fn apply_profile() {
+        let profile = TargetProfile::parse(
+            r#"
+            name = "test"
+            extensions = ["i", "Zicsr"]
+            reserved_registers = ["x2", "x5-x31"]
+
+            [csr_init]
+            "0x7c0" = 1
+            mtvec = 0x80000000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            profile.csr_values().unwrap(),
+            vec![(0x7c0, 1), (0x305, 0x8000_0000)]
+        );
+        assert_eq!(profile.csr_init_var().unwrap(), "7c0=1,305=80000000");
+        let generator = InstGenerator::with_config(profile.apply(Default::default()).unwrap());
+        assert_eq!(generator.register_subset(), Some([0, 1, 3, 4].as_slice()));
+        assert!(generator.is_excluded(&MUL));
+        assert!(!generator.is_excluded(&ADD));
+    }
+
+    #[test]
+    fn reject_invalid_profiles() {
+        let error = |text: &str| TargetProfile::parse(text).unwrap_err();
+        assert!(error("name = \"x\"\nextension = [\"I\"]").contains("extension"));
+        assert!(error("name = \"x\"\nextensions = [\"Q\"]").starts_with("extensions:"));
+        assert!(
+            error("name = \"x\"\nreserved_registers = [\"x0\"]").starts_with("reserved_registers:")
+        );
+        assert!(
+            error("name = \"x\"\ncache = { line_size = 64, sets = 64, ways = 4 }")
+                .starts_with("cache:")
+        );
+        assert!(error("name = \"x\"\ncsr_init = { nope = 1 }").starts_with("csr_init:"));
+        assert!(error("name = \"\"").starts_with("name:"));
+    }
+}
@@ -0,0 +1,260 @@
+//! Rewards inputs with instructions the corpus doesn't contain yet (see
+//! `sim-fuzzer --template-feedback`). Pushes the corpus toward covering the
+//! whole ISA instead of only the map, which saturates early for simple
+//! instructions.
+//!
+//! Besides the templates, the classes of their operand values (see
+//! `OperandClass`) are tracked, so e.g. an `add` writing `x0` is new even if
+//! the corpus has other `add`s.
+
+use core::{fmt::Debug, marker::PhantomData};
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use libafl::{
+    bolts::tuples::Named,
+    corpus::Testcase,
+    events::{Event, EventFirer},
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::UsesInput,
+    monitors::UserStats,
+    observers::{Observer, ObserversTuple},
+    state::HasClientPerfMonitor,
+    Error,
+};
+
+use crate::{
+    instructions::{Argument, Instruction},
+    program_input::ProgramInput,
+};
+
+/// A coarse class of an operand value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum OperandClass {
+    /// All bits clear (e.g. `x0` or a zero immediate).
+    Zero,
+    /// All bits set (e.g. `x31` or a -1 immediate).
+    Ones,
+    Other,
+}
+
+impl OperandClass {
+    pub fn of(arg: &Argument) -> OperandClass {
+        if arg.value() == 0 {
+            OperandClass::Zero
+        } else if arg.value() == arg.spec().max_value() - 1 {
+            OperandClass::Ones
+        } else {
+            OperandClass::Other
+        }
+    }
+}
+
+/// Something in a program the feedback tracks: a template, or the class of
+/// an operand of a template.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TemplateFeature {
+    pub template: &'static str,
+    /// The operand name and class of its value.
+    pub operand: Option<(&'static str, OperandClass)>,
+}
+
+/// The sorted features of the given program.
+pub fn template_features(insts: &[Instruction]) -> Vec<TemplateFeature> {
+    let mut result = Vec::new();
+    for inst in insts {
+        let template = inst.template().name();
+        result.push(TemplateFeature {
+            template,
+            operand: None,
+        });
+        for arg in inst.arguments() {
+            result.push(TemplateFeature {
+                template,
+                operand: Some((arg.spec().name(), OperandClass::of(arg))),
+            });
+        }
+    }
+    result.sort();
+    result.dedup();
+    result
+}
+
+/// Records the template features of every executed input. When disabled, it
+/// never records any.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TemplateObserver {
+    name: String,
+    enabled: bool,
+    // The features of the last input. Only meaningful in the process that
+    // ran it, so it isn't serialized.
+    #[serde(skip)]
+    features: Vec<TemplateFeature>,
+}
+
+impl TemplateObserver {
+    pub fn new(name: &str, enabled: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            enabled,
+            features: Vec::new(),
+        }
+    }
+
+    /// The features of the last executed input.
+    pub fn features(&self) -> &[TemplateFeature] {
+        &self.features
+    }
+}
+
+impl Named for TemplateObserver {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<S> Observer<S> for TemplateObserver
+where
+    S: UsesInput<Input = ProgramInput>,
+{
+    fn pre_exec(&mut self, _state: &mut S, input: &ProgramInput) -> Result<(), Error> {
+        if self.enabled {
+            self.features = template_features(input.insts());
+        }
+        Ok(())
+    }
+}
+
+/// Feedback that is interesting if the input has a template feature that no
+/// corpus entry has. Reports the number of templates in the corpus as
+/// 'templates' to the monitor.
+#[derive(Clone, Debug)]
+pub struct TemplateCoverageFeedback<S> {
+    observer_name: String,
+    // The features of all corpus entries.
+    seen: HashSet<TemplateFeature>,
+    // The features of the last input, added to `seen` once it is retained.
+    last: Vec<TemplateFeature>,
+    phantom: PhantomData<S>,
+}
+
+impl<S> TemplateCoverageFeedback<S> {
+    #[must_use]
+    pub fn new(observer: &TemplateObserver) -> Self {
+        Self {
+            observer_name: observer.name().to_string(),
+            seen: HashSet::new(),
+            last: Vec::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// The number of templates in the corpus.
+    pub fn templates(&self) -> usize {
+        self.seen.iter().filter(|f| f.operand.is_none()).count()
+    }
+}
+
+impl<S> Named for TemplateCoverageFeedback<S> {
+    fn name(&self) -> &str {
+        "TemplateCoverageFeedback"
+    }
+}
+
+impl<S> Feedback<S> for TemplateCoverageFeedback<S>
+where
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor + Debug,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        _input: &ProgramInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let observer = observers
+            .match_name::<TemplateObserver>(&self.observer_name)
+            .ok_or_else(|| Error::key_not_found("TemplateObserver not found".to_string()))?;
+        self.last.clear();
+        self.last.extend(
+            observer
+                .features()
+                .iter()
+                .filter(|f| !self.seen.contains(f)),
+        );
+        if self.last.is_empty() {
+            return Ok(false);
+        }
+        let new_templates = self.last.iter().filter(|f| f.operand.is_none()).count();
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: "templates".to_string(),
+                value: UserStats::Number((self.templates() + new_templates) as u64),
+                phantom: PhantomData,
+            },
+        )?;
+        Ok(true)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        // Also called if another feedback retained the input.
+        self.seen.extend(self.last.drain(..));
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &ProgramInput) -> Result<(), Error> {
+        self.last.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instructions::{
+        riscv::{args, rv_i::ADDI},
+        Argument, Instruction,
+    };
+
+    use super::{template_features, OperandClass, TemplateFeature};
+
+    #[test]
+    fn features_of_program() {
+        let addi = |rd, rs1, imm| {
+            Instruction::new(
+                &ADDI,
+                vec![
+                    Argument::new(&args::RD, rd),
+                    Argument::new(&args::RS1, rs1),
+                    Argument::new(&args::IMM12, imm),
+                ],
+            )
+        };
+        let features = template_features(&[addi(0, 1, 4095), addi(0, 2, 4095)]);
+        assert_eq!(features.len(), 4);
+        assert!(features.contains(&TemplateFeature {
+            template: "addi",
+            operand: None,
+        }));
+        assert!(features.contains(&TemplateFeature {
+            template: "addi",
+            operand: Some(("rd", OperandClass::Zero)),
+        }));
+        assert!(features.contains(&TemplateFeature {
+            template: "addi",
+            operand: Some(("imm12", OperandClass::Ones)),
+        }));
+        assert_eq!(template_features(&[]), vec![]);
+    }
+}
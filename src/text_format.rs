@@ -0,0 +1,1067 @@
+//! The text format of `inst-assembler` and `inst-fmt`: one instruction per
+//! line as 'MNEMONIC OP=VALUE...' with '#' starting a comment. A few common
+//! pseudo-instructions (e.g. 'li rd=a0 imm=0x12345678') are expanded into
+//! one or more real instructions. Lines can start with a 'NAME:' label that
+//! branches and jumps can use as their target (e.g. 'beq rs1=a0 rs2=a1
+//! offset=loop'). Words that aren't instructions (see `RAW_WORD`) are
+//! written as '.word value=0x..'.
+//!
+//! Lines starting with '.' are directives that describe the rest of an input:
+//! '.config features=.. privilege=..', '.interrupt cycle=.. kind=..',
+//! '.register reg=.. value=..' and '.memory address=.. bytes=..' (hex).
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    input_config::{
+        InputConfig, Interrupt, InterruptKind, PrivilegeLevel, RegisterInit, MAX_INTERRUPTS,
+        MAX_REGISTER_INITS,
+    },
+    instructions::{
+        self,
+        riscv::{
+            args,
+            rv64_i::{ADDIW, SLLI},
+            rv_i::{ADDI, AUIPC, JAL, JALR, LUI},
+        },
+        split_immediates::JUMP_OFFSET,
+        ArgKind, Argument, Instruction, InstructionTemplate, SplitImmediate,
+    },
+    memory_image::{MemoryImage, MAX_MEMORY_IMAGE_SIZE},
+    registers::{format_argument, parse_register, register_name, RegisterStyle},
+};
+
+/// The pseudo-instructions and the names of their operands.
+const PSEUDO_INSTS: [(&str, &[&str]); 7] = [
+    ("nop", &[]),
+    ("li", &["rd", "imm"]),
+    ("mv", &["rd", "rs1"]),
+    ("j", &["offset"]),
+    ("call", &["offset"]),
+    ("ret", &[]),
+    (".word", &["value"]),
+];
+
+/// Whether the mnemonic is a pseudo-instruction.
+pub fn is_pseudo(name: &str) -> bool {
+    let name = name.to_lowercase();
+    PSEUDO_INSTS.iter().any(|(pseudo, _)| *pseudo == name)
+}
+
+fn find_template(name: &str) -> Result<&'static InstructionTemplate, String> {
+    let name = name.to_lowercase();
+    instructions::riscv::all()
+        .iter()
+        .copied()
+        .find(|inst| inst.name() == name)
+        .ok_or_else(|| format!("Could not find instruction with name '{}'", name))
+}
+
+fn parse_arg(inst: &'static InstructionTemplate, arg_str: &str) -> Result<Argument, String> {
+    let parts: Vec<&str> = arg_str.trim().split('=').collect();
+    if parts.len() != 2 {
+        return Err(format!("Not in ARG=VALUE format: '{}'", arg_str));
+    }
+    let (name, value_str) = (parts[0], parts[1]);
+    if value_str.is_empty() {
+        return Err(format!("Missing value in arg: {}", arg_str));
+    }
+
+    let Some(spec) = inst.op_with_name(name.to_string()) else {
+        let mut msg: String = format!("Possible operands for {}:\n", inst.name());
+        for op in inst.operands() {
+            msg.push_str(format!("* {}\n", op.name()).as_str());
+        }
+        return Err(format!(
+            "Failed to find operand with name {}\n{}",
+            name, msg
+        ));
+    };
+
+    // Register operands can also be given by name (e.g. 'x10' or 'a0').
+    if let Some(reg) = parse_register(inst.arg_kind(spec), value_str) {
+        return Ok(Argument::new(spec, reg));
+    }
+
+    let value = match value_str.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value_str.parse::<u32>(),
+    }
+    .map_err(|_| format!("Invalid decimal or hex value: {}", value_str))?;
+
+    if value >= spec.max_value() {
+        return Err(format!(
+            "Too large value {} for field {} which only allows up to {}",
+            value,
+            spec.name(),
+            spec.max_value() - 1
+        ));
+    }
+
+    Ok(Argument::new(spec, value))
+}
+
+/// Parses the value of the 'offset' operand, which sets all fields of the
+/// split immediate of branches, jumps and stores at once.
+fn parse_offset(
+    inst: &'static InstructionTemplate,
+    value_str: &str,
+) -> Result<(&'static SplitImmediate, i64), String> {
+    let split = inst
+        .operands()
+        .find_map(|op| op.split_immediate())
+        .ok_or_else(|| format!("{} has no offset operand", inst.name()))?;
+    let offset = parse_signed(value_str)?;
+    let limit = 1i64 << (split.length() - 1);
+    if offset % (1 << split.lowest_bit()) != 0 || !(-limit..limit).contains(&offset) {
+        return Err(format!("Invalid offset for {}: {}", inst.name(), value_str));
+    }
+    Ok((split, offset))
+}
+
+/// Parses a single instruction. Operands may be given in any order and the
+/// mnemonic in any case.
+pub fn parse_inst(line: &str) -> Result<Instruction, String> {
+    // Remove comments.
+    let stripped = line.split('#').next().unwrap().trim();
+
+    let mut parts = stripped.split_whitespace();
+    let inst = find_template(parts.next().unwrap_or_default())?;
+
+    let mut args = Vec::<Argument>::new();
+    let mut seen_ops = HashSet::<String>::new();
+    let mut offset: Option<(&'static SplitImmediate, i64)> = None;
+    for arg_str in parts {
+        if let Some(value_str) = arg_str.strip_prefix("offset=") {
+            let (split, value) = parse_offset(inst, value_str)
+                .map_err(|err| format!("Failed to parse '{}'. Reason: {}", arg_str, err))?;
+            for op in inst
+                .operands()
+                .filter(|op| op.split_immediate() == Some(split))
+            {
+                if !seen_ops.insert(op.name().to_string()) {
+                    return Err(format!("Duplicate operand '{}'", op.name()));
+                }
+            }
+            offset = Some((split, value));
+            continue;
+        }
+        let arg = parse_arg(inst, arg_str)
+            .map_err(|err| format!("Failed to parse '{}'. Reason: {}", arg_str, err))?;
+        let arg_name = arg.spec().name().to_string();
+        if !seen_ops.insert(arg_name.clone()) {
+            return Err(format!("Duplicate operand '{}'", arg_name));
+        }
+        args.push(arg);
+    }
+
+    if seen_ops.len() != inst.operands().count() {
+        let mut msg: String = format!("Missing operands in instruction {}:\n", inst.name());
+        for op in inst.operands() {
+            if seen_ops.contains(op.name()) {
+                continue;
+            }
+            msg.push_str(format!("* {}\n", op.name()).as_str());
+        }
+        return Err(msg);
+    }
+
+    // Instructions expect their operands in template order. The fields of an
+    // offset are filled in afterwards.
+    let args = inst
+        .operands()
+        .map(|op| {
+            args.iter()
+                .find(|arg| arg.spec() == *op)
+                .cloned()
+                .unwrap_or_else(|| Argument::new(op, 0))
+        })
+        .collect();
+    let mut result = Instruction::try_new(inst, args).map_err(|err| err.to_string())?;
+    if let Some((split, value)) = offset {
+        split.apply(&mut result, value as u32);
+    }
+    Ok(result)
+}
+
+/// Parses a signed decimal or hex value (e.g. '-16' or '0xffff_ffff').
+fn parse_signed(value_str: &str) -> Result<i64, String> {
+    let (negative, digits) = match value_str.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value_str),
+    };
+    let digits = digits.replace('_', "");
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => digits.parse::<u64>(),
+    }
+    .map_err(|_| format!("Invalid decimal or hex value: {}", value_str))? as i64;
+    Ok(if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    })
+}
+
+fn sign_extend(value: i64, bits: u32) -> i64 {
+    (value << (64 - bits)) >> (64 - bits)
+}
+
+/// Splits the operands of a pseudo-instruction into their values in the
+/// order of `PSEUDO_INSTS`.
+fn pseudo_operands<'a>(name: &str, parts: &[&'a str]) -> Result<Vec<&'a str>, String> {
+    let (_, names) = PSEUDO_INSTS
+        .iter()
+        .find(|(pseudo, _)| *pseudo == name)
+        .ok_or_else(|| format!("Unknown pseudo-instruction '{}'", name))?;
+    let mut values: Vec<Option<&str>> = vec![None; names.len()];
+    for arg_str in parts {
+        let Some((op, value)) = arg_str.split_once('=') else {
+            return Err(format!("Not in ARG=VALUE format: '{}'", arg_str));
+        };
+        if value.is_empty() {
+            return Err(format!("Missing value in arg: {}", arg_str));
+        }
+        let Some(idx) = names.iter().position(|n| *n == op) else {
+            return Err(format!(
+                "Failed to find operand with name {}\nPossible operands for {}: {}",
+                op,
+                name,
+                names.join(", ")
+            ));
+        };
+        if values[idx].replace(value).is_some() {
+            return Err(format!("Duplicate operand '{}'", op));
+        }
+    }
+    names
+        .iter()
+        .zip(values)
+        .map(|(op, value)| value.ok_or_else(|| format!("Missing operand '{}' of {}", op, name)))
+        .collect()
+}
+
+fn parse_gpr(value_str: &str) -> Result<u32, String> {
+    if let Some(reg) = parse_register(ArgKind::Gpr, value_str) {
+        return Ok(reg);
+    }
+    match parse_signed(value_str)? {
+        reg @ 0..=31 => Ok(reg as u32),
+        _ => Err(format!("Invalid register: {}", value_str)),
+    }
+}
+
+fn i_type(template: &'static InstructionTemplate, rd: u32, rs1: u32, imm: i64) -> Instruction {
+    Instruction::new(
+        template,
+        vec![
+            Argument::new(&args::RD, rd),
+            Argument::new(&args::RS1, rs1),
+            Argument::new(&args::IMM12, (imm as u32) & 0xfff),
+        ],
+    )
+}
+
+fn u_type(template: &'static InstructionTemplate, rd: u32, imm: u32) -> Instruction {
+    Instruction::new(
+        template,
+        vec![
+            Argument::new(&args::RD, rd),
+            Argument::new(&args::IMM20, imm & 0xfffff),
+        ],
+    )
+}
+
+/// Loads a 64-bit constant into rd. Follows the sequence that LLVM emits:
+/// lui+addiw for 32-bit values, otherwise the upper bits are loaded first
+/// and then shifted into place.
+fn expand_li(rd: u32, value: i64, result: &mut Vec<Instruction>) {
+    let lo12 = sign_extend(value & 0xfff, 12);
+    if value == value as i32 as i64 {
+        let hi20 = (value.wrapping_add(0x800) >> 12) as u32 & 0xfffff;
+        if hi20 == 0 {
+            result.push(i_type(&ADDI, rd, 0, lo12));
+            return;
+        }
+        result.push(u_type(&LUI, rd, hi20));
+        if lo12 != 0 {
+            result.push(i_type(&ADDIW, rd, rd, lo12));
+        }
+        return;
+    }
+
+    let hi52 = (value as u64).wrapping_add(0x800) >> 12;
+    let shift = 12 + hi52.trailing_zeros();
+    let hi = sign_extend((hi52 >> (shift - 12)) as i64, 64 - shift);
+    expand_li(rd, hi, result);
+    result.push(Instruction::new(
+        &SLLI,
+        vec![
+            Argument::new(&args::RD, rd),
+            Argument::new(&args::RS1, rd),
+            Argument::new(&args::SHAMTD, shift),
+        ],
+    ));
+    if lo12 != 0 {
+        result.push(i_type(&ADDI, rd, rd, lo12));
+    }
+}
+
+/// Expands a pseudo-instruction into real instructions. Jump offsets are
+/// relative to the (first) instruction of the expansion.
+fn expand_pseudo(name: &str, parts: &[&str]) -> Result<Vec<Instruction>, String> {
+    let ops = pseudo_operands(name, parts)?;
+    let ra = 1;
+    let mut result = Vec::new();
+    match name {
+        "nop" => result.push(i_type(&ADDI, 0, 0, 0)),
+        "li" => expand_li(parse_gpr(ops[0])?, parse_signed(ops[1])?, &mut result),
+        "mv" => result.push(i_type(&ADDI, parse_gpr(ops[0])?, parse_gpr(ops[1])?, 0)),
+        "ret" => result.push(i_type(&JALR, 0, ra, 0)),
+        "j" => {
+            let offset = parse_signed(ops[0])?;
+            if offset % 2 != 0 || !(-(1 << 20)..(1 << 20)).contains(&offset) {
+                return Err(format!("Invalid jump offset: {}", ops[0]));
+            }
+            let mut inst = Instruction::new(
+                &JAL,
+                vec![Argument::new(&args::RD, 0), Argument::new(&args::JIMM20, 0)],
+            );
+            JUMP_OFFSET.apply(&mut inst, offset as u32);
+            result.push(inst);
+        }
+        "call" => {
+            let offset = parse_signed(ops[0])?;
+            if !(-(1 << 31) - 0x800..(1 << 31) - 0x800).contains(&offset) {
+                return Err(format!("Invalid call offset: {}", ops[0]));
+            }
+            let hi20 = (offset.wrapping_add(0x800) >> 12) as u32;
+            result.push(u_type(&AUIPC, ra, hi20));
+            result.push(i_type(&JALR, ra, ra, sign_extend(offset & 0xfff, 12)));
+        }
+        ".word" => {
+            let value = parse_signed(ops[0])?;
+            if !(-(1 << 31)..=u32::MAX as i64).contains(&value) {
+                return Err(format!("Invalid word: {}", ops[0]));
+            }
+            result.push(Instruction::raw_word(value as u32));
+        }
+        _ => unreachable!("Unhandled pseudo-instruction '{}'", name),
+    }
+    Ok(result)
+}
+
+/// Whether the name can be used as a label: an identifier that doesn't
+/// start with a digit.
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Splits a 'NAME:' label definition off the start of the code.
+fn split_label(code: &str) -> Result<(Option<&str>, &str), String> {
+    let Some((label, rest)) = code.split_once(':') else {
+        return Ok((None, code));
+    };
+    let label = label.trim();
+    if !is_label(label) {
+        return Err(format!("Invalid label '{}'", label));
+    }
+    Ok((Some(label), rest.trim()))
+}
+
+/// Replaces labels used as 'offset' values by their distance in bytes from
+/// the instruction at index `pc`. Without a `pc` all labels are replaced by
+/// 0, which is enough to find out the size of a pseudo-instruction.
+fn resolve_labels(
+    code: &str,
+    labels: &HashMap<String, usize>,
+    pc: Option<usize>,
+) -> Result<String, String> {
+    let mut parts = Vec::<String>::new();
+    for part in code.split_whitespace() {
+        let Some(label) = part.strip_prefix("offset=").filter(|value| is_label(value)) else {
+            parts.push(part.to_string());
+            continue;
+        };
+        let offset = match pc {
+            Some(pc) => {
+                let target = labels
+                    .get(label)
+                    .ok_or_else(|| format!("Unknown label '{}'", label))?;
+                (*target as i64 - pc as i64) * 4
+            }
+            None => 0,
+        };
+        parts.push(format!("offset={}", offset));
+    }
+    Ok(parts.join(" "))
+}
+
+/// Parses a whole file. Labels are resolved to the instruction after them,
+/// so they can also be used before their definition.
+pub fn parse_source(source: &str) -> Result<Vec<Instruction>, String> {
+    let mut labels = HashMap::<String, usize>::new();
+    // The code of every line with the index of its first instruction.
+    let mut lines = Vec::<(usize, usize, &str)>::new();
+    let mut count = 0;
+    for (idx, line) in source.lines().enumerate() {
+        let line_err = |err: String| format!("line {}: {}", idx + 1, err);
+        let code = line.split('#').next().unwrap().trim();
+        let (label, code) = split_label(code).map_err(line_err)?;
+        if let Some(label) = label {
+            if labels.insert(label.to_string(), count).is_some() {
+                return Err(line_err(format!("Duplicate label '{}'", label)));
+            }
+        }
+        // The size of an expansion doesn't depend on label offsets.
+        let resolved = resolve_labels(code, &labels, None).map_err(line_err)?;
+        let size = parse_line(&resolved).map_err(line_err)?.len();
+        lines.push((idx, count, code));
+        count += size;
+    }
+
+    let mut result = Vec::<Instruction>::new();
+    for (idx, pc, code) in lines {
+        let line_err = |err: String| format!("line {}: {}", idx + 1, err);
+        let resolved = resolve_labels(code, &labels, Some(pc)).map_err(line_err)?;
+        result.extend(parse_line(&resolved).map_err(line_err)?);
+    }
+    Ok(result)
+}
+
+/// Parses a line that contains an instruction, a pseudo-instruction or
+/// nothing (only whitespace, comments and labels). Label operands are not
+/// resolved, use `parse_source` for that.
+pub fn parse_line(line: &str) -> Result<Vec<Instruction>, String> {
+    let stripped = line.split('#').next().unwrap().trim();
+    let (_, stripped) = split_label(stripped)?;
+    let parts: Vec<&str> = stripped.split_whitespace().collect();
+    let Some(mnemonic) = parts.first() else {
+        return Ok(Vec::new());
+    };
+    if is_pseudo(mnemonic) {
+        return expand_pseudo(&mnemonic.to_lowercase(), &parts[1..]);
+    }
+    Ok(vec![parse_inst(stripped)?])
+}
+
+/// Formats a pseudo-instruction with its operands in canonical order.
+/// Registers are printed in the given style, other values as written.
+fn format_pseudo(code: &str, style: RegisterStyle) -> Result<String, String> {
+    // Reject invalid operand values.
+    parse_line(&resolve_labels(code, &HashMap::new(), None)?)?;
+    let parts: Vec<&str> = code.split_whitespace().collect();
+    let name = parts[0].to_lowercase();
+    let (_, names) = PSEUDO_INSTS.iter().find(|(p, _)| *p == name).unwrap();
+    let mut result = name.clone();
+    for (op, value) in names.iter().zip(pseudo_operands(&name, &parts[1..])?) {
+        let value = match *op {
+            "rd" | "rs1" => register_name(ArgKind::Gpr, parse_gpr(value)?, style),
+            _ if is_label(value) => value.to_string(),
+            _ => value.to_lowercase(),
+        };
+        result += &format!(" {}={}", op, value);
+    }
+    Ok(result)
+}
+
+/// Formats an instruction in the text format.
+pub fn format_inst(inst: &Instruction, style: RegisterStyle) -> String {
+    if inst.is_raw_word() {
+        return format!(".word value={:#010x}", inst.encode());
+    }
+    let mut result = inst.template().name().to_string();
+    for arg in inst.arguments() {
+        result += &format!(
+            " {}={}",
+            arg.spec().name(),
+            format_argument(inst.template(), arg, style)
+        );
+    }
+    result
+}
+
+/// Formats an instruction whose split immediate was given as an 'offset'
+/// operand. The offset is kept as written (e.g. as a label).
+fn format_offset_inst(code: &str, style: RegisterStyle) -> Result<String, String> {
+    let inst = parse_inst(&resolve_labels(code, &HashMap::new(), None)?)?;
+    let offset = code
+        .split_whitespace()
+        .find_map(|part| part.strip_prefix("offset="))
+        .unwrap();
+    let mut result = inst.template().name().to_string();
+    for arg in inst.arguments() {
+        if arg.spec().split_immediate().is_none() {
+            result += &format!(
+                " {}={}",
+                arg.spec().name(),
+                format_argument(inst.template(), arg, style)
+            );
+        }
+    }
+    if is_label(offset) {
+        result += &format!(" offset={}", offset);
+    } else {
+        result += &format!(" offset={}", offset.to_lowercase());
+    }
+    Ok(result)
+}
+
+/// The parts of an input given by the directives of a source.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Directives {
+    pub config: Option<InputConfig>,
+    pub memory: Option<MemoryImage>,
+}
+
+/// Splits the 'OP=VALUE' operands of a directive into a map and checks that
+/// exactly the given operands are present.
+fn directive_operands<'a>(
+    parts: &[&'a str],
+    names: &[&str],
+) -> Result<HashMap<&'a str, &'a str>, String> {
+    let mut result = HashMap::<&str, &str>::new();
+    for part in parts {
+        let (op, value) = part
+            .split_once('=')
+            .ok_or(format!("Expected 'OP=VALUE', got '{}'", part))?;
+        if !names.contains(&op) {
+            return Err(format!("Unknown operand '{}'", op));
+        }
+        if result.insert(op, value).is_some() {
+            return Err(format!("Duplicate operand '{}'", op));
+        }
+    }
+    if let Some(missing) = names.iter().find(|name| !result.contains_key(*name)) {
+        return Err(format!("Missing operand '{}'", missing));
+    }
+    Ok(result)
+}
+
+fn parse_hex_bytes(value: &str) -> Result<Vec<u8>, String> {
+    let err = || format!("Invalid hex bytes: {}", value);
+    value
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let digits = std::str::from_utf8(pair).map_err(|_| err())?;
+            if digits.len() != 2 {
+                return Err(err());
+            }
+            u8::from_str_radix(digits, 16).map_err(|_| err())
+        })
+        .collect()
+}
+
+/// Applies a single directive (a line starting with '.') to `directives`.
+fn apply_directive(code: &str, directives: &mut Directives) -> Result<(), String> {
+    let parts: Vec<&str> = code.split_whitespace().collect();
+    let name = parts[0].to_lowercase();
+    match name.as_str() {
+        ".config" => {
+            let ops = directive_operands(&parts[1..], &["features", "privilege"])?;
+            let config = directives.config.get_or_insert_with(InputConfig::default);
+            config.features = parse_signed(ops["features"])? as u32;
+            config.privilege = PrivilegeLevel::from_name(ops["privilege"])
+                .ok_or(format!("Unknown privilege level: '{}'", ops["privilege"]))?;
+        }
+        ".interrupt" => {
+            let ops = directive_operands(&parts[1..], &["cycle", "kind"])?;
+            let kind = InterruptKind::from_name(ops["kind"])
+                .ok_or(format!("Unknown interrupt kind: '{}'", ops["kind"]))?;
+            let config = directives.config.get_or_insert_with(InputConfig::default);
+            if config.interrupts.len() >= MAX_INTERRUPTS {
+                return Err(format!("More than {} interrupts", MAX_INTERRUPTS));
+            }
+            config.interrupts.push(Interrupt {
+                cycle: parse_signed(ops["cycle"])? as u32,
+                kind,
+            });
+            config.interrupts.sort_by_key(|interrupt| interrupt.cycle);
+        }
+        ".register" => {
+            let ops = directive_operands(&parts[1..], &["reg", "value"])?;
+            let reg = parse_gpr(ops["reg"])?;
+            if reg == 0 {
+                return Err("x0 can't have an initial value".to_string());
+            }
+            let config = directives.config.get_or_insert_with(InputConfig::default);
+            if config.registers.iter().any(|init| init.reg == reg) {
+                return Err(format!("Duplicate value for register {}", ops["reg"]));
+            }
+            if config.registers.len() >= MAX_REGISTER_INITS {
+                return Err(format!("More than {} register values", MAX_REGISTER_INITS));
+            }
+            config.registers.push(RegisterInit {
+                reg,
+                value: parse_signed(ops["value"])? as u64,
+            });
+            config.registers.sort_by_key(|init| init.reg);
+        }
+        ".memory" => {
+            if directives.memory.is_some() {
+                return Err("Only one memory image is supported".to_string());
+            }
+            let ops = directive_operands(&parts[1..], &["address", "bytes"])?;
+            let bytes = parse_hex_bytes(ops["bytes"])?;
+            if bytes.len() > MAX_MEMORY_IMAGE_SIZE {
+                return Err(format!("Memory image too large: {} bytes", bytes.len()));
+            }
+            directives.memory = Some(MemoryImage {
+                address: parse_signed(ops["address"])? as u64,
+                bytes,
+            });
+        }
+        _ => return Err(format!("Unknown directive '{}'", parts[0])),
+    }
+    Ok(())
+}
+
+/// Parses the directives of a source. Returns them with the source without
+/// the directives (they are replaced by empty lines to keep line numbers).
+pub fn parse_directives(source: &str) -> Result<(Directives, String), String> {
+    let mut directives = Directives::default();
+    let mut rest = String::new();
+    for (idx, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap().trim();
+        let mnemonic = code.split_whitespace().next().unwrap_or_default();
+        if code.starts_with('.') && !is_pseudo(mnemonic) {
+            apply_directive(code, &mut directives)
+                .map_err(|err| format!("line {}: {}", idx + 1, err))?;
+        } else {
+            rest += line;
+        }
+        rest.push('\n');
+    }
+    Ok((directives, rest))
+}
+
+/// Formats the config header and memory image of an input as directives.
+pub fn format_directives(directives: &Directives, style: RegisterStyle) -> Vec<String> {
+    let mut result = Vec::<String>::new();
+    if let Some(config) = &directives.config {
+        result.push(format!(
+            ".config features={:#x} privilege={}",
+            config.features,
+            config.privilege.name()
+        ));
+        for interrupt in &config.interrupts {
+            result.push(format!(
+                ".interrupt cycle={} kind={}",
+                interrupt.cycle,
+                interrupt.kind.name()
+            ));
+        }
+        for init in &config.registers {
+            result.push(format!(
+                ".register reg={} value={:#x}",
+                register_name(ArgKind::Gpr, init.reg, style),
+                init.value
+            ));
+        }
+    }
+    if let Some(memory) = &directives.memory {
+        let bytes: String = memory.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        result.push(format!(
+            ".memory address={:#x} bytes={}",
+            memory.address, bytes
+        ));
+    }
+    result
+}
+
+/// Reformats a whole file in the text format. Instructions are re-emitted
+/// with single spaces, lowercase mnemonics, operands in template order and
+/// lowercase hex values. Comments and blank lines are kept, but runs of
+/// blank lines are collapsed.
+pub fn format_source(source: &str, style: RegisterStyle) -> Result<String, String> {
+    let mut result = String::new();
+    let mut last_blank = true;
+    for (idx, line) in source.lines().enumerate() {
+        let (code, comment) = match line.split_once('#') {
+            Some((code, comment)) => (code.trim(), Some(comment.trim())),
+            None => (line.trim(), None),
+        };
+        if code.is_empty() && comment.is_none() {
+            if !last_blank {
+                result.push('\n');
+            }
+            last_blank = true;
+            continue;
+        }
+        last_blank = false;
+
+        if !code.is_empty() {
+            let line_err = |err: String| format!("line {}: {}", idx + 1, err);
+            let (label, code) = split_label(code).map_err(line_err)?;
+            if let Some(label) = label {
+                result += label;
+                result.push(':');
+                if !code.is_empty() {
+                    result.push(' ');
+                }
+            }
+            if let Some(mnemonic) = code.split_whitespace().next() {
+                let formatted = if is_pseudo(mnemonic) {
+                    format_pseudo(code, style)
+                } else if mnemonic.starts_with('.') {
+                    // Directives are kept as written.
+                    apply_directive(code, &mut Directives::default())
+                        .map(|()| code.split_whitespace().collect::<Vec<&str>>().join(" "))
+                } else if code.contains("offset=") {
+                    format_offset_inst(code, style)
+                } else {
+                    parse_inst(code).map(|inst| format_inst(&inst, style))
+                };
+                result += &formatted.map_err(line_err)?;
+            }
+        }
+        if let Some(comment) = comment {
+            if !code.is_empty() {
+                result += "  ";
+            }
+            result += "#";
+            if !comment.is_empty() {
+                result += " ";
+                result += comment;
+            }
+        }
+        result.push('\n');
+    }
+    // Drop a trailing blank line.
+    if result.ends_with("\n\n") {
+        result.pop();
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        input_config::{InterruptKind, PrivilegeLevel},
+        instructions::{
+            riscv::{
+                args,
+                rv64_i::{ADDIW, SLLI},
+                rv_i::{ADDI, LUI},
+            },
+            split_immediates::{BRANCH_OFFSET, JUMP_OFFSET, STORE_OFFSET},
+            Instruction,
+        },
+        registers::RegisterStyle,
+    };
+
+    use super::{
+        format_directives, format_inst, format_source, parse_directives, parse_inst, parse_line,
+        parse_source, sign_extend,
+    };
+
+    fn dump_inst(inst: &Instruction) -> String {
+        format_inst(inst, RegisterStyle::Hex)
+    }
+
+    fn has_error(res: Result<Instruction, String>, needle: &str) {
+        assert!(res.is_err());
+        let err = res.err().unwrap();
+        assert!(
+            err.contains(needle),
+            "'{}' does not contain '{}'",
+            err,
+            needle
+        );
+    }
+
+    #[test]
+    fn assembly_add() {
+        let input = "addi rd=0x1 rs1=0x1 imm12=0x3";
+        let inst = parse_inst(input).unwrap();
+        assert_eq!(dump_inst(&inst), input);
+    }
+
+    #[test]
+    fn assembly_register_names() {
+        let inst = parse_inst("addi rd=a0 rs1=x2 imm12=0x3").unwrap();
+        assert_eq!(dump_inst(&inst), "addi rd=0xa rs1=0x2 imm12=0x3");
+        let inst = parse_inst("flw rd=fa1 rs1=sp imm12=0x0").unwrap();
+        assert_eq!(dump_inst(&inst), "flw rd=0xb rs1=0x2 imm12=0x0");
+
+        let parse = parse_inst("flw rd=a1 rs1=sp imm12=0x0");
+        has_error(parse, "Invalid decimal or hex value: a1");
+    }
+
+    #[test]
+    fn assembly_invalid_inst() {
+        let parse = parse_inst("addasdf");
+        has_error(parse, "Could not find instruction");
+    }
+
+    #[test]
+    fn assembly_double_op() {
+        let parse = parse_inst("addi rd=0x1 rd=0x1 rs1=0x1 imm12=0x3");
+        has_error(parse, "Duplicate operand");
+    }
+
+    #[test]
+    fn assembly_invalid_format() {
+        let parse = parse_inst("addi rd==0x1 rs1=0x1 imm12=0x3");
+        has_error(parse, "Not in ARG=VALUE");
+    }
+
+    #[test]
+    fn assembly_invalid_op() {
+        let parse = parse_inst("addi rERR=0x1 rs1=0x1 imm12=0x3");
+        has_error(parse, "Failed to find operand with name");
+    }
+
+    #[test]
+    fn assembly_missing_op() {
+        let parse = parse_inst("addi rd=0x1 rs1=0x1");
+        has_error(parse, "Missing operands in instruction");
+    }
+
+    #[test]
+    fn assembly_no_value() {
+        let parse = parse_inst("addi rd= rs1=0x1 imm12=0x3");
+        has_error(parse, "Missing value in arg");
+    }
+
+    #[test]
+    fn assembly_too_large_value() {
+        let parse = parse_inst("addi rd=0xfff rs1=0x1 imm12=0x3");
+        has_error(parse, "Too large value ");
+        let parse = parse_inst("addi rd=32 rs1=0x1 imm12=0x3");
+        has_error(parse, "Too large value ");
+    }
+
+    #[test]
+    fn assembly_non_hex_value() {
+        let parse = parse_inst("addi rd=0xU rs1=0x1 imm12=0x3");
+        has_error(parse, "Invalid decimal or hex value: 0xU");
+    }
+
+    fn dump_line(line: &str) -> Vec<String> {
+        parse_line(line).unwrap().iter().map(dump_inst).collect()
+    }
+
+    // Computes the value that a 'li' expansion loads into its register.
+    fn run_li(insts: &[Instruction]) -> i64 {
+        let mut reg: i64 = 0;
+        for inst in insts {
+            let imm = sign_extend(inst.arg_value(&args::IMM12).unwrap_or(0) as i64, 12);
+            let rs1 = if inst.arg_value(&args::RS1) == Some(0) {
+                0
+            } else {
+                reg
+            };
+            reg = if inst.template() == &LUI {
+                sign_extend((inst.arg_value(&args::IMM20).unwrap() as i64) << 12, 32)
+            } else if inst.template() == &ADDI {
+                rs1.wrapping_add(imm)
+            } else if inst.template() == &ADDIW {
+                rs1.wrapping_add(imm) as i32 as i64
+            } else if inst.template() == &SLLI {
+                reg << inst.arg_value(&args::SHAMTD).unwrap()
+            } else {
+                panic!("Unexpected instruction {:?}", inst)
+            };
+        }
+        reg
+    }
+
+    #[test]
+    fn pseudo_instructions() {
+        assert_eq!(dump_line("nop"), vec!["addi rd=0x0 rs1=0x0 imm12=0x0"]);
+        assert_eq!(
+            dump_line("MV rs1=a1 rd=a0"),
+            vec!["addi rd=0xa rs1=0xb imm12=0x0"]
+        );
+        assert_eq!(dump_line("ret"), vec!["jalr rd=0x0 rs1=0x1 imm12=0x0"]);
+        assert_eq!(
+            dump_line("li rd=t0 imm=-1"),
+            vec!["addi rd=0x5 rs1=0x0 imm12=0xfff"]
+        );
+        assert_eq!(
+            dump_line("call offset=0x1800"),
+            vec!["auipc rd=0x1 imm20=0x2", "jalr rd=0x1 rs1=0x1 imm12=0x800"]
+        );
+        assert!(parse_line("  # only a comment").unwrap().is_empty());
+
+        let jump = parse_line("j offset=-8").unwrap();
+        assert_eq!(JUMP_OFFSET.extract(&jump[0]), Some(0x1ffff8));
+
+        has_error(parse_inst("nop"), "Could not find instruction");
+        assert!(parse_line("j offset=3").is_err());
+        assert!(parse_line("j offset=0x100000").is_err());
+        assert!(parse_line("li rd=a0").is_err());
+        assert!(parse_line("li rd=a0 imm=1 imm=2").is_err());
+        assert!(parse_line("mv rd=a0 rs1=fa0").is_err());
+    }
+
+    #[test]
+    fn raw_words() {
+        let source = ".config features=0x1 privilege=m\n.word value=0xffffffff\nnop\n";
+        let (directives, rest) = parse_directives(source).unwrap();
+        assert!(directives.config.is_some());
+        let insts = parse_source(&rest).unwrap();
+        assert_eq!(insts[0], Instruction::raw_word(0xffff_ffff));
+        assert_eq!(
+            format_inst(&insts[0], RegisterStyle::Abi),
+            ".word value=0xffffffff"
+        );
+        assert_eq!(parse_line(".word value=-1").unwrap(), insts[..1]);
+        assert_eq!(
+            format_source(".WORD  value=0x0", RegisterStyle::Abi).unwrap(),
+            ".word value=0x0\n"
+        );
+        assert!(parse_line(".word value=0x100000000").is_err());
+    }
+
+    #[test]
+    fn load_immediates() {
+        let values: [i64; 12] = [
+            0,
+            1,
+            -2048,
+            2047,
+            2048,
+            0x12345678,
+            0x7ffff800,
+            0x7fffffff,
+            0x80000000,
+            0x123456789abcdef0,
+            i64::MIN,
+            i64::MAX,
+        ];
+        for value in values.into_iter().chain(values.map(|v| v.wrapping_neg())) {
+            let insts = parse_line(&format!("li rd=a0 imm={:#x}", value as u64)).unwrap();
+            assert!(insts.len() <= 8, "{:#x}", value);
+            assert_eq!(run_li(&insts), value, "{:#x}", value);
+        }
+        assert_eq!(parse_line("li rd=a0 imm=0x12345678").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn offset_operand() {
+        let inst = parse_inst("sw rs1=sp rs2=a0 offset=-4").unwrap();
+        assert_eq!(STORE_OFFSET.extract(&inst), Some(0xffc));
+        let inst = parse_inst("bne offset=0x10 rs1=a0 rs2=zero").unwrap();
+        assert_eq!(BRANCH_OFFSET.extract(&inst), Some(0x10));
+
+        has_error(parse_inst("beq rs1=a0 rs2=a1 offset=3"), "Invalid offset");
+        has_error(
+            parse_inst("beq rs1=a0 rs2=a1 offset=0x1000"),
+            "Invalid offset",
+        );
+        has_error(
+            parse_inst("addi rd=a0 rs1=a0 offset=4"),
+            "no offset operand",
+        );
+        has_error(
+            parse_inst("beq rs1=a0 rs2=a1 bimm12lo=0 offset=4"),
+            "Duplicate operand",
+        );
+    }
+
+    #[test]
+    fn labels() {
+        let source = "
+            j offset=start    # forward reference
+            loop: addi rd=a0 rs1=a0 imm12=0xfff
+            start:
+            bne rs1=a0 rs2=zero offset=loop
+            call offset=end
+            li rd=a1 imm=0x12345678
+            end: ret
+        ";
+        let insts = parse_source(source).unwrap();
+        assert_eq!(insts.len(), 8);
+        assert_eq!(JUMP_OFFSET.extract(&insts[0]), Some(8));
+        assert_eq!(BRANCH_OFFSET.extract(&insts[2]), Some(0x1ffc));
+        // The call jumps over itself and the two instructions of 'li'.
+        assert_eq!(dump_inst(&insts[3]), "auipc rd=0x1 imm20=0x0");
+        assert_eq!(dump_inst(&insts[4]), "jalr rd=0x1 rs1=0x1 imm12=0x10");
+
+        let err = parse_source("nop\nj offset=nowhere").unwrap_err();
+        assert!(err.starts_with("line 2: Unknown label"), "{}", err);
+        let err = parse_source("a: nop\na: nop").unwrap_err();
+        assert!(err.contains("Duplicate label"), "{}", err);
+        assert!(parse_source("1a: nop").is_err());
+    }
+
+    #[test]
+    fn format_file() {
+        let source =
+            "# Seed\n\n\n  ADDI  imm12=0x3 rd=1 rs1=0xA #  bump\nsub rd=a0 rs1=a0 rs2=a1\n\n";
+        let expected =
+            "# Seed\n\naddi rd=0x1 rs1=0xa imm12=0x3  # bump\nsub rd=0xa rs1=0xa rs2=0xb\n";
+        assert_eq!(format_source(source, RegisterStyle::Hex).unwrap(), expected);
+        // Formatting is idempotent.
+        assert_eq!(
+            format_source(expected, RegisterStyle::Hex).unwrap(),
+            expected
+        );
+        assert_eq!(
+            format_source("sub rd=a0 rs1=a0 rs2=a1", RegisterStyle::Abi).unwrap(),
+            "sub rd=a0 rs1=a0 rs2=a1\n"
+        );
+
+        assert_eq!(
+            format_source("LI  imm=-0x10 rd=x10 # pseudo", RegisterStyle::Abi).unwrap(),
+            "li rd=a0 imm=-0x10  # pseudo\n"
+        );
+
+        assert_eq!(
+            format_source(
+                "Loop:  BEQ offset=Loop rs2=x11 rs1=x10\nj offset=-0xA",
+                RegisterStyle::Abi
+            )
+            .unwrap(),
+            "Loop: beq rs1=a0 rs2=a1 offset=Loop\nj offset=-0xa\n"
+        );
+
+        let err = format_source("# ok\naddi rd=1", RegisterStyle::Hex).unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+    }
+
+    #[test]
+    fn directives() {
+        let source = ".config features=0x5 privilege=s\n\
+                      .register value=-1 reg=a0  # all ones\n\
+                      .interrupt cycle=20 kind=timer\n\
+                      .interrupt cycle=10 kind=external\n\
+                      addi rd=a0 rs1=a0 imm12=1\n\
+                      .memory address=0x80001000 bytes=01ff\n";
+        let (directives, rest) = parse_directives(source).unwrap();
+        let config = directives.config.as_ref().unwrap();
+        assert_eq!(config.features, 5);
+        assert_eq!(config.privilege, PrivilegeLevel::Supervisor);
+        assert_eq!(config.interrupts[0].kind, InterruptKind::External);
+        assert_eq!(config.registers[0].reg, 10);
+        assert_eq!(config.registers[0].value, u64::MAX);
+        let memory = directives.memory.as_ref().unwrap();
+        assert_eq!(memory.address, 0x8000_1000);
+        assert_eq!(memory.bytes, vec![1, 0xff]);
+        // Only the instruction is left, on its original line.
+        assert_eq!(rest.lines().nth(4), Some("addi rd=a0 rs1=a0 imm12=1"));
+        assert_eq!(parse_source(&rest).unwrap().len(), 1);
+
+        let formatted = format_directives(&directives, RegisterStyle::Abi);
+        assert_eq!(formatted[0], ".config features=0x5 privilege=supervisor");
+        assert_eq!(formatted[3], ".register reg=a0 value=0xffffffffffffffff");
+        assert_eq!(formatted[4], ".memory address=0x80001000 bytes=01ff");
+        let (reparsed, _) = parse_directives(&formatted.join("\n")).unwrap();
+        assert_eq!(reparsed, directives);
+
+        let err = parse_directives("nop\n.register reg=x0 value=1").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+        assert!(parse_directives(".config features=1").is_err());
+        assert!(parse_directives(".memory address=0 bytes=123").is_err());
+        assert!(parse_directives(".stack size=1").is_err());
+        assert_eq!(
+            format_source(".config  privilege=m features=1", RegisterStyle::Abi).unwrap(),
+            ".config privilege=m features=1\n"
+        );
+    }
+}
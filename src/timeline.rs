@@ -0,0 +1,175 @@
+//! A structured log of the notable events of a campaign (one JSON object per
+//! line), to reconstruct what happened during long runs. See the 'timeline'
+//! tool for a readable chronology.
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The name of the timeline file in the out dir.
+pub const TIMELINE_FILE: &str = "timeline.jsonl";
+
+/// Something notable that happened during a campaign.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TimelineEvent {
+    /// The fuzzer was started with the given settings.
+    CampaignStart { cores: usize, scheduler: String },
+    /// A client reported for the first time.
+    ClientStart { client: u32 },
+    /// A client was restarted (e.g. after a crash of the fuzzer process) and
+    /// lost the executions it had done before.
+    ClientRestart { client: u32, execs: u64 },
+    /// The total coverage grew.
+    NewCoverage { client: u32, coverage: u64 },
+    /// A client found a new objective (e.g. a crash).
+    Objective { client: u32, objectives: u64 },
+    /// The harness identified a new cause.
+    Cause { name: String },
+    /// The campaign was stopped (e.g. because its budget was used up).
+    CampaignStop { reason: String, execs: u64 },
+}
+
+impl TimelineEvent {
+    /// A one-line description of the event.
+    pub fn describe(&self) -> String {
+        match self {
+            TimelineEvent::CampaignStart { cores, scheduler } => {
+                format!("Campaign started on {} cores ({})", cores, scheduler)
+            }
+            TimelineEvent::ClientStart { client } => format!("Client {} started", client),
+            TimelineEvent::ClientRestart { client, execs } => {
+                format!("Client {} restarted after {} execs", client, execs)
+            }
+            TimelineEvent::NewCoverage { client, coverage } => {
+                format!("Coverage grew to {} (client {})", coverage, client)
+            }
+            TimelineEvent::Objective { client, objectives } => {
+                format!("Objective found ({} total, client {})", objectives, client)
+            }
+            TimelineEvent::Cause { name } => format!("Cause identified: {}", name),
+            TimelineEvent::CampaignStop { reason, execs } => {
+                format!("Campaign stopped after {} execs ({})", execs, reason)
+            }
+        }
+    }
+}
+
+/// An event and when it happened.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TimelineEntry {
+    /// Seconds since the UNIX epoch.
+    pub time: u64,
+    #[serde(flatten)]
+    pub event: TimelineEvent,
+}
+
+impl TimelineEntry {
+    /// Formats the entry as a JSON line (without the newline).
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).expect("Timeline entries are always serializable")
+    }
+
+    /// Parses the output of `to_line`.
+    pub fn parse_line(line: &str) -> Option<TimelineEntry> {
+        serde_json::from_str(line).ok()
+    }
+}
+
+/// Parses a timeline, skipping malformed (e.g. partially written) lines.
+pub fn parse_timeline(text: &str) -> Vec<TimelineEntry> {
+    text.lines().filter_map(TimelineEntry::parse_line).collect()
+}
+
+/// Formats a number of seconds as 'Dd HH:MM:SS' (days only if non-zero).
+pub fn format_offset(secs: u64) -> String {
+    let hms = format!(
+        "{:02}:{:02}:{:02}",
+        (secs / 3600) % 24,
+        (secs / 60) % 60,
+        secs % 60
+    );
+    match secs / 86400 {
+        0 => hms,
+        days => format!("{}d {}", days, hms),
+    }
+}
+
+/// Appends events to the timeline file of an out dir.
+#[derive(Clone, Debug)]
+pub struct TimelineLog {
+    path: PathBuf,
+}
+
+impl TimelineLog {
+    pub fn new(out_dir: &Path) -> Self {
+        Self {
+            path: out_dir.join(TIMELINE_FILE),
+        }
+    }
+
+    /// Appends the event with the current time. Failures are only logged, a
+    /// missing timeline entry shouldn't stop the campaign.
+    pub fn record(&self, event: TimelineEvent) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_secs());
+        let line = TimelineEntry { time, event }.to_line() + "\n";
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+        if let Err(err) = written {
+            log::warn!("Failed to update {:?}: {}", self.path, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_offset, parse_timeline, TimelineEntry, TimelineEvent};
+
+    #[test]
+    fn parse_timeline_lines() {
+        let entries = vec![
+            TimelineEntry {
+                time: 100,
+                event: TimelineEvent::CampaignStart {
+                    cores: 4,
+                    scheduler: "fast".to_string(),
+                },
+            },
+            TimelineEntry {
+                time: 105,
+                event: TimelineEvent::ClientRestart {
+                    client: 2,
+                    execs: 1234,
+                },
+            },
+            TimelineEntry {
+                time: 200,
+                event: TimelineEvent::Cause {
+                    name: "missing \"fence\"".to_string(),
+                },
+            },
+        ];
+        let mut text: String = entries.iter().map(|e| e.to_line() + "\n").collect();
+        assert!(text.starts_with(r#"{"time":100,"event":"campaign_start""#));
+        // A partially written last line is skipped.
+        text += r#"{"time":300,"eve"#;
+        assert_eq!(parse_timeline(&text), entries);
+    }
+
+    #[test]
+    fn format_offsets() {
+        assert_eq!(format_offset(0), "00:00:00");
+        assert_eq!(format_offset(3725), "01:02:05");
+        assert_eq!(format_offset(2 * 86400 + 61), "2d 00:01:01");
+    }
+}
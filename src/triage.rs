@@ -0,0 +1,347 @@
+//! Groups the objectives of a campaign into buckets of (probably) the same
+//! bug, so the 'found' directories don't drown in duplicates. An objective
+//! belongs to the bucket of its cause (see `causes`) and the hash of the
+//! coverage map of its execution, which the `CoverageHashFeedback` records
+//! while fuzzing. See the 'triage' tool.
+
+use core::{fmt::Debug, marker::PhantomData};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use libafl::{
+    bolts::{tuples::Named, HasLen},
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::{HasTargetBytes, Input, UsesInput},
+    observers::{MapObserver, ObserversTuple},
+    state::HasClientPerfMonitor,
+    Error,
+};
+
+use crate::{causes::read_causes, isa_hash::ISA_HASH_FILE, program_input::ProgramInput};
+
+/// Name of the file in an objective directory that maps the names of the
+/// objectives to their coverage hashes. Hidden like the other files LibAFL
+/// stores next to the inputs.
+pub const COVERAGE_HASH_FILE: &str = ".coverage_hashes";
+/// Name of the triage report in an output directory.
+pub const TRIAGE_REPORT_FILE: &str = "triage";
+/// The cause of objectives without an input in the cause dir, e.g. timeouts.
+pub const UNKNOWN_CAUSE: &str = "Unknown";
+
+/// Formats a line of the coverage hash file.
+pub fn coverage_hash_line(name: &str, hash: u64) -> String {
+    format!("{} {:016x}\n", name, hash)
+}
+
+/// Parses a coverage hash file into hashes by objective name. Broken lines
+/// (e.g. of a client that was killed while writing) are skipped.
+pub fn parse_coverage_hashes(text: &str) -> HashMap<String, u64> {
+    text.lines()
+        .filter_map(|line| {
+            let (name, hash) = line.split_once(' ')?;
+            Some((name.to_string(), u64::from_str_radix(hash, 16).ok()?))
+        })
+        .collect()
+}
+
+/// An objective input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub path: PathBuf,
+    /// The number of instructions.
+    pub size: usize,
+    pub cause: String,
+    /// The hash of the coverage map of the execution, if it was recorded.
+    pub coverage_hash: Option<u64>,
+}
+
+/// What the objectives in a bucket have in common.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BucketKey {
+    pub cause: String,
+    pub coverage_hash: Option<u64>,
+}
+
+/// The objectives of the same cause and coverage hash, smallest first.
+#[derive(Clone, Debug)]
+pub struct Bucket {
+    pub key: BucketKey,
+    pub findings: Vec<Finding>,
+}
+
+/// Sorts the findings into buckets, ordered by cause and hash.
+pub fn bucket_findings(findings: Vec<Finding>) -> Vec<Bucket> {
+    let mut buckets = BTreeMap::<BucketKey, Vec<Finding>>::new();
+    for finding in findings {
+        let key = BucketKey {
+            cause: finding.cause.clone(),
+            coverage_hash: finding.coverage_hash,
+        };
+        buckets.entry(key).or_default().push(finding);
+    }
+    buckets
+        .into_iter()
+        .map(|(key, mut findings)| {
+            findings.sort_by(|a, b| a.size.cmp(&b.size).then_with(|| a.path.cmp(&b.path)));
+            Bucket { key, findings }
+        })
+        .collect()
+}
+
+// The objective directories of the clients in an output directory.
+fn objective_dirs(out_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let found_dir = out_dir.join("found");
+    let entries =
+        fs::read_dir(&found_dir).map_err(|e| format!("Failed to read {:?}: {}", found_dir, e))?;
+    let mut result: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    result.sort();
+    Ok(result)
+}
+
+// The inputs in an objective directory, skipping the hidden files.
+fn objective_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut result: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            !name.starts_with('.') && name != ISA_HASH_FILE
+        })
+        .collect();
+    result.sort();
+    result
+}
+
+/// Reads the objectives of all clients in an output directory. Their causes
+/// are found by comparing them with the inputs in the cause dir.
+pub fn read_findings(out_dir: &Path) -> Result<Vec<Finding>, String> {
+    let mut causes = HashMap::<Vec<u8>, String>::new();
+    for (path, record) in read_causes(&out_dir.join("causes")) {
+        if let Ok(bytes) = fs::read(&path) {
+            causes.insert(bytes, record.cause);
+        }
+    }
+
+    let mut result = Vec::new();
+    for dir in objective_dirs(out_dir)? {
+        let hashes = fs::read_to_string(dir.join(COVERAGE_HASH_FILE))
+            .map(|text| parse_coverage_hashes(&text))
+            .unwrap_or_default();
+        for path in objective_files(&dir) {
+            let input = match ProgramInput::from_file(&path) {
+                Ok(input) => input,
+                Err(err) => {
+                    log::warn!("Skipping {:?}: {}", path, err);
+                    continue;
+                }
+            };
+            // The harness saves the bytes it got, with or without epilogue.
+            let cause = causes
+                .get(input.target_bytes().as_slice())
+                .or_else(|| causes.get(&input.to_bytes()))
+                .cloned()
+                .unwrap_or_else(|| UNKNOWN_CAUSE.to_string());
+            result.push(Finding {
+                size: input.len(),
+                cause,
+                coverage_hash: hashes.get(&input.generate_name(0)).copied(),
+                path,
+            });
+        }
+    }
+    Ok(result)
+}
+
+/// Deletes all but the `keep` smallest objectives of every bucket, together
+/// with the files LibAFL stores next to them. Returns how many were deleted.
+pub fn prune(buckets: &[Bucket], keep: usize) -> Result<usize, String> {
+    let mut deleted = 0;
+    for bucket in buckets {
+        for finding in bucket.findings.iter().skip(keep) {
+            fs::remove_file(&finding.path)
+                .map_err(|e| format!("Failed to delete {:?}: {}", finding.path, e))?;
+            if let (Some(dir), Some(name)) = (finding.path.parent(), finding.path.file_name()) {
+                let name = name.to_string_lossy();
+                let _ = fs::remove_file(dir.join(format!(".{}.metadata", name)));
+                let _ = fs::remove_file(dir.join(format!(".{}.lafl_lock", name)));
+            }
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+/// A summary of the buckets with the kept objectives of each.
+pub fn report(buckets: &[Bucket], keep: usize) -> String {
+    let total: usize = buckets.iter().map(|b| b.findings.len()).sum();
+    let mut result = format!("{} objectives in {} buckets\n", total, buckets.len());
+    for bucket in buckets {
+        let hash = match bucket.key.coverage_hash {
+            Some(hash) => format!("{:016x}", hash),
+            None => "unknown coverage".to_string(),
+        };
+        result += &format!(
+            "\n{} ({}): {} objectives\n",
+            bucket.key.cause,
+            hash,
+            bucket.findings.len()
+        );
+        for finding in bucket.findings.iter().take(keep) {
+            result += &format!("  {} instructions: {:?}\n", finding.size, finding.path);
+        }
+    }
+    result
+}
+
+/// Feedback that is never interesting, but records the hash of the coverage
+/// map of every crashing objective in the coverage hash file of the objective
+/// directory. Belongs to the objective.
+#[derive(Clone, Debug)]
+pub struct CoverageHashFeedback<O, S> {
+    observer_name: String,
+    path: PathBuf,
+    // The hash of the last execution if it crashed.
+    last: Option<u64>,
+    phantom: PhantomData<(O, S)>,
+}
+
+impl<O, S> CoverageHashFeedback<O, S>
+where
+    O: MapObserver,
+{
+    #[must_use]
+    pub fn new(observer: &O, objective_dir: &Path) -> Self {
+        Self {
+            observer_name: observer.name().to_string(),
+            path: objective_dir.join(COVERAGE_HASH_FILE),
+            last: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<O, S> Named for CoverageHashFeedback<O, S> {
+    fn name(&self) -> &str {
+        "CoverageHashFeedback"
+    }
+}
+
+impl<O, S> Feedback<S> for CoverageHashFeedback<O, S>
+where
+    O: MapObserver,
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor + Debug,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &ProgramInput,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        // Hashing the whole map is too slow to do for every execution.
+        self.last = None;
+        if *exit_kind != ExitKind::Ok {
+            let observer = observers
+                .match_name::<O>(&self.observer_name)
+                .ok_or_else(|| Error::key_not_found("MapObserver not found".to_string()))?;
+            self.last = Some(observer.hash());
+        }
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        let (Some(hash), Some(input)) = (self.last.take(), testcase.input()) else {
+            return Ok(());
+        };
+        // The objective corpus names the input the same way.
+        let line = coverage_hash_line(&input.generate_name(0), hash);
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))?;
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &ProgramInput) -> Result<(), Error> {
+        self.last = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{
+        bucket_findings, coverage_hash_line, parse_coverage_hashes, prune, report, Finding,
+    };
+
+    fn finding(path: PathBuf, size: usize, cause: &str, coverage_hash: Option<u64>) -> Finding {
+        Finding {
+            path,
+            size,
+            cause: cause.to_string(),
+            coverage_hash,
+        }
+    }
+
+    #[test]
+    fn coverage_hashes() {
+        let text = coverage_hash_line("size:2-hash:0123", 0xabc) + "broken\n";
+        let hashes = parse_coverage_hashes(&text);
+        assert_eq!(hashes.len(), 1);
+        assert_eq!(hashes["size:2-hash:0123"], 0xabc);
+    }
+
+    #[test]
+    fn bucket_and_prune() {
+        let dir = std::env::temp_dir().join(format!("triage-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a", "b", "c", "d"] {
+            std::fs::write(dir.join(name), [0u8]).unwrap();
+        }
+        std::fs::write(dir.join(".a.metadata"), [0u8]).unwrap();
+
+        let buckets = bucket_findings(vec![
+            finding(dir.join("a"), 5, "Hang", Some(1)),
+            finding(dir.join("b"), 2, "Hang", Some(1)),
+            finding(dir.join("c"), 1, "Hang", Some(2)),
+            finding(dir.join("d"), 3, "Bad store", None),
+        ]);
+        assert_eq!(buckets.len(), 3);
+        assert_eq!(buckets[0].key.cause, "Bad store");
+        assert_eq!(buckets[1].findings[0].path, dir.join("b"));
+        assert!(report(&buckets, 1).starts_with("4 objectives in 3 buckets\n"));
+
+        assert_eq!(prune(&buckets, 1).unwrap(), 1);
+        assert!(!dir.join("a").exists());
+        assert!(!dir.join(".a.metadata").exists());
+        assert!(dir.join("b").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+use core::{fmt::Debug, marker::PhantomData};
+
+use serde::{Deserialize, Serialize};
+
+use libafl::{
+    bolts::tuples::Named,
+    corpus::{Corpus, CorpusId},
+    events::EventFirer,
+    executors::{Executor, ExitKind, HasObservers},
+    feedbacks::HasObserverName,
+    fuzzer::Evaluator,
+    inputs::UsesInput,
+    observers::{MapObserver, ObserversTuple, UsesObserver},
+    stages::Stage,
+    state::{HasClientPerfMonitor, HasCorpus, HasMetadata, UsesState},
+    Error,
+};
+
+use crate::program_input::ProgramInput;
+
+libafl::impl_serdeany!(TrimmedMetadata);
+/// Marks corpus entries that were already trimmed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TrimmedMetadata {
+    original_len: usize,
+}
+
+impl TrimmedMetadata {
+    /// The number of instructions before trimming.
+    #[must_use]
+    pub fn original_len(&self) -> usize {
+        self.original_len
+    }
+}
+
+/// The trimming stage removes chunks of instructions from new corpus entries
+/// as long as the coverage map stays the same. Chunks start at half of the
+/// program and are halved down to single instructions.
+#[derive(Clone, Debug)]
+pub struct ProgramTrimStage<O, OT, S> {
+    map_observer_name: String,
+    // The maximum number of executions per entry, 0 disables trimming.
+    max_execs: usize,
+    phantom: PhantomData<(O, OT, S)>,
+}
+
+impl<O, OT, S> UsesState for ProgramTrimStage<O, OT, S>
+where
+    S: UsesInput,
+{
+    type State = S;
+}
+
+impl<E, EM, O, OT, Z> Stage<E, EM, Z> for ProgramTrimStage<O, OT, E::State>
+where
+    E: Executor<EM, Z> + HasObservers<Observers = OT>,
+    EM: EventFirer<State = E::State>,
+    O: MapObserver,
+    OT: ObserversTuple<E::State>,
+    E::State: HasCorpus + HasMetadata + HasClientPerfMonitor + UsesInput<Input = ProgramInput>,
+    Z: Evaluator<E, EM, State = E::State>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut E::State,
+        mgr: &mut EM,
+        corpus_idx: CorpusId,
+    ) -> Result<(), Error> {
+        if self.max_execs == 0
+            || state
+                .corpus()
+                .get(corpus_idx)?
+                .borrow()
+                .has_metadata::<TrimmedMetadata>()
+        {
+            return Ok(());
+        }
+
+        let input = state
+            .corpus()
+            .get(corpus_idx)?
+            .borrow_mut()
+            .load_input(state.corpus())?
+            .clone();
+        let original_len = input.insts().len();
+
+        // Entries that don't run through can't be compared.
+        let current = match self.run_hash(fuzzer, executor, state, mgr, &input)? {
+            // The first execution is part of the budget.
+            Some(expected) => trim(&input, self.max_execs - 1, |candidate| {
+                Ok(self.run_hash(fuzzer, executor, state, mgr, candidate)? == Some(expected))
+            })?,
+            None => input,
+        };
+
+        let mut testcase = state.corpus().get(corpus_idx)?.borrow().clone();
+        testcase.add_metadata(TrimmedMetadata { original_len });
+        if current.insts().len() < original_len {
+            testcase.set_input(current);
+        }
+        state.corpus_mut().replace(corpus_idx, testcase)?;
+        Ok(())
+    }
+}
+
+/// Removes chunks of instructions from the input as long as `keep` accepts
+/// the result. Chunks start at half of the program and are halved down to
+/// single instructions. At most `max_tries` candidates are passed to `keep`,
+/// the input is never trimmed to nothing.
+fn trim<F>(input: &ProgramInput, max_tries: usize, mut keep: F) -> Result<ProgramInput, Error>
+where
+    F: FnMut(&ProgramInput) -> Result<bool, Error>,
+{
+    let mut current = input.clone();
+    let mut tries = 0;
+    let mut chunk = input.insts().len().next_power_of_two() / 2;
+    while chunk > 0 && tries < max_tries {
+        let mut pos = 0;
+        while pos < current.insts().len() && tries < max_tries {
+            let end = (pos + chunk).min(current.insts().len());
+            let mut candidate = current.slice(0..pos)?;
+            candidate
+                .insts_mut()
+                .extend_from_slice(&current.insts()[end..]);
+            if candidate.insts().is_empty() {
+                pos = end;
+                continue;
+            }
+            tries += 1;
+            if keep(&candidate)? {
+                current = candidate;
+            } else {
+                pos = end;
+            }
+        }
+        chunk /= 2;
+    }
+    Ok(current)
+}
+
+impl<O, OT, S> ProgramTrimStage<O, OT, S>
+where
+    O: MapObserver,
+    OT: ObserversTuple<S>,
+    S: HasCorpus + HasMetadata + UsesInput<Input = ProgramInput>,
+{
+    /// Creates a trimming stage that compares the map of the given feedback
+    /// and runs at most `max_execs` executions per entry (0 disables it).
+    #[must_use]
+    pub fn new<F>(map_feedback: &F, max_execs: usize) -> Self
+    where
+        F: HasObserverName + Named + UsesObserver<S, Observer = O>,
+    {
+        Self {
+            map_observer_name: map_feedback.observer_name().to_string(),
+            max_execs,
+            phantom: PhantomData,
+        }
+    }
+
+    // Runs the input and returns the hash of the coverage map, or None if
+    // the execution didn't finish normally.
+    fn run_hash<E, EM, Z>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        mgr: &mut EM,
+        input: &ProgramInput,
+    ) -> Result<Option<u64>, Error>
+    where
+        E: Executor<EM, Z, State = S> + HasObservers<Observers = OT>,
+        EM: UsesState<State = S>,
+        Z: UsesState<State = S>,
+    {
+        executor.observers_mut().pre_exec_all(state, input)?;
+        let exit_kind = executor.run_target(fuzzer, state, mgr, input)?;
+        executor
+            .observers_mut()
+            .post_exec_all(state, input, &exit_kind)?;
+        if exit_kind != ExitKind::Ok {
+            return Ok(None);
+        }
+        let map = executor
+            .observers()
+            .match_name::<O>(&self.map_observer_name)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found".to_string()))?;
+        Ok(Some(map.hash()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::Error;
+
+    use crate::{program_input::ProgramInput, text_format::parse_source};
+
+    use super::trim;
+
+    // A program of `len` different instructions.
+    fn program(len: usize) -> ProgramInput {
+        let source: String = (0..len)
+            .map(|i| format!("addi a{}, zero, {}\n", i, i))
+            .collect();
+        ProgramInput::new(parse_source(&source).unwrap())
+    }
+
+    #[test]
+    fn trim_to_needed_instructions() {
+        let input = program(5);
+        let needed = input.insts()[1].clone();
+        let mut tries = 0;
+        let trimmed = trim(&input, 100, |candidate| {
+            tries += 1;
+            Ok(candidate.insts().contains(&needed))
+        })
+        .unwrap();
+        assert_eq!(trimmed.insts(), &[needed]);
+        assert!(tries <= 10, "{}", tries);
+
+        // Inputs are never trimmed to nothing.
+        let trimmed = trim(&input, 100, |_| Ok(true)).unwrap();
+        assert_eq!(trimmed.insts().len(), 1);
+    }
+
+    #[test]
+    fn trim_budget_and_errors() {
+        let input = program(4);
+        let mut tries = 0;
+        let trimmed = trim(&input, 2, |_| {
+            tries += 1;
+            Ok(false)
+        })
+        .unwrap();
+        assert_eq!(tries, 2);
+        assert_eq!(trimmed, input);
+        assert_eq!(trim(&input, 0, |_| Ok(true)).unwrap(), input);
+
+        assert!(trim(&input, 10, |_| Err(Error::illegal_state("Failed"))).is_err());
+    }
+}
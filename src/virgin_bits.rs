@@ -0,0 +1,197 @@
+//! A coverage map union that persists across campaigns in the out dir, so
+//! that a campaign against the same target can skip the coverage that
+//! previous campaigns already found.
+
+use core::{fmt::Debug, marker::PhantomData};
+use std::{
+    fs,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use libafl::{
+    bolts::tuples::Named,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::{Feedback, MapFeedbackMetadata},
+    inputs::UsesInput,
+    observers::ObserversTuple,
+    state::{HasClientPerfMonitor, HasNamedMetadata},
+    Error,
+};
+use nix::fcntl::{flock, FlockArg};
+
+/// The name of the union file in the out dir.
+pub const VIRGIN_BITS_FILE: &str = "virgin_bits";
+
+/// Merges `bits` into `union` by keeping the maximum of every entry (like
+/// the history of a `MaxMapFeedback`). The union grows to the length of
+/// `bits`. Returns whether any entry of the union changed.
+pub fn merge_bits(union: &mut Vec<u8>, bits: &[u8]) -> bool {
+    if union.len() < bits.len() {
+        union.resize(bits.len(), 0);
+    }
+    let mut changed = false;
+    for (entry, bit) in union.iter_mut().zip(bits) {
+        if *bit > *entry {
+            *entry = *bit;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// The number of covered entries in the map.
+pub fn count_bits(bits: &[u8]) -> usize {
+    bits.iter().filter(|b| **b != 0).count()
+}
+
+/// The number of entries covered in `bits` that the union doesn't cover.
+pub fn count_new_bits(union: &[u8], bits: &[u8]) -> usize {
+    bits.iter()
+        .enumerate()
+        .filter(|(idx, bit)| **bit != 0 && union.get(*idx).copied().unwrap_or(0) == 0)
+        .count()
+}
+
+/// Reads the union file. A missing file is an empty union.
+pub fn load_virgin_bits(path: &Path) -> Result<Vec<u8>, String> {
+    match fs::read(path) {
+        Ok(bits) => Ok(bits),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(format!("Failed to read {:?}: {}", path, err)),
+    }
+}
+
+/// Merges `bits` into the union file. Every client of a campaign updates
+/// the same file, so updates hold an exclusive lock and replace the file
+/// with a rename so readers never see a partially written union.
+pub fn update_virgin_bits(path: &Path, bits: &[u8]) -> Result<(), String> {
+    let lock_path = path.with_extension("lock");
+    let lock = fs::File::create(&lock_path)
+        .map_err(|e| format!("Failed to create {:?}: {}", lock_path, e))?;
+    // Released when the lock file is closed.
+    flock(lock.as_raw_fd(), FlockArg::LockExclusive)
+        .map_err(|e| format!("Failed to lock {:?}: {}", lock_path, e))?;
+
+    let mut union = load_virgin_bits(path)?;
+    if !merge_bits(&mut union, bits) {
+        return Ok(());
+    }
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, &union).map_err(|e| format!("Failed to write {:?}: {}", tmp_path, e))?;
+    fs::rename(&tmp_path, path).map_err(|e| format!("Failed to replace {:?}: {}", path, e))
+}
+
+/// Copies the union into the history of the `MapFeedback` with the given
+/// name, so that the fuzzer only considers coverage interesting that isn't
+/// in the union. Must be called after the state was created.
+pub fn restore_virgin_bits<S>(state: &mut S, map_feedback: &str, bits: &[u8]) -> Result<(), Error>
+where
+    S: HasNamedMetadata,
+{
+    let history = state
+        .named_metadata_mut()
+        .get_mut::<MapFeedbackMetadata<u8>>(map_feedback)
+        .ok_or_else(|| Error::key_not_found(format!("No map feedback '{}'", map_feedback)))?;
+    merge_bits(&mut history.history_map, bits);
+    Ok(())
+}
+
+/// Feedback that is never interesting by itself, but periodically merges
+/// the history of a `MapFeedback` (the coverage this client found and
+/// restored) into the union file.
+#[derive(Clone, Debug)]
+pub struct VirginBitsFeedback<S> {
+    map_feedback: String,
+    path: PathBuf,
+    last_update: Instant,
+    interval: Duration,
+    phantom: PhantomData<S>,
+}
+
+impl<S> VirginBitsFeedback<S> {
+    /// Creates the feedback for the union file at `path`.
+    #[must_use]
+    pub fn new<F: Named>(map_feedback: &F, path: PathBuf) -> Self {
+        Self {
+            map_feedback: map_feedback.name().to_string(),
+            path,
+            last_update: Instant::now(),
+            interval: Duration::from_secs(10),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Named for VirginBitsFeedback<S> {
+    fn name(&self) -> &str {
+        "VirginBitsFeedback"
+    }
+}
+
+impl<S> Feedback<S> for VirginBitsFeedback<S>
+where
+    S: UsesInput + HasClientPerfMonitor + HasNamedMetadata + Debug,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &S::Input,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if self.last_update.elapsed() < self.interval {
+            return Ok(false);
+        }
+        self.last_update = Instant::now();
+        let Some(history) = state
+            .named_metadata()
+            .get::<MapFeedbackMetadata<u8>>(&self.map_feedback)
+        else {
+            return Ok(false);
+        };
+        if let Err(err) = update_virgin_bits(&self.path, &history.history_map) {
+            log::warn!("{}", err);
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        count_bits, count_new_bits, load_virgin_bits, merge_bits, update_virgin_bits,
+        VIRGIN_BITS_FILE,
+    };
+
+    #[test]
+    fn merge() {
+        let mut union = vec![0, 2, 0];
+        assert!(merge_bits(&mut union, &[1, 1, 0, 0, 4]));
+        assert_eq!(union, vec![1, 2, 0, 0, 4]);
+        assert!(!merge_bits(&mut union, &[1, 2]));
+        assert_eq!(count_bits(&union), 3);
+        assert_eq!(count_new_bits(&union, &[5, 0, 1, 0, 1, 1]), 2);
+    }
+
+    #[test]
+    fn update_file() {
+        let dir = std::env::temp_dir().join(format!("virgin-bits-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(VIRGIN_BITS_FILE);
+
+        assert_eq!(load_virgin_bits(&path).unwrap(), Vec::<u8>::new());
+        update_virgin_bits(&path, &[0, 1, 0]).unwrap();
+        update_virgin_bits(&path, &[2, 0]).unwrap();
+        assert_eq!(load_virgin_bits(&path).unwrap(), vec![2, 1, 0]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}